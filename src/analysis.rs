@@ -0,0 +1,656 @@
+//! Analysis of a whole capture file in one call ([`analyze_file`]), and of frames
+//! from any other source one at a time ([`Analyzer`]), for programs that embed
+//! ecdump instead of running it.
+//!
+//! ```no_run
+//! let report = ecdump::analyze_file("capture.pcapng", ecdump::AnalysisOptions::default())?;
+//! for device in report.devices() {
+//!     println!("{} {}", device.identifier(), device.state());
+//! }
+//! for (category, statistics) in report.errors.iter() {
+//!     println!("{}: {}", category, statistics.count);
+//! }
+//! # Ok::<(), ecdump::capture_file::CaptureFileError>(())
+//! ```
+
+use pnet::packet::Packet;
+use pnet::packet::ethernet::EthernetPacket;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::analyzer::{
+    AlStatusCodeUpdate, AnalyzerConfig, BusSizeChange, DeviceManager, ECDeviceError, ECError,
+    ErrorAcknowledgement, ErrorCorrelation, FirmwareUpdate, LogicalAddressEvent, MalformedFrame,
+    Rescan, SignalSample, StateTransition,
+};
+use crate::capture_file::{self, CaptureFileError};
+use crate::ec_packet::{ECFrame, FrameMalformation};
+use crate::protocol_handler::{ProtocolEvent, ProtocolHandler};
+use crate::register_watch::{RegisterChange, RegisterWatch};
+use crate::subdevice::{EscInfo, SubDevice, SubDeviceIdentity, SubDeviceStatistics};
+
+/// EtherType of EtherCAT frames.
+const ETHERTYPE_ETHERCAT: u16 = 0x88a4;
+
+/// Which way a frame travels on the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by the main device, before the subdevices processed it.
+    FromMain,
+    /// Returning to the main device after passing the subdevices.
+    ToMain,
+}
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct AnalysisOptions {
+    /// Registers whose changes are reported in [`FrameAnalysis::register_changes`].
+    pub register_watches: Vec<RegisterWatch>,
+    /// Optional subsystems of the analyzer.
+    pub analyzer: AnalyzerConfig,
+}
+
+/// Occurrences of one kind of error.
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+pub struct ErrorStatistics {
+    pub count: u64,
+    pub first_frame: u64,
+    pub last_frame: u64,
+    /// Capture-relative timestamps in seconds.
+    pub first_timestamp: f64,
+    pub last_timestamp: f64,
+}
+
+/// Errors of an analysis by category.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct ErrorCounts(BTreeMap<&'static str, ErrorStatistics>);
+
+impl ErrorCounts {
+    pub fn record(&mut self, malformed: &[MalformedFrame], error: Option<&ECError>) {
+        for frame in malformed {
+            self.count("malformed_frame", frame.packet_number, frame.timestamp);
+        }
+        match error {
+            Some(ECError::InvalidDatagram {
+                packet_number,
+                timestamp,
+                ..
+            }) => self.count("invalid_datagram", *packet_number, *timestamp),
+            Some(ECError::DeviceError(errors)) => {
+                for error in errors {
+                    self.count(
+                        device_error_category(error),
+                        error.packet_number(),
+                        error.timestamp(),
+                    );
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn count(&mut self, category: &'static str, frame: u64, timestamp: Duration) {
+        let timestamp = timestamp.as_secs_f64();
+        self.0
+            .entry(category)
+            .and_modify(|statistics| {
+                statistics.count += 1;
+                statistics.last_frame = frame;
+                statistics.last_timestamp = timestamp;
+            })
+            .or_insert(ErrorStatistics {
+                count: 1,
+                first_frame: frame,
+                last_frame: frame,
+                first_timestamp: timestamp,
+                last_timestamp: timestamp,
+            });
+    }
+
+    /// Categories in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &ErrorStatistics)> {
+        self.0
+            .iter()
+            .map(|(category, statistics)| (*category, statistics))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Result of an analysis that is identical across runs for the same capture:
+/// the subdevice table, the frame counters and a digest of the events of each
+/// category. Compared against golden files to catch changes of the results.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct AnalysisSummary {
+    pub analyzed_frames: u64,
+    /// Number of the last bus scan (1 unless the main device rescanned the bus).
+    pub scans: u32,
+    /// Frame after which all subdevices reached Op.
+    pub init_complete_frame: Option<u64>,
+    pub devices: Vec<DeviceSummary>,
+    /// Events by category, such as `state_transition` or `wkc_mismatch`.
+    pub events: BTreeMap<String, EventDigest>,
+}
+
+/// A subdevice as last seen, see [`AnalysisSummary`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct DeviceSummary {
+    pub position: usize,
+    pub configured_address: Option<u16>,
+    pub alias: Option<u16>,
+    pub state: String,
+    pub al_status_code: Option<u16>,
+    pub identity: Option<SubDeviceIdentity>,
+    pub esc: Option<EscInfo>,
+    pub statistics: SubDeviceStatistics,
+}
+
+impl DeviceSummary {
+    pub fn new(position: usize, device: &SubDevice) -> Self {
+        DeviceSummary {
+            position,
+            configured_address: device.configured_address(),
+            alias: device.configured_alias(),
+            state: device.state().to_string(),
+            al_status_code: device.al_status_code(),
+            identity: device.identity(),
+            esc: device.esc_info(),
+            statistics: *device.statistics(),
+        }
+    }
+}
+
+/// Events of one category, see [`AnalysisSummary`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct EventDigest {
+    pub count: u64,
+    pub first_frame: u64,
+    pub last_frame: u64,
+    /// FNV-1a hash (hex) of the frame numbers and contents of the events in
+    /// order, which changes with any event.
+    pub digest: String,
+}
+
+/// Running [`EventDigest`]s of an analysis.
+#[derive(Debug, Default)]
+struct EventDigests(BTreeMap<&'static str, (EventDigest, u64)>);
+
+impl EventDigests {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn record(&mut self, category: &'static str, frame: u64, event: &dyn std::fmt::Debug) {
+        let (digest, hash) = self.0.entry(category).or_insert_with(|| {
+            let digest = EventDigest {
+                count: 0,
+                first_frame: frame,
+                last_frame: frame,
+                digest: String::new(),
+            };
+            (digest, Self::FNV_OFFSET)
+        });
+        digest.count += 1;
+        digest.last_frame = frame;
+        let line = format!("{}:{:?}\n", frame, event);
+        *hash = line.bytes().fold(*hash, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(Self::FNV_PRIME)
+        });
+    }
+
+    /// Record the events of an analyzed frame, by the categories of the event stream.
+    fn record_frame(&mut self, analysis: &FrameAnalysis) {
+        let frame = analysis.frame;
+        for event in &analysis.malformed {
+            self.record("malformed_frame", frame, event);
+        }
+        match &analysis.error {
+            Some(error @ ECError::InvalidDatagram { .. }) => {
+                self.record("invalid_datagram", frame, error)
+            }
+            Some(ECError::DeviceError(errors)) => {
+                for error in errors {
+                    self.record(device_error_category(error), frame, error);
+                }
+            }
+            None => {}
+        }
+        for event in &analysis.transitions {
+            self.record("state_transition", frame, event);
+        }
+        for event in &analysis.rescans {
+            self.record("rescan", frame, event);
+        }
+        for event in &analysis.bus_size_changes {
+            self.record("bus_size_change", frame, event);
+        }
+        for event in &analysis.error_acks {
+            self.record("error_acknowledgement", frame, event);
+        }
+        for event in &analysis.register_changes {
+            self.record("register_change", frame, event);
+        }
+        for event in &analysis.logical_issues {
+            self.record("logical_address", frame, event);
+        }
+        for event in &analysis.firmware_updates {
+            self.record("firmware_update", frame, event);
+        }
+        for event in &analysis.al_status_code_updates {
+            self.record("al_status_code", frame, event);
+        }
+        for event in &analysis.protocol_events {
+            self.record("protocol", frame, event);
+        }
+    }
+
+    fn digests(&self) -> BTreeMap<String, EventDigest> {
+        self.0
+            .iter()
+            .map(|(category, (digest, hash))| {
+                let digest = EventDigest {
+                    digest: format!("{:016x}", hash),
+                    ..digest.clone()
+                };
+                (category.to_string(), digest)
+            })
+            .collect()
+    }
+}
+
+/// Category of a device error in the error counts, the report and the event
+/// stream.
+pub fn device_error_category(error: &ECDeviceError) -> &'static str {
+    match error {
+        ECDeviceError::InvalidAutoIncrementAddress { .. } => "invalid_auto_increment_address",
+        ECDeviceError::InvalidConfiguredAddress { .. } => "invalid_configured_address",
+        ECDeviceError::InvalidWkc(_) => "wkc_mismatch",
+        ECDeviceError::ESMError(_) => "esm_error",
+    }
+}
+
+/// An analyzed EtherCAT frame and the events it caused.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct FrameAnalysis {
+    /// Number of the frame among the EtherCAT frames fed, starting at 1 or after
+    /// the last frame of a restored checkpoint.
+    pub frame: u64,
+    pub timestamp: Duration,
+    pub direction: Direction,
+    pub error: Option<ECError>,
+    pub malformed: Vec<MalformedFrame>,
+    pub transitions: Vec<StateTransition>,
+    pub rescans: Vec<Rescan>,
+    pub bus_size_changes: Vec<BusSizeChange>,
+    pub error_acks: Vec<ErrorAcknowledgement>,
+    pub register_changes: Vec<RegisterChange>,
+    pub logical_issues: Vec<LogicalAddressEvent>,
+    pub firmware_updates: Vec<FirmwareUpdate>,
+    pub al_status_code_updates: Vec<AlStatusCodeUpdate>,
+    pub protocol_events: Vec<ProtocolEvent>,
+    /// Process data signal changes, see [`DeviceManager::set_signal_export`].
+    pub signal_samples: Vec<SignalSample>,
+    /// WKC errors and ESM errors of this frame that have a common cause.
+    pub correlations: Vec<ErrorCorrelation>,
+}
+
+/// Result of an analysis: the subdevices as last seen, the state changes and the
+/// errors.
+#[non_exhaustive]
+pub struct AnalysisReport {
+    /// Number of EtherCAT frames analyzed.
+    pub analyzed_frames: u64,
+    pub transitions: Vec<StateTransition>,
+    pub errors: ErrorCounts,
+    /// Frame after which all subdevices reached Op.
+    pub init_complete_frame: Option<u64>,
+    device_manager: DeviceManager,
+    events: EventDigests,
+}
+
+impl AnalysisReport {
+    /// Subdevices in bus order.
+    pub fn devices(&self) -> &[SubDevice] {
+        self.device_manager.devices()
+    }
+
+    /// The device model, for the init sequence, earlier bus scans and the
+    /// register values.
+    pub fn device_manager(&self) -> &DeviceManager {
+        &self.device_manager
+    }
+
+    /// The subdevices, counters and event digests, see [`AnalysisSummary`].
+    pub fn summary(&self) -> AnalysisSummary {
+        AnalysisSummary {
+            events: self.events.digests(),
+            ..self.device_manager.summary()
+        }
+    }
+}
+
+/// Analyzes Ethernet frames one at a time, e.g. from a live capture.
+pub struct Analyzer {
+    device_manager: DeviceManager,
+    frames: u64,
+    transitions: Vec<StateTransition>,
+    errors: ErrorCounts,
+    events: EventDigests,
+}
+
+impl Analyzer {
+    pub fn new(options: AnalysisOptions) -> Self {
+        let mut device_manager = DeviceManager::new(options.analyzer);
+        if !options.register_watches.is_empty() {
+            device_manager.set_register_watches(options.register_watches);
+        }
+        Self::with_device_manager(device_manager)
+    }
+
+    /// Analyze with a device model set up by the caller, e.g. one restored from
+    /// a checkpoint.
+    pub fn with_device_manager(device_manager: DeviceManager) -> Self {
+        Analyzer {
+            device_manager,
+            frames: 0,
+            transitions: Vec::new(),
+            errors: ErrorCounts::default(),
+            events: EventDigests::default(),
+        }
+    }
+
+    /// Decode datagrams and mailbox messages with `handler`, see
+    /// [`ProtocolHandler`]. Its messages are in [`FrameAnalysis::protocol_events`].
+    pub fn add_protocol_handler(&mut self, handler: Box<dyn ProtocolHandler>) {
+        self.device_manager.add_protocol_handler(handler);
+    }
+
+    /// Analyze an Ethernet frame captured at `timestamp`. Returns `None` for frames
+    /// that are not EtherCAT.
+    pub fn feed(
+        &mut self,
+        frame: &[u8],
+        timestamp: Duration,
+        direction: Direction,
+    ) -> Option<FrameAnalysis> {
+        let ethernet = EthernetPacket::new(frame)?;
+        if ethernet.get_ethertype().0 != ETHERTYPE_ETHERCAT {
+            return None;
+        }
+        let frame_number = self.frames + 1;
+        let Some(ethercat_frame) = ECFrame::new(ethernet.payload()) else {
+            self.frames = frame_number;
+            self.device_manager.sync_frame_number(frame_number);
+            return None;
+        };
+        Some(self.feed_ethercat(&ethercat_frame, frame_number, timestamp, direction, None))
+    }
+
+    /// Analyze an EtherCAT frame (without the Ethernet header) numbered by the
+    /// caller, e.g. with the capture's frame numbers, which leave gaps for
+    /// dropped frames. `malformations` are the results of
+    /// [`ECFrame::malformations`] if the caller checked the framing already.
+    pub fn feed_ethercat(
+        &mut self,
+        frame: &ECFrame,
+        frame_number: u64,
+        timestamp: Duration,
+        direction: Direction,
+        malformations: Option<&[FrameMalformation]>,
+    ) -> FrameAnalysis {
+        self.frames = frame_number;
+        let device_manager = &mut self.device_manager;
+        device_manager.sync_frame_number(frame_number);
+        let from_main = direction == Direction::FromMain;
+        let result = match malformations {
+            Some(malformations) => {
+                device_manager.analyze_prechecked_packet(frame, malformations, timestamp, from_main)
+            }
+            None => device_manager.analyze_packet(frame, timestamp, from_main),
+        };
+        let analysis = FrameAnalysis {
+            frame: device_manager.frame_offset() + frame_number,
+            timestamp,
+            direction,
+            error: result.err(),
+            malformed: device_manager.take_malformed_frames(),
+            transitions: device_manager.take_state_transitions(),
+            rescans: device_manager.take_rescans(),
+            bus_size_changes: device_manager.take_bus_size_changes(),
+            error_acks: device_manager.take_error_acknowledgements(),
+            register_changes: device_manager.take_register_changes(),
+            logical_issues: device_manager.take_logical_address_issues(),
+            firmware_updates: device_manager.take_firmware_updates(),
+            al_status_code_updates: device_manager.check_al_status_code_updates(),
+            protocol_events: device_manager.take_protocol_events(),
+            signal_samples: device_manager.take_signal_samples(),
+            correlations: device_manager.take_pending_correlations(),
+        };
+        self.errors
+            .record(&analysis.malformed, analysis.error.as_ref());
+        self.transitions.extend_from_slice(&analysis.transitions);
+        self.events.record_frame(&analysis);
+        analysis
+    }
+
+    /// Count frame `frame_number` without analyzing it, e.g. outside of the
+    /// analysis window.
+    pub fn skip(&mut self, frame_number: u64) {
+        self.frames = frame_number;
+        self.device_manager.sync_frame_number(frame_number);
+        self.device_manager.skip_frame();
+    }
+
+    /// Count the frames after the last one fed or skipped and before
+    /// `frame_number` as skipped, for sources that leave out the frames before
+    /// the analysis window themselves.
+    pub fn skip_until(&mut self, frame_number: u64) {
+        while self.frames + 1 < frame_number {
+            self.skip(self.frames + 1);
+        }
+    }
+
+    /// Errors of the frames fed so far.
+    pub fn errors(&self) -> &ErrorCounts {
+        &self.errors
+    }
+
+    /// The device model as of the last frame fed.
+    pub fn device_manager(&self) -> &DeviceManager {
+        &self.device_manager
+    }
+
+    /// The device model, e.g. to free memory between frames.
+    pub fn device_manager_mut(&mut self) -> &mut DeviceManager {
+        &mut self.device_manager
+    }
+
+    pub fn finish(self) -> AnalysisReport {
+        AnalysisReport {
+            analyzed_frames: self.device_manager.get_analyzed_frame_count(),
+            transitions: self.transitions,
+            errors: self.errors,
+            init_complete_frame: self.device_manager.init_complete_packet(),
+            device_manager: self.device_manager,
+            events: self.events,
+        }
+    }
+}
+
+/// Analyze a pcap or pcapng file, optionally gzip or zstd compressed. Frames
+/// from the source address of the first EtherCAT frame are taken as sent by the
+/// main device.
+pub fn analyze_file(
+    path: impl AsRef<Path>,
+    options: AnalysisOptions,
+) -> Result<AnalysisReport, CaptureFileError> {
+    analyze_reader(BufReader::new(capture_file::open(path)?), options)
+}
+
+/// Analyze a pcap or pcapng stream, see [`analyze_file`]. Timestamps are relative
+/// to the first EtherCAT frame.
+pub fn analyze_reader(
+    reader: impl Read,
+    options: AnalysisOptions,
+) -> Result<AnalysisReport, CaptureFileError> {
+    let mut analyzer = Analyzer::new(options);
+    let mut main = None;
+    let mut first_timestamp = None;
+    capture_file::read_frames(reader, |timestamp, data| {
+        let Some(ethernet) = EthernetPacket::new(data) else {
+            return;
+        };
+        if ethernet.get_ethertype().0 != ETHERTYPE_ETHERCAT {
+            return;
+        }
+        let direction = if *main.get_or_insert(ethernet.get_source()) == ethernet.get_source() {
+            Direction::FromMain
+        } else {
+            Direction::ToMain
+        };
+        let first: Duration = *first_timestamp.get_or_insert(timestamp);
+        analyzer.feed(data, timestamp.saturating_sub(first), direction);
+    })?;
+    Ok(analyzer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ec_packet::{ECCommands, ECFrameBuilder};
+
+    /// An Ethernet frame with an EtherCAT header and one BRD of the ESC type
+    /// register that `wkc` subdevices answered.
+    fn brd_frame(wkc: u16) -> Vec<u8> {
+        ECFrameBuilder::new()
+            .datagram(ECCommands::BRD, 0, 0, &[0x11, 0x00], wkc)
+            .build_ethernet()
+    }
+
+    #[test]
+    fn test_feed_skips_other_ethertypes_and_counts_frames() {
+        let mut analyzer = Analyzer::new(AnalysisOptions::default());
+        let mut arp = brd_frame(0);
+        arp[12..14].copy_from_slice(&0x0806u16.to_be_bytes());
+        assert!(
+            analyzer
+                .feed(&arp, Duration::ZERO, Direction::FromMain)
+                .is_none()
+        );
+
+        let sent = analyzer
+            .feed(&brd_frame(0), Duration::ZERO, Direction::FromMain)
+            .unwrap();
+        assert_eq!(sent.frame, 1);
+        let returned = analyzer
+            .feed(&brd_frame(2), Duration::from_micros(50), Direction::ToMain)
+            .unwrap();
+        assert_eq!(returned.frame, 2);
+        assert!(returned.error.is_none());
+
+        let report = analyzer.finish();
+        assert_eq!(report.analyzed_frames, 2);
+        assert_eq!(report.devices().len(), 2);
+    }
+
+    #[test]
+    fn test_rescans_are_not_tracked_when_not_tolerated() {
+        let capture = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/rescan.pcap");
+        let tracked = analyze_file(&capture, AnalysisOptions::default()).unwrap();
+        assert!(tracked.summary().scans > 1);
+
+        let options = AnalysisOptions {
+            analyzer: AnalyzerConfig {
+                tolerate_rescan: false,
+                ..AnalyzerConfig::default()
+            },
+            ..AnalysisOptions::default()
+        };
+        let report = analyze_file(&capture, options).unwrap();
+        let summary = report.summary();
+        assert_eq!(summary.scans, 1);
+        assert!(!summary.events.contains_key("rescan"));
+        assert!(report.device_manager().previous_scans().is_empty());
+        // The single BRD answered by a third subdevice is a WKC error, not a
+        // change of the bus size
+        assert_eq!(summary.devices.len(), 2);
+    }
+
+    #[test]
+    fn test_compressed_captures_are_analyzed_like_plain_ones() {
+        use std::io::Write;
+
+        let capture = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/rescan.pcap");
+        let data = std::fs::read(&capture).unwrap();
+        let plain = analyze_file(&capture, AnalysisOptions::default())
+            .unwrap()
+            .summary();
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&data).unwrap();
+        let compressed = [
+            ("gz", gzip.finish().unwrap()),
+            ("zst", zstd::encode_all(&data[..], 0).unwrap()),
+        ];
+        for (extension, compressed) in compressed {
+            let path = std::env::temp_dir().join(format!(
+                "ecdump-compressed-{}.pcap.{}",
+                std::process::id(),
+                extension
+            ));
+            std::fs::write(&path, compressed).unwrap();
+            let summary = analyze_file(&path, AnalysisOptions::default()).map(|r| r.summary());
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(summary.unwrap(), plain, "{}", extension);
+        }
+    }
+
+    /// Analyze every capture in `testdata/` and compare its summary with the
+    /// `.summary.json` next to it. `UPDATE_GOLDEN=1 cargo test` rewrites them.
+    #[test]
+    fn test_reference_captures_match_golden_summaries() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let mut captures: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "pcap")
+            })
+            .collect();
+        captures.sort();
+        assert!(!captures.is_empty());
+
+        for capture in captures {
+            let summary = analyze_file(&capture, AnalysisOptions::default())
+                .unwrap()
+                .summary();
+            let again = analyze_file(&capture, AnalysisOptions::default())
+                .unwrap()
+                .summary();
+            assert_eq!(summary, again, "{} differs between runs", capture.display());
+
+            let json = serde_json::to_string_pretty(&summary).unwrap() + "\n";
+            let golden = capture.with_extension("summary.json");
+            if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                std::fs::write(&golden, json).unwrap();
+                continue;
+            }
+            let expected = std::fs::read_to_string(&golden).unwrap_or_default();
+            assert!(
+                expected == json,
+                "{} no longer matches {}; rerun with UPDATE_GOLDEN=1 if the change is intended\n{}",
+                capture.display(),
+                golden.display(),
+                json
+            );
+        }
+    }
+}
@@ -1,12 +1,29 @@
-use std::collections::HashMap;
-use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
-use log::{debug, error, trace, warn};
-
-use crate::ec_packet::ECFrame;
-use ecdump::ec_packet::{ECCommand, ECCommands, ECDatagram, ECPacketError};
-use ecdump::subdevice::{self, ECState, ESMError, SubDevice, SubdeviceIdentifier};
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use crate::analysis::{AnalysisSummary, DeviceSummary};
+use crate::checkpoint::{CHECKPOINT_VERSION, Checkpoint};
+use crate::ec_packet::{
+    ECCommand, ECCommands, ECDatagram, ECFrame, ECPacketError, FrameMalformation,
+};
+use crate::init_sequence::{InitAction, InitStep};
+use crate::logical_map::{LogicalConflict, LogicalMapping, find_conflicts, unmapped_ranges};
+use crate::mailbox::MailboxHeader;
+use crate::messages::{Lang, Msg, text};
+use crate::pdo::{PdoDirection, PdoSignal};
+use crate::protocol_handler::{DatagramContext, MailboxMessage, ProtocolEvent, ProtocolHandler};
+use crate::register_watch::{RegisterChange, RegisterWatch};
+use crate::registers::{RegisterAddress, collect_bytes};
+use crate::subdevice::{
+    self, ECState, ESMError, ErrorAckSequence, FirmwareUpdateSession, RegisterShadow, SubDevice,
+    SubDeviceStatistics, SubdeviceIdentifier,
+};
 
 #[derive(Debug, Copy, Clone)]
 pub struct WkcErrorDetail {
@@ -18,6 +35,8 @@ pub struct WkcErrorDetail {
     pub register: u16,
     pub length: u16,
     pub subdevice_id: Option<SubdeviceIdentifier>,
+    /// Physical position on the bus (0 = first subdevice) of the addressed subdevice.
+    pub position: Option<usize>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -44,6 +63,8 @@ pub enum ECDeviceError {
         timestamp: Duration,
         command: ECCommand,
         address: u16,
+        /// Physical bus position the auto-increment address translates to.
+        position: usize,
     },
     InvalidConfiguredAddress {
         packet_number: u64,
@@ -109,98 +130,95 @@ impl ECDeviceError {
 
     /// Returns a short diagnostic description for this specific error instance.
     pub fn diagnosis(&self) -> String {
+        self.diagnosis_in(Lang::En)
+    }
+
+    /// [`diagnosis`](Self::diagnosis) in `lang`.
+    pub fn diagnosis_in(&self, lang: Lang) -> String {
         match self {
-            ECDeviceError::InvalidAutoIncrementAddress { address, .. } => {
-                format!(
-                    "Auto-increment address {:#06x} does not map to any known device. \
-                     Possible cause: device disconnected or topology change.",
-                    address
-                )
-            }
-            ECDeviceError::InvalidConfiguredAddress { address, .. } => {
-                format!(
-                    "Configured address {:#06x} not found in device map. \
-                     Possible cause: device not yet configured or address conflict.",
-                    address
-                )
-            }
+            ECDeviceError::InvalidAutoIncrementAddress {
+                address, position, ..
+            } => text(
+                lang,
+                Msg::DiagnosisAutoIncrement,
+                &[&format_args!("{:#06x}", address), position],
+            ),
+            ECDeviceError::InvalidConfiguredAddress { address, .. } => text(
+                lang,
+                Msg::DiagnosisConfigured,
+                &[&format_args!("{:#06x}", address)],
+            ),
             ECDeviceError::InvalidWkc(d) => {
+                let command = d.command.as_str();
+                let register = format!("{:#06x}", d.register);
                 if d.actual == 0 {
-                    format!(
-                        "WKC=0 (expected {}): Complete communication failure — \
-                         no device responded to {} command (register address = {:#06x}, length = {}). \
-                         Check: cable connections, device power, network topology.",
-                        d.expected,
-                        d.command.as_str(),
-                        d.register,
-                        d.length,
+                    text(
+                        lang,
+                        Msg::DiagnosisWkcNoResponse,
+                        &[&d.expected, &command, &register, &d.length],
                     )
                 } else if d.actual < d.expected {
-                    let missing = d.expected - d.actual;
-                    format!(
-                        "WKC={} (expected {}): {} device(s) did not respond to {} command (register address = {:#06x}, length = {}). \
-                         Partial failure — check individual device status and wiring.",
-                        d.actual,
-                        d.expected,
-                        missing,
-                        d.command.as_str(),
-                        d.register,
-                        d.length,
+                    text(
+                        lang,
+                        Msg::DiagnosisWkcPartial,
+                        &[
+                            &d.actual,
+                            &d.expected,
+                            &(d.expected - d.actual),
+                            &command,
+                            &register,
+                            &d.length,
+                        ],
                     )
                 } else {
-                    format!(
-                        "WKC={} (expected {}): Unexpected extra responses to {} command (register address = {:#06x}, length = {}). \
-                         Possible address conflict or duplicate device configuration.",
-                        d.actual,
-                        d.expected,
-                        d.command.as_str(),
-                        d.register,
-                        d.length,
+                    text(
+                        lang,
+                        Msg::DiagnosisWkcOverCount,
+                        &[&d.actual, &d.expected, &command, &register, &d.length],
                     )
                 }
             }
             ECDeviceError::ESMError(d) => {
-                let base = match &d.error {
-                    ESMError::IllegalTransition { to } => {
-                        format!("Illegal state transition to {:?}.", to)
+                let error_flag = |has_error: bool, flag: Msg| {
+                    if has_error {
+                        text(lang, flag, &[])
+                    } else {
+                        String::new()
                     }
+                };
+                let base = match &d.error {
+                    ESMError::IllegalTransition { to } => text(lang, Msg::DiagnosisIllegal, &[to]),
                     ESMError::InvalidStateTransition { requested, current } => {
-                        format!(
-                            "Invalid state transition: requested {:?} but device is in {:?}.",
-                            requested, current
-                        )
+                        text(lang, Msg::DiagnosisInvalid, &[requested, current])
                     }
                     ESMError::BackwardTransition {
                         from,
                         to,
                         has_error,
-                    } => {
-                        let err_hint = if *has_error {
-                            " Device reported an error flag."
-                        } else {
-                            ""
-                        };
-                        format!(
-                            "Backward state transition {} -> {}.{} \
-                             The device may have encountered an internal fault.",
-                            from, to, err_hint
-                        )
-                    }
+                    } => text(
+                        lang,
+                        Msg::DiagnosisBackward,
+                        &[
+                            from,
+                            to,
+                            &error_flag(*has_error, Msg::DiagnosisBackwardErrorFlag),
+                        ],
+                    ),
                     ESMError::TransitionFailed {
                         requested,
                         current,
                         has_error,
-                    } => {
-                        let err_hint = if *has_error {
-                            " Error flag is set."
-                        } else {
-                            ""
-                        };
-                        format!(
-                            "State transition to {:?} failed; device stuck in {:?}.{} \
-                             Check AL Status Code for details.",
-                            requested, current, err_hint
-                        )
+                    } => text(
+                        lang,
+                        Msg::DiagnosisFailed,
+                        &[
+                            requested,
+                            current,
+                            &error_flag(*has_error, Msg::DiagnosisFailedErrorFlag),
+                        ],
+                    ),
+                    ESMError::InvalidBootstrapTransition { from, to } => {
+                        text(lang, Msg::DiagnosisBootstrap, &[from, to])
                     }
                 };
                 format!("[{}] {}", d.subdevice_id, base)
@@ -219,6 +237,193 @@ pub struct StateTransition {
     pub to: ECState,
 }
 
+/// An AL Status error indication that was cleared, optionally after an acknowledge
+/// from the main device (error set -> main device acks -> subdevice clears).
+#[derive(Debug, Clone)]
+pub struct ErrorAcknowledgement {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub subdevice_id: SubdeviceIdentifier,
+    pub sequence: ErrorAckSequence,
+}
+
+/// A firmware update session completed on a subdevice.
+#[derive(Debug, Clone)]
+pub struct FirmwareUpdate {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub subdevice_id: SubdeviceIdentifier,
+    pub session: FirmwareUpdateSession,
+}
+
+/// A new value of a process data signal selected for export.
+#[derive(Debug, Clone)]
+pub struct SignalSample {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub subdevice_id: SubdeviceIdentifier,
+    pub name: String,
+    pub key: String,
+    pub direction: PdoDirection,
+    pub value: i64,
+}
+
+/// Process data signals of all subdevices, rebuilt when a PDO mapping,
+/// FMMU or sync manager configuration changes.
+#[derive(Debug, Default)]
+struct SignalMap {
+    /// `DeviceManager::process_data_generation` the map was built for.
+    generation: Option<u64>,
+    signals: Vec<(usize, PdoSignal)>,
+    last_values: Vec<Option<i64>>,
+}
+
+/// Active FMMUs of all subdevices, rebuilt when an FMMU configuration changes.
+#[derive(Debug, Default)]
+struct LogicalMap {
+    /// `DeviceManager::process_data_generation` the map was built for.
+    generation: Option<u64>,
+    mappings: Vec<LogicalMapping>,
+    /// Unmapped logical ranges already reported for the current mappings.
+    reported_unmapped: HashSet<(u32, u32)>,
+    /// Conflicts already reported in this scan, kept when the map is rebuilt.
+    reported_conflicts: HashSet<LogicalConflict>,
+}
+
+/// A logical process image configuration bug.
+#[derive(Debug, Clone)]
+pub enum LogicalAddressIssue {
+    /// Two subdevices map overlapping logical memory with incompatible directions.
+    Conflict {
+        first: SubdeviceIdentifier,
+        second: SubdeviceIdentifier,
+        conflict: LogicalConflict,
+    },
+    /// A logical datagram covers memory that no subdevice maps.
+    Unmapped {
+        command: ECCommand,
+        range: std::ops::Range<u32>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct LogicalAddressEvent {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub issue: LogicalAddressIssue,
+}
+
+/// Optional behavior of a [`DeviceManager`]. The defaults analyze everything;
+/// turning subsystems off trades their events for throughput on busy live
+/// captures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnalyzerConfig {
+    /// Report every WKC that differs from the expected one. Off, only WKCs below
+    /// it are errors, and datagrams more subdevices answered are analyzed as
+    /// successful.
+    pub strict_wkc: bool,
+    /// Start a new device model when the main device scans the bus again,
+    /// keeping the old one in [`DeviceManager::previous_scans`]. Off, the model
+    /// of the first scan is updated in place and no rescans are reported.
+    pub tolerate_rescan: bool,
+    /// Check the logical addressing of LRD/LWR/LRW datagrams against the FMMUs.
+    pub logical_addressing: bool,
+    /// Decode mailbox messages: CoE PDO assignments, FoE firmware transfers, SDO
+    /// downloads of the init sequence and the messages passed to protocol
+    /// handlers.
+    pub mailbox_decoding: bool,
+    /// WKC errors kept to correlate with later ESM errors of the same subdevice;
+    /// 0 disables the correlation.
+    pub journal_depth: usize,
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> Self {
+        AnalyzerConfig {
+            strict_wkc: true,
+            tolerate_rescan: true,
+            logical_addressing: true,
+            mailbox_decoding: true,
+            journal_depth: 200,
+        }
+    }
+}
+
+impl AnalyzerConfig {
+    fn subdevice(&self) -> SubDevice {
+        let mut device = SubDevice::new();
+        device.set_mailbox_decoding(self.mailbox_decoding);
+        device
+    }
+}
+
+/// Frames between two checks of the memory budget. Register pages age by one
+/// period per check.
+pub const MEMORY_CHECK_INTERVAL: u64 = 1024;
+
+/// Consecutive BRDs that must answer with the same deviating WKC before the
+/// device model follows the new number of subdevices. A single deviating BRD is
+/// a WKC error.
+const BUS_RESIZE_CONFIRMATIONS: u32 = 3;
+
+/// Data the analyzer dropped to stay within its memory budget, see
+/// [`DeviceManager::set_memory_budget`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Evictions {
+    /// 256-byte pages of register shadows.
+    pub register_pages: u64,
+    /// Known register values held by the evicted pages.
+    pub register_values: u64,
+    /// Device models of earlier bus scans.
+    pub scans: u64,
+    /// Init sequence steps not recorded while over the budget.
+    pub init_steps: u64,
+}
+
+impl Evictions {
+    pub fn is_empty(&self) -> bool {
+        *self == Evictions::default()
+    }
+}
+
+/// Subdevices discovered by one bus scan, kept when the main device scans the bus again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceScan {
+    pub number: u32,
+    pub start_packet: u64,
+    pub end_packet: u64,
+    pub devices: Vec<SubDevice>,
+}
+
+/// The main device restarted bus scanning after subdevices were configured.
+#[derive(Debug, Clone)]
+pub struct Rescan {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    /// Number of the scan that starts with this packet.
+    pub scan_number: u32,
+    pub previous_device_count: usize,
+    pub device_count: usize,
+}
+
+/// The number of subdevices answering a BRD changed without a bus rescan.
+#[derive(Debug, Clone)]
+pub struct BusSizeChange {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub previous_device_count: usize,
+    pub device_count: usize,
+}
+
+/// A frame that was parsed but violates Ethernet/EtherCAT framing rules.
+#[derive(Debug, Clone)]
+pub struct MalformedFrame {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub from_main: bool,
+    pub malformation: FrameMalformation,
+}
+
 #[derive(Debug)]
 pub enum ECError {
     InvalidDatagram {
@@ -238,8 +443,13 @@ pub struct ErrorCorrelation {
 }
 
 pub struct DeviceManager {
+    config: AnalyzerConfig,
     uninitialized: bool,
     num_frames: u64,
+    /// Frames of the captures before this one, see [`DeviceManager::restore`].
+    frame_offset: u64,
+    analyzed_frames: u64,
+    skipped_frames: u64,
     expected_wkc: u16,
     devices: Vec<SubDevice>,
     config_address_map: HashMap<u16, usize>,
@@ -248,23 +458,111 @@ pub struct DeviceManager {
     pending_transitions: Vec<StateTransition>,
     /// Correlations detected during the most recent analyze_packet call.
     pending_correlations: Vec<ErrorCorrelation>,
+    /// Error acknowledge sequences completed during the most recent analyze_packet call.
+    pending_error_acks: Vec<ErrorAcknowledgement>,
+    /// Firmware update sessions completed during the most recent analyze_packet call.
+    pending_firmware_updates: Vec<FirmwareUpdate>,
+    /// Registers selected with `--watch-reg`.
+    register_watches: Vec<RegisterWatch>,
+    /// Last observed value per (device index, watch index, shadow).
+    register_watch_values: HashMap<(usize, usize, RegisterShadow), SmallVec<[u8; 8]>>,
+    /// Watched register changes detected during the most recent analyze_packet call.
+    pending_register_changes: Vec<RegisterChange>,
+    /// Signal selectors for process data export; `None` disables extraction.
+    signal_selectors: Option<Vec<String>>,
+    signal_map: SignalMap,
+    /// Signal value changes detected during the most recent analyze_packet call.
+    pending_signal_samples: Vec<SignalSample>,
+    /// Number of the current bus scan, starting at 1.
+    scan_number: u32,
+    scan_start_packet: u64,
+    /// Device models of earlier scans, oldest first.
+    previous_scans: Vec<DeviceScan>,
+    /// Rescans detected during the most recent analyze_packet call.
+    pending_rescans: Vec<Rescan>,
+    logical_map: LogicalMap,
+    /// Changes whenever the process data layout of any subdevice may have
+    /// changed, or subdevices were added or removed.
+    process_data_generation: u64,
+    /// Configuration writes per subdevice until all subdevices reached Op.
+    init_steps: Vec<Vec<InitStep>>,
+    /// Packet in which the last subdevice reached Op.
+    init_complete_packet: Option<u64>,
+    /// Logical addressing issues detected during the most recent analyze_packet call.
+    pending_logical_issues: Vec<LogicalAddressEvent>,
+    /// BRD device count changes detected during the most recent analyze_packet call.
+    pending_bus_size_changes: Vec<BusSizeChange>,
+    /// Deviating BRD WKC and the number of consecutive BRDs that answered with it.
+    bus_size_candidate: Option<(u16, u32)>,
+    /// Models of the subdevices lost at the end of the bus, in bus order from the
+    /// first lost position, reattached when the bus grows again.
+    detached_devices: Vec<SubDevice>,
+    /// `config_address_map` entries of `detached_devices`.
+    detached_config_addresses: HashMap<u16, usize>,
+    /// Framing deviations detected during the most recent analyze_packet call.
+    pending_malformed_frames: Vec<MalformedFrame>,
+    protocol_handlers: Vec<Box<dyn ProtocolHandler>>,
+    /// Messages of the protocol handlers during the most recent analyze_packet call.
+    pending_protocol_events: Vec<ProtocolEvent>,
     /// Tracks devices with pending ESM errors whose AL Status Code was unknown.
     /// Maps device index to the last known al_status_code (None if not yet known).
     pending_esm_al_status: Vec<(usize, Option<u16>)>,
+    /// Bytes the device model may use, see `set_memory_budget`.
+    memory_budget: Option<usize>,
+    /// Set when evicting could not bring the device model within the budget.
+    over_memory_budget: bool,
+    evictions: Evictions,
+}
+
+impl Default for DeviceManager {
+    fn default() -> Self {
+        Self::new(AnalyzerConfig::default())
+    }
 }
 
 impl DeviceManager {
-    pub fn new() -> Self {
+    pub fn new(config: AnalyzerConfig) -> Self {
         DeviceManager {
+            config,
             uninitialized: true,
             num_frames: 0,
+            frame_offset: 0,
+            analyzed_frames: 0,
+            skipped_frames: 0,
             expected_wkc: 0,
             devices: Vec::new(),
             config_address_map: HashMap::new(),
             wkc_error_history: VecDeque::new(),
             pending_transitions: Vec::new(),
             pending_correlations: Vec::new(),
+            pending_error_acks: Vec::new(),
+            pending_firmware_updates: Vec::new(),
+            register_watches: Vec::new(),
+            register_watch_values: HashMap::new(),
+            pending_register_changes: Vec::new(),
+            signal_selectors: None,
+            scan_number: 1,
+            scan_start_packet: 0,
+            previous_scans: Vec::new(),
+            pending_rescans: Vec::new(),
+            pending_malformed_frames: Vec::new(),
+            protocol_handlers: Vec::new(),
+            pending_protocol_events: Vec::new(),
+            pending_bus_size_changes: Vec::new(),
+            bus_size_candidate: None,
+            detached_devices: Vec::new(),
+            detached_config_addresses: HashMap::new(),
+            logical_map: LogicalMap::default(),
+            process_data_generation: 0,
+            init_steps: Vec::new(),
+            init_complete_packet: None,
+            pending_logical_issues: Vec::new(),
+            signal_map: SignalMap::default(),
+            pending_signal_samples: Vec::new(),
             pending_esm_al_status: Vec::new(),
+            memory_budget: None,
+            over_memory_budget: false,
+            evictions: Evictions::default(),
         }
     }
 
@@ -273,8 +571,37 @@ impl DeviceManager {
         packet: &ECFrame,
         timestamp: Duration,
         from_main: bool,
+    ) -> Result<(), ECError> {
+        self.analyze_packet_with_checks(packet, None, timestamp, from_main)
+    }
+
+    /// Analyze a packet whose framing was already checked by a parse stage
+    /// (see [`ECFrame::malformations`]), so the checks are not repeated here.
+    pub fn analyze_prechecked_packet(
+        &mut self,
+        packet: &ECFrame,
+        malformations: &[FrameMalformation],
+        timestamp: Duration,
+        from_main: bool,
+    ) -> Result<(), ECError> {
+        self.analyze_packet_with_checks(packet, Some(malformations), timestamp, from_main)
+    }
+
+    fn analyze_packet_with_checks(
+        &mut self,
+        packet: &ECFrame,
+        malformations: Option<&[FrameMalformation]>,
+        timestamp: Duration,
+        from_main: bool,
     ) -> Result<(), ECError> {
         self.num_frames += 1;
+        self.analyzed_frames += 1;
+        if self.memory_budget.is_some()
+            && self.analyzed_frames.is_multiple_of(MEMORY_CHECK_INTERVAL)
+        {
+            self.age_shadows();
+            self.enforce_memory_budget();
+        }
 
         if packet.protocol_type() != 0x01 {
             return Err(ECError::InvalidDatagram {
@@ -291,7 +618,28 @@ impl DeviceManager {
                 error: e,
             })?;
 
-        for d in datagrams.iter() {
+        let checked;
+        let malformations = match malformations {
+            Some(malformations) => malformations,
+            None => {
+                checked = packet.malformations(&datagrams);
+                &checked[..]
+            }
+        };
+        for &malformation in malformations {
+            debug!(
+                "Malformed EtherCAT frame #{}: {}",
+                self.num_frames, malformation
+            );
+            self.pending_malformed_frames.push(MalformedFrame {
+                packet_number: self.num_frames,
+                timestamp,
+                from_main,
+                malformation,
+            });
+        }
+
+        for d in &datagrams {
             trace!(
                 "Parsed EtherCAT Datagram #{} -> command: {}, length: {}",
                 self.num_frames,
@@ -300,6 +648,7 @@ impl DeviceManager {
             );
         }
 
+        let scan_before = self.scan_number;
         // Snapshot device states before processing datagrams
         let states_before: Vec<(SubdeviceIdentifier, ECState)> = self
             .devices
@@ -308,7 +657,7 @@ impl DeviceManager {
             .collect();
 
         let mut errors = Vec::<ECDeviceError>::new();
-        for datagram in datagrams.iter() {
+        for datagram in &datagrams {
             let result = match datagram.command() {
                 ECCommands::BRD => BrdCommand {
                     timestamp,
@@ -343,13 +692,27 @@ impl DeviceManager {
                 _ => Ok(()),
             };
 
+            if !self.register_watches.is_empty() {
+                self.check_register_watches(datagram, timestamp);
+            }
+            if self.signal_selectors.is_some() && !from_main {
+                self.extract_signals(datagram, timestamp);
+            }
+            if !from_main && self.config.logical_addressing {
+                self.check_logical_addressing(datagram, timestamp);
+            }
+            if !self.protocol_handlers.is_empty() {
+                self.run_protocol_handlers(datagram, timestamp, from_main);
+            }
+
             match result {
                 Err(ECDeviceError::InvalidAutoIncrementAddress {
                     packet_number,
                     address,
+                    position,
                     ..
                 }) => {
-                    warn!(
+                    debug!(
                         "Invalid auto-increment address {:#06x} in frame #{}",
                         address, packet_number
                     );
@@ -358,6 +721,7 @@ impl DeviceManager {
                         timestamp,
                         command: datagram.command(),
                         address,
+                        position,
                     };
                     errors.push(err);
                 }
@@ -366,7 +730,7 @@ impl DeviceManager {
                     address,
                     ..
                 }) => {
-                    warn!(
+                    debug!(
                         "Invalid configured address {:#06x} in frame #{}",
                         address, packet_number
                     );
@@ -379,14 +743,11 @@ impl DeviceManager {
                     errors.push(err);
                 }
                 Err(ECDeviceError::InvalidWkc(wkc_err)) => {
-                    warn!(
+                    debug!(
                         "#{} WKC error: {} [{}], adp {:04x}, ado {:#06x}, expected {}, got {}",
                         wkc_err.packet_number,
                         wkc_err.command.as_str(),
-                        wkc_err
-                            .subdevice_id
-                            .unwrap_or(SubdeviceIdentifier::Unknown)
-                            .to_string(),
+                        wkc_err.subdevice_id.unwrap_or(SubdeviceIdentifier::Unknown),
                         datagram.address().0,
                         datagram.address().1,
                         wkc_err.expected,
@@ -394,15 +755,17 @@ impl DeviceManager {
                     );
 
                     let err = ECDeviceError::InvalidWkc(wkc_err);
-                    self.wkc_error_history.push_back(wkc_err);
-                    // Keep WKC history bounded
-                    if self.wkc_error_history.len() > 200 {
-                        self.wkc_error_history.pop_front();
+                    if self.config.journal_depth > 0 {
+                        // Keep WKC history bounded
+                        if self.wkc_error_history.len() >= self.config.journal_depth {
+                            self.wkc_error_history.pop_front();
+                        }
+                        self.wkc_error_history.push_back(wkc_err);
                     }
                     errors.push(err);
                 }
                 Err(ECDeviceError::ESMError(esm_error)) => {
-                    error!(
+                    debug!(
                         "#{} ESM Error [{}]: {:?}",
                         esm_error.packet_number, esm_error.subdevice_id, esm_error.error
                     );
@@ -416,6 +779,7 @@ impl DeviceManager {
                             .iter()
                             .position(|d| d.identifier() == esm_error.subdevice_id);
                         if let Some(idx) = device_idx {
+                            self.devices[idx].statistics_mut().esm_errors += 1;
                             // Remove any previous tracking for the same device
                             self.pending_esm_al_status.retain(|(i, _)| *i != idx);
                             self.pending_esm_al_status
@@ -431,11 +795,18 @@ impl DeviceManager {
             }
         }
 
-        // Detect state transitions by comparing before/after snapshots
+        // Detect state transitions by comparing before/after snapshots.
+        // A rescan replaces the device model, so the snapshot no longer applies.
+        let states_before = if self.scan_number == scan_before {
+            states_before
+        } else {
+            Vec::new()
+        };
         for (i, (id, old_state)) in states_before.iter().enumerate() {
             if i < self.devices.len() {
                 let new_state = self.devices[i].state();
                 if new_state != *old_state {
+                    self.devices[i].statistics_mut().state_transitions += 1;
                     self.pending_transitions.push(StateTransition {
                         packet_number: self.num_frames,
                         timestamp,
@@ -447,6 +818,36 @@ impl DeviceManager {
             }
         }
 
+        for device in self.devices.iter_mut() {
+            for sequence in device.take_error_ack_sequences() {
+                self.pending_error_acks.push(ErrorAcknowledgement {
+                    packet_number: self.num_frames,
+                    timestamp,
+                    subdevice_id: device.identifier(),
+                    sequence,
+                });
+            }
+            for session in device.take_firmware_updates() {
+                self.pending_firmware_updates.push(FirmwareUpdate {
+                    packet_number: self.num_frames,
+                    timestamp,
+                    subdevice_id: device.identifier(),
+                    session,
+                });
+            }
+        }
+
+        if self.init_complete_packet.is_none()
+            && !self.devices.is_empty()
+            && self.devices.iter().all(|d| d.state() == ECState::Op)
+        {
+            debug!(
+                "#{} All subdevices reached Op, startup sequence complete",
+                self.num_frames
+            );
+            self.init_complete_packet = Some(self.num_frames);
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -454,28 +855,555 @@ impl DeviceManager {
         }
     }
 
+    /// Record a configuration write to subdevice `index` while the bus starts up.
+    fn record_init_write(&mut self, index: usize, datagram: &ECDatagram, timestamp: Duration) {
+        if self.init_complete_packet.is_some() || index >= self.devices.len() {
+            return;
+        }
+        if self.over_memory_budget {
+            self.evictions.init_steps += 1;
+            return;
+        }
+        if self.init_steps.len() < self.devices.len() {
+            self.init_steps.resize_with(self.devices.len(), Vec::new);
+        }
+        let address = datagram.address().1;
+        let is_mailbox =
+            self.config.mailbox_decoding && self.devices[index].is_mailbox_access(address);
+        self.init_steps[index].push(InitStep {
+            packet_number: self.num_frames,
+            timestamp,
+            action: InitAction::from_write(
+                datagram.command(),
+                address,
+                datagram.payload(),
+                is_mailbox,
+            ),
+        });
+    }
+
+    /// Configuration writes (register writes and SDO downloads) of the main device
+    /// per subdevice in bus order, from the first frame until all subdevices reached Op.
+    pub fn init_sequence(&self) -> &[Vec<InitStep>] {
+        &self.init_steps
+    }
+
+    /// Packet in which the last subdevice reached Op, if that happened.
+    pub fn init_complete_packet(&self) -> Option<u64> {
+        self.init_complete_packet
+    }
+
+    /// Keep the device model within `budget` bytes: evict the register pages
+    /// written longest ago, then the device models of the oldest bus scans, and
+    /// stop recording the init sequence. Checked every
+    /// [`MEMORY_CHECK_INTERVAL`] frames; `None` (the default) sets no limit.
+    ///
+    /// The ESC information, AL, FMMU and sync manager registers and the SII
+    /// identity are never evicted.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.memory_budget = budget;
+        self.enforce_memory_budget();
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// Approximate heap usage of the register shadows, earlier bus scans and init
+    /// sequence in bytes.
+    pub fn memory_usage(&self) -> usize {
+        let devices =
+            |devices: &[SubDevice]| devices.iter().map(SubDevice::memory_usage).sum::<usize>();
+        devices(&self.devices)
+            + self
+                .previous_scans
+                .iter()
+                .map(|scan| devices(&scan.devices))
+                .sum::<usize>()
+            + self
+                .init_steps
+                .iter()
+                .map(|steps| steps.capacity() * std::mem::size_of::<InitStep>())
+                .sum::<usize>()
+    }
+
+    /// Data dropped so far to stay within the memory budget.
+    pub fn evictions(&self) -> Evictions {
+        self.evictions
+    }
+
+    fn age_shadows(&mut self) {
+        let scans = self
+            .previous_scans
+            .iter_mut()
+            .flat_map(|scan| scan.devices.iter_mut());
+        for device in self.devices.iter_mut().chain(scans) {
+            device.age_shadows();
+        }
+    }
+
+    fn enforce_memory_budget(&mut self) {
+        let Some(budget) = self.memory_budget else {
+            self.over_memory_budget = false;
+            return;
+        };
+        let mut usage = self.memory_usage();
+        if usage > budget {
+            // Longest idle first; on ties, earlier scans before the current one
+            let mut candidates = Vec::new();
+            for (scan_idx, scan) in self.previous_scans.iter().enumerate() {
+                for (device_idx, device) in scan.devices.iter().enumerate() {
+                    for (range, idle) in device.evictable_ranges() {
+                        candidates.push((idle, Some(scan_idx), device_idx, range));
+                    }
+                }
+            }
+            for (device_idx, device) in self.devices.iter().enumerate() {
+                for (range, idle) in device.evictable_ranges() {
+                    candidates.push((idle, None, device_idx, range));
+                }
+            }
+            candidates.sort_by_key(|&(idle, ..)| Reverse(idle));
+
+            for (_, scan_idx, device_idx, range) in candidates {
+                if usage <= budget {
+                    break;
+                }
+                let device = match scan_idx {
+                    Some(scan_idx) => &mut self.previous_scans[scan_idx].devices[device_idx],
+                    None => &mut self.devices[device_idx],
+                };
+                let before = device.memory_usage();
+                let (pages, values) = device.evict(range);
+                usage -= before - device.memory_usage();
+                self.evictions.register_pages += pages;
+                self.evictions.register_values += values;
+            }
+        }
+        while usage > budget && !self.previous_scans.is_empty() {
+            let scan = self.previous_scans.remove(0);
+            usage -= scan
+                .devices
+                .iter()
+                .map(SubDevice::memory_usage)
+                .sum::<usize>();
+            self.evictions.scans += 1;
+        }
+        if usage > budget && !self.over_memory_budget {
+            debug!(
+                "#{} Device model uses {} bytes after evicting, over the budget of {}",
+                self.num_frames, usage, budget
+            );
+        }
+        self.over_memory_budget = usage > budget;
+    }
+
+    /// Report changes of the given registers through `take_register_changes`.
+    pub fn set_register_watches(&mut self, watches: Vec<RegisterWatch>) {
+        self.register_watches = watches;
+        self.register_watch_values.clear();
+    }
+
+    /// Compare watched registers touched by `datagram` with their last observed value.
+    fn check_register_watches(&mut self, datagram: &ECDatagram, timestamp: Duration) {
+        let shadow = match datagram.command() {
+            ECCommands::APRD | ECCommands::FPRD => RegisterShadow::Read,
+            ECCommands::BRD => RegisterShadow::BroadcastRead,
+            ECCommands::APWR | ECCommands::FPWR | ECCommands::BWR => RegisterShadow::Written,
+            _ => return,
+        };
+        let (_, ado) = datagram.address();
+
+        for (watch_idx, watch) in self.register_watches.iter().enumerate() {
+            if !watch.overlaps(ado, datagram.length()) {
+                continue;
+            }
+            for (device_idx, device) in self.devices.iter().enumerate() {
+                if !watch.matches_device(device.configured_address()) {
+                    continue;
+                }
+                let image = device.register_image(shadow);
+                let Some(bytes) = collect_bytes(image.read(watch.address, watch.length)) else {
+                    continue;
+                };
+                let key = (device_idx, watch_idx, shadow);
+                let old = self.register_watch_values.get(&key);
+                if old == Some(&bytes) {
+                    continue;
+                }
+                let Some(new) = watch.decode(&bytes) else {
+                    continue;
+                };
+                self.pending_register_changes.push(RegisterChange {
+                    packet_number: self.num_frames,
+                    timestamp,
+                    subdevice_id: device.identifier(),
+                    watch: *watch,
+                    shadow,
+                    old: old.and_then(|old| watch.decode(old)),
+                    new,
+                });
+                self.register_watch_values.insert(key, bytes);
+            }
+        }
+    }
+
+    /// Decode datagrams and mailbox messages with `handler` besides the built-in
+    /// analysis; its messages are returned by `take_protocol_events`.
+    pub fn add_protocol_handler(&mut self, handler: Box<dyn ProtocolHandler>) {
+        self.protocol_handlers.push(handler);
+    }
+
+    /// Index of the subdevice addressed by a physical datagram.
+    fn addressed_device(&self, datagram: &ECDatagram, from_main: bool) -> Option<usize> {
+        let (adp, _) = datagram.address();
+        let index = match datagram.command() {
+            ECCommands::APRD | ECCommands::APWR | ECCommands::APRW | ECCommands::ARMW => {
+                auto_increment_position(self, adp, from_main)
+            }
+            ECCommands::FPRD | ECCommands::FPWR | ECCommands::FPRW | ECCommands::FRMW => {
+                *self.config_address_map.get(&adp)?
+            }
+            _ => return None,
+        };
+        (index < self.devices.len()).then_some(index)
+    }
+
+    fn run_protocol_handlers(
+        &mut self,
+        datagram: &ECDatagram,
+        timestamp: Duration,
+        from_main: bool,
+    ) {
+        let index = self.addressed_device(datagram, from_main);
+        let device = index.map(|index| &self.devices[index]);
+        let context = DatagramContext {
+            packet_number: self.num_frames,
+            timestamp,
+            from_main,
+            datagram,
+            device,
+        };
+        // Mailbox messages are complete once a single subdevice processed the
+        // datagram, also for writes
+        let to_subdevice = match datagram.command() {
+            ECCommands::APWR | ECCommands::FPWR => Some(true),
+            ECCommands::APRD | ECCommands::FPRD => Some(false),
+            _ => None,
+        };
+        let payload = datagram.payload();
+        let message = match (device, to_subdevice) {
+            (Some(device), Some(to_subdevice))
+                if self.config.mailbox_decoding
+                    && !from_main
+                    && datagram.wkc() == 1
+                    && device.is_mailbox_access(datagram.address().1) =>
+            {
+                MailboxHeader::from_bytes(payload).map(|header| MailboxMessage {
+                    packet_number: self.num_frames,
+                    timestamp,
+                    device,
+                    to_subdevice,
+                    header,
+                    data: &payload[MailboxHeader::SIZE
+                        ..(MailboxHeader::SIZE + header.length as usize).min(payload.len())],
+                })
+            }
+            _ => None,
+        };
+
+        for handler in self.protocol_handlers.iter_mut() {
+            let mut messages = Vec::new();
+            handler.datagram(&context, &mut messages);
+            if let Some(message) = &message {
+                handler.mailbox(message, &mut messages);
+            }
+            for message in messages {
+                self.pending_protocol_events.push(ProtocolEvent {
+                    packet_number: self.num_frames,
+                    timestamp,
+                    subdevice_id: device.map(SubDevice::identifier),
+                    handler: handler.name().to_string(),
+                    message,
+                });
+            }
+        }
+    }
+
+    /// Extract process data signals matching `selectors` (object key such as `0x6064:00`
+    /// or signal name) from logical datagrams. An empty list selects every signal.
+    pub fn set_signal_export(&mut self, selectors: Vec<String>) {
+        self.signal_selectors = Some(selectors);
+        self.signal_map = SignalMap::default();
+    }
+
+    fn refresh_signal_map(&mut self) {
+        if self.signal_map.generation == Some(self.process_data_generation) {
+            return;
+        }
+
+        let selectors = self.signal_selectors.as_deref().unwrap_or_default();
+        let signals: Vec<(usize, PdoSignal)> = self
+            .devices
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, device)| device.pdo_signals().into_iter().map(move |s| (idx, s)))
+            .filter(|(_, signal)| {
+                selectors.is_empty() || selectors.iter().any(|s| signal.matches(s))
+            })
+            .collect();
+        debug!("Process data signal map rebuilt: {} signals", signals.len());
+
+        self.signal_map = SignalMap {
+            generation: Some(self.process_data_generation),
+            last_values: vec![None; signals.len()],
+            signals,
+        };
+    }
+
+    /// Check a logical datagram against the FMMU configuration of all subdevices:
+    /// report new conflicting mappings whenever the configuration changes and
+    /// memory accessed by the datagram that nobody maps.
+    fn check_logical_addressing(&mut self, datagram: &ECDatagram, timestamp: Duration) {
+        if !matches!(
+            datagram.command(),
+            ECCommands::LRD | ECCommands::LWR | ECCommands::LRW
+        ) {
+            return;
+        }
+
+        if self.logical_map.generation != Some(self.process_data_generation) {
+            let mappings: Vec<LogicalMapping> = self
+                .devices
+                .iter()
+                .enumerate()
+                .flat_map(|(idx, device)| {
+                    device
+                        .fmmus()
+                        .filter_map(move |(n, fmmu)| LogicalMapping::new(idx, n, &fmmu))
+                })
+                .collect();
+            let mut reported_conflicts = std::mem::take(&mut self.logical_map.reported_conflicts);
+            for conflict in find_conflicts(&mappings) {
+                if !reported_conflicts.insert(conflict.clone()) {
+                    continue;
+                }
+                self.pending_logical_issues.push(LogicalAddressEvent {
+                    packet_number: self.num_frames,
+                    timestamp,
+                    issue: LogicalAddressIssue::Conflict {
+                        first: self.devices[conflict.first.subdevice].identifier(),
+                        second: self.devices[conflict.second.subdevice].identifier(),
+                        conflict,
+                    },
+                });
+            }
+            self.logical_map = LogicalMap {
+                generation: Some(self.process_data_generation),
+                mappings,
+                reported_unmapped: HashSet::new(),
+                reported_conflicts,
+            };
+        }
+
+        // Without any known FMMU (e.g. capture started after configuration)
+        // everything would be reported as unmapped.
+        if self.logical_map.mappings.is_empty() {
+            return;
+        }
+        let start = datagram.logical_address();
+        let range = start..start.saturating_add(datagram.length() as u32);
+        for gap in unmapped_ranges(&self.logical_map.mappings, range) {
+            if self
+                .logical_map
+                .reported_unmapped
+                .insert((gap.start, gap.end))
+            {
+                self.pending_logical_issues.push(LogicalAddressEvent {
+                    packet_number: self.num_frames,
+                    timestamp,
+                    issue: LogicalAddressIssue::Unmapped {
+                        command: datagram.command(),
+                        range: gap,
+                    },
+                });
+            }
+        }
+    }
+
+    /// Record signals whose value in a logical datagram differs from the last observation.
+    fn extract_signals(&mut self, datagram: &ECDatagram, timestamp: Duration) {
+        if !matches!(
+            datagram.command(),
+            ECCommands::LRD | ECCommands::LWR | ECCommands::LRW
+        ) {
+            return;
+        }
+        self.refresh_signal_map();
+
+        let logical_address = datagram.logical_address();
+        let data = datagram.payload();
+        for (i, (device_idx, signal)) in self.signal_map.signals.iter().enumerate() {
+            let Some(value) = signal.extract(logical_address, data) else {
+                continue;
+            };
+            if self.signal_map.last_values[i] == Some(value) {
+                continue;
+            }
+            self.signal_map.last_values[i] = Some(value);
+            self.pending_signal_samples.push(SignalSample {
+                packet_number: self.num_frames,
+                timestamp,
+                subdevice_id: self.devices[*device_idx].identifier(),
+                name: signal.name.clone(),
+                key: signal.key(),
+                direction: signal.direction,
+                value,
+            });
+        }
+    }
+
+    /// Archive the current device model and start a new scan with `num_subdevices` devices.
+    fn start_new_scan(&mut self, num_subdevices: u16, timestamp: Duration) {
+        let config = self.config;
+        let devices = std::mem::replace(
+            &mut self.devices,
+            (0..num_subdevices).map(|_| config.subdevice()).collect(),
+        );
+        let previous_device_count = devices.len();
+        self.previous_scans.push(DeviceScan {
+            number: self.scan_number,
+            start_packet: self.scan_start_packet,
+            end_packet: self.num_frames.saturating_sub(1),
+            devices,
+        });
+        self.scan_number += 1;
+        self.scan_start_packet = self.num_frames;
+
+        self.config_address_map.clear();
+        self.process_data_generation += 1;
+        self.bus_size_candidate = None;
+        self.detached_devices.clear();
+        self.detached_config_addresses.clear();
+        self.wkc_error_history.clear();
+        self.pending_esm_al_status.clear();
+        self.register_watch_values.clear();
+        self.signal_map = SignalMap::default();
+        self.logical_map = LogicalMap::default();
+        if self.init_complete_packet.is_none() {
+            self.init_steps.clear();
+        }
+
+        debug!(
+            "#{} Bus rescan: scan #{} with {} subdevices",
+            self.num_frames, self.scan_number, num_subdevices
+        );
+        self.pending_rescans.push(Rescan {
+            packet_number: self.num_frames,
+            timestamp,
+            scan_number: self.scan_number,
+            previous_device_count,
+            device_count: num_subdevices as usize,
+        });
+    }
+
+    /// Write `data` to the written register shadow of subdevice `index`, tracking
+    /// changes of its process data layout.
+    fn write_device_register(&mut self, index: usize, reg_addr: u16, data: &[u8]) {
+        let device = &mut self.devices[index];
+        let generation = device.process_data_generation();
+        device.write_reg_wr(reg_addr, data);
+        if device.process_data_generation() != generation {
+            self.process_data_generation += 1;
+        }
+    }
+
+    /// Re-synchronize the device model with the number of subdevices answering a BRD.
+    ///
+    /// Subdevices are assumed to be added or lost at the end of the bus (a cable
+    /// pulled or plugged behind the last reachable subdevice), so the models of
+    /// the remaining subdevices are kept. Lost subdevices are kept aside and
+    /// reattached at their positions when the bus grows again.
+    fn resize_bus(&mut self, device_count: u16, timestamp: Duration) {
+        let previous_device_count = self.devices.len();
+        let device_count = device_count as usize;
+        self.process_data_generation += 1;
+        if device_count < previous_device_count {
+            let mut detached = self.devices.split_off(device_count);
+            detached.append(&mut self.detached_devices);
+            self.detached_devices = detached;
+            let map = std::mem::take(&mut self.config_address_map);
+            let (kept, lost): (HashMap<_, _>, HashMap<_, _>) =
+                map.into_iter().partition(|(_, idx)| *idx < device_count);
+            self.config_address_map = kept;
+            self.detached_config_addresses.extend(lost);
+            self.pending_esm_al_status
+                .retain(|(idx, _)| *idx < device_count);
+            self.register_watch_values
+                .retain(|(idx, _, _), _| *idx < device_count);
+            self.init_steps.truncate(device_count);
+        } else {
+            let reattached =
+                (device_count - previous_device_count).min(self.detached_devices.len());
+            self.devices
+                .extend(self.detached_devices.drain(..reattached));
+            let config = self.config;
+            self.devices
+                .resize_with(device_count, || config.subdevice());
+            self.detached_config_addresses.retain(|&address, &mut idx| {
+                if idx < device_count {
+                    self.config_address_map.insert(address, idx);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        debug!(
+            "#{} Bus size changed: {} -> {} subdevices",
+            self.num_frames, previous_device_count, device_count
+        );
+        self.pending_bus_size_changes.push(BusSizeChange {
+            packet_number: self.num_frames,
+            timestamp,
+            previous_device_count,
+            device_count,
+        });
+    }
+
+    /// Number of the current bus scan (1 unless the main device rescanned the bus).
+    pub fn scan_number(&self) -> u32 {
+        self.scan_number
+    }
+
+    /// Device models of earlier scans, oldest first.
+    pub fn previous_scans(&self) -> &[DeviceScan] {
+        &self.previous_scans
+    }
+
     /// Correlate ESM errors with recent WKC errors on the same device.
     fn correlate_esm_with_wkc(&mut self, esm_error: &ESMErrorDetail) {
         // Search backward through WKC history for matching subdevice
         let mut best_match: Option<(WkcErrorDetail, u64)> = None;
 
         for wkc_err in self.wkc_error_history.iter().rev() {
-            if let Some(wkc_err_subdevice) = wkc_err.subdevice_id {
-                if wkc_err_subdevice == esm_error.subdevice_id {
-                    let gap = esm_error
-                        .packet_number
-                        .saturating_sub(wkc_err.packet_number);
-
-                    // Prefer the closest (most recent) WKC error
-                    match &best_match {
-                        Some((_, existing_gap)) if gap >= *existing_gap => {}
-                        _ => {
-                            best_match = Some((*wkc_err, gap));
-                        }
+            if let Some(wkc_err_subdevice) = wkc_err.subdevice_id
+                && wkc_err_subdevice == esm_error.subdevice_id
+            {
+                let gap = esm_error
+                    .packet_number
+                    .saturating_sub(wkc_err.packet_number);
+
+                // Prefer the closest (most recent) WKC error
+                match &best_match {
+                    Some((_, existing_gap)) if gap >= *existing_gap => {}
+                    _ => {
+                        best_match = Some((*wkc_err, gap));
                     }
-                    // The first match from the end is the closest, so we can break
-                    break;
                 }
+                // The first match from the end is the closest, so we can break
+                break;
             }
         }
 
@@ -496,10 +1424,134 @@ impl DeviceManager {
         }
     }
 
+    /// Count a frame without analyzing it, keeping frame numbers aligned with the capture.
+    pub fn skip_frame(&mut self) {
+        self.num_frames += 1;
+        self.skipped_frames += 1;
+    }
+
+    /// Number of frames counted with `skip_frame`.
+    pub fn get_skipped_frame_count(&self) -> u64 {
+        self.skipped_frames
+    }
+
+    /// Number of the last frame seen, in capture sequence numbering.
     pub fn get_frame_count(&self) -> u64 {
         self.num_frames
     }
 
+    /// Number the next frame `sequence`, leaving a gap for frames that were
+    /// dropped between the capture and the analyzer.
+    pub fn sync_frame_number(&mut self, sequence: u64) {
+        self.num_frames = self
+            .num_frames
+            .max(self.frame_offset + sequence.saturating_sub(1));
+    }
+
+    /// Number of the frames in the captures before this one, added to the
+    /// capture's own frame numbers after [`DeviceManager::restore`].
+    pub fn frame_offset(&self) -> u64 {
+        self.frame_offset
+    }
+
+    /// Number of frames analyzed, excluding skipped and dropped frames.
+    pub fn get_analyzed_frame_count(&self) -> u64 {
+        self.analyzed_frames
+    }
+
+    /// The subdevices discovered so far, in bus order.
+    pub fn devices(&self) -> &[SubDevice] {
+        &self.devices
+    }
+
+    /// The subdevice table and frame counters; the events are only known to
+    /// [`Analyzer`](crate::analysis::Analyzer), see
+    /// [`AnalysisReport::summary`](crate::analysis::AnalysisReport::summary).
+    pub fn summary(&self) -> AnalysisSummary {
+        AnalysisSummary {
+            analyzed_frames: self.analyzed_frames,
+            scans: self.scan_number,
+            init_complete_frame: self.init_complete_packet,
+            devices: self
+                .devices
+                .iter()
+                .enumerate()
+                .map(|(position, device)| DeviceSummary::new(position, device))
+                .collect(),
+            events: BTreeMap::new(),
+        }
+    }
+
+    /// The device model to continue with in a later capture, see
+    /// [`DeviceManager::restore`].
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            frames: self.num_frames,
+            analyzed_frames: self.analyzed_frames,
+            skipped_frames: self.skipped_frames,
+            uninitialized: self.uninitialized,
+            expected_wkc: self.expected_wkc,
+            devices: self.devices.clone(),
+            scan_number: self.scan_number,
+            scan_start_packet: self.scan_start_packet,
+            previous_scans: self.previous_scans.clone(),
+            init_steps: self.init_steps.clone(),
+            init_complete_packet: self.init_complete_packet,
+        }
+    }
+
+    /// Continue the device model of an earlier capture. Call before the first
+    /// packet; frame numbers continue after the last frame of the checkpoint, so
+    /// those in the scan, init and statistics history stay unique. The register
+    /// watches, signal selectors and protocol handlers are kept.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.uninitialized = checkpoint.uninitialized;
+        self.num_frames = checkpoint.frames;
+        self.frame_offset = checkpoint.frames;
+        self.analyzed_frames = checkpoint.analyzed_frames;
+        self.skipped_frames = checkpoint.skipped_frames;
+        self.expected_wkc = checkpoint.expected_wkc;
+        self.devices = checkpoint.devices;
+        self.process_data_generation += 1;
+        for device in &mut self.devices {
+            device.set_mailbox_decoding(self.config.mailbox_decoding);
+        }
+        self.config_address_map = self
+            .devices
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, device)| Some((device.configured_address()?, idx)))
+            .collect();
+        self.scan_number = checkpoint.scan_number;
+        self.scan_start_packet = checkpoint.scan_start_packet;
+        self.previous_scans = checkpoint.previous_scans;
+        self.init_steps = checkpoint.init_steps;
+        self.init_complete_packet = checkpoint.init_complete_packet;
+    }
+
+    /// Number of subdevices discovered on the bus.
+    pub fn device_count(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// The subdevice at bus position `position` (0 = closest to the main device).
+    pub fn device(&self, position: usize) -> Option<&SubDevice> {
+        self.devices.get(position)
+    }
+
+    /// The subdevice with the given configured station address.
+    pub fn device_by_configured_address(&self, address: u16) -> Option<&SubDevice> {
+        self.config_address_map
+            .get(&address)
+            .and_then(|&idx| self.devices.get(idx))
+    }
+
+    /// The subdevice matching the given identifier.
+    pub fn device_by_identifier(&self, id: SubdeviceIdentifier) -> Option<&SubDevice> {
+        self.devices.iter().find(|d| d.identifier() == id)
+    }
+
     /// Check if any tracked devices have had their AL Status Code updated since the last ESM error.
     /// Returns updates for devices whose AL Status Code has changed or become available.
     /// This should be called after `analyze_packet` to detect deferred AL Status Code availability.
@@ -548,13 +1600,78 @@ impl DeviceManager {
     pub fn take_pending_correlations(&mut self) -> Vec<ErrorCorrelation> {
         std::mem::take(&mut self.pending_correlations)
     }
+
+    /// Take any error acknowledge sequences completed during the last analyze_packet call.
+    /// This drains the internal buffer; each sequence is returned only once.
+    pub fn take_error_acknowledgements(&mut self) -> Vec<ErrorAcknowledgement> {
+        std::mem::take(&mut self.pending_error_acks)
+    }
+
+    /// Take any firmware update sessions completed during the last analyze_packet call.
+    /// This drains the internal buffer; each session is returned only once.
+    pub fn take_firmware_updates(&mut self) -> Vec<FirmwareUpdate> {
+        std::mem::take(&mut self.pending_firmware_updates)
+    }
+
+    /// Take any watched register changes detected during the last analyze_packet call.
+    /// This drains the internal buffer; each change is returned only once.
+    pub fn take_register_changes(&mut self) -> Vec<RegisterChange> {
+        std::mem::take(&mut self.pending_register_changes)
+    }
+
+    /// Take any process data signal changes detected during the last analyze_packet call.
+    /// This drains the internal buffer; each sample is returned only once.
+    pub fn take_signal_samples(&mut self) -> Vec<SignalSample> {
+        std::mem::take(&mut self.pending_signal_samples)
+    }
+
+    /// Take any rescans detected during the last analyze_packet call.
+    /// This drains the internal buffer; each rescan is returned only once.
+    pub fn take_rescans(&mut self) -> Vec<Rescan> {
+        std::mem::take(&mut self.pending_rescans)
+    }
+
+    /// Take logical addressing issues detected since the last call.
+    ///
+    /// This drains the internal buffer; each issue is returned only once.
+    pub fn take_logical_address_issues(&mut self) -> Vec<LogicalAddressEvent> {
+        std::mem::take(&mut self.pending_logical_issues)
+    }
+
+    /// Take BRD device count changes detected since the last call.
+    ///
+    /// This drains the internal buffer; each change is returned only once.
+    pub fn take_bus_size_changes(&mut self) -> Vec<BusSizeChange> {
+        std::mem::take(&mut self.pending_bus_size_changes)
+    }
+
+    /// Take the messages of the protocol handlers since the last call.
+    pub fn take_protocol_events(&mut self) -> Vec<ProtocolEvent> {
+        std::mem::take(&mut self.pending_protocol_events)
+    }
+
+    /// Take framing deviations detected since the last call.
+    ///
+    /// This drains the internal buffer; each malformed frame is returned only once.
+    pub fn take_malformed_frames(&mut self) -> Vec<MalformedFrame> {
+        std::mem::take(&mut self.pending_malformed_frames)
+    }
 }
 
 impl Drop for DeviceManager {
     fn drop(&mut self) {
-        debug!("Total analyzed EtherCAT frames: {}", self.num_frames);
+        debug!("Total analyzed EtherCAT frames: {}", self.analyzed_frames);
         for (i, device) in self.devices.iter_mut().enumerate() {
             debug!("SubDevice {}: {}", i, device.identifier());
+            for entry in device.register_dump() {
+                trace!(
+                    "  {:#06x} = {:#04x} ({}, {})",
+                    entry.address,
+                    entry.value,
+                    entry.shadow,
+                    entry.owner()
+                );
+            }
         }
     }
 }
@@ -569,7 +1686,17 @@ trait Command {
             return Ok(());
         }
 
-        if !self.check_wkc(manager, datagram) {
+        if !self.is_from_main() {
+            self.record_statistics(manager, datagram);
+        }
+
+        // Lenient checking accepts more answers than expected
+        let wkc_ok = self.check_wkc(manager, datagram)
+            || (!manager.config.strict_wkc && datagram.wkc() > manager.expected_wkc);
+        if !wkc_ok {
+            if let Some(idx) = self.get_subdevice_index(manager, datagram) {
+                manager.devices[idx].statistics_mut().wkc_errors += 1;
+            }
             self.process_fallback(manager, datagram);
             return Err(ECDeviceError::InvalidWkc(WkcErrorDetail {
                 packet_number: manager.num_frames,
@@ -578,6 +1705,7 @@ trait Command {
                 register: datagram.address().1,
                 length: datagram.length(),
                 subdevice_id: self.get_subdevice_id(manager, datagram),
+                position: self.get_subdevice_position(manager, datagram),
                 expected: manager.expected_wkc,
                 actual: datagram.wkc(),
             }));
@@ -602,11 +1730,76 @@ trait Command {
 
     fn timestamp(&self) -> Duration;
 
+    fn is_from_main(&self) -> bool;
+
+    /// Index of the single subdevice addressed by this datagram, if any.
+    fn get_subdevice_index(
+        &self,
+        _manager: &DeviceManager,
+        _datagram: &ECDatagram,
+    ) -> Option<usize> {
+        None
+    }
+
+    /// Physical position of the single subdevice addressed by this datagram, if any.
+    /// Unlike [`Command::get_subdevice_index`] this may lie beyond the known subdevices.
+    fn get_subdevice_position(
+        &self,
+        manager: &DeviceManager,
+        datagram: &ECDatagram,
+    ) -> Option<usize> {
+        self.get_subdevice_index(manager, datagram)
+    }
+
     fn get_subdevice_id(
         &self,
         manager: &DeviceManager,
         datagram: &ECDatagram,
-    ) -> Option<SubdeviceIdentifier>;
+    ) -> Option<SubdeviceIdentifier> {
+        self.get_subdevice_index(manager, datagram)
+            .map(|idx| manager.devices[idx].identifier())
+    }
+
+    /// Update per-subdevice traffic counters for a datagram returned by the subdevices.
+    fn record_statistics(&self, manager: &mut DeviceManager, datagram: &ECDatagram) {
+        if let Some(idx) = self.get_subdevice_index(manager, datagram) {
+            let device = &mut manager.devices[idx];
+            let is_mailbox = device.is_mailbox_access(datagram.address().1);
+            count_datagram(device.statistics_mut(), datagram, is_mailbox);
+        }
+    }
+}
+
+/// Translate an auto-increment address into the physical bus position it addresses.
+/// Each subdevice increments the address on the way, so a returned datagram carries
+/// the original address plus the number of subdevices on the bus.
+fn auto_increment_position(
+    manager: &DeviceManager,
+    auto_increment_addr: u16,
+    from_main: bool,
+) -> usize {
+    let position = if from_main {
+        0_u16.wrapping_sub(auto_increment_addr)
+    } else {
+        (manager.devices.len() as u16).wrapping_sub(auto_increment_addr)
+    };
+    position as usize
+}
+
+fn count_datagram(statistics: &mut SubDeviceStatistics, datagram: &ECDatagram, is_mailbox: bool) {
+    statistics.datagrams += 1;
+    match datagram.command() {
+        ECCommands::APRD | ECCommands::FPRD | ECCommands::BRD => {
+            statistics.bytes_read += datagram.length() as u64;
+        }
+        ECCommands::APWR | ECCommands::FPWR | ECCommands::BWR => {
+            statistics.bytes_written += datagram.length() as u64;
+        }
+        _ => {}
+    }
+    if is_mailbox {
+        statistics.mailbox_messages += 1;
+    }
 }
 
 struct BrdCommand {
@@ -646,24 +1839,56 @@ impl Command for BrdCommand {
     }
 
     fn check_wkc(&self, manager: &mut DeviceManager, datagram: &ECDatagram) -> bool {
-        if self.from_main {
-            true
-        } else {
+        if !self.from_main {
+            // Every subdevice increments the WKC of a BRD. A different WKC is a
+            // WKC error, unless it persists: then subdevices were added or lost.
+            let wkc = datagram.wkc();
+            if wkc as usize == manager.devices.len() {
+                manager.bus_size_candidate = None;
+            } else {
+                let confirmations = match manager.bus_size_candidate {
+                    Some((count, confirmations)) if count == wkc => confirmations + 1,
+                    _ => 1,
+                };
+                if confirmations < BUS_RESIZE_CONFIRMATIONS {
+                    manager.bus_size_candidate = Some((wkc, confirmations));
+                    manager.expected_wkc = manager.devices.len() as u16;
+                    return false;
+                }
+                manager.bus_size_candidate = None;
+                manager.resize_bus(wkc, self.timestamp);
+            }
             manager.expected_wkc = manager.devices.len() as u16;
-            datagram.wkc() == manager.expected_wkc
         }
+        true
     }
 
     fn uninitialized(&self, manager: &mut DeviceManager, datagram: &ECDatagram) -> bool {
         if manager.uninitialized && !self.from_main {
             let num_subdevices = datagram.wkc();
-            manager.devices = (0..num_subdevices).map(|_| SubDevice::new()).collect();
+            let config = manager.config;
+            manager.devices = (0..num_subdevices).map(|_| config.subdevice()).collect();
+            manager.process_data_generation += 1;
             manager.uninitialized = false;
+            manager.scan_start_packet = manager.num_frames;
             debug!(
                 "Initialized DeviceManager with {} subdevices",
                 num_subdevices
             );
             false
+        } else if !manager.uninitialized
+            && manager.config.tolerate_rescan
+            && !self.from_main
+            && datagram.address().1 == RegisterAddress::Type
+            && manager
+                .devices
+                .iter()
+                .any(|d| d.configured_address().is_some())
+        {
+            // Scanning the type register again after configuration means the
+            // main device restarted its bus scan.
+            manager.start_new_scan(datagram.wkc(), self.timestamp);
+            false
         } else {
             manager.uninitialized
         }
@@ -673,12 +1898,14 @@ impl Command for BrdCommand {
         self.timestamp
     }
 
-    fn get_subdevice_id(
-        &self,
-        _manager: &DeviceManager,
-        _datagram: &ECDatagram,
-    ) -> Option<SubdeviceIdentifier> {
-        None
+    fn is_from_main(&self) -> bool {
+        self.from_main
+    }
+
+    fn record_statistics(&self, manager: &mut DeviceManager, datagram: &ECDatagram) {
+        for device in manager.devices.iter_mut() {
+            count_datagram(device.statistics_mut(), datagram, false);
+        }
     }
 }
 
@@ -695,8 +1922,18 @@ impl Command for BwrCommand {
     ) -> Result<(), ECDeviceError> {
         let reg_addr = datagram.address().1;
         let data = datagram.payload();
-        for device in manager.devices.iter_mut() {
-            device.write_reg_wr(reg_addr, data);
+        for index in 0..manager.devices.len() {
+            manager.write_device_register(index, reg_addr, data);
+        }
+        if !self.from_main {
+            let packet_num = manager.num_frames;
+            for index in 0..manager.devices.len() {
+                manager.record_init_write(index, datagram, self.timestamp);
+                // Like FPWR, step once per datagram, on the returning copy.
+                // Writes only update the requested state and never fail
+                let _ = manager.devices[index]
+                    .state_machine_step::<subdevice::WriteCommandStepper>(packet_num);
+            }
         }
         Ok(())
     }
@@ -718,12 +1955,14 @@ impl Command for BwrCommand {
         self.timestamp
     }
 
-    fn get_subdevice_id(
-        &self,
-        _manager: &DeviceManager,
-        _datagram: &ECDatagram,
-    ) -> Option<SubdeviceIdentifier> {
-        None
+    fn is_from_main(&self) -> bool {
+        self.from_main
+    }
+
+    fn record_statistics(&self, manager: &mut DeviceManager, datagram: &ECDatagram) {
+        for device in manager.devices.iter_mut() {
+            count_datagram(device.statistics_mut(), datagram, false);
+        }
     }
 }
 
@@ -746,13 +1985,14 @@ impl Command for ApwrCommand {
                 timestamp: self.timestamp,
                 command: datagram.command(),
                 address: auto_increment_addr,
+                position: auto_increment_position(manager, auto_increment_addr, self.from_main),
             })?;
 
         if !self.from_main {
+            manager.record_init_write(subdevice_index, datagram, self.timestamp);
             let reg_addr = datagram.address().1;
-            let device = &mut manager.devices[subdevice_index];
             let data = &datagram.payload()[0..datagram.length() as usize];
-            device.write_reg_wr(reg_addr, data);
+            manager.write_device_register(subdevice_index, reg_addr, data);
         }
 
         Ok(())
@@ -765,9 +2005,8 @@ impl Command for ApwrCommand {
                 self.get_idx_from_auto_increment_address(manager, auto_increment_addr)
         {
             let reg_addr = datagram.address().1;
-            let device = &mut manager.devices[subdevice_index];
             let data = &datagram.payload()[0..datagram.length() as usize];
-            device.write_reg_wr(reg_addr, data);
+            manager.write_device_register(subdevice_index, reg_addr, data);
         }
     }
 
@@ -784,14 +2023,24 @@ impl Command for ApwrCommand {
         self.timestamp
     }
 
-    fn get_subdevice_id(
+    fn is_from_main(&self) -> bool {
+        self.from_main
+    }
+
+    fn get_subdevice_index(&self, manager: &DeviceManager, datagram: &ECDatagram) -> Option<usize> {
+        self.get_idx_from_auto_increment_address(manager, datagram.address().0)
+    }
+
+    fn get_subdevice_position(
         &self,
         manager: &DeviceManager,
         datagram: &ECDatagram,
-    ) -> Option<SubdeviceIdentifier> {
-        let auto_increment_addr = datagram.address().0;
-        self.get_idx_from_auto_increment_address(manager, auto_increment_addr)
-            .map(|idx| manager.devices[idx].identifier())
+    ) -> Option<usize> {
+        Some(auto_increment_position(
+            manager,
+            datagram.address().0,
+            self.from_main,
+        ))
     }
 }
 
@@ -837,6 +2086,7 @@ impl Command for AprdCommand {
                 timestamp: self.timestamp,
                 command: datagram.command(),
                 address: auto_increment_addr,
+                position: auto_increment_position(manager, auto_increment_addr, self.from_main),
             })?;
 
         let device = &mut manager.devices[subdevice_index];
@@ -884,14 +2134,24 @@ impl Command for AprdCommand {
         self.timestamp
     }
 
-    fn get_subdevice_id(
+    fn is_from_main(&self) -> bool {
+        self.from_main
+    }
+
+    fn get_subdevice_index(&self, manager: &DeviceManager, datagram: &ECDatagram) -> Option<usize> {
+        self.get_index_from_auto_increment_address(manager, datagram.address().0)
+    }
+
+    fn get_subdevice_position(
         &self,
         manager: &DeviceManager,
         datagram: &ECDatagram,
-    ) -> Option<SubdeviceIdentifier> {
-        let auto_increment_addr = datagram.address().0;
-        self.get_index_from_auto_increment_address(manager, auto_increment_addr)
-            .map(|idx| manager.devices[idx].identifier())
+    ) -> Option<usize> {
+        Some(auto_increment_position(
+            manager,
+            datagram.address().0,
+            self.from_main,
+        ))
     }
 }
 
@@ -940,8 +2200,12 @@ impl Command for FpwrCommand {
         )?;
 
         if !self.from_main {
-            let data = datagram.payload();
-            manager.devices[*subdevice_index].write_reg_wr(ado, data);
+            let subdevice_index = *subdevice_index;
+            manager.record_init_write(subdevice_index, datagram, self.timestamp);
+            manager.write_device_register(subdevice_index, ado, datagram.payload());
+            let device = &mut manager.devices[subdevice_index];
+            // Writes only update the requested state and never fail
+            let _ = device.state_machine_step::<subdevice::WriteCommandStepper>(manager.num_frames);
         }
 
         Ok(())
@@ -952,8 +2216,7 @@ impl Command for FpwrCommand {
         if !self.from_main
             && let Some(&subdevice_index) = manager.config_address_map.get(&configured_address)
         {
-            let data = datagram.payload();
-            manager.devices[subdevice_index].write_reg_wr(ado, data);
+            manager.write_device_register(subdevice_index, ado, datagram.payload());
         }
     }
 
@@ -970,16 +2233,13 @@ impl Command for FpwrCommand {
         self.timestamp
     }
 
-    fn get_subdevice_id(
-        &self,
-        manager: &DeviceManager,
-        datagram: &ECDatagram,
-    ) -> Option<SubdeviceIdentifier> {
+    fn is_from_main(&self) -> bool {
+        self.from_main
+    }
+
+    fn get_subdevice_index(&self, manager: &DeviceManager, datagram: &ECDatagram) -> Option<usize> {
         let (configured_address, _) = datagram.address();
-        manager
-            .config_address_map
-            .get(&configured_address)
-            .map(|&idx| manager.devices[idx].identifier())
+        manager.config_address_map.get(&configured_address).copied()
     }
 }
 
@@ -1040,15 +2300,255 @@ impl Command for FprdCommand {
         self.timestamp
     }
 
-    fn get_subdevice_id(
-        &self,
-        manager: &DeviceManager,
-        datagram: &ECDatagram,
-    ) -> Option<SubdeviceIdentifier> {
+    fn is_from_main(&self) -> bool {
+        self.from_main
+    }
+
+    fn get_subdevice_index(&self, manager: &DeviceManager, datagram: &ECDatagram) -> Option<usize> {
         let (configured_address, _) = datagram.address();
-        manager
-            .config_address_map
-            .get(&configured_address)
-            .map(|&idx| manager.devices[idx].identifier())
+        manager.config_address_map.get(&configured_address).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ec_packet::ECFrameBuilder;
+
+    /// An EtherCAT frame (without the Ethernet header) with one datagram.
+    fn frame(command: ECCommand, adp: u16, ado: u16, data: &[u8], wkc: u16) -> Vec<u8> {
+        ECFrameBuilder::new()
+            .datagram(command, adp, ado, data, wkc)
+            .build()
+    }
+
+    fn try_analyze(
+        device_manager: &mut DeviceManager,
+        frame: &[u8],
+        from_main: bool,
+    ) -> Result<(), ECError> {
+        device_manager.analyze_packet(&ECFrame::new(frame).unwrap(), Duration::ZERO, from_main)
+    }
+
+    fn analyze(device_manager: &mut DeviceManager, frame: &[u8], from_main: bool) {
+        try_analyze(device_manager, frame, from_main).unwrap();
+    }
+
+    /// Two subdevices configured to station addresses 0x1001 and 0x1002.
+    fn configured_bus() -> DeviceManager {
+        const BRD: ECCommand = ECCommands::BRD;
+        const APWR: ECCommand = ECCommands::APWR;
+        const APRD: ECCommand = ECCommands::APRD;
+        let mut device_manager = DeviceManager::default();
+        let address = RegisterAddress::ConfiguredStationAddress;
+        let mut exchange = |outgoing: Vec<u8>, returning: Vec<u8>| {
+            analyze(&mut device_manager, &outgoing, true);
+            analyze(&mut device_manager, &returning, false);
+        };
+        exchange(
+            frame(BRD, 0, RegisterAddress::Type, &[0, 0], 0),
+            frame(BRD, 2, RegisterAddress::Type, &[0x11, 0], 2),
+        );
+        // Auto-increment addresses count up once per subdevice passed
+        for (position, station) in [(0u16, [0x01, 0x10]), (1, [0x02, 0x10])] {
+            let adp = 0u16.wrapping_sub(position);
+            exchange(
+                frame(APWR, adp, address, &station, 0),
+                frame(APWR, adp.wrapping_add(2), address, &station, 1),
+            );
+            exchange(
+                frame(APRD, adp, address, &[0, 0], 0),
+                frame(APRD, adp.wrapping_add(2), address, &station, 1),
+            );
+        }
+        device_manager
+    }
+
+    /// A BRD of DL Status answered by `wkc` subdevices.
+    fn brd_dl_status(device_manager: &mut DeviceManager, wkc: u16) -> Result<(), ECError> {
+        let outgoing = frame(ECCommands::BRD, 0, RegisterAddress::DlStatus, &[0, 0], 0);
+        analyze(device_manager, &outgoing, true);
+        let returning = frame(
+            ECCommands::BRD,
+            wkc,
+            RegisterAddress::DlStatus,
+            &[0x30, 0],
+            wkc,
+        );
+        try_analyze(device_manager, &returning, false)
+    }
+
+    /// An FPRD of DL Status from `station`, answered by it.
+    fn fprd_dl_status(device_manager: &mut DeviceManager, station: u16) -> Result<(), ECError> {
+        let outgoing = frame(
+            ECCommands::FPRD,
+            station,
+            RegisterAddress::DlStatus,
+            &[0, 0],
+            0,
+        );
+        analyze(device_manager, &outgoing, true);
+        let returning = frame(
+            ECCommands::FPRD,
+            station,
+            RegisterAddress::DlStatus,
+            &[0x30, 0],
+            1,
+        );
+        try_analyze(device_manager, &returning, false)
+    }
+
+    fn is_wkc_error(result: Result<(), ECError>) -> bool {
+        matches!(
+            result,
+            Err(ECError::DeviceError(errors))
+                if matches!(errors[..], [ECDeviceError::InvalidWkc(_)])
+        )
+    }
+
+    #[test]
+    fn test_logical_conflict_is_reported_once() {
+        let mut device_manager = configured_bus();
+        // Both subdevices map their inputs to logical 0x00010000..0x00010004
+        let fmmu = [
+            0x00, 0x00, 0x01, 0x00, 0x04, 0x00, 0x00, 0x07, 0x00, 0x11, 0x00, 0x01, 0x01, 0, 0, 0,
+        ];
+        let fpwr = |device_manager: &mut DeviceManager, station: u16, ado: u16, data: &[u8]| {
+            analyze(
+                device_manager,
+                &frame(ECCommands::FPWR, station, ado, data, 0),
+                true,
+            );
+            analyze(
+                device_manager,
+                &frame(ECCommands::FPWR, station, ado, data, 1),
+                false,
+            );
+        };
+        let lrd = |device_manager: &mut DeviceManager| {
+            let lrd = frame(ECCommands::LRD, 0x0000, 0x0001, &[0; 4], 0);
+            analyze(device_manager, &lrd, true);
+            let lrd = frame(ECCommands::LRD, 0x0000, 0x0001, &[0; 4], 2);
+            analyze(device_manager, &lrd, false);
+            device_manager
+                .take_logical_address_issues()
+                .into_iter()
+                .filter(|event| matches!(event.issue, LogicalAddressIssue::Conflict { .. }))
+                .count()
+        };
+        fpwr(&mut device_manager, 0x1001, RegisterAddress::Fmmu0, &fmmu);
+        fpwr(&mut device_manager, 0x1002, RegisterAddress::Fmmu0, &fmmu);
+        assert_eq!(lrd(&mut device_manager), 1);
+
+        // Rebuilding the map after another configuration write reports nothing new
+        fpwr(&mut device_manager, 0x1002, RegisterAddress::Sm0, &[0; 8]);
+        assert_eq!(lrd(&mut device_manager), 0);
+        assert_eq!(lrd(&mut device_manager), 0);
+    }
+
+    #[test]
+    fn test_single_brd_wkc_deviation_is_a_wkc_error() {
+        let mut device_manager = configured_bus();
+        assert!(is_wkc_error(brd_dl_status(&mut device_manager, 0)));
+        assert_eq!(device_manager.device_count(), 2);
+        assert!(device_manager.take_bus_size_changes().is_empty());
+
+        brd_dl_status(&mut device_manager, 2).unwrap();
+        fprd_dl_status(&mut device_manager, 0x1001).unwrap();
+        fprd_dl_status(&mut device_manager, 0x1002).unwrap();
+        // The deviation has to persist over consecutive BRDs
+        assert!(is_wkc_error(brd_dl_status(&mut device_manager, 1)));
+        assert!(is_wkc_error(brd_dl_status(&mut device_manager, 1)));
+        brd_dl_status(&mut device_manager, 2).unwrap();
+        assert!(is_wkc_error(brd_dl_status(&mut device_manager, 1)));
+        assert_eq!(device_manager.device_count(), 2);
+    }
+
+    #[test]
+    fn test_bus_shrinks_and_regrows() {
+        let mut device_manager = configured_bus();
+        for _ in 1..BUS_RESIZE_CONFIRMATIONS {
+            assert!(is_wkc_error(brd_dl_status(&mut device_manager, 1)));
+        }
+        brd_dl_status(&mut device_manager, 1).unwrap();
+        assert_eq!(device_manager.device_count(), 1);
+        let changes = device_manager.take_bus_size_changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            (changes[0].previous_device_count, changes[0].device_count),
+            (2, 1)
+        );
+        fprd_dl_status(&mut device_manager, 0x1001).unwrap();
+        assert!(
+            device_manager
+                .device_by_configured_address(0x1002)
+                .is_none()
+        );
+
+        // The lost subdevice comes back with its model and station address
+        for _ in 1..BUS_RESIZE_CONFIRMATIONS {
+            assert!(is_wkc_error(brd_dl_status(&mut device_manager, 2)));
+        }
+        brd_dl_status(&mut device_manager, 2).unwrap();
+        assert_eq!(device_manager.device_count(), 2);
+        assert_eq!(
+            device_manager
+                .device_by_configured_address(0x1002)
+                .and_then(|device| device.configured_address()),
+            Some(0x1002)
+        );
+        fprd_dl_status(&mut device_manager, 0x1002).unwrap();
+        brd_dl_status(&mut device_manager, 2).unwrap();
+    }
+
+    #[test]
+    fn test_al_control_writes_update_requested_state() {
+        const BRD: ECCommand = ECCommands::BRD;
+        const APWR: ECCommand = ECCommands::APWR;
+        const APRD: ECCommand = ECCommands::APRD;
+        const FPWR: ECCommand = ECCommands::FPWR;
+        const BWR: ECCommand = ECCommands::BWR;
+        let mut device_manager = DeviceManager::default();
+        let mut exchange = |outgoing: Vec<u8>, returning: Vec<u8>| {
+            analyze(&mut device_manager, &outgoing, true);
+            analyze(&mut device_manager, &returning, false);
+        };
+
+        // One subdevice, configured to station address 0x1001
+        exchange(
+            frame(BRD, 0, RegisterAddress::Type, &[0, 0], 0),
+            frame(BRD, 1, RegisterAddress::Type, &[0x11, 0], 1),
+        );
+        let address = RegisterAddress::ConfiguredStationAddress;
+        exchange(
+            frame(APWR, 0, address, &[0x01, 0x10], 0),
+            frame(APWR, 1, address, &[0x01, 0x10], 1),
+        );
+        exchange(
+            frame(APRD, 0, address, &[0, 0], 0),
+            frame(APRD, 1, address, &[0x01, 0x10], 1),
+        );
+        let requested_state = |device_manager: &DeviceManager| {
+            device_manager
+                .device_by_configured_address(0x1001)
+                .unwrap()
+                .requested_state()
+        };
+        assert_eq!(requested_state(&device_manager), None);
+
+        // Writes step the state machine on the returning copy only
+        let fpwr = frame(FPWR, 0x1001, RegisterAddress::AlControl, &[0x02, 0], 0);
+        analyze(&mut device_manager, &fpwr, true);
+        assert_eq!(requested_state(&device_manager), None);
+        let fpwr = frame(FPWR, 0x1001, RegisterAddress::AlControl, &[0x02, 0], 1);
+        analyze(&mut device_manager, &fpwr, false);
+        assert_eq!(requested_state(&device_manager), Some(ECState::PreOp));
+
+        let bwr = frame(BWR, 0, RegisterAddress::AlControl, &[0x04, 0], 0);
+        analyze(&mut device_manager, &bwr, true);
+        assert_eq!(requested_state(&device_manager), Some(ECState::PreOp));
+        let bwr = frame(BWR, 0, RegisterAddress::AlControl, &[0x04, 0], 1);
+        analyze(&mut device_manager, &bwr, false);
+        assert_eq!(requested_state(&device_manager), Some(ECState::SafeOp));
     }
 }
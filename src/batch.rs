@@ -0,0 +1,439 @@
+//! `ecdump batch DIR`: analyzes the capture files under a directory in parallel
+//! worker threads, e.g. the nightly captures of all machines of a plant.
+//!
+//! Each file gets a `--report` next to it (or under `--out`), and the batch an
+//! aggregate summary: the errors by category over all files and the subdevices
+//! with the most errors, printed and written to `ecdump-batch.json`.
+
+use anyhow::{Context, Result};
+use console::style;
+use crossbeam_channel::unbounded;
+use ecdump::analysis::{AnalysisOptions, Analyzer, Direction};
+use ecdump::capture_file;
+use ecdump::subdevice::SubDeviceIdentity;
+use pnet::packet::Packet;
+use pnet::packet::ethernet::EthernetPacket;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::buffer_pool::PoolMetrics;
+use crate::compression::{self, Compression};
+use crate::error_formatter::CaptureSummary;
+use crate::event_stream::FrameEvents;
+use crate::report::ReportBuilder;
+
+const ETHERTYPE_ETHERCAT: u16 = 0x88a4;
+/// Subdevices listed in the aggregate summary.
+const WORST_DEVICES: usize = 10;
+/// File name of the aggregate summary.
+const SUMMARY_FILE: &str = "ecdump-batch.json";
+
+/// `ecdump batch` arguments.
+pub struct Batch {
+    pub dir: String,
+    /// Directory of the reports, by default next to the capture files.
+    pub out: Option<String>,
+    pub jobs: usize,
+}
+
+/// Outcome of the analysis of one capture file.
+struct FileResult {
+    /// Path relative to the batch directory.
+    name: String,
+    outcome: Result<FileAnalysis>,
+    elapsed: Duration,
+}
+
+struct FileAnalysis {
+    analyzed_frames: u64,
+    errors: Vec<(&'static str, u64)>,
+    devices: Vec<DeviceErrors>,
+}
+
+/// Aggregate summary of a batch, `ecdump-batch.json`.
+#[derive(Serialize)]
+struct BatchSummary {
+    files: usize,
+    analyzed_frames: u64,
+    failed: Vec<FailedFile>,
+    errors: BTreeMap<&'static str, CategoryTotal>,
+    /// Subdevices with the most errors over all files.
+    worst_devices: Vec<DeviceErrors>,
+}
+
+#[derive(Serialize)]
+struct FailedFile {
+    file: String,
+    error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct CategoryTotal {
+    count: u64,
+    /// Files with errors of the category.
+    files: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DeviceErrors {
+    file: String,
+    position: usize,
+    subdevice: String,
+    identity: Option<SubDeviceIdentity>,
+    wkc_errors: u64,
+    esm_errors: u64,
+}
+
+impl DeviceErrors {
+    fn total(&self) -> u64 {
+        self.wkc_errors + self.esm_errors
+    }
+}
+
+/// Analyze the capture files under `batch.dir` and print the summary. Returns
+/// whether a file could not be analyzed.
+pub fn run(batch: &Batch) -> Result<bool> {
+    let dir = Path::new(&batch.dir);
+    let mut files = Vec::new();
+    find_captures(dir, &mut files).with_context(|| format!("Failed to list {}", batch.dir))?;
+    files.sort();
+    let out = batch.out.as_deref().map(Path::new).unwrap_or(dir);
+    std::fs::create_dir_all(out).with_context(|| format!("Failed to create {}", out.display()))?;
+    println!(
+        "{}",
+        style(format!(
+            "■ {}: {} capture files, {} workers",
+            batch.dir,
+            files.len(),
+            batch.jobs
+        ))
+        .bold()
+    );
+
+    let (tx_file, rx_file) = unbounded::<PathBuf>();
+    let (tx_result, rx_result) = unbounded::<FileResult>();
+    for file in &files {
+        tx_file.send(file.clone()).ok();
+    }
+    drop(tx_file);
+    let mut results = std::thread::scope(|scope| {
+        for _ in 0..batch.jobs.min(files.len()) {
+            let (rx_file, tx_result) = (rx_file.clone(), tx_result.clone());
+            scope.spawn(move || {
+                for path in rx_file {
+                    let name = relative_name(dir, &path);
+                    let started = Instant::now();
+                    let outcome = analyze(&path, &name, &report_path(out, &name));
+                    let elapsed = started.elapsed();
+                    if tx_result
+                        .send(FileResult {
+                            name,
+                            outcome,
+                            elapsed,
+                        })
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx_result);
+        // Progress as the files finish, in any order
+        let mut results = Vec::new();
+        for result in rx_result {
+            print_result(&result);
+            results.push(result);
+        }
+        results
+    });
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let summary = summarize(&results);
+    print_summary(&summary);
+    let path = out.join(SUMMARY_FILE);
+    write_summary(&path, &summary)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(!summary.failed.is_empty())
+}
+
+/// Capture files under `dir`, also compressed ones, in all subdirectories.
+fn find_captures(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_captures(&path, files)?;
+        } else if is_capture(&path.to_string_lossy()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_capture(path: &str) -> bool {
+    let path = Compression::strip_extension(path).to_lowercase();
+    path.ends_with(".pcap") || path.ends_with(".pcapng")
+}
+
+fn relative_name(dir: &Path, path: &Path) -> String {
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// `--report` of the capture file `name` in `out`.
+fn report_path(out: &Path, name: &str) -> PathBuf {
+    out.join(format!(
+        "{}.report.json",
+        Compression::strip_extension(name)
+    ))
+}
+
+/// Analyze the capture file at `path`, named `name` in the summary, and write its
+/// report to `report`.
+fn analyze(path: &Path, name: &str, report: &Path) -> Result<FileAnalysis> {
+    let started = Instant::now();
+    let file = compression::open_input(&path.to_string_lossy())
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut analyzer = Analyzer::new(AnalysisOptions::default());
+    let mut report_builder = ReportBuilder::default();
+    let mut main = None;
+    let mut first_timestamp = None;
+    capture_file::read_frames(file, |timestamp, data| {
+        let Some(ethernet) = EthernetPacket::new(data) else {
+            return;
+        };
+        if ethernet.get_ethertype().0 != ETHERTYPE_ETHERCAT {
+            return;
+        }
+        let direction = if *main.get_or_insert(ethernet.get_source()) == ethernet.get_source() {
+            Direction::FromMain
+        } else {
+            Direction::ToMain
+        };
+        let first: Duration = *first_timestamp.get_or_insert(timestamp);
+        if let Some(analysis) = analyzer.feed(data, timestamp.saturating_sub(first), direction) {
+            let events = FrameEvents::from_analysis(&analysis, ethernet.payload().len());
+            report_builder.record_frame(&events);
+        }
+    })
+    .with_context(|| format!("Failed to read {}", path.display()))?;
+    let analysis = analyzer.finish();
+
+    if let Some(parent) = report.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let capture = CaptureSummary {
+        analyzed_frames: analysis.analyzed_frames,
+        elapsed: started.elapsed(),
+        dropped_frames: 0,
+        pool: PoolMetrics::default(),
+        clock_source: None,
+        evictions: analysis.device_manager().evictions(),
+    };
+    report_builder.write(
+        &report.to_string_lossy(),
+        analysis.device_manager(),
+        &capture,
+        &analysis.errors,
+        None,
+    )?;
+
+    Ok(FileAnalysis {
+        analyzed_frames: analysis.analyzed_frames,
+        errors: analysis
+            .errors
+            .iter()
+            .map(|(category, statistics)| (category, statistics.count))
+            .collect(),
+        devices: analysis
+            .devices()
+            .iter()
+            .enumerate()
+            .map(|(position, device)| DeviceErrors {
+                file: name.to_string(),
+                position,
+                subdevice: device.identifier().to_string(),
+                identity: device.identity(),
+                wkc_errors: device.statistics().wkc_errors,
+                esm_errors: device.statistics().esm_errors,
+            })
+            .collect(),
+    })
+}
+
+fn summarize(results: &[FileResult]) -> BatchSummary {
+    let mut summary = BatchSummary {
+        files: results.len(),
+        analyzed_frames: 0,
+        failed: Vec::new(),
+        errors: BTreeMap::new(),
+        worst_devices: Vec::new(),
+    };
+    let mut devices = Vec::new();
+    for result in results {
+        let analysis = match &result.outcome {
+            Ok(analysis) => analysis,
+            Err(e) => {
+                summary.failed.push(FailedFile {
+                    file: result.name.clone(),
+                    error: format!("{:#}", e),
+                });
+                continue;
+            }
+        };
+        summary.analyzed_frames += analysis.analyzed_frames;
+        for &(category, count) in &analysis.errors {
+            let total = summary.errors.entry(category).or_default();
+            total.count += count;
+            total.files += 1;
+        }
+        devices.extend(
+            analysis
+                .devices
+                .iter()
+                .filter(|device| device.total() > 0)
+                .cloned(),
+        );
+    }
+    devices.sort_by_key(|device| Reverse(device.total()));
+    devices.truncate(WORST_DEVICES);
+    summary.worst_devices = devices;
+    summary
+}
+
+fn print_result(result: &FileResult) {
+    match &result.outcome {
+        Ok(analysis) => {
+            let errors: u64 = analysis.errors.iter().map(|(_, count)| count).sum();
+            let line = format!(
+                "  ✓ {}: {} frames, {} errors in {:.1}s",
+                result.name,
+                analysis.analyzed_frames,
+                errors,
+                result.elapsed.as_secs_f64()
+            );
+            if errors > 0 {
+                println!("{}", style(line).yellow());
+            } else {
+                println!("{}", line);
+            }
+        }
+        Err(e) => println!("{}", style(format!("  ✗ {}: {:#}", result.name, e)).red()),
+    }
+}
+
+fn print_summary(summary: &BatchSummary) {
+    println!();
+    println!(
+        "{}",
+        style(format!(
+            "■ {} files, {} frames analyzed, {} failed",
+            summary.files,
+            summary.analyzed_frames,
+            summary.failed.len()
+        ))
+        .bold()
+    );
+    println!();
+    println!("{}", style("  ■ errors").bold());
+    if summary.errors.is_empty() {
+        println!("    no errors");
+    }
+    for (category, total) in &summary.errors {
+        println!(
+            "    {:<30} {:>8} in {} files",
+            category, total.count, total.files
+        );
+    }
+    if !summary.worst_devices.is_empty() {
+        println!();
+        println!("{}", style("  ■ subdevices with the most errors").bold());
+        for device in &summary.worst_devices {
+            let identity = device
+                .identity
+                .map(|identity| format!(" {}", identity))
+                .unwrap_or_default();
+            println!(
+                "    {:>8}  {} #{} [{}]{} wkc:{} esm:{}",
+                device.total(),
+                device.file,
+                device.position,
+                device.subdevice,
+                identity,
+                device.wkc_errors,
+                device.esm_errors
+            );
+        }
+    }
+}
+
+fn write_summary(path: &Path, summary: &BatchSummary) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, summary)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_totals_errors_and_ranks_devices() {
+        let device = |file: &str, position: usize, wkc_errors: u64| DeviceErrors {
+            file: file.to_string(),
+            position,
+            subdevice: format!("Address {:04x}", 0x1001 + position),
+            identity: None,
+            wkc_errors,
+            esm_errors: 0,
+        };
+        let file = |name: &str, errors: Vec<(&'static str, u64)>, devices| FileResult {
+            name: name.to_string(),
+            outcome: Ok(FileAnalysis {
+                analyzed_frames: 100,
+                errors,
+                devices,
+            }),
+            elapsed: Duration::ZERO,
+        };
+        let results = [
+            file(
+                "a.pcap",
+                vec![("wkc_error", 3)],
+                vec![device("a.pcap", 0, 0), device("a.pcap", 1, 3)],
+            ),
+            file(
+                "b.pcap",
+                vec![("wkc_error", 5)],
+                vec![device("b.pcap", 0, 5)],
+            ),
+            FileResult {
+                name: "c.pcap".to_string(),
+                outcome: Err(anyhow::anyhow!("truncated")),
+                elapsed: Duration::ZERO,
+            },
+        ];
+
+        let summary = summarize(&results);
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.analyzed_frames, 200);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.errors["wkc_error"].count, 8);
+        assert_eq!(summary.errors["wkc_error"].files, 2);
+        let worst: Vec<_> = summary
+            .worst_devices
+            .iter()
+            .map(|device| (device.file.as_str(), device.position))
+            .collect();
+        assert_eq!(worst, [("b.pcap", 0), ("a.pcap", 1)]);
+    }
+}
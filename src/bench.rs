@@ -0,0 +1,210 @@
+//! `ecdump bench FILE`: throughput of the parser, the analyzer and the whole
+//! pipeline on a capture file, to measure performance changes.
+//!
+//! The file is read into memory first, so every stage starts from the same
+//! bytes and disk speed does not count. Each stage runs `--runs` times and the
+//! fastest run is reported.
+
+use anyhow::{Context, Result};
+use bytes::BytesMut;
+use console::style;
+use crossbeam_channel::bounded;
+use ecdump::analysis::{AnalysisOptions, Analyzer, Direction, analyze_reader};
+use ecdump::capture_file;
+use ecdump::ec_packet::ECFrame;
+use pnet::packet::Packet;
+use pnet::packet::ethernet::EthernetPacket;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::heap;
+use crate::packet_source::{self, CapturedData};
+use crate::pipeline::{ParsePipeline, ParsedFrame};
+use crate::startup::AnalysisWindow;
+
+const ETHERTYPE_ETHERCAT: u16 = 0x88a4;
+
+/// `ecdump bench` arguments.
+pub struct Bench {
+    pub path: String,
+    pub runs: u32,
+}
+
+/// Result of the fastest run of a stage.
+struct Measurement {
+    frames: u64,
+    elapsed: Duration,
+    allocations: u64,
+    allocated_bytes: u64,
+}
+
+/// Run the stages on the capture at `bench.path` and print a table. The
+/// pipeline uses `parse_threads` parse workers and `pool_size` buffers like a
+/// normal run.
+pub fn run(bench: &Bench, parse_threads: usize, pool_size: usize) -> Result<()> {
+    let mut capture = Vec::new();
+    crate::compression::open_input(&bench.path)
+        .with_context(|| format!("Failed to open {}", &bench.path))?
+        .read_to_end(&mut capture)
+        .with_context(|| format!("Failed to read {}", &bench.path))?;
+    let capture: Arc<[u8]> = capture.into();
+
+    // EtherCAT frames and their bytes, the same for every stage
+    let mut frames = 0u64;
+    let mut bytes = 0u64;
+    capture_file::read_frames(&capture[..], |_, data| {
+        if is_ethercat(data) {
+            frames += 1;
+            bytes += data.len() as u64;
+        }
+    })?;
+    println!(
+        "{}",
+        style(format!(
+            "■ {}: {} EtherCAT frames, {:.1} MB, best of {} runs",
+            bench.path,
+            frames,
+            bytes as f64 / 1e6,
+            bench.runs
+        ))
+        .bold()
+    );
+
+    let stages: [(&str, &dyn Fn() -> Result<u64>); 3] = [
+        ("parse", &|| parse(&capture)),
+        ("parse + analyze", &|| analyze(&capture)),
+        ("pipeline", &|| {
+            pipeline(capture.clone(), parse_threads, pool_size)
+        }),
+    ];
+    println!(
+        "  {:<16} {:>10} {:>12} {:>9} {:>12} {:>12}",
+        "stage", "frames", "frames/s", "MB/s", "allocs/frame", "bytes/frame"
+    );
+    for (name, stage) in stages {
+        let measurement = measure(bench.runs, stage)?;
+        let seconds = measurement.elapsed.as_secs_f64().max(1e-9);
+        let per_frame = |value: u64| value as f64 / measurement.frames.max(1) as f64;
+        println!(
+            "  {:<16} {:>10} {:>12.0} {:>9.1} {:>12.1} {:>12.0}",
+            name,
+            measurement.frames,
+            measurement.frames as f64 / seconds,
+            bytes as f64 / 1e6 / seconds,
+            per_frame(measurement.allocations),
+            per_frame(measurement.allocated_bytes),
+        );
+    }
+    Ok(())
+}
+
+/// The fastest of `runs` runs of `stage`, which returns the number of frames it
+/// processed.
+fn measure(runs: u32, stage: &dyn Fn() -> Result<u64>) -> Result<Measurement> {
+    let mut best: Option<Measurement> = None;
+    for _ in 0..runs.max(1) {
+        let allocations = heap::allocations();
+        let allocated_bytes = heap::allocated_bytes();
+        let started = Instant::now();
+        let frames = stage()?;
+        let measurement = Measurement {
+            frames,
+            elapsed: started.elapsed(),
+            allocations: heap::allocations() - allocations,
+            allocated_bytes: heap::allocated_bytes() - allocated_bytes,
+        };
+        if best
+            .as_ref()
+            .is_none_or(|best| measurement.elapsed < best.elapsed)
+        {
+            best = Some(measurement);
+        }
+    }
+    Ok(best.expect("at least one run"))
+}
+
+fn is_ethercat(data: &[u8]) -> bool {
+    EthernetPacket::new(data)
+        .is_some_and(|ethernet| ethernet.get_ethertype().0 == ETHERTYPE_ETHERCAT)
+}
+
+/// Decode the capture and parse the datagrams of every EtherCAT frame, as the
+/// parse workers do.
+fn parse(capture: &[u8]) -> Result<u64> {
+    let mut frames = 0;
+    capture_file::read_frames(capture, |_, data| {
+        let Some(ethernet) = EthernetPacket::new(data) else {
+            return;
+        };
+        if ethernet.get_ethertype().0 != ETHERTYPE_ETHERCAT {
+            return;
+        }
+        frames += 1;
+        if let Some(frame) = ECFrame::new(ethernet.payload())
+            && let Ok(datagrams) = frame.parse_datagram()
+        {
+            std::hint::black_box(frame.malformations(&datagrams));
+        }
+    })?;
+    Ok(frames)
+}
+
+/// Decode and analyze the capture in the calling thread, see [`analyze_reader`].
+fn analyze(capture: &[u8]) -> Result<u64> {
+    let report = analyze_reader(capture, AnalysisOptions::default())?;
+    Ok(report.analyzed_frames)
+}
+
+/// Read the capture through the packet source, the parse workers and the
+/// analyzer, as `ecdump -f` does without outputs.
+fn pipeline(capture: Arc<[u8]>, parse_threads: usize, pool_size: usize) -> Result<u64> {
+    let (_abort_tx, abort_rx) = bounded::<bool>(0);
+    let (handle, buffer_pool, rx_data, _rx_status) = packet_source::start_read_pcap(
+        Box::new(Cursor::new(capture)),
+        None,
+        abort_rx,
+        false,
+        AnalysisWindow::default(),
+        pool_size,
+        None,
+    )?;
+    let mut pipeline = ParsePipeline::start(rx_data, parse_threads);
+    let mut analyzer = Analyzer::new(AnalysisOptions::default());
+    while let Ok(ParsedFrame {
+        captured:
+            CapturedData {
+                sequence,
+                data: packet,
+                timestamp,
+                from_main,
+            },
+        valid,
+        malformations,
+    }) = pipeline.receiver().recv()
+    {
+        pipeline.advance();
+        if let Some(frame) = ECFrame::new(packet.as_ref()) {
+            let direction = if from_main {
+                Direction::FromMain
+            } else {
+                Direction::ToMain
+            };
+            std::hint::black_box(analyzer.feed_ethercat(
+                &frame,
+                sequence,
+                timestamp,
+                direction,
+                valid.then_some(&malformations[..]),
+            ));
+        }
+        buffer_pool.put(BytesMut::from(packet));
+    }
+    drop(pipeline);
+    if let Some(handle) = handle {
+        handle
+            .join()
+            .map_err(|_| anyhow::anyhow!("Packet source thread panicked"))?;
+    }
+    Ok(analyzer.device_manager().get_analyzed_frame_count())
+}
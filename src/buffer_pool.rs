@@ -0,0 +1,191 @@
+use bytes::BytesMut;
+use crossbeam_channel::{Receiver as CbReceiver, Sender as CbSender, bounded};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Default number of frame buffers, enough for the capture, writer and parse queues.
+pub const DEFAULT_POOL_SIZE: usize = 1024;
+
+/// Capacity of a pooled buffer: the largest VLAN tagged Ethernet frame, so reused
+/// buffers never have to grow.
+const BUFFER_CAPACITY: usize = 1522;
+
+/// How long `PoolExhaustion::Wait` waits for a buffer to be returned.
+const WAIT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What live capture does when every buffer of the pool is in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PoolExhaustion {
+    /// Allocate a buffer outside the pool; it is freed instead of returned.
+    #[default]
+    Allocate,
+    /// Wait for the analyzer to return a buffer, allocating after 100 ms.
+    Wait,
+    /// Drop the frame before the analyzer. The output file still gets it.
+    Drop,
+}
+
+/// Counters of a buffer pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// Buffers taken from the pool.
+    pub hits: u64,
+    /// Requests that found no free buffer in the pool.
+    pub misses: u64,
+    /// Buffers allocated, inside or outside the pool.
+    pub allocations: u64,
+    /// Misses with every pool buffer in use.
+    pub exhausted: u64,
+}
+
+struct Counters {
+    capacity: usize,
+    exhaustion: PoolExhaustion,
+    allocated: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    allocations: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+/// Fixed-capacity pool of frame buffers shared by a packet source and the threads
+/// returning the buffers once a frame is processed.
+///
+/// Buffers are allocated on demand up to the capacity; the free list never holds
+/// more than that, so extra buffers returned to a full pool are freed.
+#[derive(Clone)]
+pub struct BufferPool {
+    tx_free: CbSender<BytesMut>,
+    rx_free: CbReceiver<BytesMut>,
+    counters: Arc<Counters>,
+}
+
+impl BufferPool {
+    pub fn new(capacity: usize, exhaustion: PoolExhaustion) -> Self {
+        let (tx_free, rx_free) = bounded(capacity);
+        BufferPool {
+            tx_free,
+            rx_free,
+            counters: Arc::new(Counters {
+                capacity,
+                exhaustion,
+                allocated: AtomicUsize::new(0),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                allocations: AtomicU64::new(0),
+                exhausted: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// An empty buffer for a frame of `len` bytes, `None` if the pool is exhausted
+    /// and its policy is to drop the frame.
+    pub fn get(&self, len: usize) -> Option<BytesMut> {
+        if let Some(buffer) = self.take() {
+            return Some(buffer);
+        }
+        if self.reserve() {
+            return Some(self.allocate(len));
+        }
+        self.counters.exhausted.fetch_add(1, Ordering::Relaxed);
+        match self.counters.exhaustion {
+            PoolExhaustion::Allocate => Some(self.allocate(len)),
+            PoolExhaustion::Wait => match self.rx_free.recv_timeout(WAIT_TIMEOUT) {
+                Ok(mut buffer) => {
+                    buffer.clear();
+                    Some(buffer)
+                }
+                Err(_) => Some(self.allocate(len)),
+            },
+            PoolExhaustion::Drop => None,
+        }
+    }
+
+    /// Like `get`, but allocates instead of dropping when the pool is exhausted.
+    /// For file sources and the output file, which must not lose frames.
+    pub fn get_or_allocate(&self, len: usize) -> BytesMut {
+        if let Some(buffer) = self.take() {
+            return buffer;
+        }
+        if !self.reserve() {
+            self.counters.exhausted.fetch_add(1, Ordering::Relaxed);
+        }
+        self.allocate(len)
+    }
+
+    /// Return a buffer to the pool.
+    pub fn put(&self, buffer: BytesMut) {
+        // A full free list means the buffer was allocated outside the pool
+        self.tx_free.try_send(buffer).ok();
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            allocations: self.counters.allocations.load(Ordering::Relaxed),
+            exhausted: self.counters.exhausted.load(Ordering::Relaxed),
+        }
+    }
+
+    fn take(&self) -> Option<BytesMut> {
+        match self.rx_free.try_recv() {
+            Ok(mut buffer) => {
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                buffer.clear();
+                Some(buffer)
+            }
+            Err(_) => {
+                self.counters.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Count a new pool buffer if the pool is below its capacity.
+    fn reserve(&self) -> bool {
+        self.counters
+            .allocated
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |allocated| {
+                (allocated < self.counters.capacity).then_some(allocated + 1)
+            })
+            .is_ok()
+    }
+
+    fn allocate(&self, len: usize) -> BytesMut {
+        self.counters.allocations.fetch_add(1, Ordering::Relaxed);
+        BytesMut::with_capacity(len.max(BUFFER_CAPACITY))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_reuses_buffers_up_to_capacity() {
+        let pool = BufferPool::new(2, PoolExhaustion::Drop);
+        let a = pool.get(60).unwrap();
+        let b = pool.get(60).unwrap();
+        assert!(pool.get(60).is_none());
+
+        pool.put(a);
+        pool.put(b);
+        // Buffers allocated outside the pool do not fit into the free list
+        pool.put(BytesMut::new());
+        assert!(pool.get(60).is_some());
+        assert!(pool.get(60).is_some());
+        assert_eq!(pool.get_or_allocate(60).capacity(), BUFFER_CAPACITY);
+
+        assert_eq!(
+            pool.metrics(),
+            PoolMetrics {
+                hits: 2,
+                misses: 4,
+                allocations: 3,
+                exhausted: 2,
+            }
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Reading pcap and pcapng capture files.
+
+use flate2::read::MultiGzDecoder;
+use log::warn;
+use pcap_file::pcapng::blocks::interface_description::{
+    InterfaceDescriptionBlock, InterfaceDescriptionOption,
+};
+use pcap_file::{PcapError, pcap, pcapng, pcapng::Block as PcapNgBlock};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+/// Timestamp resolution of pcapng interfaces without an `if_tsresol` option (microseconds).
+pub const DEFAULT_TS_RESOLUTION: u8 = 6;
+
+#[derive(Debug)]
+pub enum CaptureFileError {
+    Io(std::io::Error),
+    /// The file is neither pcap nor pcapng.
+    UnknownFormat,
+    Pcap(PcapError),
+}
+
+impl fmt::Display for CaptureFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureFileError::Io(e) => write!(f, "Failed to read the capture file: {}", e),
+            CaptureFileError::UnknownFormat => write!(f, "Not a pcap or pcapng capture file"),
+            CaptureFileError::Pcap(e) => write!(f, "Failed to read the capture file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CaptureFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CaptureFileError::Io(e) => Some(e),
+            CaptureFileError::UnknownFormat => None,
+            CaptureFileError::Pcap(e) => Some(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for CaptureFileError {
+    fn from(error: std::io::Error) -> Self {
+        CaptureFileError::Io(error)
+    }
+}
+
+impl From<PcapError> for CaptureFileError {
+    fn from(error: PcapError) -> Self {
+        CaptureFileError::Pcap(error)
+    }
+}
+
+/// Compression of capture files. Data is compressed and decompressed
+/// in-process, no external tools are needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// From a `.gz` or `.zst` file name extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.to_lowercase();
+        if path.ends_with(".gz") {
+            Some(Compression::Gzip)
+        } else if path.ends_with(".zst") {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_magic(magic: &[u8]) -> Option<Self> {
+        match magic {
+            [0x1f, 0x8b, ..] => Some(Compression::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// `path` without a compression extension, e.g. `trace.pcapng` for `trace.pcapng.gz`.
+    pub fn strip_extension(path: &str) -> &str {
+        match Compression::from_path(path) {
+            Some(compression) => &path[..path.len() - compression.extension().len()],
+            None => path,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+/// Open a capture file for reading, decompressing gzip and zstd files (detected
+/// by their magic bytes) on the fly.
+pub fn open(path: impl AsRef<Path>) -> io::Result<Box<dyn Read + Send>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let len = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(match Compression::from_magic(&magic[..len]) {
+        Some(Compression::Gzip) => Box::new(MultiGzDecoder::new(BufReader::new(file))),
+        Some(Compression::Zstd) => Box::new(zstd::stream::read::Decoder::new(file)?),
+        None => Box::new(file),
+    })
+}
+
+pub fn interface_ts_resolution(idb: &InterfaceDescriptionBlock) -> u8 {
+    idb.options
+        .iter()
+        .find_map(|option| match option {
+            InterfaceDescriptionOption::IfTsResol(resolution) => Some(*resolution),
+            _ => None,
+        })
+        .unwrap_or(DEFAULT_TS_RESOLUTION)
+}
+
+/// pcap-file reads EPB timestamps as nanoseconds regardless of the interface's
+/// `if_tsresol`; rescale the raw value to the actual resolution.
+pub fn scale_pcapng_timestamp(raw: Duration, resolution: u8) -> Duration {
+    let units = raw.as_nanos();
+    let nanos = if resolution & 0x80 != 0 {
+        // Negative power of two
+        let exponent = (resolution & 0x7F).min(64) as u32;
+        (units * 1_000_000_000) >> exponent
+    } else if resolution <= 9 {
+        units * 10_u128.pow(9 - resolution as u32)
+    } else {
+        units / 10_u128.pow((resolution as u32 - 9).min(38))
+    };
+    Duration::from_nanos(nanos.min(u64::MAX as u128) as u64)
+}
+
+/// Whether a capture file starting with `magic` is pcapng (`true`) or pcap
+/// (`false`), in either byte order and, for pcap, microsecond or nanosecond
+/// resolution. `None` for anything else.
+pub fn is_pcapng_magic(magic: [u8; 4]) -> Option<bool> {
+    match u32::from_be_bytes(magic) {
+        0x0A0D_0D0A => Some(true),
+        0xA1B2_C3D4 | 0xD4C3_B2A1 | 0xA1B2_3C4D | 0x4D3C_B2A1 => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether reading stopped in the middle of a frame, e.g. because the capture
+/// was still being written.
+pub fn is_truncated(error: &PcapError) -> bool {
+    match error {
+        PcapError::IncompleteBuffer => true,
+        PcapError::IoError(e) => e.kind() == std::io::ErrorKind::UnexpectedEof,
+        _ => false,
+    }
+}
+
+/// Call `frame` with the absolute timestamp and the data of every packet of a
+/// pcap or pcapng stream; the format is detected from the first bytes. A
+/// truncated file ends at its last complete packet.
+pub fn read_frames(
+    mut reader: impl Read,
+    mut frame: impl FnMut(Duration, &[u8]),
+) -> Result<(), CaptureFileError> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    let is_pcapng = is_pcapng_magic(magic).ok_or(CaptureFileError::UnknownFormat)?;
+    let reader = Cursor::new(magic).chain(reader);
+
+    let result = if is_pcapng {
+        let mut reader = pcapng::PcapNgReader::new(reader)?;
+        // if_tsresol of each interface of the current section
+        let mut ts_resolutions: Vec<u8> = Vec::new();
+        loop {
+            match reader.next_block() {
+                Some(Ok(PcapNgBlock::SectionHeader(_))) => ts_resolutions.clear(),
+                Some(Ok(PcapNgBlock::InterfaceDescription(idb))) => {
+                    ts_resolutions.push(interface_ts_resolution(&idb))
+                }
+                Some(Ok(PcapNgBlock::EnhancedPacket(epb))) => {
+                    let resolution = ts_resolutions
+                        .get(epb.interface_id as usize)
+                        .copied()
+                        .unwrap_or(DEFAULT_TS_RESOLUTION);
+                    frame(scale_pcapng_timestamp(epb.timestamp, resolution), &epb.data);
+                }
+                Some(Ok(PcapNgBlock::Packet(p))) => {
+                    frame(Duration::from_secs(p.timestamp), &p.data)
+                }
+                Some(Ok(PcapNgBlock::SimplePacket(sp))) => frame(Duration::ZERO, &sp.data),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => break Err(e),
+                None => break Ok(()),
+            }
+        }
+    } else {
+        let mut reader = pcap::PcapReader::new(reader)?;
+        loop {
+            match reader.next_packet() {
+                Some(Ok(packet)) => frame(packet.timestamp, &packet.data),
+                Some(Err(e)) => break Err(e),
+                None => break Ok(()),
+            }
+        }
+    };
+    match result {
+        Err(e) if is_truncated(&e) => {
+            warn!("The capture file is truncated, stopping at the last complete frame");
+            Ok(())
+        }
+        result => result.map_err(CaptureFileError::from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_pcapng_timestamp() {
+        let raw = Duration::from_nanos(1_500);
+        assert_eq!(scale_pcapng_timestamp(raw, 6), Duration::from_micros(1_500));
+        assert_eq!(scale_pcapng_timestamp(raw, 9), raw);
+        // 2^-10 s units
+        assert_eq!(
+            scale_pcapng_timestamp(Duration::from_nanos(1024), 0x8A),
+            Duration::from_secs(1)
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use ecdump::analyzer::{ECDeviceError, ECError};
+use std::fmt;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Analyzer events that can trigger the capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TriggerEvent {
+    /// Working counter mismatch
+    WkcError,
+    /// Unexpected or failed state transition
+    EsmError,
+    /// Datagram addressed to a subdevice that does not exist
+    AddressError,
+    /// Undersized frame, length mismatch, non-zero padding or invalid datagram
+    MalformedFrame,
+    /// Bus rescan or change of the number of subdevices
+    BusChange,
+    /// Any error or malformed frame
+    AnyError,
+}
+
+impl TriggerEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TriggerEvent::WkcError => "WKC error",
+            TriggerEvent::EsmError => "ESM error",
+            TriggerEvent::AddressError => "address error",
+            TriggerEvent::MalformedFrame => "malformed frame",
+            TriggerEvent::BusChange => "bus change",
+            TriggerEvent::AnyError => "error",
+        }
+    }
+
+    /// Whether the analysis of one frame produced this event.
+    pub fn occurred(&self, error: Option<&ECError>, malformed: bool, bus_changed: bool) -> bool {
+        let device_error = |matches: fn(&ECDeviceError) -> bool| matches!(error, Some(ECError::DeviceError(errors)) if errors.iter().any(matches));
+        match self {
+            TriggerEvent::WkcError => device_error(|e| matches!(e, ECDeviceError::InvalidWkc(_))),
+            TriggerEvent::EsmError => device_error(|e| matches!(e, ECDeviceError::ESMError(_))),
+            TriggerEvent::AddressError => device_error(|e| {
+                matches!(
+                    e,
+                    ECDeviceError::InvalidAutoIncrementAddress { .. }
+                        | ECDeviceError::InvalidConfiguredAddress { .. }
+                )
+            }),
+            TriggerEvent::MalformedFrame => {
+                malformed || matches!(error, Some(ECError::InvalidDatagram { .. }))
+            }
+            TriggerEvent::BusChange => bus_changed,
+            TriggerEvent::AnyError => malformed || error.is_some(),
+        }
+    }
+}
+
+/// What the output does around trigger events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// `--freeze-on`: write everything up to the first event.
+    Freeze,
+    /// `--start-on`: write from an event on, for `post_trigger` after each event
+    /// (until the end of the capture without it), ending after
+    /// `stop_after_events` events.
+    Start {
+        post_trigger: Option<Duration>,
+        stop_after_events: Option<u64>,
+    },
+}
+
+/// Change of the output caused by a trigger event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerAction {
+    Frozen,
+    Started,
+    Stopped { events: u64 },
+}
+
+impl fmt::Display for TriggerAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerAction::Frozen => write!(f, "output file frozen"),
+            TriggerAction::Started => write!(f, "output started"),
+            TriggerAction::Stopped { events: 1 } => write!(f, "output ends after this event"),
+            TriggerAction::Stopped { events } => {
+                write!(f, "output ends after {} events", events)
+            }
+        }
+    }
+}
+
+struct TriggerState {
+    events: u64,
+    /// Capture time windows written to the output, in order; `None` for an
+    /// open end.
+    windows: Vec<(Duration, Option<Duration>)>,
+    /// Timestamp of the last frame the analyzer is done with.
+    analyzed_until: Option<Duration>,
+    /// The analyzer has stopped; undecided frames are decided with the windows as is.
+    finished: bool,
+}
+
+/// Trigger shared between the analyzer loop, which fires it, and the thread
+/// writing the output file, which holds back frames until the analyzer has
+/// decided on them.
+///
+/// Timestamps are capture-relative, as passed to the analyzer.
+pub struct CaptureTrigger {
+    event: TriggerEvent,
+    mode: TriggerMode,
+    state: Mutex<TriggerState>,
+    finished: Condvar,
+}
+
+impl CaptureTrigger {
+    pub fn new(event: TriggerEvent, mode: TriggerMode) -> Self {
+        let windows = match mode {
+            TriggerMode::Freeze => vec![(Duration::ZERO, None)],
+            TriggerMode::Start { .. } => Vec::new(),
+        };
+        CaptureTrigger {
+            event,
+            mode,
+            state: Mutex::new(TriggerState {
+                events: 0,
+                windows,
+                analyzed_until: None,
+                finished: false,
+            }),
+            finished: Condvar::new(),
+        }
+    }
+
+    pub fn event(&self) -> TriggerEvent {
+        self.event
+    }
+
+    fn state(&self) -> MutexGuard<'_, TriggerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_stopped(&self, state: &TriggerState) -> bool {
+        match self.mode {
+            TriggerMode::Freeze => state.events > 0,
+            TriggerMode::Start {
+                stop_after_events, ..
+            } => stop_after_events.is_some_and(|count| state.events >= count),
+        }
+    }
+
+    /// The trigger event occurred in a frame captured at `timestamp`.
+    pub fn fire(&self, timestamp: Duration) -> Option<TriggerAction> {
+        let mut state = self.state();
+        if self.is_stopped(&state) {
+            return None;
+        }
+        state.events += 1;
+        let stopped = self.is_stopped(&state);
+
+        let TriggerMode::Start { post_trigger, .. } = self.mode else {
+            if let Some(window) = state.windows.last_mut() {
+                window.1 = Some(timestamp);
+            }
+            return Some(TriggerAction::Frozen);
+        };
+
+        let mut end = post_trigger.map(|post_trigger| timestamp + post_trigger);
+        if stopped {
+            end = end.or(Some(timestamp));
+        }
+        match state.windows.last_mut() {
+            Some(window) if window.1.is_none_or(|window_end| window_end >= timestamp) => {
+                if window.1.is_some() || stopped {
+                    window.1 = end;
+                }
+            }
+            _ => state.windows.push((timestamp, end)),
+        }
+
+        if stopped {
+            Some(TriggerAction::Stopped {
+                events: state.events,
+            })
+        } else if state.events == 1 {
+            Some(TriggerAction::Started)
+        } else {
+            None
+        }
+    }
+
+    /// The analyzer is done with all frames up to `timestamp`.
+    pub fn analyzed(&self, timestamp: Duration) {
+        self.state().analyzed_until = Some(timestamp);
+    }
+
+    /// The analyzer has stopped.
+    pub fn finish(&self) {
+        self.state().finished = true;
+        self.finished.notify_all();
+    }
+
+    pub fn wait_finished(&self) {
+        let state = self.state();
+        let _state = self
+            .finished
+            .wait_while(state, |state| !state.finished)
+            .unwrap_or_else(|e| e.into_inner());
+    }
+
+    /// Whether the output is complete once frames up to `timestamp` are analyzed.
+    pub fn is_complete(&self, timestamp: Duration) -> bool {
+        let state = self.state();
+        matches!(self.mode, TriggerMode::Start { .. })
+            && self.is_stopped(&state)
+            && state
+                .windows
+                .last()
+                .and_then(|window| window.1)
+                .is_some_and(|end| timestamp > end)
+    }
+
+    /// Whether a frame captured at `timestamp` is written to the output file, or
+    /// `None` while the analyzer has not reached it yet.
+    pub fn decide(&self, timestamp: Duration) -> Option<bool> {
+        let state = self.state();
+        if !state.finished && state.analyzed_until.is_none_or(|until| timestamp > until) {
+            return None;
+        }
+        let next = state
+            .windows
+            .partition_point(|(start, _)| *start <= timestamp);
+        Some(next > 0 && state.windows[next - 1].1.is_none_or(|end| timestamp <= end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_start_trigger_windows() {
+        let trigger = CaptureTrigger::new(
+            TriggerEvent::WkcError,
+            TriggerMode::Start {
+                post_trigger: Some(ms(10)),
+                stop_after_events: Some(3),
+            },
+        );
+        trigger.analyzed(ms(5));
+        assert_eq!(trigger.decide(ms(5)), Some(false));
+        assert_eq!(trigger.decide(ms(20)), None);
+
+        assert_eq!(trigger.fire(ms(20)), Some(TriggerAction::Started));
+        assert_eq!(trigger.fire(ms(25)), None);
+        assert_eq!(
+            trigger.fire(ms(100)),
+            Some(TriggerAction::Stopped { events: 3 })
+        );
+        assert_eq!(trigger.fire(ms(105)), None);
+        trigger.analyzed(ms(200));
+
+        assert_eq!(trigger.decide(ms(19)), Some(false));
+        assert_eq!(trigger.decide(ms(35)), Some(true));
+        assert_eq!(trigger.decide(ms(36)), Some(false));
+        assert_eq!(trigger.decide(ms(110)), Some(true));
+        assert_eq!(trigger.decide(ms(111)), Some(false));
+        assert!(!trigger.is_complete(ms(110)));
+        assert!(trigger.is_complete(ms(111)));
+    }
+
+    #[test]
+    fn test_freeze_trigger() {
+        let trigger = CaptureTrigger::new(TriggerEvent::AnyError, TriggerMode::Freeze);
+        trigger.analyzed(ms(10));
+        assert_eq!(trigger.decide(ms(10)), Some(true));
+        assert_eq!(trigger.fire(ms(15)), Some(TriggerAction::Frozen));
+        assert_eq!(trigger.fire(ms(16)), None);
+        trigger.finish();
+        assert_eq!(trigger.decide(ms(15)), Some(true));
+        assert_eq!(trigger.decide(ms(16)), Some(false));
+        assert!(!trigger.is_complete(ms(100)));
+    }
+}
@@ -0,0 +1,759 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use log::warn;
+use pcap_file::DataLink;
+use pcap_file::pcap::{self, PcapWriter};
+use pcap_file::pcapng::blocks::enhanced_packet::{EnhancedPacketBlock, EnhancedPacketOption};
+use pcap_file::pcapng::blocks::interface_description::{
+    InterfaceDescriptionBlock, InterfaceDescriptionOption,
+};
+use pcap_file::pcapng::blocks::section_header::SectionHeaderBlock;
+use pcap_file::pcapng::{Block, PcapNgWriter};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::capture_trigger::CaptureTrigger;
+use crate::compression::{Compression, OutputSink};
+use crate::packet_source::ClockSource;
+
+/// EPB flags direction bits (pcapng `epb_flags`, bits 0-1).
+const EPB_FLAG_INBOUND: u32 = 0x01;
+const EPB_FLAG_OUTBOUND: u32 = 0x02;
+
+/// File format of the `-w` output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Pcap,
+    Pcapng,
+}
+
+impl OutputFormat {
+    /// pcapng for `.pcapng` files (also compressed), legacy pcap otherwise.
+    pub fn from_path(path: &str) -> Self {
+        if Compression::strip_extension(path)
+            .to_lowercase()
+            .ends_with(".pcapng")
+        {
+            OutputFormat::Pcapng
+        } else {
+            OutputFormat::Pcap
+        }
+    }
+}
+
+/// Limits after which the `-w` output continues in a new file (`-C`, `-G`, `-W`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// Maximum number of bytes per file, before compression.
+    pub max_size: Option<u64>,
+    /// Maximum capture time spanned by one file.
+    pub interval: Option<Duration>,
+    /// Number of files to keep; older files are deleted.
+    pub max_files: Option<usize>,
+}
+
+/// Number of files a ring buffer is split into.
+const RING_SEGMENTS: u32 = 10;
+
+impl Rotation {
+    /// Ring buffer keeping the most recent `size` bytes and/or `duration` of
+    /// capture time, split into small files so the oldest can be deleted.
+    pub fn ring(size: Option<u64>, duration: Option<Duration>) -> Self {
+        Rotation {
+            max_size: size.map(|size| (size / RING_SEGMENTS as u64).max(1)),
+            interval: duration
+                .map(|duration| duration / RING_SEGMENTS)
+                .filter(|interval| !interval.is_zero()),
+            // The file being written is not full yet
+            max_files: Some(RING_SEGMENTS as usize + 1),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_size.is_some() || self.interval.is_some()
+    }
+}
+
+/// The `-w` output: file name (or name template when rotating), format and rotation.
+pub struct OutputFile {
+    pub path: String,
+    pub format: OutputFormat,
+    pub rotation: Rotation,
+    /// Restricts the output to the frames around trigger events.
+    pub trigger: Option<Arc<CaptureTrigger>>,
+    /// Clock of live captured frames, recorded in pcapng output.
+    pub clock_source: Option<ClockSource>,
+    /// Bytes of each frame kept, `None` for whole frames.
+    pub snaplen: Option<u32>,
+    /// Flush whenever all captured frames are written, for a reader following
+    /// the file live (Wireshark reading the extcap fifo).
+    pub flush_when_idle: bool,
+    /// Comment every pcapng packet with the frame number and capture time the
+    /// analyzer reports it with, see [`frame_comment`].
+    pub frame_comments: bool,
+}
+
+impl OutputFile {
+    pub fn into_capture_writer(self, datalink: DataLink) -> Result<RotatingWriter> {
+        RotatingWriter::open(self, datalink, None)
+    }
+
+    /// pcapng writer continuing the section of a pcapng input file, see
+    /// [`CaptureWriter::with_pcapng_section`].
+    pub fn into_pcapng_copy_writer(
+        self,
+        section: &SectionHeaderBlock<'static>,
+    ) -> Result<RotatingWriter> {
+        RotatingWriter::open(self, DataLink::ETHERNET, Some(InputHeader::Pcapng(section)))
+    }
+
+    /// Writer keeping the header of a pcap input file, so the timestamp resolution
+    /// and byte order are preserved when copying pcap to pcap. pcapng output uses
+    /// the header's link type only.
+    pub fn into_pcap_copy_writer(self, header: pcap::PcapHeader) -> Result<RotatingWriter> {
+        match self.format {
+            OutputFormat::Pcap => {
+                RotatingWriter::open(self, header.datalink, Some(InputHeader::Pcap(header)))
+            }
+            OutputFormat::Pcapng => self.into_capture_writer(header.datalink),
+        }
+    }
+}
+
+/// Header of the input file an output file continues.
+enum InputHeader<'a> {
+    Pcap(pcap::PcapHeader),
+    Pcapng(&'a SectionHeaderBlock<'static>),
+}
+
+/// [`CaptureWriter`] that starts a new file whenever a [`Rotation`] limit is reached.
+///
+/// Without time fields in the name, files are numbered `NAME_00000.EXT`,
+/// `NAME_00001.EXT`, ... With strftime-style fields (e.g. `ecat-%Y%m%d-%H%M%S.pcap`)
+/// the name is expanded with the local time when the file is opened. Every file
+/// starts with the pcap header, or the current pcapng section and interfaces, so
+/// it can be opened on its own.
+pub struct RotatingWriter {
+    template: String,
+    format: OutputFormat,
+    rotation: Rotation,
+    trigger: Option<Arc<CaptureTrigger>>,
+    datalink: DataLink,
+    writer: CaptureWriter,
+    /// Files written so far, oldest first.
+    files: VecDeque<PathBuf>,
+    index: u64,
+    file_size: u64,
+    /// Timestamp of the first packet in the current file.
+    file_start: Option<Duration>,
+    /// Frames the trigger has not decided on yet, in capture order.
+    held: VecDeque<HeldFrame>,
+    flush_when_idle: bool,
+    frame_comments: bool,
+}
+
+/// A frame or pcapng block held back until the analyzer has caught up with it.
+enum HeldFrame {
+    Packet {
+        timestamp: Duration,
+        capture_time: Duration,
+        data: Vec<u8>,
+        orig_len: u32,
+        from_main: Option<bool>,
+        comment: Option<String>,
+    },
+    PacketBlock {
+        block: Block<'static>,
+        timestamp: Duration,
+        capture_time: Duration,
+        comment: Option<String>,
+    },
+    Block(Block<'static>),
+}
+
+impl HeldFrame {
+    /// `None` for blocks that are always written.
+    fn capture_time(&self) -> Option<Duration> {
+        match self {
+            HeldFrame::Packet { capture_time, .. }
+            | HeldFrame::PacketBlock { capture_time, .. } => Some(*capture_time),
+            HeldFrame::Block(_) => None,
+        }
+    }
+}
+
+impl RotatingWriter {
+    fn open(
+        output_file: OutputFile,
+        datalink: DataLink,
+        header: Option<InputHeader>,
+    ) -> Result<Self> {
+        let OutputFile {
+            path: template,
+            format,
+            rotation,
+            trigger,
+            clock_source,
+            snaplen,
+            flush_when_idle,
+            frame_comments,
+        } = output_file;
+        let path = next_file_path(&template, &rotation, 0, &VecDeque::new());
+        let file = create_file(&path)?;
+        let writer = match header {
+            Some(InputHeader::Pcap(header)) => {
+                CaptureWriter::Pcap(PcapWriter::with_header(file, header)?)
+            }
+            Some(InputHeader::Pcapng(section)) => {
+                CaptureWriter::with_pcapng_section(file, section)?
+            }
+            None => CaptureWriter::new(file, format, datalink, snaplen, clock_source)?,
+        };
+        Ok(RotatingWriter {
+            template,
+            format,
+            rotation,
+            trigger,
+            datalink,
+            writer,
+            files: VecDeque::from([path]),
+            index: 0,
+            file_size: 0,
+            file_start: None,
+            held: VecDeque::new(),
+            flush_when_idle,
+            frame_comments,
+        })
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Flush the file if [`OutputFile::flush_when_idle`] is set; called by live
+    /// captures when no more frames are queued.
+    pub fn flush_idle(&mut self) -> Result<()> {
+        if self.flush_when_idle {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write one frame, see [`CaptureWriter::write_packet`]. `sequence` and
+    /// `capture_time` are the frame number and the capture-relative timestamp
+    /// passed to the analyzer, which decides whether the frame is written when
+    /// there is a trigger.
+    pub fn write_packet(
+        &mut self,
+        sequence: u64,
+        timestamp: Duration,
+        capture_time: Duration,
+        data: &[u8],
+        orig_len: u32,
+        from_main: Option<bool>,
+    ) -> Result<()> {
+        let comment = self.comment(sequence, capture_time);
+        if self.trigger.is_none() {
+            return self.write_packet_now(timestamp, data, orig_len, from_main, comment);
+        }
+        self.held.push_back(HeldFrame::Packet {
+            timestamp,
+            capture_time,
+            data: data.to_vec(),
+            orig_len,
+            from_main,
+            comment,
+        });
+        self.release_held()
+    }
+
+    /// Copy a packet block, see [`Self::write_packet`] and [`CaptureWriter::copy_block`].
+    pub fn copy_packet_block(
+        &mut self,
+        block: &Block,
+        sequence: u64,
+        timestamp: Duration,
+        capture_time: Duration,
+    ) -> Result<()> {
+        let comment = self.comment(sequence, capture_time);
+        if self.trigger.is_none() {
+            return self.copy_packet_block_now(block, timestamp, comment);
+        }
+        self.held.push_back(HeldFrame::PacketBlock {
+            block: block.clone().into_owned(),
+            timestamp,
+            capture_time,
+            comment,
+        });
+        self.release_held()
+    }
+
+    /// Copy a non-packet block, see [`CaptureWriter::copy_block`]. Blocks stay in
+    /// order with packets that are held back.
+    pub fn copy_block(&mut self, block: &Block) -> Result<()> {
+        if self.held.is_empty() {
+            self.file_size += self.writer.copy_block(block)? as u64;
+        } else {
+            self.held
+                .push_back(HeldFrame::Block(block.clone().into_owned()));
+        }
+        Ok(())
+    }
+
+    /// Write the frames held back for the trigger once the analyzer has stopped,
+    /// and flush the file.
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(trigger) = &self.trigger {
+            trigger.wait_finished();
+            self.release_held()?;
+        }
+        self.writer.into_inner().finish()?;
+        Ok(())
+    }
+
+    fn comment(&self, sequence: u64, capture_time: Duration) -> Option<String> {
+        (self.frame_comments && self.format == OutputFormat::Pcapng)
+            .then(|| frame_comment(sequence, capture_time))
+    }
+
+    fn write_packet_now(
+        &mut self,
+        timestamp: Duration,
+        data: &[u8],
+        orig_len: u32,
+        from_main: Option<bool>,
+        comment: Option<String>,
+    ) -> Result<()> {
+        self.rotate_if_due(timestamp)?;
+        self.file_size +=
+            self.writer
+                .write_packet(timestamp, data, orig_len, from_main, comment.as_deref())?
+                as u64;
+        Ok(())
+    }
+
+    fn copy_packet_block_now(
+        &mut self,
+        block: &Block,
+        timestamp: Duration,
+        comment: Option<String>,
+    ) -> Result<()> {
+        self.rotate_if_due(timestamp)?;
+        self.file_size += match (block, comment) {
+            (Block::EnhancedPacket(packet), Some(comment)) => {
+                let mut packet = packet.clone();
+                packet
+                    .options
+                    .push(EnhancedPacketOption::Comment(Cow::Owned(comment)));
+                self.writer.copy_block(&Block::EnhancedPacket(packet))?
+            }
+            _ => self.writer.copy_block(block)?,
+        } as u64;
+        Ok(())
+    }
+
+    /// Write or discard the held frames the trigger has decided on, in order.
+    fn release_held(&mut self) -> Result<()> {
+        let Some(trigger) = self.trigger.clone() else {
+            return Ok(());
+        };
+        while let Some(capture_time) = self.held.front().map(HeldFrame::capture_time) {
+            let write = match capture_time {
+                Some(capture_time) => match trigger.decide(capture_time) {
+                    Some(write) => write,
+                    None => break,
+                },
+                None => true,
+            };
+            let Some(frame) = self.held.pop_front() else {
+                break;
+            };
+            if !write {
+                continue;
+            }
+            match frame {
+                HeldFrame::Packet {
+                    timestamp,
+                    data,
+                    orig_len,
+                    from_main,
+                    comment,
+                    ..
+                } => self.write_packet_now(timestamp, &data, orig_len, from_main, comment)?,
+                HeldFrame::PacketBlock {
+                    block,
+                    timestamp,
+                    comment,
+                    ..
+                } => self.copy_packet_block_now(&block, timestamp, comment)?,
+                HeldFrame::Block(block) => {
+                    self.file_size += self.writer.copy_block(&block)? as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn rotate_if_due(&mut self, timestamp: Duration) -> Result<()> {
+        let file_start = *self.file_start.get_or_insert(timestamp);
+        let full = self
+            .rotation
+            .max_size
+            .is_some_and(|max_size| self.file_size >= max_size);
+        let expired = self
+            .rotation
+            .interval
+            .is_some_and(|interval| timestamp.saturating_sub(file_start) >= interval);
+        if !(full || expired) {
+            return Ok(());
+        }
+
+        self.index += 1;
+        let path = next_file_path(&self.template, &self.rotation, self.index, &self.files);
+        let writer = self.writer.reopen(create_file(&path)?, self.datalink)?;
+        std::mem::replace(&mut self.writer, writer)
+            .into_inner()
+            .finish()?;
+        self.file_size = 0;
+        self.file_start = Some(timestamp);
+
+        self.files.push_back(path);
+        if let Some(max_files) = self.rotation.max_files {
+            while self.files.len() > max_files {
+                let Some(oldest) = self.files.pop_front() else {
+                    break;
+                };
+                if let Err(e) = std::fs::remove_file(&oldest) {
+                    warn!(
+                        "Failed to remove old output file {}: {}",
+                        oldest.display(),
+                        e
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create an output file, compressed if its name ends in `.gz` or `.zst`.
+fn create_file(path: &Path) -> Result<OutputSink> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create output file: {}", path.display()))?;
+    OutputSink::create(file, Compression::from_path(&path.to_string_lossy()))
+}
+
+/// Name of the `index`th output file. Without rotation the template is used as is.
+fn next_file_path(
+    template: &str,
+    rotation: &Rotation,
+    index: u64,
+    written: &VecDeque<PathBuf>,
+) -> PathBuf {
+    if !rotation.is_enabled() {
+        return PathBuf::from(template);
+    }
+    let mut expanded = String::new();
+    let timed =
+        template.contains('%') && write!(expanded, "{}", Local::now().format(template)).is_ok();
+    if !timed {
+        return indexed_path(Path::new(template), index);
+    }
+    let path = PathBuf::from(expanded);
+    if written.contains(&path) {
+        // Rotated again within the resolution of the time fields
+        indexed_path(&path, index)
+    } else {
+        path
+    }
+}
+
+/// `dir/NAME.EXT` -> `dir/NAME_00001.EXT`, `dir/NAME.EXT.gz` -> `dir/NAME_00001.EXT.gz`
+fn indexed_path(path: &Path, index: u64) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = Compression::strip_extension(&file_name);
+    let compressed = &file_name[name.len()..];
+    let name = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            format!("{}_{:05}.{}{}", stem, index, extension, compressed)
+        }
+        _ => format!("{}_{:05}{}", name, index, compressed),
+    };
+    path.with_file_name(name)
+}
+
+/// Packet comment naming a frame as the analyzer reports it, e.g.
+/// `ecdump frame #123456 [12.345678s]`, so an event can be found in Wireshark
+/// with `frame.comment contains "#123456 "` even when the output file numbers
+/// its frames differently (rotation, triggers, dropped frames).
+pub fn frame_comment(sequence: u64, capture_time: Duration) -> String {
+    format!(
+        "ecdump frame #{} [{:.6}s]",
+        sequence,
+        capture_time.as_secs_f64()
+    )
+}
+
+/// Writes captured frames as pcap or pcapng.
+///
+/// pcapng output has a single Ethernet interface with nanosecond timestamp
+/// resolution and records the direction of every frame (outbound for frames
+/// sent by the main device, inbound for frames returned by the subdevices).
+pub enum CaptureWriter<W: Write = OutputSink> {
+    Pcap(PcapWriter<W>),
+    PcapNg(PcapNgWriter<W>),
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Frames longer than `snaplen` are truncated. `clock_source` is recorded as
+    /// a comment on the pcapng interface.
+    pub fn new(
+        writer: W,
+        format: OutputFormat,
+        datalink: DataLink,
+        snaplen: Option<u32>,
+        clock_source: Option<ClockSource>,
+    ) -> Result<Self> {
+        let snaplen = snaplen.unwrap_or(0xFFFF);
+        Ok(match format {
+            OutputFormat::Pcap => {
+                let header = pcap::PcapHeader {
+                    datalink,
+                    snaplen,
+                    ..pcap::PcapHeader::default()
+                };
+                CaptureWriter::Pcap(PcapWriter::with_header(writer, header)?)
+            }
+            OutputFormat::Pcapng => {
+                let mut pcapng_writer = PcapNgWriter::new(writer)?;
+                pcapng_writer.write_pcapng_block(InterfaceDescriptionBlock {
+                    linktype: datalink,
+                    snaplen,
+                    options: std::iter::once(InterfaceDescriptionOption::IfTsResol(9))
+                        .chain(clock_source.map(|clock_source| {
+                            InterfaceDescriptionOption::Comment(Cow::Owned(format!(
+                                "timestamps: {} clock",
+                                clock_source.as_str()
+                            )))
+                        }))
+                        .collect(),
+                })?;
+                CaptureWriter::PcapNg(pcapng_writer)
+            }
+        })
+    }
+
+    /// pcapng writer continuing the section of a pcapng input file. Interfaces
+    /// and packets are expected to be copied with [`Self::copy_block`].
+    pub fn with_pcapng_section(writer: W, section: &SectionHeaderBlock<'static>) -> Result<Self> {
+        Ok(CaptureWriter::PcapNg(PcapNgWriter::with_section_header(
+            writer,
+            section.clone(),
+        )?))
+    }
+
+    /// Writer for a new file with the same pcap header, or the same pcapng section
+    /// and interfaces.
+    fn reopen(&self, writer: W, datalink: DataLink) -> Result<Self> {
+        match self {
+            CaptureWriter::Pcap(current) => {
+                let header = pcap::PcapHeader {
+                    datalink,
+                    endianness: current.endianness(),
+                    snaplen: current.snaplen(),
+                    ts_resolution: current.ts_resolution(),
+                    ..pcap::PcapHeader::default()
+                };
+                Ok(CaptureWriter::Pcap(PcapWriter::with_header(
+                    writer, header,
+                )?))
+            }
+            CaptureWriter::PcapNg(current) => {
+                let mut pcapng_writer =
+                    PcapNgWriter::with_section_header(writer, current.section().clone())?;
+                for interface in current.interfaces() {
+                    pcapng_writer.write_pcapng_block(interface.clone())?;
+                }
+                Ok(CaptureWriter::PcapNg(pcapng_writer))
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        match self {
+            CaptureWriter::Pcap(writer) => writer.into_writer(),
+            CaptureWriter::PcapNg(writer) => writer.into_inner(),
+        }
+    }
+
+    /// Flush the buffered pcapng output. The pcap writer does not give access to
+    /// its destination, so pcap output is only flushed when it is finished.
+    pub fn flush(&mut self) -> Result<()> {
+        if let CaptureWriter::PcapNg(writer) = self {
+            writer.get_mut().flush()?;
+        }
+        Ok(())
+    }
+
+    /// Copy a pcapng block unchanged (options, timestamps and interface IDs included).
+    /// Only packet blocks can be written to pcap output; other blocks are skipped.
+    /// Returns the number of bytes written.
+    pub fn copy_block(&mut self, block: &Block) -> Result<usize> {
+        Ok(match self {
+            CaptureWriter::PcapNg(writer) => writer.write_block(block)?,
+            CaptureWriter::Pcap(_) => 0,
+        })
+    }
+
+    /// Largest number of bytes stored per frame.
+    fn snaplen(&self) -> usize {
+        let snaplen = match self {
+            CaptureWriter::Pcap(writer) => writer.snaplen(),
+            CaptureWriter::PcapNg(writer) => writer
+                .interfaces()
+                .first()
+                .map_or(0, |interface| interface.snaplen),
+        };
+        // A snaplen of 0 means unlimited in pcapng
+        if snaplen == 0 {
+            usize::MAX
+        } else {
+            snaplen as usize
+        }
+    }
+
+    /// Write one frame, truncated to the snaplen. `from_main` is the frame
+    /// direction, if known. The comment is only written to pcapng output.
+    /// Returns the number of bytes written.
+    pub fn write_packet(
+        &mut self,
+        timestamp: Duration,
+        data: &[u8],
+        orig_len: u32,
+        from_main: Option<bool>,
+        comment: Option<&str>,
+    ) -> Result<usize> {
+        let data = &data[..data.len().min(self.snaplen())];
+        Ok(match self {
+            CaptureWriter::Pcap(writer) => writer.write_packet(&pcap::PcapPacket {
+                timestamp,
+                orig_len,
+                data: Cow::Borrowed(data),
+            })?,
+            CaptureWriter::PcapNg(writer) => {
+                let mut options = match from_main {
+                    Some(true) => vec![EnhancedPacketOption::Flags(EPB_FLAG_OUTBOUND)],
+                    Some(false) => vec![EnhancedPacketOption::Flags(EPB_FLAG_INBOUND)],
+                    None => Vec::new(),
+                };
+                options.extend(
+                    comment.map(|comment| EnhancedPacketOption::Comment(Cow::Borrowed(comment))),
+                );
+                writer.write_pcapng_block(EnhancedPacketBlock {
+                    interface_id: 0,
+                    timestamp,
+                    original_len: orig_len,
+                    data: Cow::Borrowed(data),
+                    options,
+                })?
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pcap_file::pcapng::{Block, PcapNgReader};
+
+    #[test]
+    fn test_pcapng_keeps_direction_and_nanoseconds() {
+        let mut writer = CaptureWriter::new(
+            Vec::new(),
+            OutputFormat::Pcapng,
+            DataLink::ETHERNET,
+            None,
+            None,
+        )
+        .unwrap();
+        let timestamp = Duration::new(1, 123_456_789);
+        writer
+            .write_packet(timestamp, &[0xAA; 60], 60, Some(true), Some("frame #1"))
+            .unwrap();
+        let CaptureWriter::PcapNg(writer) = writer else {
+            unreachable!();
+        };
+
+        let bytes = writer.into_inner();
+        let mut reader = PcapNgReader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(
+            reader.next_block(),
+            Some(Ok(Block::InterfaceDescription(_)))
+        ));
+        let Some(Ok(Block::EnhancedPacket(epb))) = reader.next_block() else {
+            panic!("expected an enhanced packet block");
+        };
+        assert_eq!(epb.timestamp, timestamp);
+        assert_eq!(
+            epb.options,
+            vec![
+                EnhancedPacketOption::Flags(EPB_FLAG_OUTBOUND),
+                EnhancedPacketOption::Comment(Cow::Borrowed("frame #1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rotation_by_size_keeps_newest_files() {
+        let dir = std::env::temp_dir().join(format!("ecdump-rotation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("capture.pcap");
+        let output_file = OutputFile {
+            path: template.to_string_lossy().into_owned(),
+            format: OutputFormat::Pcap,
+            rotation: Rotation {
+                max_size: Some(100),
+                interval: None,
+                max_files: Some(2),
+            },
+            trigger: None,
+            clock_source: None,
+            snaplen: None,
+            flush_when_idle: false,
+            frame_comments: false,
+        };
+        let mut writer = output_file.into_capture_writer(DataLink::ETHERNET).unwrap();
+        // 16-byte record header + 60 bytes: two frames per file
+        for i in 0..6 {
+            writer
+                .write_packet(
+                    i + 1,
+                    Duration::from_millis(i),
+                    Duration::from_millis(i),
+                    &[0xAA; 60],
+                    60,
+                    None,
+                )
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["capture_00001.pcap", "capture_00002.pcap"]);
+        let size = std::fs::metadata(dir.join("capture_00002.pcap"))
+            .unwrap()
+            .len();
+        assert_eq!(size, 24 + 2 * 76);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+//! Saving the device model at the end of a capture and continuing the analysis
+//! with a later one, so monitoring split across rotated capture files keeps one
+//! device history.
+//!
+//! A [`Checkpoint`] holds the subdevices with their register shadows, identities
+//! and statistics, the earlier bus scans and the init sequence. Frame numbers
+//! continue across captures: the first frame of a capture is numbered after the
+//! last one of the checkpoint.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::analyzer::DeviceScan;
+use crate::init_sequence::InitStep;
+use crate::subdevice::SubDevice;
+
+/// Raised when the checkpoint format changes; older checkpoints are rejected.
+pub const CHECKPOINT_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    /// Written by another version of ecdump.
+    Version(u32),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "Failed to access the checkpoint: {}", e),
+            CheckpointError::Format(e) => write!(f, "Invalid checkpoint: {}", e),
+            CheckpointError::Version(version) => write!(
+                f,
+                "Checkpoint version {} is not supported (expected {})",
+                version, CHECKPOINT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CheckpointError::Io(e) => Some(e),
+            CheckpointError::Format(e) => Some(e),
+            CheckpointError::Version(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(error: std::io::Error) -> Self {
+        CheckpointError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for CheckpointError {
+    fn from(error: serde_json::Error) -> Self {
+        CheckpointError::Format(error)
+    }
+}
+
+/// State of a [`DeviceManager`](crate::analyzer::DeviceManager), taken with
+/// [`DeviceManager::checkpoint`](crate::analyzer::DeviceManager::checkpoint) and
+/// continued with [`DeviceManager::restore`](crate::analyzer::DeviceManager::restore).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub(crate) version: u32,
+    /// Number of the last frame of all captures so far.
+    pub(crate) frames: u64,
+    /// Frames analyzed and skipped in all captures so far.
+    pub(crate) analyzed_frames: u64,
+    pub(crate) skipped_frames: u64,
+    pub(crate) uninitialized: bool,
+    pub(crate) expected_wkc: u16,
+    pub(crate) devices: Vec<SubDevice>,
+    pub(crate) scan_number: u32,
+    pub(crate) scan_start_packet: u64,
+    pub(crate) previous_scans: Vec<DeviceScan>,
+    pub(crate) init_steps: Vec<Vec<InitStep>>,
+    pub(crate) init_complete_packet: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Read a checkpoint written by [`Checkpoint::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CheckpointError> {
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+
+        let data = std::fs::read(path)?;
+        let Version { version } = serde_json::from_slice(&data)?;
+        if version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::Version(version));
+        }
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Write the checkpoint to `path`, replacing it only once it was written
+    /// completely.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CheckpointError> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// The subdevices as of the end of the last capture.
+    pub fn devices(&self) -> &[SubDevice] {
+        &self.devices
+    }
+
+    /// Frames analyzed in all captures so far.
+    pub fn analyzed_frames(&self) -> u64 {
+        self.analyzed_frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{AnalysisOptions, Analyzer, Direction, analyze_file};
+    use crate::analyzer::DeviceManager;
+    use crate::capture_file;
+
+    #[test]
+    fn test_restored_checkpoint_continues_the_device_model() {
+        let capture = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/pdo_mapping.pcap");
+        let report = analyze_file(&capture, AnalysisOptions::default()).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("ecdump-checkpoint-{}.json", std::process::id()));
+
+        report.device_manager().checkpoint().save(&path).unwrap();
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        let mut device_manager = DeviceManager::default();
+        device_manager.restore(checkpoint);
+        assert_eq!(device_manager.summary(), report.device_manager().summary());
+        for (position, device) in report.devices().iter().enumerate() {
+            let address = device.configured_address().unwrap();
+            assert_eq!(
+                device_manager
+                    .device_by_configured_address(address)
+                    .map(|d| d.configured_address()),
+                Some(Some(address)),
+                "subdevice {}",
+                position
+            );
+        }
+
+        let mut checkpoint = report.device_manager().checkpoint();
+        checkpoint.version = CHECKPOINT_VERSION + 1;
+        checkpoint.save(&path).unwrap();
+        assert!(matches!(
+            Checkpoint::load(&path),
+            Err(CheckpointError::Version(version)) if version == CHECKPOINT_VERSION + 1
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_restored_checkpoint_continues_frame_numbers() {
+        let capture = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/pdo_mapping.pcap");
+        let report = analyze_file(&capture, AnalysisOptions::default()).unwrap();
+        let frames = report.device_manager().get_frame_count();
+        assert!(frames > 0);
+
+        let mut device_manager = DeviceManager::default();
+        device_manager.restore(report.device_manager().checkpoint());
+        let mut analyzer = Analyzer::with_device_manager(device_manager);
+        let mut main = None;
+        let mut numbers = Vec::new();
+        let file = File::open(&capture).unwrap();
+        capture_file::read_frames(file, |timestamp, data| {
+            let source = data[6..12].to_vec();
+            let direction = if *main.get_or_insert(source.clone()) == source {
+                Direction::FromMain
+            } else {
+                Direction::ToMain
+            };
+            if let Some(analysis) = analyzer.feed(data, timestamp, direction) {
+                numbers.push(analysis.frame);
+            }
+        })
+        .unwrap();
+
+        assert_eq!(numbers.first(), Some(&(frames + 1)));
+        let second = analyzer.finish();
+        assert_eq!(second.device_manager().get_frame_count(), 2 * frames);
+        assert!(
+            second
+                .transitions
+                .iter()
+                .all(|transition| transition.packet_number > frames)
+        );
+        // The init sequence of the first capture keeps its frame numbers
+        assert_eq!(
+            second.device_manager().init_complete_packet(),
+            report.device_manager().init_complete_packet()
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use anyhow::{Context, Result};
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// Standard output of a child process, read as a stream. The process is killed
+/// when the stream is dropped, as reading may stop before it ends.
+pub struct ChildStream {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl ChildStream {
+    pub fn spawn(command: &mut Command) -> Result<Self> {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let mut child = command
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", program))?;
+        let stdout = child.stdout.take().expect("piped stdout");
+        Ok(ChildStream { child, stdout })
+    }
+}
+
+impl Read for ChildStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+impl Drop for ChildStream {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+use ecdump::capture_file;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+
+pub use ecdump::capture_file::Compression;
+
+/// Open a capture file for reading, decompressing gzip and zstd files on the fly.
+pub fn open_input(path: &str) -> Result<Box<dyn Read + Send>> {
+    Ok(capture_file::open(path)?)
+}
+
+/// Destination of an output file, compressed on the fly if requested.
+pub enum OutputSink {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<File>>),
+}
+
+impl OutputSink {
+    pub fn create(file: File, compression: Option<Compression>) -> Result<Self> {
+        let writer = BufWriter::new(file);
+        Ok(match compression {
+            None => OutputSink::Plain(writer),
+            Some(Compression::Gzip) => {
+                OutputSink::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            Some(Compression::Zstd) => OutputSink::Zstd(
+                zstd::stream::write::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .context("Failed to create zstd encoder")?,
+            ),
+        })
+    }
+
+    /// Flush all data and, for compressed output, write the end of the
+    /// compressed stream. Without this a compressed file is truncated.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            OutputSink::Plain(mut writer) => writer.flush(),
+            OutputSink::Gzip(encoder) => encoder.finish()?.flush(),
+            OutputSink::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for OutputSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputSink::Plain(writer) => writer.write(buf),
+            OutputSink::Gzip(encoder) => encoder.write(buf),
+            OutputSink::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputSink::Plain(writer) => writer.flush(),
+            OutputSink::Gzip(encoder) => encoder.flush(),
+            OutputSink::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
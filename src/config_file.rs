@@ -0,0 +1,173 @@
+//! `ecdump.toml`: default values of command-line options, optionally grouped
+//! into profiles selected with `--profile`.
+//!
+//! ```toml
+//! interface = "eth1"
+//! report = "report.json"
+//!
+//! [profile.commissioning]
+//! expect-devices = 12
+//! watch-reg = ["0x0130", "0x0134"]
+//! inventory = "inventory.csv"
+//! ```
+//!
+//! Keys are long option names (`watch-reg` or `watch_reg`). Values of the file
+//! are turned into arguments placed before the command-line arguments, skipping
+//! options that are given on the command line or conflict with one, so the
+//! command line always wins.
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, Command};
+use std::ffi::OsString;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Config file read when `--config` is not given, if it exists.
+pub const DEFAULT_PATH: &str = "ecdump.toml";
+
+/// Arguments for the values of the config file given with `--config`, or of
+/// [`DEFAULT_PATH`]. `matches` are the command-line arguments alone.
+pub fn arguments(command: &Command, matches: &ArgMatches) -> Result<Vec<OsString>, String> {
+    let path = match matches.get_one::<String>("config") {
+        Some(path) => path.as_str(),
+        None if Path::new(DEFAULT_PATH).exists() => DEFAULT_PATH,
+        None => {
+            return match matches.get_one::<String>("profile") {
+                Some(_) => Err("--profile needs a config file, see --config".to_string()),
+                None => Ok(Vec::new()),
+            };
+        }
+    };
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read the config file {}: {}", path, e))?;
+    let table: Table = text
+        .parse()
+        .map_err(|e| format!("Failed to parse the config file {}: {}", path, e))?;
+    let profile = matches.get_one::<String>("profile").map(String::as_str);
+    config_arguments(command, matches, &table, profile).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Arguments for the values of the profile and the top-level values of `table`.
+/// A value is left out if the command line, or the profile for top-level values,
+/// sets the same option or one that conflicts with it.
+fn config_arguments(
+    command: &Command,
+    matches: &ArgMatches,
+    table: &Table,
+    profile: Option<&str>,
+) -> Result<Vec<OsString>, String> {
+    let mut values: Vec<(&String, &Value)> = Vec::new();
+    if let Some(name) = profile {
+        let profile = table
+            .get("profile")
+            .and_then(|profiles| profiles.get(name))
+            .and_then(Value::as_table)
+            .ok_or_else(|| format!("no profile named '{}'", name))?;
+        values.extend(profile);
+    }
+    values.extend(table.iter().filter(|(key, _)| *key != "profile"));
+
+    let conflicts = |a: &Arg, b: &Arg| {
+        a.get_id() == b.get_id()
+            || command
+                .get_arg_conflicts_with(a)
+                .iter()
+                .chain(&command.get_arg_conflicts_with(b))
+                .any(|conflict| conflict.get_id() == a.get_id() || conflict.get_id() == b.get_id())
+    };
+    // Options set so far, from the command line first
+    let mut set: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect();
+    let mut arguments = Vec::new();
+    for (key, value) in values {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .filter(|arg| !matches!(arg.get_id().as_str(), "config" | "profile"))
+            .ok_or_else(|| format!("unknown option '{}'", key))?;
+        if set.iter().any(|other| conflicts(arg, other)) {
+            continue;
+        }
+        set.push(arg);
+
+        let flag = format!("--{}", long);
+        if !arg.get_action().takes_values() {
+            // Flags: `true`, or the number of times for counted flags such as `verbose`
+            let count = match value {
+                Value::Boolean(set) => *set as i64,
+                Value::Integer(count) => *count,
+                _ => return Err(format!("'{}' must be true or false", key)),
+            };
+            arguments.extend((0..count).map(|_| OsString::from(&flag)));
+            continue;
+        }
+        let items = match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for item in items {
+            let item = match item {
+                Value::String(s) => s.clone(),
+                Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => item.to_string(),
+                _ => return Err(format!("unsupported value of '{}'", key)),
+            };
+            arguments.push(OsString::from(format!("{}={}", flag, item)));
+        }
+    }
+    Ok(arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ArgAction;
+
+    #[test]
+    fn test_config_arguments_skip_command_line_options() {
+        let command = Command::new("ecdump")
+            .arg(Arg::new("interface").long("interface").short('i'))
+            .arg(
+                Arg::new("file")
+                    .long("file")
+                    .short('f')
+                    .conflicts_with("interface"),
+            )
+            .arg(
+                Arg::new("watch_reg")
+                    .long("watch-reg")
+                    .action(ArgAction::Append),
+            )
+            .arg(Arg::new("quiet").long("quiet").action(ArgAction::SetTrue))
+            .arg(Arg::new("profile").long("profile"));
+        let table: Table = r#"
+            interface = "eth1"
+            quiet = true
+            watch_reg = ["0x0130", "0x0134"]
+
+            [profile.lab]
+            file = "lab.pcap"
+        "#
+        .parse()
+        .unwrap();
+
+        let matches = command.clone().get_matches_from(["ecdump"]);
+        assert_eq!(
+            config_arguments(&command, &matches, &table, Some("lab")).unwrap(),
+            [
+                "--file=lab.pcap",
+                "--quiet",
+                "--watch-reg=0x0130",
+                "--watch-reg=0x0134"
+            ]
+        );
+        let matches = command.clone().get_matches_from(["ecdump", "-f", "a.pcap"]);
+        assert_eq!(
+            config_arguments(&command, &matches, &table, None).unwrap(),
+            ["--quiet", "--watch-reg=0x0130", "--watch-reg=0x0134"]
+        );
+        assert!(config_arguments(&command, &matches, &table, Some("office")).is_err());
+    }
+}
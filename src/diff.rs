@@ -0,0 +1,290 @@
+//! `ecdump diff A B`: analyzes two captures and reports how the subdevices, the
+//! init sequence of the main device, the final register configuration and the
+//! errors differ.
+
+use anyhow::{Context, Result};
+use console::style;
+use ecdump::analysis::{AnalysisOptions, AnalysisReport, ErrorStatistics, analyze_reader};
+use ecdump::init_sequence::InitStep;
+use ecdump::subdevice::SubDevice;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::compression;
+use crate::register_dump;
+
+/// Longest init sequences (product of both lengths) compared line by line;
+/// longer ones only report where they start to differ.
+const MAX_SEQUENCE_CELLS: usize = 16_000_000;
+/// Start of the ESC process memory.
+const PROCESS_MEMORY: u16 = 0x1000;
+
+/// Analyze a capture file without printing its events.
+fn analyze(path: &str) -> Result<AnalysisReport> {
+    let file = compression::open_input(path).with_context(|| format!("Failed to open {}", path))?;
+    analyze_reader(file, AnalysisOptions::default())
+        .with_context(|| format!("Failed to read {}", path))
+}
+
+/// Compare the captures at `a` and `b` and print the differences. Returns whether
+/// there were any.
+pub fn run(a: &str, b: &str) -> Result<bool> {
+    let analysis_a = analyze(a)?;
+    let analysis_b = analyze(b)?;
+    let devices_a = analysis_a.devices();
+    let devices_b = analysis_b.devices();
+
+    println!(
+        "{}",
+        style(format!("■ Differences between {} (-) and {} (+)", a, b)).bold()
+    );
+    let mut differences = 0;
+
+    let mut lines = Vec::new();
+    for position in 0..devices_a.len().max(devices_b.len()) {
+        match (devices_a.get(position), devices_b.get(position)) {
+            (Some(device_a), Some(device_b)) => {
+                let (fields_a, fields_b) = (device_fields(device_a), device_fields(device_b));
+                for ((name, value_a), (_, value_b)) in fields_a.iter().zip(&fields_b) {
+                    if value_a != value_b {
+                        lines.push(format!(
+                            "    #{:<3} {}: {} → {}",
+                            position, name, value_a, value_b
+                        ));
+                    }
+                }
+            }
+            (Some(device), None) => {
+                lines.push(removed(format!("#{:<3} {}", position, describe(device))))
+            }
+            (None, Some(device)) => {
+                lines.push(added(format!("#{:<3} {}", position, describe(device))))
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    differences += print_section("subdevices", &lines);
+
+    let mut lines = Vec::new();
+    let sequence_a = analysis_a.device_manager().init_sequence();
+    let sequence_b = analysis_b.device_manager().init_sequence();
+    for position in 0..sequence_a.len().max(sequence_b.len()) {
+        let steps = |sequence: &[Vec<InitStep>]| {
+            sequence
+                .get(position)
+                .map(|steps| {
+                    steps
+                        .iter()
+                        .map(|step| step.action.to_string())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+        let changes = diff_lines(&steps(sequence_a), &steps(sequence_b));
+        if !changes.is_empty() {
+            lines.push(format!("    #{}", position));
+            lines.extend(changes);
+        }
+    }
+    differences += print_section("init sequence", &lines);
+
+    let mut lines = Vec::new();
+    for (position, (device_a, device_b)) in devices_a.iter().zip(devices_b).enumerate() {
+        let (registers_a, registers_b) = (configuration(device_a), configuration(device_b));
+        let addresses: BTreeSet<_> = registers_a.keys().chain(registers_b.keys()).collect();
+        let mut changes = Vec::new();
+        for address in addresses {
+            let (line_a, line_b) = (registers_a.get(address), registers_b.get(address));
+            if line_a.map(|line| &line.1) == line_b.map(|line| &line.1) {
+                continue;
+            }
+            let name = line_a.or(line_b).and_then(|line| line.0).unwrap_or("-");
+            let value = |line: Option<&(Option<&str>, String)>| {
+                line.map(|line| line.1.clone())
+                    .unwrap_or_else(|| "not written".to_string())
+            };
+            changes.push(format!(
+                "      {:#06x} {:<28} {} → {}",
+                address,
+                name,
+                value(line_a),
+                value(line_b)
+            ));
+        }
+        if !changes.is_empty() {
+            lines.push(format!("    #{}", position));
+            lines.extend(changes);
+        }
+    }
+    differences += print_section("register configuration", &lines);
+
+    let mut lines = Vec::new();
+    let counts_a: BTreeMap<_, _> = analysis_a.errors.iter().collect();
+    let counts_b: BTreeMap<_, _> = analysis_b.errors.iter().collect();
+    let categories: BTreeSet<_> = counts_a.keys().chain(counts_b.keys()).collect();
+    for category in categories {
+        let count = |counts: &BTreeMap<&str, &ErrorStatistics>| {
+            counts
+                .get(category)
+                .map_or(0, |statistics| statistics.count)
+        };
+        let (count_a, count_b) = (count(&counts_a), count(&counts_b));
+        if count_a != count_b {
+            lines.push(format!("    {:<30} {} → {}", category, count_a, count_b));
+        }
+    }
+    differences += print_section("errors", &lines);
+
+    if differences == 0 {
+        println!("{}", style("■ No differences").green().bold());
+    }
+    Ok(differences > 0)
+}
+
+/// Print a section with its differences, and return their number.
+fn print_section(title: &str, lines: &[String]) -> usize {
+    if lines.is_empty() {
+        return 0;
+    }
+    println!();
+    println!("{}", style(format!("  ■ {}", title)).bold());
+    for line in lines {
+        println!("{}", line);
+    }
+    lines.len()
+}
+
+fn removed(line: String) -> String {
+    style(format!("    - {}", line)).red().to_string()
+}
+
+fn added(line: String) -> String {
+    style(format!("    + {}", line)).green().to_string()
+}
+
+fn describe(device: &SubDevice) -> String {
+    match device.identity() {
+        Some(identity) => format!("{} {}", device.identifier(), identity),
+        None => device.identifier().to_string(),
+    }
+}
+
+/// The compared properties of a subdevice, `-` where unknown.
+fn device_fields(device: &SubDevice) -> Vec<(&'static str, String)> {
+    let known = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let identity = device.identity();
+    let esc = device.esc_info();
+    vec![
+        (
+            "configured address",
+            known(device.configured_address().map(|a| format!("{:#06x}", a))),
+        ),
+        (
+            "alias",
+            known(device.configured_alias().map(|a| format!("{:#06x}", a))),
+        ),
+        (
+            "vendor",
+            known(identity.map(|i| format!("{:#010x}", i.vendor_id))),
+        ),
+        (
+            "product",
+            known(identity.map(|i| format!("{:#010x}", i.product_code))),
+        ),
+        (
+            "revision",
+            known(
+                identity
+                    .and_then(|i| i.revision)
+                    .map(|r| format!("{:#010x}", r)),
+            ),
+        ),
+        (
+            "serial",
+            known(
+                identity
+                    .and_then(|i| i.serial_number)
+                    .map(|s| s.to_string()),
+            ),
+        ),
+        (
+            "ESC type",
+            known(esc.map(|esc| format!("{:#04x}", esc.esc_type))),
+        ),
+        ("final state", device.state().to_string()),
+    ]
+}
+
+/// Registers written by the main device, by address with name and value. The
+/// process memory from 0x1000 holds mailbox and process data, not configuration.
+fn configuration(device: &SubDevice) -> BTreeMap<u16, (Option<&'static str>, String)> {
+    register_dump::dump_lines(device)
+        .into_iter()
+        .filter(|line| line.address < PROCESS_MEMORY)
+        .filter(|line| line.source.iter().any(|source| source == "wr"))
+        .map(|line| (line.address, (line.name, line.display_value())))
+        .collect()
+}
+
+/// Lines only in `a` (`-`) and only in `b` (`+`), from their longest common
+/// subsequence.
+fn diff_lines(a: &[String], b: &[String]) -> Vec<String> {
+    if a == b {
+        return Vec::new();
+    }
+    if a.len() * b.len() > MAX_SEQUENCE_CELLS {
+        let index = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+        return vec![format!(
+            "      sequences of {} and {} steps differ from step {}",
+            a.len(),
+            b.len(),
+            index
+        )];
+    }
+
+    // lengths[i][j]: longest common subsequence of a[i..] and b[j..]
+    let width = b.len() + 1;
+    let mut lengths = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i * width + j] = if a[i] == b[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if j == b.len()
+            || (i < a.len() && lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+        {
+            lines.push(removed(format!("  {}", a[i])));
+            i += 1;
+        } else {
+            lines.push(added(format!("  {}", b[j])));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines_reports_changed_steps() {
+        console::set_colors_enabled(false);
+        let a = ["A", "B", "C"].map(String::from);
+        let b = ["A", "X", "C", "D"].map(String::from);
+        assert_eq!(
+            diff_lines(&a, &b),
+            ["    -   B", "    +   X", "    +   D"].map(String::from)
+        );
+        assert!(diff_lines(&a, &a).is_empty());
+    }
+}
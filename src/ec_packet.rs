@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use std::fmt;
+use std::ops::Index;
 
 #[derive(Debug)]
 pub enum ECPacketError {
@@ -16,6 +18,52 @@ impl fmt::Display for ECPacketError {
     }
 }
 
+/// Minimum Ethernet frame size without FCS (destination, source, EtherType and 46 payload bytes).
+pub const ETHERNET_MIN_FRAME_SIZE: usize = 60;
+/// Ethernet header size without VLAN tag.
+pub const ETHERNET_HEADER_SIZE: usize = 14;
+
+/// A framing deviation that does not prevent parsing the frame but may confuse subdevices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameMalformation {
+    /// The Ethernet frame is shorter than 60 bytes (FCS excluded).
+    Undersized { frame_size: usize },
+    /// The "more" flags of the datagrams do not describe the datagrams covered by
+    /// the EtherCAT length header.
+    LengthMismatch {
+        header_length: u16,
+        /// Length of the datagrams up to the first one with the "more" flag cleared.
+        datagram_length: u16,
+    },
+    /// Bytes after the last datagram are not zero.
+    NonZeroPadding { offset: usize, length: usize },
+}
+
+impl fmt::Display for FrameMalformation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameMalformation::Undersized { frame_size } => write!(
+                f,
+                "Ethernet frame of {} bytes is below the {}-byte minimum",
+                frame_size, ETHERNET_MIN_FRAME_SIZE
+            ),
+            FrameMalformation::LengthMismatch {
+                header_length,
+                datagram_length,
+            } => write!(
+                f,
+                "EtherCAT length header {} does not match datagram chain length {}",
+                header_length, datagram_length
+            ),
+            FrameMalformation::NonZeroPadding { offset, length } => write!(
+                f,
+                "{} non-zero padding byte(s) after last datagram at offset {}",
+                length, offset
+            ),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ECFrame<'a> {
     total_length: u16,
@@ -64,7 +112,7 @@ pub enum ECAddress {
     Logical(u32),                    // Logical Addressing
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ECCommand(u8);
 impl ECCommand {
     pub fn as_str(&self) -> &'static str {
@@ -94,7 +142,47 @@ pub struct ECDatagrams<'a> {
 }
 
 impl<'a> ECDatagrams<'a> {
-    pub fn iter(&self) -> impl Iterator<Item = &ECDatagram<'a>> {
+    pub fn iter(&self) -> std::slice::Iter<'_, ECDatagram<'a>> {
+        self.inner.iter()
+    }
+
+    /// Number of datagrams in the frame, at least one once parsed.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// The datagram at `index` (0 = first in the frame), if the frame has one.
+    pub fn get(&self, index: usize) -> Option<&ECDatagram<'a>> {
+        self.inner.get(index)
+    }
+}
+
+impl<'a> Index<usize> for ECDatagrams<'a> {
+    type Output = ECDatagram<'a>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.inner[index]
+    }
+}
+
+impl<'a> IntoIterator for ECDatagrams<'a> {
+    type Item = ECDatagram<'a>;
+    type IntoIter = smallvec::IntoIter<[ECDatagram<'a>; 1]>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.into_iter()
+    }
+}
+
+impl<'s, 'a> IntoIterator for &'s ECDatagrams<'a> {
+    type Item = &'s ECDatagram<'a>;
+    type IntoIter = std::slice::Iter<'s, ECDatagram<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
         self.inner.iter()
     }
 }
@@ -167,6 +255,44 @@ impl<'a> ECFrame<'a> {
         }
         Ok(ECDatagrams { inner: datagrams })
     }
+
+    /// Check Ethernet minimum size, the EtherCAT length header against the
+    /// datagram "more" chain and the padding after the last datagram.
+    pub fn malformations(&self, datagrams: &ECDatagrams) -> SmallVec<[FrameMalformation; 2]> {
+        let mut malformations = SmallVec::new();
+
+        let frame_size = ETHERNET_HEADER_SIZE + 2 + self.payload.len();
+        if frame_size < ETHERNET_MIN_FRAME_SIZE {
+            malformations.push(FrameMalformation::Undersized { frame_size });
+        }
+
+        let mut datagram_length = 0;
+        for datagram in datagrams {
+            datagram_length += 10 + datagram.length + 2;
+            if !datagram.has_more() {
+                break;
+            }
+        }
+        if datagram_length != self.total_length {
+            malformations.push(FrameMalformation::LengthMismatch {
+                header_length: self.total_length,
+                datagram_length,
+            });
+        }
+
+        let padding = self
+            .payload
+            .get(self.total_length as usize..)
+            .unwrap_or_default();
+        if let Some(first) = padding.iter().position(|b| *b != 0) {
+            malformations.push(FrameMalformation::NonZeroPadding {
+                offset: 2 + self.total_length as usize + first,
+                length: padding.iter().filter(|b| **b != 0).count(),
+            });
+        }
+
+        malformations
+    }
 }
 
 impl<'a> ECDatagram<'a> {
@@ -217,6 +343,10 @@ impl<'a> ECDatagram<'a> {
     pub fn address(&self) -> (u16, u16) {
         (self.adp, self.ado)
     }
+    /// The 32-bit logical address of LRD/LWR/LRW datagrams.
+    pub fn logical_address(&self) -> u32 {
+        ((self.ado as u32) << 16) | self.adp as u32
+    }
     pub fn length(&self) -> u16 {
         self.length
     }
@@ -319,3 +449,147 @@ pub mod ECCommands {
     pub const ARMW: ECCommand = ECCommand(0x0D); // Auto Increment Physical Read Modify Write
     pub const FRMW: ECCommand = ECCommand(0x0E); // Configured Address Physical Read Modify Write
 }
+
+/// Builds EtherCAT frames datagram by datagram, e.g. to test code that
+/// analyzes them.
+#[derive(Debug, Clone, Default)]
+pub struct ECFrameBuilder {
+    datagrams: Vec<u8>,
+    /// Offset of the length field of the last datagram, which announces the next one.
+    last_length: Option<usize>,
+}
+
+impl ECFrameBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a datagram addressed with `adp` and `ado`, carrying `data` and
+    /// the working counter `wkc`.
+    pub fn datagram(
+        mut self,
+        command: ECCommand,
+        adp: u16,
+        ado: u16,
+        data: &[u8],
+        wkc: u16,
+    ) -> Self {
+        if let Some(offset) = self.last_length {
+            self.datagrams[offset + 1] |= 0x80;
+        }
+        self.datagrams.extend_from_slice(&[command.0, 0]);
+        self.datagrams.extend_from_slice(&adp.to_le_bytes());
+        self.datagrams.extend_from_slice(&ado.to_le_bytes());
+        self.last_length = Some(self.datagrams.len());
+        self.datagrams
+            .extend_from_slice(&(data.len() as u16).to_le_bytes());
+        self.datagrams.extend_from_slice(&[0, 0]);
+        self.datagrams.extend_from_slice(data);
+        self.datagrams.extend_from_slice(&wkc.to_le_bytes());
+        self
+    }
+
+    /// The EtherCAT frame, starting with the EtherCAT header.
+    pub fn build(&self) -> Vec<u8> {
+        let mut frame = (0x1000 | self.datagrams.len() as u16)
+            .to_le_bytes()
+            .to_vec();
+        frame.extend_from_slice(&self.datagrams);
+        frame
+    }
+
+    /// The EtherCAT frame in an Ethernet frame from 02:00:00:00:00:01 to the
+    /// broadcast address.
+    pub fn build_ethernet(&self) -> Vec<u8> {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 1, 0x88, 0xa4]);
+        frame.extend_from_slice(&self.build());
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An EtherCAT frame with a datagram of `length` zero data bytes for each
+    /// `(command, length)`.
+    fn frame(datagrams: &[(ECCommand, usize)]) -> Vec<u8> {
+        datagrams
+            .iter()
+            .fold(ECFrameBuilder::new(), |builder, &(command, length)| {
+                builder.datagram(command, 0, 0, &vec![0; length], 0)
+            })
+            .build()
+    }
+
+    fn malformations(data: &[u8]) -> Vec<FrameMalformation> {
+        let frame = ECFrame::new(data).unwrap();
+        let datagrams = frame.parse_datagram().unwrap();
+        frame.malformations(&datagrams).into_vec()
+    }
+
+    /// Pad an EtherCAT frame to the Ethernet minimum size.
+    fn padded(mut frame: Vec<u8>) -> Vec<u8> {
+        frame.resize(ETHERNET_MIN_FRAME_SIZE - ETHERNET_HEADER_SIZE, 0);
+        frame
+    }
+
+    #[test]
+    fn test_clean_padded_frame() {
+        let data = padded(frame(&[(ECCommands::BRD, 2)]));
+        assert!(malformations(&data).is_empty());
+    }
+
+    #[test]
+    fn test_undersized_frame() {
+        let data = frame(&[(ECCommands::BRD, 2)]);
+        assert_eq!(
+            malformations(&data),
+            [FrameMalformation::Undersized { frame_size: 30 }]
+        );
+    }
+
+    #[test]
+    fn test_length_header_against_more_chain() {
+        // The header covers two datagrams, but the first one ends the chain
+        let mut data = padded(frame(&[(ECCommands::BRD, 2), (ECCommands::BRD, 2)]));
+        data[2 + 7] &= 0x7F;
+        assert_eq!(
+            malformations(&data),
+            [FrameMalformation::LengthMismatch {
+                header_length: 28,
+                datagram_length: 14,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_non_zero_padding() {
+        let mut data = padded(frame(&[(ECCommands::BRD, 2)]));
+        data[20] = 0xAA;
+        data[23] = 0x55;
+        assert_eq!(
+            malformations(&data),
+            [FrameMalformation::NonZeroPadding {
+                offset: 20,
+                length: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_datagrams_index_and_iterate() {
+        let data = frame(&[(ECCommands::BRD, 2), (ECCommands::LRW, 4)]);
+        let frame = ECFrame::new(&data).unwrap();
+        let datagrams = frame.parse_datagram().unwrap();
+        assert_eq!(datagrams.len(), 2);
+        assert_eq!(datagrams[0].command(), ECCommands::BRD);
+        assert_eq!(datagrams[1].command(), ECCommands::LRW);
+
+        let borrowed: Vec<_> = (&datagrams).into_iter().map(|d| d.length()).collect();
+        assert_eq!(borrowed, [2, 4]);
+        let owned: Vec<_> = datagrams.into_iter().map(|d| d.command()).collect();
+        assert_eq!(owned, [ECCommands::BRD, ECCommands::LRW]);
+    }
+}
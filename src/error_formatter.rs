@@ -1,25 +1,64 @@
 use console::{Color, Style, Term, measure_text_width, style};
+use std::collections::BTreeMap;
 use std::time::Duration;
 
-use crate::analyzer::{
-    AlStatusCodeUpdate, ECDeviceError, ECError, ErrorCorrelation, StateTransition, WkcErrorDetail,
+use ecdump::analysis::{ErrorCounts, ErrorStatistics};
+use ecdump::analyzer::{
+    AlStatusCodeUpdate, BusSizeChange, DeviceScan, ECDeviceError, ECError, ErrorAcknowledgement,
+    ErrorCorrelation, Evictions, FirmwareUpdate, LogicalAddressEvent, LogicalAddressIssue,
+    MalformedFrame, Rescan, StateTransition, WkcErrorDetail,
 };
-use ecdump::ec_packet::ECPacketError;
-use ecdump::registers::format_al_status_code;
-use ecdump::subdevice::SubdeviceIdentifier;
+use ecdump::ec_packet::{ECFrame, ECPacketError};
+use ecdump::messages::{Lang, Msg, format_al_status_code, text};
+use ecdump::pdo::PdoSignal;
+use ecdump::register_watch::RegisterChange;
+use ecdump::subdevice::{ECState, SubDevice, SubDeviceStatistics, SubdeviceIdentifier};
+use ecdump::topology::TopologyMismatch;
+
+use crate::buffer_pool::PoolMetrics;
+use crate::capture_trigger::{TriggerAction, TriggerEvent};
+use crate::event_renderer::{self, EventLine};
+use crate::packet_printer;
+use crate::packet_source::{ClockSource, NetworkInterfaceInfo, PacketSourceError};
+use crate::script::ScriptWarning;
+use crate::self_stats::SelfStatsReport;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum VerboseLevel {
     Nothing = 0,  // 何も出力しない
     Normal = 1,   // 基本的なエラー情報
-    Detailed = 2, // 詳細なエラー情報
+    Frames = 2,   // エラー情報と各フレームの概要
+    Detailed = 3, // 詳細なエラー情報と全データグラムのデコード
+}
+
+/// How events are printed, see `--console`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConsoleMode {
+    /// `terminal` when standard output is a terminal, `plain` otherwise
+    Auto,
+    /// Colors, and repeated events and late AL Status Codes update earlier lines
+    Terminal,
+    /// No escape sequences: repeats are summarized in a line of their own, and a
+    /// status block is printed every `--status-interval`
+    Plain,
+}
+
+impl ConsoleMode {
+    pub fn is_plain(self) -> bool {
+        match self {
+            ConsoleMode::Auto => !Term::stdout().is_term(),
+            ConsoleMode::Terminal => false,
+            ConsoleMode::Plain => true,
+        }
+    }
 }
 
 impl VerboseLevel {
+    /// Level for the number of `-v` flags. Errors are reported without any.
     pub fn from_u8(level: u8) -> Self {
         match level {
-            0 => VerboseLevel::Nothing,
-            1 => VerboseLevel::Normal,
+            0 => VerboseLevel::Normal,
+            1 => VerboseLevel::Frames,
             _ => VerboseLevel::Detailed,
         }
     }
@@ -34,8 +73,28 @@ struct EventSignature {
     base_message: String,
 }
 
+/// Subdevice and AL Status Code of an emitted ESM error.
+type EsmInfo = (SubdeviceIdentifier, Option<u16>);
+
+/// Figures of the capture shown at the top of the summary.
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureSummary {
+    pub analyzed_frames: u64,
+    pub elapsed: Duration,
+    /// Frames lost before the analyzer, see `--backpressure` and `--pool-exhaustion`.
+    pub dropped_frames: u64,
+    pub pool: PoolMetrics,
+    pub clock_source: Option<ClockSource>,
+    /// Data the analyzer dropped to stay within `--analyzer-memory`.
+    pub evictions: Evictions,
+}
+
 pub struct ErrorFormatter {
     verbose: VerboseLevel,
+    /// Never move the cursor, see [`ConsoleMode::Plain`].
+    plain: bool,
+    /// `--lang` of the event messages.
+    lang: Lang,
     term: Term,
     /// The signature of the most recently displayed event line.
     last_event: Option<EventSignature>,
@@ -64,9 +123,11 @@ pub struct ErrorFormatter {
 }
 
 impl ErrorFormatter {
-    pub fn new(verbose_level: u8) -> Self {
+    pub fn new(verbose: VerboseLevel, plain: bool, lang: Lang) -> Self {
         ErrorFormatter {
-            verbose: VerboseLevel::from_u8(verbose_level),
+            verbose,
+            plain,
+            lang,
             term: Term::stdout(),
             last_event: None,
             repeat_count: 0,
@@ -85,6 +146,34 @@ impl ErrorFormatter {
 
     // ─── Public API: called during capture ───
 
+    /// Print an analyzed frame: a summary line at `-v`, every datagram with the
+    /// registers it accesses at `-vv`. Printed before the frame's events, which
+    /// start a new line instead of updating an earlier repeated event.
+    pub fn report_frame(
+        &mut self,
+        frame: &ECFrame,
+        frame_number: u64,
+        timestamp: Duration,
+        from_main: bool,
+        error: Option<&ECError>,
+    ) {
+        match self.verbose {
+            VerboseLevel::Nothing | VerboseLevel::Normal => return,
+            VerboseLevel::Frames => println!(
+                "{}",
+                packet_printer::format_summary(frame, frame_number, timestamp, from_main, error)
+            ),
+            VerboseLevel::Detailed => {
+                for line in
+                    packet_printer::format_detail(frame, frame_number, timestamp, from_main, error)
+                {
+                    println!("{}", line);
+                }
+            }
+        }
+        self.flush_repeat();
+    }
+
     /// Report AL Status Code updates for devices with pending ESM errors.
     /// If the last displayed event was an ESM error for the given subdevice,
     /// the output will be rewritten to include the updated AL Status Code.
@@ -138,8 +227,175 @@ impl ErrorFormatter {
         }
     }
 
-    /// Print a final summary line with frame count (called after capture ends).
-    pub fn print_summary(&mut self, total_frames: u64) {
+    /// Report completed AL Status error acknowledge sequences.
+    pub fn report_error_acknowledgements(&mut self, acks: &[ErrorAcknowledgement]) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+
+        for ack in acks {
+            self.emit_error_acknowledgement(ack);
+        }
+    }
+
+    /// Report completed firmware update sessions (Bootstrap + FoE + re-initialization).
+    pub fn report_firmware_updates(&mut self, updates: &[FirmwareUpdate]) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+
+        for update in updates {
+            self.emit_firmware_update(update);
+        }
+    }
+
+    /// Report value changes of registers selected with `--watch-reg`.
+    /// Printed regardless of the verbosity level since watches are requested explicitly.
+    pub fn report_register_changes(&mut self, changes: &[RegisterChange]) {
+        for change in changes {
+            self.emit_register_change(change);
+        }
+    }
+
+    /// Print a status block with the frame and error counts and the states of
+    /// the subdevices so far, every `--status-interval` in plain mode.
+    pub fn print_status(
+        &mut self,
+        analyzed_frames: u64,
+        dropped_frames: u64,
+        errors: &ErrorCounts,
+        devices: &[SubDevice],
+    ) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+        self.flush_repeat();
+
+        let mut states = BTreeMap::new();
+        for device in devices {
+            *states.entry(device.state()).or_insert(0) += 1;
+        }
+        let error_list = errors
+            .iter()
+            .map(|(category, statistics)| format!("{} {}", category, statistics.count))
+            .collect::<Vec<_>>();
+        let state_list = states
+            .iter()
+            .map(|(state, count)| format!("{} {}", state, count))
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            style(format!(
+                "  ■ status {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+            ))
+            .bold()
+        );
+        println!(
+            "    frames     {} analyzed, {} dropped",
+            analyzed_frames, dropped_frames
+        );
+        println!(
+            "    errors     {}",
+            if error_list.is_empty() {
+                "none".to_string()
+            } else {
+                error_list.join(", ")
+            }
+        );
+        println!(
+            "    subdevices {}{}",
+            devices.len(),
+            if state_list.is_empty() {
+                String::new()
+            } else {
+                format!(": {}", state_list.join(", "))
+            }
+        );
+    }
+
+    /// Print that `--replay` sent all frames of its file.
+    pub fn print_replay_done(&mut self, frames: u64) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+        self.flush_repeat();
+        println!("{}", style(format!("■ Replayed {} frames", frames)).bold());
+    }
+
+    /// Print the `--self-stats` measurements of the last interval.
+    pub fn print_self_stats(&mut self, report: &SelfStatsReport) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+        self.flush_repeat();
+        let micros = |time: Option<Duration>| {
+            time.map_or("-".to_string(), |time| {
+                format!("{:.1}µs", time.as_secs_f64() * 1e6)
+            })
+        };
+        println!(
+            "{}",
+            style(format!(
+                "  ■ self-stats (last {:.1}s)",
+                report.elapsed.as_secs_f64()
+            ))
+            .bold()
+        );
+        println!(
+            "    frames     {} analyzed ({:.0}/s), {} dropped",
+            report.analyzed_frames,
+            report.analyzed_frames as f64 / report.elapsed.as_secs_f64().max(1e-9),
+            report.dropped_frames
+        );
+        let source = if report.source_capacity == 0 {
+            "unbuffered".to_string()
+        } else {
+            format!("{}/{}", report.source_queue_peak, report.source_capacity)
+        };
+        println!(
+            "    queues     source {}, parsed {}/{} (peak)",
+            source, report.parsed_queue_peak, report.parsed_capacity
+        );
+        println!(
+            "    pool       {}",
+            report
+                .pool_hit_rate
+                .map_or("no requests".to_string(), |rate| {
+                    format!("{:.1}% hits", rate * 100.0)
+                })
+        );
+        println!(
+            "    per frame  parse {}, analyze {}, outputs {}",
+            micros(report.parse_per_frame),
+            micros(report.analyze_per_frame),
+            micros(report.outputs_per_frame)
+        );
+        if let Some(bottleneck) = report.bottleneck {
+            println!(
+                "{}",
+                style(format!("    drops in   {}", bottleneck.as_str())).yellow()
+            );
+        }
+    }
+
+    /// Print a final summary with frame count, errors by category and
+    /// per-subdevice statistics (called after capture ends).
+    pub fn print_summary(
+        &mut self,
+        capture: &CaptureSummary,
+        errors: &ErrorCounts,
+        previous_scans: &[DeviceScan],
+        devices: &[SubDevice],
+    ) {
+        let CaptureSummary {
+            analyzed_frames: total_frames,
+            elapsed,
+            dropped_frames,
+            pool,
+            clock_source,
+            evictions,
+        } = *capture;
         if self.verbose == VerboseLevel::Nothing {
             return;
         }
@@ -151,11 +407,409 @@ impl ErrorFormatter {
         println!("{}", style("  ■ capture complete").green().bold());
         println!(
             "{}",
-            style(format!("    {} frames analyzed", total_frames)).color256(244)
+            style(format!(
+                "    {} frames analyzed in {:.3}s ({:.0} frames/s)",
+                total_frames,
+                elapsed.as_secs_f64(),
+                total_frames as f64 / elapsed.as_secs_f64().max(1e-9)
+            ))
+            .color256(244)
         );
+        if let Some(clock_source) = clock_source {
+            println!(
+                "{}",
+                style(format!(
+                    "    timestamps from the {} clock",
+                    clock_source.as_str()
+                ))
+                .color256(244)
+            );
+        }
+        if dropped_frames > 0 {
+            println!(
+                "{}",
+                style(format!(
+                    "    {} frames dropped (analyzer could not keep up)",
+                    dropped_frames
+                ))
+                .yellow()
+            );
+        }
+        let pool_line = format!(
+            "    buffer pool: {} reused, {} allocated, {} misses",
+            pool.hits, pool.allocations, pool.misses
+        );
+        if pool.exhausted > 0 {
+            println!(
+                "{}",
+                style(format!(
+                    "{}, exhausted {} times (raise --pool-size)",
+                    pool_line, pool.exhausted
+                ))
+                .yellow()
+            );
+        } else {
+            println!("{}", style(pool_line).color256(244));
+        }
+        if !evictions.is_empty() {
+            println!(
+                "{}",
+                style(format!(
+                    "    memory budget: evicted {} register pages ({} values), {} scans, {} init steps (raise --analyzer-memory)",
+                    evictions.register_pages,
+                    evictions.register_values,
+                    evictions.scans,
+                    evictions.init_steps
+                ))
+                .yellow()
+            );
+        }
+        println!();
+        if errors.is_empty() {
+            println!("{}", style("    no errors").green());
+        }
+        for (category, statistics) in errors.iter() {
+            println!("{}", Self::format_error_count_line(category, statistics));
+        }
+        // One section per scan when the main device rescanned the bus
+        for scan in previous_scans {
+            println!();
+            println!(
+                "{}",
+                style(format!(
+                    "    scan #{} (frames #{}-#{})",
+                    scan.number, scan.start_packet, scan.end_packet
+                ))
+                .bold()
+            );
+            self.print_device_lines(&scan.devices);
+        }
+        if let Some(last) = previous_scans.last() {
+            println!();
+            println!(
+                "{}",
+                style(format!(
+                    "    scan #{} (frames #{}-#{})",
+                    last.number + 1,
+                    last.end_packet + 1,
+                    total_frames
+                ))
+                .bold()
+            );
+        } else if !devices.is_empty() {
+            println!();
+        }
+        self.print_device_lines(devices);
         self.print_heavy_separator();
     }
 
+    fn print_device_lines(&self, devices: &[SubDevice]) {
+        for (position, device) in devices.iter().enumerate() {
+            println!(
+                "{}",
+                Self::format_device_statistics_line(
+                    position,
+                    device.identifier(),
+                    device.state(),
+                    device.statistics(),
+                )
+            );
+            if let Some(identity) = device.identity() {
+                println!("{}", style(format!("         {}", identity)).color256(244));
+            }
+            if self.verbose >= VerboseLevel::Detailed {
+                for signal in device.pdo_signals() {
+                    println!("{}", Self::format_signal_line(&signal));
+                }
+            }
+        }
+    }
+
+    /// Print the result of the expected topology check. Printed regardless of the
+    /// verbosity level since the check is requested explicitly.
+    pub fn print_topology_check(&mut self, mismatches: &[TopologyMismatch]) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+        self.flush_repeat();
+
+        if mismatches.is_empty() {
+            println!(
+                "{}",
+                style("  ■ topology matches expectation").green().bold()
+            );
+            return;
+        }
+
+        println!("{}", style("  ■ topology mismatch").red().bold());
+        let format_address = |address: Option<u16>| match address {
+            Some(address) => format!("{:#06x}", address),
+            None => "-".to_string(),
+        };
+        for mismatch in mismatches {
+            let line = match mismatch {
+                TopologyMismatch::DeviceCount { expected, actual } => {
+                    format!("device count: expected {}, found {}", expected, actual)
+                }
+                TopologyMismatch::Address {
+                    position,
+                    expected,
+                    actual,
+                } => format!(
+                    "#{:<3} expected {}, found {}",
+                    position,
+                    format_address(*expected),
+                    format_address(*actual)
+                ),
+            };
+            println!("    {} {}", style("└─").red(), line);
+        }
+    }
+
+    /// Report that the main device restarted its bus scan.
+    pub fn report_rescans(&mut self, rescans: &[Rescan]) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+
+        for rescan in rescans {
+            let key = format!("rescan:{}", rescan.scan_number);
+            let detail = format!(
+                "bus rescan #{}: {} -> {} subdevices, device model reset",
+                rescan.scan_number, rescan.previous_device_count, rescan.device_count
+            );
+            let color = if rescan.device_count == rescan.previous_device_count {
+                Color::Cyan
+            } else {
+                Color::Yellow
+            };
+            let msg = Self::format_event_line(
+                "SCAN",
+                None,
+                &detail,
+                Some(rescan.packet_number),
+                Some(rescan.timestamp),
+                color,
+            );
+            self.emit_event(key, msg, rescan.packet_number, rescan.timestamp);
+        }
+    }
+
+    pub fn report_bus_size_changes(&mut self, changes: &[BusSizeChange]) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+
+        for change in changes {
+            let (verb, color) = if change.device_count < change.previous_device_count {
+                ("shrank", Color::Red)
+            } else {
+                ("grew", Color::Yellow)
+            };
+            let key = format!(
+                "bus-size:{}:{}",
+                change.previous_device_count, change.device_count
+            );
+            let detail = format!(
+                "bus {}: {} -> {} subdevices (BRD WKC), device model re-synchronized",
+                verb, change.previous_device_count, change.device_count
+            );
+            let msg = Self::format_event_line(
+                "BUS",
+                None,
+                &detail,
+                Some(change.packet_number),
+                Some(change.timestamp),
+                color,
+            );
+            self.emit_event(key, msg, change.packet_number, change.timestamp);
+        }
+    }
+
+    /// Report a change of the output caused by `--freeze-on` or `--start-on`.
+    /// Shown at every verbosity level.
+    pub fn report_capture_trigger(
+        &mut self,
+        event: TriggerEvent,
+        action: TriggerAction,
+        packet_number: u64,
+        timestamp: Duration,
+    ) {
+        let detail = format!("{} triggered: {}", event.as_str(), action);
+        let msg = Self::format_event_line(
+            "TRIGGER",
+            None,
+            &detail,
+            Some(packet_number),
+            Some(timestamp),
+            Color::Magenta,
+        );
+        self.emit_event(
+            format!("trigger:{:?}", action),
+            msg,
+            packet_number,
+            timestamp,
+        );
+    }
+
+    /// Report the `warn` messages of the `--script`. Shown at every verbosity
+    /// level since the script is given explicitly.
+    pub fn report_script_warnings(&mut self, warnings: &[ScriptWarning]) {
+        for warning in warnings {
+            let msg = Self::format_event_line(
+                "SCRIPT",
+                None,
+                &warning.message,
+                Some(warning.packet_number),
+                Some(warning.timestamp),
+                Color::Yellow,
+            );
+            self.emit_event(
+                format!("script:{}", warning.message),
+                msg,
+                warning.packet_number,
+                warning.timestamp,
+            );
+        }
+    }
+
+    /// Print the counters of the `--script` at the end of the run.
+    pub fn print_script_counters(&mut self, counters: &[(String, i64)]) {
+        if self.verbose == VerboseLevel::Nothing || counters.is_empty() {
+            return;
+        }
+        self.flush_repeat();
+
+        println!("{}", style("  ■ script counters").bold());
+        for (name, value) in counters {
+            println!("    {:<24} {}", name, value);
+        }
+    }
+
+    /// Report the capture interface going down or coming back up. Shown at every
+    /// verbosity level, since frames are missing in between.
+    pub fn report_link_change(&mut self, up: bool, packet_number: u64, timestamp: Duration) {
+        let (detail, color) = if up {
+            ("Link up, capture resumed", Color::Green)
+        } else {
+            (
+                "Link down, no frames are captured until it returns",
+                Color::Red,
+            )
+        };
+        let msg = Self::format_event_line(
+            "LINK",
+            None,
+            detail,
+            Some(packet_number),
+            Some(timestamp),
+            color,
+        );
+        self.emit_event(format!("link:{}", up), msg, packet_number, timestamp);
+    }
+
+    /// Report a record of the capture file that could not be analyzed. Shown at
+    /// every verbosity level, since the frame is missing from the analysis.
+    pub fn report_skipped_record(
+        &mut self,
+        error: &PacketSourceError,
+        packet_number: u64,
+        timestamp: Duration,
+    ) {
+        let msg = Self::format_event_line(
+            "SOURCE",
+            None,
+            &error.to_string(),
+            Some(packet_number),
+            Some(timestamp),
+            Color::Yellow,
+        );
+        self.emit_event(format!("source:{}", error), msg, packet_number, timestamp);
+    }
+
+    pub fn report_logical_address_issues(&mut self, events: &[LogicalAddressEvent]) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+
+        let direction = |read: bool, write: bool| match (read, write) {
+            (true, true) => "in/out",
+            (true, false) => "in",
+            _ => "out",
+        };
+        for event in events {
+            let (key, detail) = match &event.issue {
+                LogicalAddressIssue::Conflict {
+                    first,
+                    second,
+                    conflict,
+                } => (
+                    format!(
+                        "logical:conflict:{}:{}:{}:{}",
+                        conflict.first.subdevice,
+                        conflict.first.fmmu,
+                        conflict.second.subdevice,
+                        conflict.second.fmmu
+                    ),
+                    format!(
+                        "[{}] FMMU{} ({}) overlaps [{}] FMMU{} ({}) at {:#010x}..{:08x}",
+                        first,
+                        conflict.first.fmmu,
+                        direction(conflict.first.read, conflict.first.write),
+                        second,
+                        conflict.second.fmmu,
+                        direction(conflict.second.read, conflict.second.write),
+                        conflict.overlap.start,
+                        conflict.overlap.end - 1
+                    ),
+                ),
+                LogicalAddressIssue::Unmapped { command, range } => (
+                    format!("logical:unmapped:{}:{}", range.start, range.end),
+                    format!(
+                        "{} {:#010x}..{:08x} not mapped by any subdevice",
+                        command.as_str(),
+                        range.start,
+                        range.end - 1
+                    ),
+                ),
+            };
+            let msg = Self::format_event_line(
+                "LOGIC",
+                None,
+                &detail,
+                Some(event.packet_number),
+                Some(event.timestamp),
+                Color::Yellow,
+            );
+            self.emit_event(key, msg, event.packet_number, event.timestamp);
+        }
+    }
+
+    pub fn report_malformed_frames(&mut self, frames: &[MalformedFrame]) {
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
+
+        for frame in frames {
+            let direction = if frame.from_main { "out" } else { "in" };
+            // Soft-master framing bugs repeat on every frame; collapse them per kind
+            let key = format!(
+                "malformed:{:?}",
+                std::mem::discriminant(&frame.malformation)
+            );
+            let detail = format!("malformed {} frame: {}", direction, frame.malformation);
+            let msg = Self::format_event_line(
+                "FRAME",
+                None,
+                &detail,
+                Some(frame.packet_number),
+                Some(frame.timestamp),
+                Color::Yellow,
+            );
+            self.emit_event(key, msg, frame.packet_number, frame.timestamp);
+        }
+    }
+
     // ─── Event emission ───
 
     fn emit_datagram_error(
@@ -166,8 +820,9 @@ impl ErrorFormatter {
     ) {
         let detail = error.to_string();
         let key = format!("datagram:{}", detail);
-        let msg = Self::format_tagged_line(
+        let msg = Self::format_event_line(
             "FRAME",
+            None,
             &detail,
             Some(packet_number),
             Some(timestamp),
@@ -176,6 +831,37 @@ impl ErrorFormatter {
         self.emit_event(key, msg, packet_number, timestamp);
     }
 
+    /// Subdevice addressed by a WKC error, or its physical position when the
+    /// subdevice is not known.
+    fn wkc_subdevice(d: &WkcErrorDetail) -> Option<String> {
+        match (d.subdevice_id, d.position) {
+            (Some(SubdeviceIdentifier::Unknown) | None, Some(position)) => {
+                Some(format!("position {}", position))
+            }
+            (Some(id), _) => Some(id.to_string()),
+            (None, None) => None,
+        }
+    }
+
+    /// Command and registers of a WKC error, with the physical position when the
+    /// subdevice is shown by its address.
+    fn wkc_access(d: &WkcErrorDetail) -> String {
+        let registers = if d.length == 1 {
+            format!("{:#06x}", d.register)
+        } else {
+            format!("{:#06x}..{:04x}", d.register, d.register + d.length - 1)
+        };
+        match (d.subdevice_id, d.position) {
+            (Some(id), Some(position)) if id != SubdeviceIdentifier::Unknown => format!(
+                "{} {} @ position {}",
+                d.command.as_str(),
+                registers,
+                position
+            ),
+            _ => format!("{} {}", d.command.as_str(), registers),
+        }
+    }
+
     fn emit_device_error(&mut self, error: &ECDeviceError, correlations: &[ErrorCorrelation]) {
         // A new device error is being emitted — clear ESM tracking
         // (it will be re-set below if this error is itself an ESM error)
@@ -187,22 +873,25 @@ impl ErrorFormatter {
             u64,
             Duration,
             Option<WkcErrorDetail>,
-            Option<(SubdeviceIdentifier, Option<u16>)>,
+            Option<EsmInfo>,
         ) = match error {
             ECDeviceError::InvalidAutoIncrementAddress {
                 packet_number,
                 timestamp,
                 command,
                 address,
+                position,
             } => {
                 let key = format!("addr:auto_inc:{:#06x}:{}", address, command.as_str());
                 let detail = format!(
-                    "{} auto-increment {:#06x} not found",
+                    "{} auto-increment {:#06x} (position {}) not found",
                     command.as_str(),
-                    address
+                    address,
+                    position
                 );
-                let msg = Self::format_tagged_line(
+                let msg = Self::format_event_line(
                     "ADDR",
+                    None,
                     &detail,
                     Some(*packet_number),
                     Some(*timestamp),
@@ -218,8 +907,9 @@ impl ErrorFormatter {
             } => {
                 let key = format!("addr:config:{:#06x}:{}", address, command.as_str());
                 let detail = format!("{} configured {:#06x} not found", command.as_str(), address);
-                let msg = Self::format_tagged_line(
+                let msg = Self::format_event_line(
                     "ADDR",
+                    None,
                     &detail,
                     Some(*packet_number),
                     Some(*timestamp),
@@ -228,35 +918,24 @@ impl ErrorFormatter {
                 (key, msg, *packet_number, *timestamp, None, None)
             }
             ECDeviceError::InvalidWkc(d) => {
-                let sub = d
-                    .subdevice_id
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| "—".to_string());
-                let cause = Self::wkc_cause_short(d.expected, d.actual);
+                let cause = Self::wkc_cause_short(self.lang, d.expected, d.actual);
                 let key = format!(
-                    "wkc:{}:{}:{}:{}:{}",
+                    "wkc:{}:{}:{:?}:{:?}:{}:{}",
                     d.command.as_str(),
                     d.register,
-                    sub,
+                    d.subdevice_id,
+                    d.position,
                     d.expected,
                     d.actual
                 );
-                let reg_str = if d.length == 1 {
-                    format!("{:#06x}", d.register)
-                } else {
-                    format!("{:#06x}..{:04x}", d.register, d.register + d.length - 1)
-                };
-                let detail = format!(
-                    "[{}] {} {}; expected:{} actual:{} ({})",
-                    sub,
-                    d.command.as_str(),
-                    reg_str,
-                    d.expected,
-                    d.actual,
-                    cause,
+                let detail = text(
+                    self.lang,
+                    Msg::WkcDetail,
+                    &[&Self::wkc_access(d), &d.expected, &d.actual, &cause],
                 );
-                let msg = Self::format_tagged_line(
+                let msg = Self::format_event_line(
                     "WKC",
+                    Self::wkc_subdevice(d),
                     &detail,
                     Some(d.packet_number),
                     Some(d.timestamp),
@@ -265,7 +944,7 @@ impl ErrorFormatter {
                 (key, msg, d.packet_number, d.timestamp, None, None)
             }
             ECDeviceError::ESMError(d) => {
-                let esm_short = Self::esm_error_short(&d.error);
+                let esm_short = Self::esm_error_short(self.lang, &d.error);
                 // Include the correlated WKC error in the dedup key so that
                 // the same ESM+WKC pair collapses together.
                 let corr = Self::find_correlation_for_esm(d, correlations);
@@ -274,9 +953,10 @@ impl ErrorFormatter {
                     d.subdevice_id,
                     std::mem::discriminant(&d.error)
                 );
-                let detail = format!("[{}] {}; {}", d.subdevice_id, d.command.as_str(), esm_short);
-                let msg = Self::format_tagged_line(
+                let detail = format!("{}; {}", d.command.as_str(), esm_short);
+                let msg = Self::format_event_line(
                     "ESM",
+                    Some(d.subdevice_id.to_string()),
                     &detail,
                     Some(d.packet_number),
                     Some(d.timestamp),
@@ -294,39 +974,36 @@ impl ErrorFormatter {
         // Print sub-lines only for the first occurrence (not during repeats)
         let mut sub_lines_count: usize = 0;
         if self.repeat_count <= 1 {
-            // Show correlated WKC error as a sub-line (same format as WKC error display)
+            // Show the correlated WKC error as a sub-line
             if let Some(ref c) = corr {
-                let sub = c
-                    .subdevice_id
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| "—".to_string());
-                let cause = Self::wkc_cause_short(c.expected, c.actual);
-                let wkc_detail = format!(
-                    "[{}] {}; expected:{} actual:{} ({})",
-                    sub,
-                    c.command.as_str(),
-                    c.expected,
-                    c.actual,
-                    cause,
-                );
-                let wkc_sub_line = Self::format_tagged_line(
-                    "WKC",
-                    &wkc_detail,
-                    Some(c.packet_number),
-                    Some(c.timestamp),
-                    Color::Red,
-                );
-                println!("{} {}", style("         └─").color256(244), wkc_sub_line);
-                sub_lines_count +=
-                    self.count_terminal_lines(&format!("         └─ {}", wkc_sub_line));
+                let wkc_line = event_renderer::sub_line(&format!(
+                    "WKC #{} [{:.6}s] {}{}",
+                    c.packet_number,
+                    c.timestamp.as_secs_f64(),
+                    Self::wkc_subdevice(c)
+                        .map(|sub| format!("{} ", sub))
+                        .unwrap_or_default(),
+                    text(
+                        self.lang,
+                        Msg::WkcDetail,
+                        &[
+                            &Self::wkc_access(c),
+                            &c.expected,
+                            &c.actual,
+                            &Self::wkc_cause_short(self.lang, c.expected, c.actual),
+                        ],
+                    ),
+                ));
+                sub_lines_count += self.count_terminal_lines(&wkc_line);
+                println!("{}", wkc_line);
             }
 
             // In Detailed mode, also print the diagnosis on a separate line
             if self.verbose >= VerboseLevel::Detailed {
-                let diagnosis = error.diagnosis();
-                let diag_line = format!("         └─ {}", diagnosis);
+                let diagnosis = error.diagnosis_in(self.lang);
+                let diag_line = event_renderer::sub_line(&diagnosis);
                 sub_lines_count += self.count_terminal_lines(&diag_line);
-                println!("{}", style(&diag_line).color256(244));
+                println!("{}", diag_line);
             }
 
             // For ESM errors, print AL Status Code sub-line if available
@@ -340,12 +1017,13 @@ impl ErrorFormatter {
                 self.last_esm_sub_lines = sub_lines_count;
 
                 if let Some(code) = al_code {
-                    let al_line = format!(
-                        "         └─ AL Status Code: {}",
-                        format_al_status_code(code)
-                    );
+                    let al_line = event_renderer::sub_line(&text(
+                        self.lang,
+                        Msg::AlStatusCode,
+                        &[&format_al_status_code(self.lang, code)],
+                    ));
                     let lines = self.count_terminal_lines(&al_line);
-                    println!("{}", style(&al_line).color256(244));
+                    println!("{}", al_line);
                     self.last_esm_al_status_code = Some(code);
                     self.last_al_status_lines = lines;
                     self.last_esm_sub_lines += lines;
@@ -356,23 +1034,24 @@ impl ErrorFormatter {
 
     /// Rewrite (or append) the AL Status Code sub-line for the last ESM error.
     fn rewrite_al_status_code_line(&mut self, code: u16) {
-        let al_line = format!(
-            "         └─ AL Status Code: {}",
-            format_al_status_code(code)
-        );
+        let al_line = event_renderer::sub_line(&text(
+            self.lang,
+            Msg::AlStatusCode,
+            &[&format_al_status_code(self.lang, code)],
+        ));
         let new_lines = self.count_terminal_lines(&al_line);
 
-        if self.last_al_status_lines > 0 {
+        if self.last_al_status_lines > 0 && !self.plain {
             // There is an existing AL Status Code line — overwrite it
             let _ = self.term.clear_last_lines(self.last_al_status_lines);
-            println!("{}", style(&al_line).color256(244));
+            println!("{}", al_line);
             let _ = self.term.flush();
             // Update the line count difference in sub_lines
             self.last_esm_sub_lines =
                 self.last_esm_sub_lines - self.last_al_status_lines + new_lines;
         } else {
             // No existing AL Status Code line — append a new one
-            println!("{}", style(&al_line).color256(244));
+            println!("{}", al_line);
             let _ = self.term.flush();
             self.last_esm_sub_lines += new_lines;
         }
@@ -405,9 +1084,10 @@ impl ErrorFormatter {
             style("->").red().to_string()
         };
 
-        let detail = format!("[{}] {} {} {}", tr.subdevice_id, tr.from, arrow, tr.to);
-        let msg = Self::format_tagged_line(
+        let detail = format!("{} {} {}", tr.from, arrow, tr.to);
+        let msg = Self::format_event_line(
             "STATE",
+            Some(tr.subdevice_id.to_string()),
             &detail,
             Some(tr.packet_number),
             Some(tr.timestamp),
@@ -416,9 +1096,124 @@ impl ErrorFormatter {
         self.emit_event(key, msg, tr.packet_number, tr.timestamp);
     }
 
+    fn emit_error_acknowledgement(&mut self, ack: &ErrorAcknowledgement) {
+        let key = format!("error_ack:{}", ack.subdevice_id);
+        let seq = &ack.sequence;
+
+        let handshake = match seq.ack_packet {
+            Some(ack_packet) => text(
+                self.lang,
+                Msg::ErrorAcknowledged,
+                &[&seq.error_packet, &ack_packet, &seq.cleared_packet],
+            ),
+            None => text(
+                self.lang,
+                Msg::ErrorClearedWithoutAck,
+                &[&seq.error_packet, &seq.cleared_packet],
+            ),
+        };
+        let mut detail = handshake;
+        if let Some(code) = seq.al_status_code {
+            detail.push_str(&text(
+                self.lang,
+                Msg::AlStatusCodeSuffix,
+                &[&format_al_status_code(self.lang, code)],
+            ));
+        }
+        let color = if seq.ack_packet.is_some() {
+            Color::Cyan
+        } else {
+            Color::Yellow
+        };
+        let msg = Self::format_event_line(
+            "ACK",
+            Some(ack.subdevice_id.to_string()),
+            &detail,
+            Some(ack.packet_number),
+            Some(ack.timestamp),
+            color,
+        );
+        self.emit_event(key, msg, ack.packet_number, ack.timestamp);
+    }
+
+    fn emit_register_change(&mut self, change: &RegisterChange) {
+        let key = format!(
+            "register_change:{}:{}:{}:{}",
+            change.subdevice_id, change.watch.address, change.shadow, change.packet_number
+        );
+        let name = change
+            .watch
+            .name()
+            .map(|name| format!(" {}", name))
+            .unwrap_or_default();
+        let value = match &change.old {
+            Some(old) => format!("{} -> {}", old, change.new),
+            None => format!("= {}", change.new),
+        };
+        let detail = format!(
+            "{:#06x}{} ({}) {}",
+            change.watch.address, name, change.shadow, value
+        );
+        let msg = Self::format_event_line(
+            "WATCH",
+            Some(change.subdevice_id.to_string()),
+            &detail,
+            Some(change.packet_number),
+            Some(change.timestamp),
+            Color::Magenta,
+        );
+        self.emit_event(key, msg, change.packet_number, change.timestamp);
+    }
+
+    fn emit_firmware_update(&mut self, update: &FirmwareUpdate) {
+        let key = format!(
+            "firmware_update:{}:{}",
+            update.subdevice_id, update.packet_number
+        );
+        let session = &update.session;
+
+        let mut detail = format!("boot #{}", session.enter_packet);
+        detail.push_str(&format!(
+            " -> FoE {}{} B written, {} B read in {} packets",
+            session
+                .file_name
+                .as_ref()
+                .map(|name| format!("\"{}\" ", name))
+                .unwrap_or_default(),
+            session.bytes_written,
+            session.bytes_read,
+            session.data_packets
+        ));
+        match session.reboot_packet {
+            Some(packet) => detail.push_str(&format!(" -> reboot #{}", packet)),
+            None => detail.push_str(" -> no reboot"),
+        }
+        if let Some(packet) = session.reinit_packet {
+            detail.push_str(&format!(" -> reinit #{}", packet));
+        }
+        if session.foe_errors > 0 {
+            detail.push_str(&format!(", {} FoE errors", session.foe_errors));
+        }
+        let color = if session.foe_errors > 0 || session.reinit_packet.is_none() {
+            Color::Yellow
+        } else {
+            Color::Cyan
+        };
+
+        let msg = Self::format_event_line(
+            "FW",
+            Some(update.subdevice_id.to_string()),
+            &detail,
+            Some(update.packet_number),
+            Some(update.timestamp),
+            color,
+        );
+        self.emit_event(key, msg, update.packet_number, update.timestamp);
+    }
+
     /// Find a correlation that matches this ESM error (same subdevice, same ESM error).
     fn find_correlation_for_esm(
-        esm: &crate::analyzer::ESMErrorDetail,
+        esm: &ecdump::analyzer::ESMErrorDetail,
         correlations: &[ErrorCorrelation],
     ) -> Option<WkcErrorDetail> {
         correlations
@@ -435,24 +1230,32 @@ impl ErrorFormatter {
     /// Emit a single event. If the same event key was just displayed, overwrite
     /// the last line with an updated repeat count instead of printing a new line.
     fn emit_event(&mut self, key: String, base_message: String, frame: u64, ts: Duration) {
+        // Also the events shown at every level: standard output belongs to the
+        // JSON event stream or Wireshark
+        if self.verbose == VerboseLevel::Nothing {
+            return;
+        }
         let sig = EventSignature {
             key,
             base_message: base_message.clone(),
         };
 
-        if let Some(ref last) = self.last_event {
-            if last.key == sig.key {
-                // Same event repeating — increment count and overwrite last line
-                self.repeat_count += 1;
-                self.repeat_last_frame = frame;
-                self.repeat_last_ts = ts;
+        if let Some(ref last) = self.last_event
+            && last.key == sig.key
+        {
+            // Same event repeating — increment count and overwrite last line
+            self.repeat_count += 1;
+            self.repeat_last_frame = frame;
+            self.repeat_last_ts = ts;
+            if !self.plain {
                 self.overwrite_repeat_line(sig);
-                return;
             }
+            return;
         }
 
         // Different event — start a new line
         // (The previous repeat line, if any, is already finalized on stdout)
+        self.print_repeat_summary();
         self.last_event = Some(sig);
         self.repeat_count = 1;
         self.repeat_first_frame = frame;
@@ -475,7 +1278,7 @@ impl ErrorFormatter {
             return 1;
         }
         // Ceiling division: how many rows the text spans
-        (visible_width + term_width - 1) / term_width
+        visible_width.div_ceil(term_width)
     }
 
     /// Get the current terminal width, with a safe fallback.
@@ -524,8 +1327,27 @@ impl ErrorFormatter {
         self.last_printed_lines = new_lines;
     }
 
+    /// In plain mode, print how often the last event repeated, since its line
+    /// cannot be updated.
+    fn print_repeat_summary(&self) {
+        if self.plain && self.repeat_count > 1 {
+            println!(
+                "{}",
+                event_renderer::sub_line(&format!(
+                    "repeated ×{}, #{}-#{}, {:.3}s-{:.3}s",
+                    self.repeat_count,
+                    self.repeat_first_frame,
+                    self.repeat_last_frame,
+                    self.repeat_first_ts.as_secs_f64(),
+                    self.repeat_last_ts.as_secs_f64(),
+                ))
+            );
+        }
+    }
+
     /// Flush any pending repeat state. Called before printing non-event output.
     fn flush_repeat(&mut self) {
+        self.print_repeat_summary();
         self.last_event = None;
         self.repeat_count = 0;
         self.last_printed_lines = 0;
@@ -534,52 +1356,133 @@ impl ErrorFormatter {
 
     // ─── Formatting helpers ───
 
-    /// Format a tagged error line in the pop style:
-    ///   ▌ TAG  #frame [timestamp] detail
-    fn format_tagged_line(
-        tag: &str,
+    /// Format an event line, see [`EventLine`].
+    fn format_event_line(
+        category: &str,
+        device: Option<String>,
         detail: &str,
         frame: Option<u64>,
         timestamp: Option<Duration>,
-        tag_color: Color,
+        color: Color,
     ) -> String {
-        let tag_style = Style::new().fg(tag_color).bold();
-        let dim_style = Style::new().color256(244); // dark grey
-
-        let mut out = String::new();
-
-        // "  ▌ "
-        out.push_str(&format!("  {} ", tag_style.apply_to("▌")));
+        EventLine {
+            timestamp,
+            frame,
+            device,
+            category,
+            color,
+            message: detail,
+        }
+        .render()
+    }
 
-        // "TAG     " (left-padded to 8 chars)
-        out.push_str(&format!("{}", tag_style.apply_to(format!("{:<8}", tag))));
+    // ─── Visual helpers ───
 
-        // "#frame  [timestamp] " (dim)
-        if let (Some(f), Some(ts)) = (frame, timestamp) {
-            out.push_str(&format!(
-                "{} ",
-                dim_style.apply_to(format!("#{:<6} [{:>9.6}s]", f, ts.as_secs_f64()))
-            ));
+    /// Format an interface info line, the name tagged like the event categories,
+    /// followed by a dimmed line with its addresses and link speed.
+    pub fn format_interface_line(iface: &NetworkInterfaceInfo) -> String {
+        let name_style = Style::new().green().bold();
+        let dim_style = Style::new().color256(244);
+        let suffix = if iface.is_default { ", default" } else { "" };
+        let mut detail = format!("#{}", iface.index);
+        if let Some(friendly_name) = &iface.friendly_name {
+            detail.push_str(&format!(" \"{}\"", friendly_name));
+        }
+        if !iface.description.is_empty() {
+            detail.push_str(&format!(" {}", iface.description));
+        }
+        detail.push_str(&format!(" [{}{}]", iface.oper_state.as_str(), suffix));
+        if iface.ethercat_candidate {
+            detail.push_str(&format!(" {}", style("EtherCAT candidate").cyan().bold()));
         }
 
-        // detail text (unstyled)
-        out.push_str(detail);
+        let mut properties = Vec::new();
+        if let Some(mac) = &iface.mac {
+            properties.push(mac.clone());
+        }
+        if let Some(speed) = iface.speed {
+            properties.push(match speed {
+                s if s >= 1_000_000_000 && s % 1_000_000_000 == 0 => {
+                    format!("{} Gbit/s", s / 1_000_000_000)
+                }
+                s => format!("{} Mbit/s", s / 1_000_000),
+            });
+        }
+        properties.extend(iface.addresses.iter().cloned());
+        if !iface.raw_ethernet {
+            properties.push("no raw Ethernet capture".to_string());
+        }
+        format!(
+            "  {} {} {}\n      {}",
+            name_style.apply_to("▌"),
+            name_style.apply_to(format!("{:<8}", iface.name)),
+            detail,
+            dim_style.apply_to(properties.join("  "))
+        )
+    }
 
-        out
+    /// Format a per-subdevice statistics line for the exit summary.
+    /// Error counters are highlighted so a flaky device stands out.
+    fn format_device_statistics_line(
+        position: usize,
+        id: SubdeviceIdentifier,
+        state: ECState,
+        stats: &SubDeviceStatistics,
+    ) -> String {
+        let dim_style = Style::new().color256(244);
+        let error_style = if stats.total_errors() > 0 {
+            Style::new().red().bold()
+        } else {
+            dim_style.clone()
+        };
+        format!(
+            "    {} {:<14} {:<9} {} {}",
+            dim_style.apply_to(format!("#{:<3}", position)),
+            format!("[{}]", id),
+            state.to_string(),
+            dim_style.apply_to(format!(
+                "dgrams:{} rd:{}B wr:{}B mbx:{} transitions:{}",
+                stats.datagrams,
+                stats.bytes_read,
+                stats.bytes_written,
+                stats.mailbox_messages,
+                stats.state_transitions
+            )),
+            error_style.apply_to(format!("wkc:{} esm:{}", stats.wkc_errors, stats.esm_errors)),
+        )
     }
 
-    // ─── Visual helpers ───
+    /// Format the occurrences of one error category for the exit summary.
+    fn format_error_count_line(category: &str, statistics: &ErrorStatistics) -> String {
+        let dim_style = Style::new().color256(244);
+        format!(
+            "    {} {} {}",
+            style(format!("{:<30}", category)).red(),
+            style(format!("{:>7}", statistics.count)).red().bold(),
+            dim_style.apply_to(format!(
+                "first #{} [{:.6}s]  last #{} [{:.6}s]",
+                statistics.first_frame,
+                statistics.first_timestamp,
+                statistics.last_frame,
+                statistics.last_timestamp
+            )),
+        )
+    }
 
-    /// Format an interface info line in the same tagged-line style as errors.
-    pub fn format_interface_line(
-        name: &str,
-        description: &str,
-        oper_state: &str,
-        is_default: bool,
-    ) -> String {
-        let suffix = if is_default { ", default" } else { "" };
-        let detail = format!("{} [{}{}]", description, oper_state, suffix);
-        Self::format_tagged_line(name, &detail, None, None, Color::Green)
+    /// Format a process image entry for the exit summary.
+    fn format_signal_line(signal: &PdoSignal) -> String {
+        let dim_style = Style::new().color256(244);
+        format!(
+            "         {} {:<3} {} {}",
+            dim_style.apply_to(format!(
+                "L{:#010x}.{}",
+                signal.logical_bit / 8,
+                signal.logical_bit % 8
+            )),
+            signal.direction,
+            signal.name,
+            dim_style.apply_to(format!("({}, {} bit)", signal.key(), signal.bit_length)),
+        )
     }
 
     fn print_heavy_separator(&self) {
@@ -588,40 +1491,47 @@ impl ErrorFormatter {
 
     // ─── Data helpers ───
 
-    fn wkc_cause_short(expected: u16, actual: u16) -> &'static str {
-        if actual == 0 {
-            "no response"
+    fn wkc_cause_short(lang: Lang, expected: u16, actual: u16) -> String {
+        let cause = if actual == 0 {
+            Msg::WkcNoResponse
         } else if actual < expected {
-            "partial"
+            Msg::WkcPartial
         } else {
-            "over-count"
-        }
+            Msg::WkcOverCount
+        };
+        text(lang, cause, &[])
     }
 
-    fn esm_error_short(error: &ecdump::subdevice::ESMError) -> String {
+    fn esm_error_short(lang: Lang, error: &ecdump::subdevice::ESMError) -> String {
         use ecdump::subdevice::ESMError;
-        match error {
-            ESMError::IllegalTransition { to } => {
-                format!("illegal -> {}", to)
+        let flag = |has_error: bool| {
+            if has_error {
+                text(lang, Msg::EsmErrorFlag, &[])
+            } else {
+                String::new()
             }
+        };
+        match error {
+            ESMError::IllegalTransition { to } => text(lang, Msg::EsmIllegal, &[to]),
             ESMError::InvalidStateTransition { requested, current } => {
-                format!("{} -> {} invalid", current, requested)
+                text(lang, Msg::EsmInvalid, &[current, requested])
             }
             ESMError::BackwardTransition {
                 from,
                 to,
                 has_error,
-            } => {
-                let flag = if *has_error { " +err" } else { "" };
-                format!("{} -> {} backward{}", from, to, flag)
-            }
+            } => text(lang, Msg::EsmBackward, &[from, to, &flag(*has_error)]),
             ESMError::TransitionFailed {
                 requested,
                 current,
                 has_error,
-            } => {
-                let flag = if *has_error { " +err" } else { "" };
-                format!("-> {} failed @{}{}", requested, current, flag)
+            } => text(
+                lang,
+                Msg::EsmFailed,
+                &[requested, current, &flag(*has_error)],
+            ),
+            ESMError::InvalidBootstrapTransition { from, to } => {
+                text(lang, Msg::EsmBootstrap, &[from, to])
             }
         }
     }
@@ -640,14 +1550,21 @@ mod tests {
     #[test]
     fn test_verbose_level_ordering() {
         assert!(VerboseLevel::Nothing < VerboseLevel::Normal);
-        assert!(VerboseLevel::Normal < VerboseLevel::Detailed);
+        assert!(VerboseLevel::Normal < VerboseLevel::Frames);
+        assert!(VerboseLevel::Frames < VerboseLevel::Detailed);
     }
 
     #[test]
     fn test_wkc_cause_short() {
-        assert_eq!(ErrorFormatter::wkc_cause_short(3, 0), "no response");
-        assert_eq!(ErrorFormatter::wkc_cause_short(3, 2), "partial");
-        assert_eq!(ErrorFormatter::wkc_cause_short(1, 3), "over-count");
+        assert_eq!(
+            ErrorFormatter::wkc_cause_short(Lang::En, 3, 0),
+            "no response"
+        );
+        assert_eq!(ErrorFormatter::wkc_cause_short(Lang::En, 3, 2), "partial");
+        assert_eq!(
+            ErrorFormatter::wkc_cause_short(Lang::En, 1, 3),
+            "over-count"
+        );
     }
 
     #[test]
@@ -659,7 +1576,7 @@ mod tests {
             to: ECState::SafeOp,
             has_error: true,
         };
-        let s = ErrorFormatter::esm_error_short(&err);
+        let s = ErrorFormatter::esm_error_short(Lang::En, &err);
         assert!(s.contains("backward"), "got: {}", s);
         assert!(s.contains("+err"), "got: {}", s);
 
@@ -668,36 +1585,44 @@ mod tests {
             current: ECState::SafeOp,
             has_error: false,
         };
-        let s2 = ErrorFormatter::esm_error_short(&err2);
+        let s2 = ErrorFormatter::esm_error_short(Lang::En, &err2);
         assert!(s2.contains("failed"), "got: {}", s2);
         assert!(!s2.contains("+err"), "got: {}", s2);
     }
 
     #[test]
-    fn test_format_tagged_line_with_frame() {
-        let line = ErrorFormatter::format_tagged_line(
+    fn test_format_event_line_with_frame() {
+        let line = ErrorFormatter::format_event_line(
             "WKC",
+            Some("Address 1001".to_string()),
             "some detail",
             Some(42),
             Some(Duration::from_secs_f64(1.234)),
             Color::Red,
         );
         assert!(line.contains("WKC"), "got: {}", line);
+        assert!(line.contains("Address 1001"), "got: {}", line);
         assert!(line.contains("some detail"), "got: {}", line);
         assert!(line.contains("42"), "got: {}", line);
     }
 
     #[test]
-    fn test_format_tagged_line_without_frame() {
-        let line =
-            ErrorFormatter::format_tagged_line("DATAGRAM", "bad packet", None, None, Color::Red);
+    fn test_format_event_line_without_frame() {
+        let line = ErrorFormatter::format_event_line(
+            "DATAGRAM",
+            None,
+            "bad packet",
+            None,
+            None,
+            Color::Red,
+        );
         assert!(line.contains("DATAGRAM"), "got: {}", line);
         assert!(line.contains("bad packet"), "got: {}", line);
     }
 
     #[test]
     fn test_find_correlation_for_esm() {
-        use crate::analyzer::{ESMErrorDetail, WkcErrorDetail};
+        use ecdump::analyzer::{ESMErrorDetail, WkcErrorDetail};
         use ecdump::ec_packet::ECCommands;
         use ecdump::subdevice::{ECState, ESMError, SubdeviceIdentifier};
 
@@ -710,6 +1635,7 @@ mod tests {
             expected: 1,
             actual: 0,
             subdevice_id: Some(SubdeviceIdentifier::Address(0x1001)),
+            position: Some(0),
         };
 
         let esm = ESMErrorDetail {
@@ -732,7 +1658,7 @@ mod tests {
         };
 
         // Should find the correlation when packet_number matches
-        let found = ErrorFormatter::find_correlation_for_esm(&esm, &[corr.clone()]);
+        let found = ErrorFormatter::find_correlation_for_esm(&esm, std::slice::from_ref(&corr));
         assert!(found.is_some());
         assert_eq!(found.unwrap().packet_number, 10);
 
@@ -743,9 +1669,31 @@ mod tests {
         assert!(not_found.is_none());
     }
 
+    #[test]
+    fn test_format_device_statistics_line() {
+        use ecdump::subdevice::{ECState, SubDeviceStatistics, SubdeviceIdentifier};
+
+        let stats = SubDeviceStatistics {
+            datagrams: 10,
+            bytes_read: 20,
+            bytes_written: 4,
+            wkc_errors: 3,
+            ..Default::default()
+        };
+        let line = ErrorFormatter::format_device_statistics_line(
+            1,
+            SubdeviceIdentifier::Address(0x1001),
+            ECState::Op,
+            &stats,
+        );
+        assert!(line.contains("Address 1001"), "got: {}", line);
+        assert!(line.contains("dgrams:10"), "got: {}", line);
+        assert!(line.contains("wkc:3"), "got: {}", line);
+    }
+
     #[test]
     fn test_count_terminal_lines() {
-        let formatter = ErrorFormatter::new(1);
+        let formatter = ErrorFormatter::new(VerboseLevel::Normal, false, Lang::En);
         // A short string should be 1 line
         assert_eq!(formatter.count_terminal_lines("hello"), 1);
         // Empty string should be 1 line
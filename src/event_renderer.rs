@@ -0,0 +1,96 @@
+//! Console rendering of analyzer events: one line per event with the timestamp,
+//! frame number, subdevice, category and message in aligned columns.
+//!
+//! Colors follow the `console` crate, which drops them for `NO_COLOR`, output
+//! that is not a terminal and `--no-color`.
+
+use console::{Color, Style};
+use std::time::Duration;
+
+/// Widths of the columns before the message; longer values shift the rest of
+/// the line instead of being cut.
+const TIMESTAMP_WIDTH: usize = 12;
+const FRAME_WIDTH: usize = 8;
+const DEVICE_WIDTH: usize = 13;
+const CATEGORY_WIDTH: usize = 7;
+
+/// Indentation of the sub-lines of an event, under its device column.
+const SUB_LINE_INDENT: usize = 4 + TIMESTAMP_WIDTH + 1 + FRAME_WIDTH + 1;
+
+/// An analyzer event as shown on the console.
+pub struct EventLine<'a> {
+    pub timestamp: Option<Duration>,
+    pub frame: Option<u64>,
+    /// Subdevice the event concerns, empty for bus-wide events.
+    pub device: Option<String>,
+    pub category: &'a str,
+    pub color: Color,
+    pub message: &'a str,
+}
+
+impl EventLine<'_> {
+    ///   ▌   1.234000s #42      Address 1001  WKC     message
+    pub fn render(&self) -> String {
+        let category_style = Style::new().fg(self.color).bold();
+        let dim_style = Style::new().color256(244);
+        let timestamp = self
+            .timestamp
+            .map(|timestamp| format!("{:.6}s", timestamp.as_secs_f64()))
+            .unwrap_or_default();
+        let frame = self
+            .frame
+            .map(|frame| format!("#{}", frame))
+            .unwrap_or_default();
+        format!(
+            "  {} {} {} {} {} {}",
+            category_style.apply_to("▌"),
+            dim_style.apply_to(format!("{:>TIMESTAMP_WIDTH$}", timestamp)),
+            dim_style.apply_to(format!("{:<FRAME_WIDTH$}", frame)),
+            format_args!("{:<DEVICE_WIDTH$}", self.device.as_deref().unwrap_or("")),
+            category_style.apply_to(format!("{:<CATEGORY_WIDTH$}", self.category)),
+            self.message
+        )
+    }
+}
+
+/// A dimmed detail line under the last event.
+pub fn sub_line(text: &str) -> String {
+    Style::new()
+        .color256(244)
+        .apply_to(format!("{:SUB_LINE_INDENT$}└─ {}", "", text))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use console::measure_text_width;
+
+    #[test]
+    fn test_render_aligns_columns() {
+        console::set_colors_enabled(false);
+        let line = |timestamp, frame, device: Option<&str>, category| {
+            EventLine {
+                timestamp: Some(Duration::from_secs_f64(timestamp)),
+                frame: Some(frame),
+                device: device.map(String::from),
+                category,
+                color: Color::Red,
+                message: "message",
+            }
+            .render()
+        };
+        let wkc = line(1.5, 42, Some("Address 1001"), "WKC");
+        assert_eq!(
+            wkc,
+            "  ▌    1.500000s #42      Address 1001  WKC     message"
+        );
+        let link = line(1234.5, 1234567, None, "LINK");
+        assert_eq!(
+            measure_text_width(&link) - "message".len(),
+            measure_text_width(&wkc) - "message".len()
+        );
+        let column = |line: &str, c| line.chars().position(|x| x == c);
+        assert_eq!(column(&sub_line("detail"), '└'), column(&wkc, 'A'));
+    }
+}
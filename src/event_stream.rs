@@ -0,0 +1,339 @@
+use anyhow::{Context, Result};
+use ecdump::analysis::{Direction, FrameAnalysis, device_error_category};
+use ecdump::analyzer::{
+    AlStatusCodeUpdate, BusSizeChange, ECDeviceError, ECError, ErrorAcknowledgement,
+    FirmwareUpdate, LogicalAddressEvent, LogicalAddressIssue, MalformedFrame, Rescan,
+    StateTransition,
+};
+use ecdump::register_watch::RegisterChange;
+use ecdump::subdevice::SubdeviceIdentifier;
+use schemars::{JsonSchema, Schema, schema_for};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+
+use crate::schema::{self, EVENTS_VERSION};
+
+/// An analyzed frame and its events, handed to the report, the event stream and
+/// the database.
+pub struct FrameEvents<'a> {
+    pub frame: u64,
+    pub timestamp: Duration,
+    pub from_main: bool,
+    /// EtherCAT frame length (without the Ethernet header).
+    pub length: usize,
+    pub malformed: &'a [MalformedFrame],
+    pub rescans: &'a [Rescan],
+    pub bus_size_changes: &'a [BusSizeChange],
+    pub transitions: &'a [StateTransition],
+    pub error_acks: &'a [ErrorAcknowledgement],
+    pub register_changes: &'a [RegisterChange],
+    pub logical_issues: &'a [LogicalAddressEvent],
+    pub firmware_updates: &'a [FirmwareUpdate],
+    pub error: Option<&'a ECError>,
+    pub al_status_code_updates: &'a [AlStatusCodeUpdate],
+}
+
+impl<'a> FrameEvents<'a> {
+    /// The events of a frame analyzed with an [`Analyzer`](ecdump::Analyzer);
+    /// `length` is that of the EtherCAT frame.
+    pub fn from_analysis(analysis: &'a FrameAnalysis, length: usize) -> Self {
+        FrameEvents {
+            frame: analysis.frame,
+            timestamp: analysis.timestamp,
+            from_main: analysis.direction == Direction::FromMain,
+            length,
+            malformed: &analysis.malformed,
+            rescans: &analysis.rescans,
+            bus_size_changes: &analysis.bus_size_changes,
+            transitions: &analysis.transitions,
+            error_acks: &analysis.error_acks,
+            register_changes: &analysis.register_changes,
+            logical_issues: &analysis.logical_issues,
+            firmware_updates: &analysis.firmware_updates,
+            error: analysis.error.as_ref(),
+            al_status_code_updates: &analysis.al_status_code_updates,
+        }
+    }
+}
+
+impl FrameEvents<'_> {
+    /// The events in the order they are reported.
+    pub fn events(&self) -> Vec<Event> {
+        let mut out = Vec::new();
+        for frame in self.malformed {
+            out.push(Event::new(
+                frame.packet_number,
+                frame.timestamp,
+                None,
+                "malformed_frame",
+                json!({
+                    "from_main": frame.from_main,
+                    "malformation": frame.malformation.to_string(),
+                }),
+            ));
+        }
+        for rescan in self.rescans {
+            out.push(Event::new(
+                rescan.packet_number,
+                rescan.timestamp,
+                None,
+                "rescan",
+                json!({
+                    "scan_number": rescan.scan_number,
+                    "previous_device_count": rescan.previous_device_count,
+                    "device_count": rescan.device_count,
+                }),
+            ));
+        }
+        for change in self.bus_size_changes {
+            out.push(Event::new(
+                change.packet_number,
+                change.timestamp,
+                None,
+                "bus_size_change",
+                json!({
+                    "previous_device_count": change.previous_device_count,
+                    "device_count": change.device_count,
+                }),
+            ));
+        }
+        for transition in self.transitions {
+            out.push(Event::new(
+                transition.packet_number,
+                transition.timestamp,
+                Some(transition.subdevice_id),
+                "state_transition",
+                json!({ "from": transition.from, "to": transition.to }),
+            ));
+        }
+        for ack in self.error_acks {
+            out.push(Event::new(
+                ack.packet_number,
+                ack.timestamp,
+                Some(ack.subdevice_id),
+                "error_acknowledgement",
+                json!({
+                    "error_frame": ack.sequence.error_packet,
+                    "ack_frame": ack.sequence.ack_packet,
+                    "cleared_frame": ack.sequence.cleared_packet,
+                    "al_status_code": ack.sequence.al_status_code,
+                }),
+            ));
+        }
+        for change in self.register_changes {
+            out.push(Event::new(
+                change.packet_number,
+                change.timestamp,
+                Some(change.subdevice_id),
+                "register_change",
+                json!({
+                    "register": change.watch.address,
+                    "shadow": change.shadow.to_string(),
+                    "old": change.old.as_ref().map(|value| value.to_string()),
+                    "new": change.new.to_string(),
+                }),
+            ));
+        }
+        for event in self.logical_issues {
+            let payload = match &event.issue {
+                LogicalAddressIssue::Conflict {
+                    first,
+                    second,
+                    conflict,
+                } => json!({
+                    "issue": "conflict",
+                    "first": first.to_string(),
+                    "first_fmmu": conflict.first.fmmu,
+                    "second": second.to_string(),
+                    "second_fmmu": conflict.second.fmmu,
+                    "overlap_start": conflict.overlap.start,
+                    "overlap_end": conflict.overlap.end,
+                }),
+                LogicalAddressIssue::Unmapped { command, range } => json!({
+                    "issue": "unmapped",
+                    "command": command.as_str(),
+                    "start": range.start,
+                    "end": range.end,
+                }),
+            };
+            out.push(Event::new(
+                event.packet_number,
+                event.timestamp,
+                None,
+                "logical_address",
+                payload,
+            ));
+        }
+        for update in self.firmware_updates {
+            out.push(Event::new(
+                update.packet_number,
+                update.timestamp,
+                Some(update.subdevice_id),
+                "firmware_update",
+                json!({
+                    "enter_frame": update.session.enter_packet,
+                    "reboot_frame": update.session.reboot_packet,
+                    "reinit_frame": update.session.reinit_packet,
+                    "file_name": update.session.file_name,
+                    "bytes_written": update.session.bytes_written,
+                    "bytes_read": update.session.bytes_read,
+                }),
+            ));
+        }
+        match self.error {
+            Some(ECError::InvalidDatagram {
+                packet_number,
+                timestamp,
+                error,
+            }) => out.push(Event::new(
+                *packet_number,
+                *timestamp,
+                None,
+                "invalid_datagram",
+                json!({ "error": error.to_string() }),
+            )),
+            Some(ECError::DeviceError(errors)) => {
+                for error in errors {
+                    out.push(Event::device_error(error));
+                }
+            }
+            None => {}
+        }
+        for update in self.al_status_code_updates {
+            out.push(Event::new(
+                self.frame,
+                self.timestamp,
+                Some(update.subdevice_id),
+                "al_status_code",
+                json!({ "al_status_code": update.al_status_code }),
+            ));
+        }
+        out
+    }
+}
+
+/// An analyzer event with its category-specific payload, one line of the event
+/// stream.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Event {
+    pub schema_version: u32,
+    pub frame: u64,
+    /// Seconds since the first frame.
+    pub timestamp: f64,
+    pub subdevice: Option<String>,
+    /// `malformed_frame`, `invalid_datagram`, `invalid_auto_increment_address`,
+    /// `invalid_configured_address`, `wkc_mismatch`, `esm_error`, `al_status_code`,
+    /// `state_transition`, `error_acknowledgement`, `rescan`, `bus_size_change`,
+    /// `logical_address`, `register_change`, `firmware_update` or `link`.
+    pub category: &'static str,
+    /// Category-specific fields.
+    pub payload: Value,
+}
+
+impl Event {
+    pub fn new(
+        frame: u64,
+        timestamp: Duration,
+        subdevice: Option<SubdeviceIdentifier>,
+        category: &'static str,
+        payload: Value,
+    ) -> Self {
+        Event {
+            schema_version: EVENTS_VERSION,
+            frame,
+            timestamp: timestamp.as_secs_f64(),
+            subdevice: subdevice.map(|id| id.to_string()),
+            category,
+            payload,
+        }
+    }
+
+    pub fn link_change(up: bool, frame: u64, timestamp: Duration) -> Self {
+        Event::new(frame, timestamp, None, "link", json!({ "up": up }))
+    }
+
+    fn device_error(error: &ECDeviceError) -> Self {
+        let mut payload = match error {
+            ECDeviceError::InvalidAutoIncrementAddress {
+                address, position, ..
+            } => json!({ "address": address, "position": position }),
+            ECDeviceError::InvalidConfiguredAddress { address, .. } => {
+                json!({ "address": address })
+            }
+            ECDeviceError::InvalidWkc(detail) => json!({
+                "expected": detail.expected,
+                "actual": detail.actual,
+                "register": detail.register,
+                "length": detail.length,
+                "position": detail.position,
+            }),
+            ECDeviceError::ESMError(detail) => json!({
+                "error": detail.error,
+                "al_status_code": detail.al_status_code,
+            }),
+        };
+        payload["command"] = json!(error.command().as_str());
+        payload["diagnosis"] = json!(error.diagnosis());
+        Event::new(
+            error.packet_number(),
+            error.timestamp(),
+            error.subdevice_id(),
+            device_error_category(error),
+            payload,
+        )
+    }
+}
+
+/// Writes analyzer events as JSON Lines (`--events`), one object per event with
+/// frame number, timestamp, subdevice, category and a category-specific payload.
+/// Lines are flushed after every frame, so other processes can follow the file.
+pub struct EventStream {
+    writer: Box<dyn Write>,
+}
+
+impl EventStream {
+    /// Write to `path`, or to standard output for `-`.
+    pub fn create(path: &str) -> Result<Self> {
+        let writer: Box<dyn Write> = if path == "-" {
+            Box::new(io::stdout())
+        } else {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create event file: {}", path))?;
+            Box::new(BufWriter::new(file))
+        };
+        Ok(EventStream { writer })
+    }
+
+    pub fn write_frame(&mut self, events: &FrameEvents) -> Result<()> {
+        for event in events.events() {
+            self.write(&event)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_link_change(&mut self, up: bool, frame: u64, timestamp: Duration) -> Result<()> {
+        self.write(&Event::link_change(up, frame, timestamp))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write(&mut self, event: &Event) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+}
+
+/// JSON Schema of one line of the `--events` stream.
+pub fn schema() -> Schema {
+    schema::versioned(schema_for!(Event), EVENTS_VERSION)
+}
@@ -0,0 +1,97 @@
+//! Wireshark extcap interface, so Wireshark lists ecdump as a capture source.
+//!
+//! Link or copy the `ecdump` binary into Wireshark's extcap directory (see
+//! Help > About > Folders). Wireshark then captures through ecdump, which streams
+//! the frames to Wireshark as pcapng while running its analysis; the analysis
+//! outputs are selected in the interface options.
+
+use crate::packet_source;
+
+/// Name of the single extcap interface; the capture interface is an option.
+pub const INTERFACE: &str = "ecdump";
+
+/// What Wireshark asked for with the `--extcap-*` and `--capture` options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtcapRequest {
+    /// `--extcap-interfaces`: list the interfaces this extcap provides.
+    Interfaces,
+    /// `--extcap-dlts`: link types of the interface.
+    Dlts,
+    /// `--extcap-config`: options shown in the interface options dialog.
+    Config,
+    /// `--capture`: capture into the `--fifo` Wireshark reads.
+    Capture,
+}
+
+/// Answer a query, every request but `Capture`.
+pub fn answer(request: ExtcapRequest) {
+    match request {
+        ExtcapRequest::Interfaces => {
+            println!(
+                "extcap {{version={}}}{{help=https://github.com/kajity/ecdump}}",
+                env!("CARGO_PKG_VERSION")
+            );
+            println!(
+                "interface {{value={}}}{{display=EtherCAT analyzer (ecdump)}}",
+                INTERFACE
+            );
+        }
+        ExtcapRequest::Dlts => println!("dlt {{number=1}}{{name=EN10MB}}{{display=Ethernet}}"),
+        ExtcapRequest::Config => print_config(),
+        ExtcapRequest::Capture => {}
+    }
+}
+
+fn print_config() {
+    println!(
+        "arg {{number=0}}{{call=--interface}}{{display=Capture interface}}{{type=selector}}\
+         {{required=true}}{{tooltip=Network interface connected to the EtherCAT segment}}"
+    );
+    let interfaces = packet_source::get_interface_list();
+    // The list starts with the interfaces that look like EtherCAT segments
+    for (i, iface) in interfaces
+        .iter()
+        .filter(|iface| iface.raw_ethernet)
+        .enumerate()
+    {
+        println!(
+            "value {{arg=0}}{{value={}}}{{display={}}}{{default={}}}",
+            iface.name,
+            iface.friendly_name.as_deref().unwrap_or(&iface.name),
+            i == 0
+        );
+    }
+
+    let outputs = [
+        (
+            "--events",
+            "Event log (JSON Lines)",
+            "Analyzer events as they happen",
+        ),
+        (
+            "--report",
+            "Report (JSON)",
+            "Analysis results written when the capture stops",
+        ),
+        (
+            "--sqlite",
+            "SQLite database",
+            "Frames, events and subdevice snapshots",
+        ),
+    ];
+    for (number, (call, display, tooltip)) in outputs.iter().enumerate() {
+        println!(
+            "arg {{number={}}}{{call={}}}{{display={}}}{{type=fileselect}}{{mustexist=false}}\
+             {{tooltip={}}}{{group=Analysis}}",
+            number + 1,
+            call,
+            display,
+            tooltip
+        );
+    }
+    println!(
+        "arg {{number={}}}{{call=--metrics}}{{display=Prometheus metrics address}}\
+         {{type=string}}{{tooltip=e.g. 127.0.0.1:9898}}{{group=Analysis}}",
+        outputs.len() + 1
+    );
+}
@@ -0,0 +1,104 @@
+//! Heap accounting of the process. The global allocator counts allocations for
+//! `ecdump bench` and the bytes in use for the `--max-memory` guard.
+
+use anyhow::{Result, bail};
+use ecdump::analyzer::DeviceManager;
+use log::warn;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The system allocator, counting allocations and the bytes in use.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+static LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        LIVE_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        LIVE_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        LIVE_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        LIVE_BYTES.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Allocations since the start of the process.
+pub fn allocations() -> u64 {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// Bytes allocated since the start of the process, including freed ones.
+pub fn allocated_bytes() -> u64 {
+    ALLOCATED_BYTES.load(Ordering::Relaxed)
+}
+
+/// Bytes allocated and not freed yet.
+pub fn live_bytes() -> u64 {
+    LIVE_BYTES.load(Ordering::Relaxed)
+}
+
+/// `--max-memory`: keeps the heap below a limit by shrinking the memory budget
+/// of the analyzer, and stops the analysis once that frees nothing more.
+pub struct MemoryGuard {
+    limit: u64,
+}
+
+impl MemoryGuard {
+    pub fn new(limit: u64) -> Self {
+        MemoryGuard { limit }
+    }
+
+    /// Check the heap after a frame. Fails when it stays above the limit although
+    /// the analyzer evicted all it could.
+    pub fn check(&self, device_manager: &mut DeviceManager) -> Result<()> {
+        let live = live_bytes();
+        if live <= self.limit {
+            return Ok(());
+        }
+        let usage = device_manager.memory_usage();
+        let budget = usage / 2;
+        device_manager.set_memory_budget(Some(
+            device_manager
+                .memory_budget()
+                .map_or(budget, |current| current.min(budget)),
+        ));
+        let freed = usage - device_manager.memory_usage();
+        if freed == 0 && live_bytes() > self.limit {
+            bail!(
+                "The heap reached --max-memory ({:.1} MB) and nothing more can be evicted",
+                self.limit as f64 / 1e6
+            );
+        }
+        warn!(
+            "The heap reached --max-memory ({:.1} MB): freed {} bytes of register shadows, limiting the analyzer to {} bytes",
+            self.limit as f64 / 1e6,
+            freed,
+            budget
+        );
+        Ok(())
+    }
+}
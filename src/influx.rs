@@ -0,0 +1,327 @@
+use anyhow::{Context, Result, anyhow, bail};
+use crossbeam_channel::{Sender, TrySendError, bounded};
+use ecdump::analyzer::{DeviceManager, ECDeviceError, ECError, SignalSample};
+use ecdump::subdevice::SubdeviceIdentifier;
+use log::warn;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::event_stream::FrameEvents;
+
+/// Lines sent per HTTP request at most.
+const BATCH_LINES: usize = 5000;
+/// Longest time lines are held back before they are written or sent.
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// Connect, read and write timeout of the HTTP requests.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Batches waiting for the HTTP sender; more are dropped while the database is
+/// slow or down.
+const QUEUED_BATCHES: usize = 8;
+
+enum Destination {
+    File(BufWriter<File>),
+    /// Batches are posted by a thread of their own, so a slow or unreachable
+    /// database does not hold up the analysis.
+    Http {
+        sender: Sender<String>,
+        handle: JoinHandle<()>,
+    },
+}
+
+/// HTTP write endpoint of the database.
+struct Endpoint {
+    host: String,
+    /// Path and query of the write endpoint.
+    path: String,
+    /// `INFLUX_TOKEN`, sent as `Authorization: Token ...`.
+    token: Option<String>,
+}
+
+/// A line without its timestamp, which is only known relative to the first frame
+/// until the source reported the wall-clock time of that frame.
+struct PendingLine {
+    line: String,
+    timestamp: Duration,
+}
+
+/// Exports per-cycle metrics and process data signals in InfluxDB line protocol
+/// (`--influx`), to a file or to the HTTP write API of a time-series database.
+///
+/// Measurements:
+/// - `ecdump_cycle`: one point per frame sent by the main device, with fields
+///   `interval` (seconds since the previous one), `length` and `wkc_errors` of
+///   the returning frame. Frames with WKC faults that are not from the main
+///   device are counted on the next cycle.
+/// - `ecdump_signal`: process data signal changes (see `--signal`), tagged with
+///   `subdevice`, `object`, `signal` and `direction`, field `value`.
+/// - `ecdump_dc`: changes of the DC System Time Difference read from a
+///   subdevice, tagged with `subdevice`, field `difference` in ns.
+pub struct InfluxWriter {
+    destination: Destination,
+    source: String,
+    pending: Vec<PendingLine>,
+    /// Wall-clock time of the first frame, ecdump's start time if the source
+    /// does not report it.
+    start_time: SystemTime,
+    last_flush: Instant,
+    last_main_timestamp: Option<Duration>,
+    wkc_errors: u64,
+    /// Last exported DC System Time Difference of each subdevice.
+    dc_differences: BTreeMap<SubdeviceIdentifier, i32>,
+    /// Batches dropped because the HTTP sender fell behind.
+    dropped_batches: u64,
+}
+
+impl InfluxWriter {
+    /// Write to `destination`, an `http://` write endpoint URL such as
+    /// `http://localhost:8086/api/v2/write?org=plant&bucket=ethercat&precision=ns`
+    /// or a file path. `source` is the `source` tag of every point.
+    pub fn create(destination: &str, source: &str) -> Result<Self> {
+        let destination = match destination.strip_prefix("http://") {
+            Some(url) => {
+                let (host, path) = match url.find('/') {
+                    Some(index) => (&url[..index], &url[index..]),
+                    None => (url, "/"),
+                };
+                if host.is_empty() {
+                    bail!("Missing host in InfluxDB URL: {}", destination);
+                }
+                let endpoint = Endpoint {
+                    host: host.to_string(),
+                    path: path.to_string(),
+                    token: std::env::var("INFLUX_TOKEN").ok(),
+                };
+                let (sender, receiver) = bounded::<String>(QUEUED_BATCHES);
+                let handle = std::thread::Builder::new()
+                    .name("InfluxDB Sender".to_string())
+                    .spawn(move || {
+                        for body in receiver {
+                            // A database that is down loses this batch but not the capture
+                            if let Err(e) = endpoint.post(&body) {
+                                warn!("Failed to send metrics to InfluxDB: {:#}", e);
+                            }
+                        }
+                    })
+                    .context("Failed to start the InfluxDB sender thread")?;
+                Destination::Http { sender, handle }
+            }
+            None if destination.starts_with("https://") => {
+                bail!("HTTPS is not supported, use http:// or write to a file");
+            }
+            None => {
+                let file = File::create(destination).with_context(|| {
+                    format!("Failed to create InfluxDB export file: {}", destination)
+                })?;
+                Destination::File(BufWriter::new(file))
+            }
+        };
+        Ok(InfluxWriter {
+            destination,
+            source: escape_tag(source),
+            pending: Vec::new(),
+            start_time: SystemTime::now(),
+            last_flush: Instant::now(),
+            last_main_timestamp: None,
+            wkc_errors: 0,
+            dc_differences: BTreeMap::new(),
+            dropped_batches: 0,
+        })
+    }
+
+    /// Set the wall-clock time of the first frame.
+    pub fn set_start_time(&mut self, time: SystemTime) {
+        self.start_time = time;
+    }
+
+    pub fn write_frame(
+        &mut self,
+        events: &FrameEvents,
+        samples: &[SignalSample],
+        device_manager: &DeviceManager,
+    ) -> Result<()> {
+        if let Some(ECError::DeviceError(errors)) = events.error {
+            self.wkc_errors += errors
+                .iter()
+                .filter(|error| matches!(error, ECDeviceError::InvalidWkc(_)))
+                .count() as u64;
+        }
+        if events.from_main {
+            let mut line = format!(
+                "ecdump_cycle,source={} length={}i,wkc_errors={}i",
+                self.source, events.length, self.wkc_errors
+            );
+            if let Some(last) = self.last_main_timestamp {
+                let interval = events.timestamp.saturating_sub(last).as_secs_f64();
+                write!(line, ",interval={}", interval).unwrap();
+            }
+            self.last_main_timestamp = Some(events.timestamp);
+            self.wkc_errors = 0;
+            self.pending.push(PendingLine {
+                line,
+                timestamp: events.timestamp,
+            });
+        }
+
+        for sample in samples {
+            self.pending.push(PendingLine {
+                line: format!(
+                    "ecdump_signal,source={},subdevice={},object={},signal={},direction={} value={}i",
+                    self.source,
+                    escape_tag(&sample.subdevice_id.to_string()),
+                    escape_tag(&sample.key),
+                    escape_tag(&sample.name),
+                    sample.direction,
+                    sample.value
+                ),
+                timestamp: sample.timestamp,
+            });
+        }
+
+        // Register reads come back in the returning frames
+        if !events.from_main {
+            for device in device_manager.devices() {
+                let Some(difference) = device.dc_system_time_difference() else {
+                    continue;
+                };
+                let subdevice = device.identifier();
+                if self.dc_differences.insert(subdevice, difference) == Some(difference) {
+                    continue;
+                }
+                self.pending.push(PendingLine {
+                    line: format!(
+                        "ecdump_dc,source={},subdevice={} difference={}i",
+                        self.source,
+                        escape_tag(&subdevice.to_string()),
+                        difference
+                    ),
+                    timestamp: events.timestamp,
+                });
+            }
+        }
+
+        if self.pending.len() >= BATCH_LINES || self.last_flush.elapsed() >= BATCH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the remaining lines. Waits until the queued batches were sent.
+    pub fn finish(mut self) -> Result<()> {
+        self.flush()?;
+        match self.destination {
+            Destination::File(mut writer) => writer.flush()?,
+            Destination::Http { sender, handle } => {
+                drop(sender);
+                handle
+                    .join()
+                    .map_err(|_| anyhow!("InfluxDB sender thread panicked"))?;
+            }
+        }
+        if self.dropped_batches > 0 {
+            warn!(
+                "{} InfluxDB batches were dropped while the database did not keep up",
+                self.dropped_batches
+            );
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let start = self
+            .start_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut body = String::new();
+        for pending in self.pending.drain(..) {
+            writeln!(
+                body,
+                "{} {}",
+                pending.line,
+                (start + pending.timestamp).as_nanos()
+            )
+            .unwrap();
+        }
+
+        match &mut self.destination {
+            Destination::File(writer) => writer.write_all(body.as_bytes())?,
+            Destination::Http { sender, .. } => match sender.try_send(body) {
+                Err(TrySendError::Full(_)) => self.dropped_batches += 1,
+                Err(TrySendError::Disconnected(_)) => bail!("InfluxDB sender thread stopped"),
+                Ok(()) => {}
+            },
+        }
+        Ok(())
+    }
+}
+
+impl Endpoint {
+    fn post(&self, body: &str) -> Result<()> {
+        let (host, path) = (&self.host, &self.path);
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:80", host)
+        };
+        let address = std::net::ToSocketAddrs::to_socket_addrs(&address)?
+            .next()
+            .ok_or_else(|| anyhow!("Failed to resolve {}", host))?;
+        let mut stream = TcpStream::connect_timeout(&address, HTTP_TIMEOUT)?;
+        stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: {}\r\nConnection: close\r\n",
+            path,
+            host,
+            body.len()
+        );
+        if let Some(token) = &self.token {
+            write!(request, "Authorization: Token {}\r\n", token).unwrap();
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+        stream.write_all(body.as_bytes())?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let status = response.split(' ').nth(1).unwrap_or_default();
+        if !status.starts_with('2') {
+            let message = response.split("\r\n\r\n").nth(1).unwrap_or_default();
+            bail!("HTTP status {}: {}", status, message.trim());
+        }
+        Ok(())
+    }
+}
+
+/// Escape a tag value; spaces, commas and equals signs separate the line protocol.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ' ' | ',' | '=' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_tag() {
+        assert_eq!(escape_tag("Address 1001"), "Address\\ 1001");
+        assert_eq!(escape_tag("a,b=c"), "a\\,b\\=c");
+    }
+}
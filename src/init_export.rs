@@ -0,0 +1,46 @@
+use anyhow::{Context, Result};
+use ecdump::init_sequence::InitStep;
+use ecdump::subdevice::SubDevice;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Write the startup sequence of the main device as CSV, one row per
+/// configuration write, grouped by subdevice in bus order. Comparing two files
+/// without the frame and timestamp columns compares the init behavior of two
+/// main device stacks.
+pub fn write_init_sequence(
+    path: &str,
+    devices: &[SubDevice],
+    steps: &[Vec<InitStep>],
+) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create init sequence file: {}", path))?;
+    let mut writer = BufWriter::new(file);
+    writeln!(
+        writer,
+        "position,subdevice,frame,timestamp,command,target,data"
+    )?;
+    for (position, (device, steps)) in devices.iter().zip(steps).enumerate() {
+        for step in steps {
+            let data: Vec<String> = step
+                .action
+                .data()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            writeln!(
+                writer,
+                "{},{},{},{:.6},{},{},{}",
+                position,
+                device.identifier(),
+                step.packet_number,
+                step.timestamp.as_secs_f64(),
+                step.action.kind(),
+                step.action.target(),
+                data.join(" ")
+            )?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use crate::ec_packet::ECCommand;
+use crate::mailbox::SdoDownload;
+
+/// A configuration action of the main device towards one subdevice.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InitAction {
+    RegisterWrite {
+        command: ECCommand,
+        address: u16,
+        data: Vec<u8>,
+    },
+    SdoDownload {
+        index: u16,
+        subindex: u8,
+        complete_access: bool,
+        data: Vec<u8>,
+    },
+}
+
+impl InitAction {
+    /// A mailbox write carrying an SDO download is recorded as the download,
+    /// anything else as the raw register write.
+    pub fn from_write(command: ECCommand, address: u16, data: &[u8], is_mailbox: bool) -> Self {
+        if is_mailbox && let Some(sdo) = SdoDownload::from_mailbox(data) {
+            return InitAction::SdoDownload {
+                index: sdo.index,
+                subindex: sdo.subindex,
+                complete_access: sdo.complete_access,
+                data: sdo.data.to_vec(),
+            };
+        }
+        InitAction::RegisterWrite {
+            command,
+            address,
+            data: data.to_vec(),
+        }
+    }
+
+    /// `APWR`, `FPWR`, `BWR` or `SDO`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            InitAction::RegisterWrite { command, .. } => command.as_str(),
+            InitAction::SdoDownload { .. } => "SDO",
+        }
+    }
+
+    /// Register address (`0x0120`) or object (`0x1c12:00`, `0x1c12:*` for complete access).
+    pub fn target(&self) -> String {
+        match self {
+            InitAction::RegisterWrite { address, .. } => format!("{:#06x}", address),
+            InitAction::SdoDownload {
+                index,
+                subindex,
+                complete_access: true,
+                ..
+            } => format!("{:#06x}:*{:02x}", index, subindex),
+            InitAction::SdoDownload {
+                index, subindex, ..
+            } => format!("{:#06x}:{:02x}", index, subindex),
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        match self {
+            InitAction::RegisterWrite { data, .. } | InitAction::SdoDownload { data, .. } => data,
+        }
+    }
+}
+
+impl fmt::Display for InitAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind(), self.target())?;
+        for byte in self.data() {
+            write!(f, " {:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InitStep {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub action: InitAction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ec_packet::ECCommands;
+
+    #[test]
+    fn test_mailbox_sdo_is_recorded_as_download() {
+        let mailbox = [
+            0x0a, 0x00, 0x00, 0x00, 0x00, 0x23, 0x00, 0x20, 0x2f, 0x12, 0x1c, 0x00, 0x01, 0x00,
+            0x00, 0x00,
+        ];
+        let action = InitAction::from_write(ECCommands::FPWR, 0x1000, &mailbox, true);
+        assert_eq!(action.to_string(), "SDO 0x1c12:00 01");
+
+        let action = InitAction::from_write(ECCommands::FPWR, 0x0120, &[0x02, 0x00], false);
+        assert_eq!(action.to_string(), "FPWR 0x0120 02 00");
+    }
+}
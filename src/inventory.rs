@@ -0,0 +1,165 @@
+use anyhow::{Context, Result};
+use ecdump::analyzer::DeviceManager;
+use ecdump::subdevice::{EscInfo, PortInfo, SubDevice, SubDeviceIdentity};
+use schemars::{JsonSchema, Schema, schema_for};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::schema::{self, INVENTORY_VERSION};
+
+/// Subdevices found on the bus, in bus order.
+#[derive(Serialize, JsonSchema)]
+struct Inventory {
+    schema_version: u32,
+    /// Capture source: pcap file, interface or SSH destination.
+    source: String,
+    devices: Vec<InventoryEntry>,
+}
+
+/// One subdevice of the `--inventory`, in bus order.
+#[derive(Serialize, JsonSchema)]
+struct InventoryEntry {
+    position: usize,
+    configured_address: Option<u16>,
+    alias: Option<u16>,
+    identity: Option<SubDeviceIdentity>,
+    esc: Option<EscInfo>,
+    ports: [PortInfo; 4],
+}
+
+impl InventoryEntry {
+    fn new(position: usize, device: &SubDevice) -> Self {
+        InventoryEntry {
+            position,
+            configured_address: device.configured_address(),
+            alias: device.configured_alias(),
+            identity: device.identity(),
+            esc: device.esc_info(),
+            ports: device.ports(),
+        }
+    }
+}
+
+/// Write the subdevices of the last bus scan to `path`: CSV for `.csv` files,
+/// JSON otherwise. `source` names the capture source.
+pub fn write(path: &str, source: &str, device_manager: &DeviceManager) -> Result<()> {
+    let devices = device_manager
+        .devices()
+        .iter()
+        .enumerate()
+        .map(|(position, device)| InventoryEntry::new(position, device))
+        .collect::<Vec<_>>();
+
+    let file =
+        File::create(path).with_context(|| format!("Failed to create inventory file: {}", path))?;
+    let mut writer = BufWriter::new(file);
+    if path.to_ascii_lowercase().ends_with(".csv") {
+        write_csv(&mut writer, &devices)?;
+    } else {
+        let inventory = Inventory {
+            schema_version: INVENTORY_VERSION,
+            source: source.to_string(),
+            devices,
+        };
+        serde_json::to_writer_pretty(&mut writer, &inventory)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// JSON Schema of the JSON `--inventory` file. CSV files have the columns of
+/// the same version.
+pub fn schema() -> Schema {
+    schema::versioned(schema_for!(Inventory), INVENTORY_VERSION)
+}
+
+fn write_csv(writer: &mut impl Write, devices: &[InventoryEntry]) -> Result<()> {
+    writeln!(
+        writer,
+        "position,configured_address,alias,vendor_id,product_code,revision,serial_number,\
+         esc_type,esc_revision,esc_build,port0,port1,port2,port3"
+    )?;
+    for device in devices {
+        let identity = device.identity.as_ref();
+        let esc = device.esc.as_ref();
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            device.position,
+            hex(device.configured_address, 4),
+            hex(device.alias, 4),
+            hex(identity.map(|identity| identity.vendor_id), 8),
+            hex(identity.map(|identity| identity.product_code), 8),
+            hex(identity.and_then(|identity| identity.revision), 8),
+            identity
+                .and_then(|identity| identity.serial_number)
+                .map(|serial| serial.to_string())
+                .unwrap_or_default(),
+            hex(esc.map(|esc| esc.esc_type), 2),
+            hex(esc.and_then(|esc| esc.revision), 2),
+            hex(esc.and_then(|esc| esc.build), 4),
+            device
+                .ports
+                .iter()
+                .map(port_usage)
+                .collect::<Vec<_>>()
+                .join(","),
+        )?;
+    }
+    Ok(())
+}
+
+/// Hex value padded to `digits`, empty if unknown.
+fn hex(value: Option<impl Into<u64>>, digits: usize) -> String {
+    value
+        .map(|value| format!("{:#0width$x}", value.into(), width = digits + 2))
+        .unwrap_or_default()
+}
+
+/// Port column of the CSV, e.g. `EBUS link open`: the port kind, whether a link is
+/// detected and whether the port is open to the next subdevice or closed.
+fn port_usage(port: &PortInfo) -> String {
+    let mut parts = Vec::new();
+    if let Some(kind) = port.kind {
+        parts.push(kind.to_string());
+    }
+    match port.link {
+        Some(true) => parts.push("link".to_string()),
+        Some(false) => parts.push("no link".to_string()),
+        None => {}
+    }
+    match port.loop_closed {
+        Some(true) => parts.push("closed".to_string()),
+        Some(false) => parts.push("open".to_string()),
+        None => {}
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecdump::subdevice::PortKind;
+
+    #[test]
+    fn test_port_usage() {
+        let port = PortInfo {
+            port: 1,
+            kind: Some(PortKind::Ebus),
+            link: Some(true),
+            loop_closed: Some(false),
+            communication: Some(true),
+        };
+        assert_eq!(port_usage(&port), "EBUS link open");
+        let unknown = PortInfo {
+            port: 2,
+            kind: None,
+            link: None,
+            loop_closed: None,
+            communication: None,
+        };
+        assert_eq!(port_usage(&unknown), "");
+    }
+}
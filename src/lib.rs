@@ -1,3 +1,21 @@
+pub mod analysis;
+pub mod analyzer;
+pub mod capture_file;
+pub mod checkpoint;
 pub mod ec_packet;
+pub mod init_sequence;
+pub mod logical_map;
+pub mod mailbox;
+pub mod messages;
+pub mod pdo;
+pub mod protocol_handler;
+pub mod register_image;
+pub mod register_watch;
+pub mod registers;
 pub mod subdevice;
-pub mod registers;
\ No newline at end of file
+pub mod topology;
+
+pub use analysis::{
+    AnalysisOptions, AnalysisReport, AnalysisSummary, Analyzer, Direction, FrameAnalysis,
+    analyze_file, analyze_reader,
+};
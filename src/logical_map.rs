@@ -0,0 +1,158 @@
+use std::ops::Range;
+
+use crate::registers::FmmuConfig;
+
+/// An active FMMU of a subdevice, located in the logical address space with bit
+/// granularity.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LogicalMapping {
+    /// Index of the subdevice in bus order.
+    pub subdevice: usize,
+    pub fmmu: u16,
+    /// Bit range `[start, end)` in the logical address space.
+    pub bits: Range<u64>,
+    /// Inputs: the subdevice writes its data into the datagram.
+    pub read: bool,
+    /// Outputs: the subdevice takes its data from the datagram.
+    pub write: bool,
+}
+
+impl LogicalMapping {
+    pub fn new(subdevice: usize, fmmu: u16, config: &FmmuConfig) -> Option<Self> {
+        if !config.active || config.length == 0 || !(config.read || config.write) {
+            return None;
+        }
+        let start = config.logical_start as u64 * 8 + config.logical_start_bit as u64;
+        let end = (config.logical_start as u64 + config.length as u64 - 1) * 8
+            + config.logical_end_bit as u64
+            + 1;
+        (start < end).then_some(LogicalMapping {
+            subdevice,
+            fmmu,
+            bits: start..end,
+            read: config.read,
+            write: config.write,
+        })
+    }
+
+    /// Two mappings may share logical memory only if both just consume outputs.
+    /// Anything else lets one subdevice overwrite data meant for or produced by the other.
+    pub fn conflicts_with(&self, other: &LogicalMapping) -> bool {
+        self.subdevice != other.subdevice
+            && (self.read || other.read)
+            && self.bits.start < other.bits.end
+            && other.bits.start < self.bits.end
+    }
+
+    /// Byte range `[start, end)` touched by this mapping.
+    pub fn byte_range(&self) -> Range<u32> {
+        (self.bits.start / 8) as u32..self.bits.end.div_ceil(8) as u32
+    }
+}
+
+/// Two FMMUs of different subdevices mapping overlapping logical memory with
+/// incompatible directions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LogicalConflict {
+    pub first: LogicalMapping,
+    pub second: LogicalMapping,
+    /// Overlapping bytes.
+    pub overlap: Range<u32>,
+}
+
+/// All conflicting pairs among `mappings`.
+pub fn find_conflicts(mappings: &[LogicalMapping]) -> Vec<LogicalConflict> {
+    let mut conflicts = Vec::new();
+    for (i, first) in mappings.iter().enumerate() {
+        for second in &mappings[i + 1..] {
+            if first.conflicts_with(second) {
+                let start = first.bits.start.max(second.bits.start);
+                let end = first.bits.end.min(second.bits.end);
+                conflicts.push(LogicalConflict {
+                    first: first.clone(),
+                    second: second.clone(),
+                    overlap: (start / 8) as u32..end.div_ceil(8) as u32,
+                });
+            }
+        }
+    }
+    conflicts
+}
+
+/// Byte ranges within `range` that no mapping covers.
+pub fn unmapped_ranges(mappings: &[LogicalMapping], range: Range<u32>) -> Vec<Range<u32>> {
+    let mut covered: Vec<Range<u32>> = mappings.iter().map(|m| m.byte_range()).collect();
+    covered.sort_by_key(|r| r.start);
+
+    let mut gaps = Vec::new();
+    let mut cursor = range.start;
+    for r in covered {
+        if r.end <= cursor {
+            continue;
+        }
+        if r.start >= range.end {
+            break;
+        }
+        if r.start > cursor {
+            gaps.push(cursor..r.start);
+        }
+        cursor = r.end;
+    }
+    if cursor < range.end {
+        gaps.push(cursor..range.end);
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(subdevice: usize, bytes: Range<u64>, read: bool) -> LogicalMapping {
+        LogicalMapping {
+            subdevice,
+            fmmu: 0,
+            bits: bytes.start * 8..bytes.end * 8,
+            read,
+            write: !read,
+        }
+    }
+
+    #[test]
+    fn test_conflicts_and_gaps() {
+        let mappings = [
+            mapping(0, 0x00..0x04, false),
+            mapping(1, 0x02..0x06, false),
+            mapping(1, 0x10..0x14, true),
+            mapping(2, 0x12..0x16, true),
+        ];
+        let conflicts = find_conflicts(&mappings);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].overlap, 0x12..0x14);
+
+        assert_eq!(
+            unmapped_ranges(&mappings, 0x00..0x20),
+            vec![0x06..0x10, 0x16..0x20]
+        );
+        assert!(unmapped_ranges(&mappings, 0x01..0x05).is_empty());
+    }
+
+    #[test]
+    fn test_bit_mappings_in_shared_byte_do_not_conflict() {
+        let fmmu = |start_bit: u8, end_bit: u8| FmmuConfig {
+            logical_start: 0x100,
+            length: 1,
+            logical_start_bit: start_bit,
+            logical_end_bit: end_bit,
+            physical_start: 0x1000,
+            physical_start_bit: 0,
+            read: true,
+            write: false,
+            active: true,
+        };
+        let a = LogicalMapping::new(0, 0, &fmmu(0, 3)).unwrap();
+        let b = LogicalMapping::new(1, 0, &fmmu(4, 7)).unwrap();
+        assert!(!a.conflicts_with(&b));
+        assert_eq!(a.byte_range(), 0x100..0x101);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::registers::{read_le_u16, read_le_u32};
+
+/// Mailbox protocol carried in a mailbox message (mailbox header byte 5, bits 0-3).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MailboxType {
+    Error,
+    AoE,
+    EoE,
+    CoE,
+    FoE,
+    SoE,
+    VoE,
+    Unknown(u8),
+}
+
+impl MailboxType {
+    pub fn from_u8(value: u8) -> Self {
+        match value & 0x0F {
+            0x00 => MailboxType::Error,
+            0x01 => MailboxType::AoE,
+            0x02 => MailboxType::EoE,
+            0x03 => MailboxType::CoE,
+            0x04 => MailboxType::FoE,
+            0x05 => MailboxType::SoE,
+            0x0F => MailboxType::VoE,
+            other => MailboxType::Unknown(other),
+        }
+    }
+}
+
+/// The 6-byte header in front of every mailbox message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MailboxHeader {
+    /// Length of the mailbox service data following the header.
+    pub length: u16,
+    pub address: u16,
+    pub mailbox_type: MailboxType,
+    /// Mailbox counter (1-7, 0 if the counter is not used).
+    pub counter: u8,
+}
+
+impl MailboxHeader {
+    pub const SIZE: usize = 6;
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE {
+            return None;
+        }
+        Some(MailboxHeader {
+            length: read_le_u16(bytes[0..2].iter().map(|b| Some(*b)))?,
+            address: read_le_u16(bytes[2..4].iter().map(|b| Some(*b)))?,
+            mailbox_type: MailboxType::from_u8(bytes[5]),
+            counter: (bytes[5] >> 4) & 0x07,
+        })
+    }
+}
+
+/// FoE (File access over EtherCAT) operation codes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FoeOpCode {
+    ReadRequest,
+    WriteRequest,
+    Data,
+    Ack,
+    Error,
+    Busy,
+    Unknown(u8),
+}
+
+impl FoeOpCode {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0x01 => FoeOpCode::ReadRequest,
+            0x02 => FoeOpCode::WriteRequest,
+            0x03 => FoeOpCode::Data,
+            0x04 => FoeOpCode::Ack,
+            0x05 => FoeOpCode::Error,
+            0x06 => FoeOpCode::Busy,
+            other => FoeOpCode::Unknown(other),
+        }
+    }
+}
+
+/// A decoded FoE mailbox message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoeMessage<'a> {
+    pub opcode: FoeOpCode,
+    /// Password for read/write requests, packet number for data/ack, error code for errors.
+    pub value: u32,
+    /// File name for read/write requests, file data for data messages.
+    pub data: &'a [u8],
+}
+
+impl<'a> FoeMessage<'a> {
+    /// FoE header size (opcode, reserved byte and 32-bit value).
+    pub const HEADER_SIZE: usize = 6;
+
+    /// Decode an FoE message from a complete mailbox (mailbox header included).
+    /// Returns `None` if the mailbox does not carry FoE.
+    pub fn from_mailbox(mailbox: &'a [u8]) -> Option<Self> {
+        let header = MailboxHeader::from_bytes(mailbox)?;
+        if header.mailbox_type != MailboxType::FoE {
+            return None;
+        }
+        let end = (MailboxHeader::SIZE + header.length as usize).min(mailbox.len());
+        let service_data = mailbox.get(MailboxHeader::SIZE..end)?;
+        if service_data.len() < Self::HEADER_SIZE {
+            return None;
+        }
+        Some(FoeMessage {
+            opcode: FoeOpCode::from_u8(service_data[0]),
+            value: read_le_u32(service_data[2..6].iter().map(|b| Some(*b)))?,
+            data: &service_data[Self::HEADER_SIZE..],
+        })
+    }
+
+    /// File name of a read/write request.
+    pub fn file_name(&self) -> Option<String> {
+        match self.opcode {
+            FoeOpCode::ReadRequest | FoeOpCode::WriteRequest => {
+                Some(String::from_utf8_lossy(self.data).into_owned())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// CoE service carried in the CoE header (bits 12-15).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CoeService {
+    Emergency,
+    SdoRequest,
+    SdoResponse,
+    TxPdo,
+    RxPdo,
+    TxPdoRemoteRequest,
+    RxPdoRemoteRequest,
+    SdoInformation,
+    Unknown(u8),
+}
+
+impl CoeService {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0x01 => CoeService::Emergency,
+            0x02 => CoeService::SdoRequest,
+            0x03 => CoeService::SdoResponse,
+            0x04 => CoeService::TxPdo,
+            0x05 => CoeService::RxPdo,
+            0x06 => CoeService::TxPdoRemoteRequest,
+            0x07 => CoeService::RxPdoRemoteRequest,
+            0x08 => CoeService::SdoInformation,
+            other => CoeService::Unknown(other),
+        }
+    }
+}
+
+/// An SDO download initiated by the main device (expedited or normal transfer).
+/// Segmented transfers are not reassembled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdoDownload<'a> {
+    pub index: u16,
+    pub subindex: u8,
+    pub complete_access: bool,
+    pub data: &'a [u8],
+}
+
+impl<'a> SdoDownload<'a> {
+    /// CoE header (2 bytes) plus SDO header (command, index, subindex).
+    const HEADER_SIZE: usize = 6;
+
+    /// Decode an SDO download request from a complete mailbox (mailbox header included).
+    /// Returns `None` for anything other than an initiate download request.
+    pub fn from_mailbox(mailbox: &'a [u8]) -> Option<Self> {
+        let header = MailboxHeader::from_bytes(mailbox)?;
+        if header.mailbox_type != MailboxType::CoE {
+            return None;
+        }
+        let end = (MailboxHeader::SIZE + header.length as usize).min(mailbox.len());
+        let coe = mailbox.get(MailboxHeader::SIZE..end)?;
+        if coe.len() < Self::HEADER_SIZE + 4 {
+            return None;
+        }
+        if CoeService::from_u8(coe[1] >> 4) != CoeService::SdoRequest {
+            return None;
+        }
+
+        let command = coe[2];
+        // Client command specifier 1: initiate download
+        if command >> 5 != 0x01 {
+            return None;
+        }
+        let complete_access = command & 0x10 != 0;
+        let expedited = command & 0x02 != 0;
+        let size_indicated = command & 0x01 != 0;
+        let index = u16::from_le_bytes([coe[3], coe[4]]);
+        let subindex = coe[5];
+
+        let body = &coe[Self::HEADER_SIZE..];
+        let data = if expedited {
+            let size = if size_indicated {
+                4 - ((command >> 2) & 0x03) as usize
+            } else {
+                4
+            };
+            &body[..size]
+        } else {
+            let size = read_le_u32(body[..4].iter().map(|b| Some(*b)))? as usize;
+            let data = &body[4..];
+            &data[..size.min(data.len())]
+        };
+
+        Some(SdoDownload {
+            index,
+            subindex,
+            complete_access,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_foe_write_request() {
+        let mailbox = [
+            0x0d, 0x00, 0x00, 0x00, 0x00, 0x14, // mailbox header: 13 bytes, FoE, counter 1
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x00, // WRQ, password 0
+            b'a', b'p', b'p', b'.', b'b', b'i', b'n', 0x00, 0x00, // padding
+        ];
+        let foe = FoeMessage::from_mailbox(&mailbox).unwrap();
+        assert_eq!(foe.opcode, FoeOpCode::WriteRequest);
+        assert_eq!(foe.file_name().as_deref(), Some("app.bin"));
+    }
+
+    #[test]
+    fn test_non_foe_mailbox_is_ignored() {
+        let mailbox = [0x0a, 0x00, 0x00, 0x00, 0x00, 0x13, 0x00, 0x20];
+        assert!(FoeMessage::from_mailbox(&mailbox).is_none());
+    }
+
+    #[test]
+    fn test_expedited_sdo_download() {
+        // 0x1C12:00 = 1 (u8 expedited)
+        let mailbox = [
+            0x0a, 0x00, 0x00, 0x00, 0x00, 0x23, // mailbox header: 10 bytes, CoE, counter 2
+            0x00, 0x20, // CoE header: SDO request
+            0x2f, 0x12, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x00,
+        ];
+        let sdo = SdoDownload::from_mailbox(&mailbox).unwrap();
+        assert_eq!(sdo.index, 0x1C12);
+        assert_eq!(sdo.subindex, 0);
+        assert!(!sdo.complete_access);
+        assert_eq!(sdo.data, &[0x01]);
+    }
+}
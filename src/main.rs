@@ -1,58 +1,166 @@
-mod analyzer;
+mod batch;
+mod bench;
+mod buffer_pool;
+mod capture_trigger;
+mod capture_writer;
+mod child_stream;
+mod compression;
+mod config_file;
+mod diff;
 mod error_formatter;
+mod event_renderer;
+mod event_stream;
+mod extcap;
+mod heap;
+mod influx;
+mod init_export;
+mod inventory;
+mod metrics;
+mod packet_printer;
 mod packet_source;
+mod pipeline;
+mod register_dump;
+mod remote;
+mod replay;
+mod report;
+mod schema;
+mod script;
+mod seek_index;
+mod self_stats;
+mod signal_export;
+mod sinks;
+mod snapshot;
+mod sqlite_store;
 mod startup;
+mod system_log;
+#[cfg(target_os = "linux")]
+mod tpacket;
 
 use anyhow::{Context, Result};
 use bytes::BytesMut;
+use capture_writer::{OutputFile, OutputFormat};
 use console::style;
-use crossbeam_channel::{bounded, select};
-use ecdump::ec_packet;
-use error_formatter::ErrorFormatter;
+use crossbeam_channel::{bounded, never, select, tick};
+use ecdump::analysis::{Analyzer, Direction};
+use ecdump::checkpoint::Checkpoint;
+use ecdump::{analyzer, ec_packet};
+use error_formatter::{CaptureSummary, ConsoleMode, ErrorFormatter, VerboseLevel};
+use event_stream::FrameEvents;
+use extcap::ExtcapRequest;
+use heap::MemoryGuard;
 use log::{debug, error, warn};
-use packet_source::CapturedData;
+use packet_source::{CapturedData, SourceEvent};
+use pipeline::{ParsePipeline, ParsedFrame};
+use script::Script;
+use self_stats::SelfStats;
+use sinks::FrameSinks;
 use startup::PcapSource;
-use std::fs::File;
-use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
 
 fn main() -> Result<()> {
     let config = startup::parse_args();
+    let plain_console = config.console.is_plain();
+    if config.no_color || plain_console {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    } else if config.console == ConsoleMode::Terminal && std::env::var_os("NO_COLOR").is_none() {
+        console::set_colors_enabled(true);
+    }
+
+    if let Some(request) = config.extcap
+        && request != ExtcapRequest::Capture
+    {
+        extcap::answer(request);
+        return Ok(());
+    }
 
     if config.list_interfaces {
+        let interfaces = packet_source::get_interface_list();
+        if config.json {
+            println!("{}", serde_json::to_string_pretty(&interfaces)?);
+            return Ok(());
+        }
         println!("{}", style("■ Available network interfaces:").bold());
-        for iface in packet_source::get_interface_list() {
-            println!(
-                "{}",
-                ErrorFormatter::format_interface_line(
-                    &iface.name,
-                    &iface.description,
-                    iface.oper_state.as_str(),
-                    iface.is_default,
-                )
-            );
+        for iface in &interfaces {
+            println!("{}", ErrorFormatter::format_interface_line(iface));
         }
         return Ok(());
     }
 
-    startup::set_up_logging(config.debug);
+    if let Some(output) = config.print_schema {
+        return schema::print(output);
+    }
+
+    // Log messages must not end up in a JSON event stream on standard output
+    let events_to_stdout = config.outputs.events.as_deref() == Some("-");
+    startup::set_up_logging(config.debug, config.log_file.as_ref(), events_to_stdout)?;
+
+    if let Some((a, b)) = &config.diff {
+        if diff::run(a, b)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(bench) = &config.bench {
+        return bench::run(bench, config.parse_threads, config.pool_size);
+    }
+    if let Some(batch) = &config.batch {
+        if batch::run(batch)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    let mut error_formatter = ErrorFormatter::new(config.verbose);
+    // JSON events on standard output replace the terminal report, and Wireshark
+    // does not show the standard output of an extcap
+    let verbose = if config.extcap.is_some() || events_to_stdout {
+        VerboseLevel::Nothing
+    } else {
+        VerboseLevel::from_u8(config.verbose)
+    };
+    let mut error_formatter = ErrorFormatter::new(verbose, plain_console, config.lang);
     let (abort_tx, abort_rx) = bounded::<bool>(0);
+    let capture_trigger = config.capture_trigger.map(Arc::new);
     let file_out = match &config.output_file {
         Some(path) => {
-            if let PcapSource::File(file_in) = &config.pcap_source {
-                if file_in.file_path == *path {
-                    anyhow::bail!("Output file path must be different from input file path");
-                }
+            if let PcapSource::File(file_in) = &config.pcap_source
+                && file_in.file_path == *path
+            {
+                anyhow::bail!("Output file path must be different from input file path");
             }
-            let file_out = File::create(path)
-                .with_context(|| format!("Failed to create output file: {}", path))?;
-            Some(BufWriter::new(file_out))
+            let format = config
+                .output_format
+                .unwrap_or_else(|| OutputFormat::from_path(path));
+            if config.frame_comments && format == OutputFormat::Pcap {
+                anyhow::bail!("--frame-comments needs pcapng output (--format pcapng)");
+            }
+            Some(OutputFile {
+                path: path.clone(),
+                format,
+                rotation: config.rotation,
+                trigger: capture_trigger.clone(),
+                clock_source: None,
+                snaplen: None,
+                flush_when_idle: config.extcap == Some(ExtcapRequest::Capture),
+                frame_comments: config.frame_comments,
+            })
         }
         None => None,
     };
 
-    let (handle, tx_buffer, rx_data) = match config.pcap_source {
+    let dropped_frames = Arc::new(AtomicU64::new(0));
+    // Completion of --replay, and the writer's abort signal to stop the capture then
+    let mut replay_done = never();
+    let mut writer_abort = None;
+    let replay_stop = Arc::new(AtomicBool::new(false));
+    // Capture files and streams leave out the frames before the window themselves
+    let window_in_reader = !matches!(config.pcap_source, PcapSource::Interface(_));
+    // Where the frames come from, for the database
+    let source_name;
+    let ((handle, buffer_pool, rx_data, rx_status), clock_source) = match config.pcap_source {
         PcapSource::File(file) => {
             let (abort_tx2, abort_rx2) = bounded::<bool>(0);
             ctrlc::set_handler(move || {
@@ -61,17 +169,54 @@ fn main() -> Result<()> {
             })
             .expect("Error setting Ctrl-C handler");
 
-            let file_in = File::open(&file.file_path)
-                .with_context(|| format!("Failed to open pcap file: {}", &file.file_path))?;
+            let (file_in, indexing) = if file.index {
+                seek_index::open(&file.file_path, &config.window)
+            } else {
+                compression::open_input(&file.file_path).map(|file_in| (file_in, None))
+            }
+            .with_context(|| format!("Failed to open pcap file: {}", &file.file_path))?;
 
-            packet_source::start_read_pcap(
+            let handles = packet_source::start_read_pcap(
                 file_in,
                 file_out,
-                file.is_pcapng,
                 abort_rx2,
                 config.time_sync,
+                config.window,
+                config.pool_size,
+                indexing,
+            )
+            .with_context(|| format!("Failed to start reading pcap file: {}", &file.file_path))?;
+            source_name = file.file_path;
+            (handles, None)
+        }
+
+        PcapSource::Remote(remote) => {
+            let (abort_tx2, abort_rx2) = bounded::<bool>(0);
+            ctrlc::set_handler(move || {
+                abort_tx2.send(true).ok();
+                abort_tx.send(true).ok();
+            })
+            .expect("Error setting Ctrl-C handler");
+
+            debug!(
+                "Capturing on {} of {} over SSH",
+                remote.interface, remote.destination
+            );
+            let stream = remote
+                .start()
+                .with_context(|| "Failed to start remote capture over SSH")?;
+            let handles = packet_source::start_read_pcap(
+                Box::new(stream),
+                file_out,
+                abort_rx2,
+                false,
+                config.window,
+                config.pool_size,
+                None,
             )
-            .with_context(|| format!("Failed to start reading pcap file: {}", &file.file_path))?
+            .with_context(|| format!("Failed to read the capture from {}", remote.destination))?;
+            source_name = format!("{}:{}", remote.destination, remote.interface);
+            (handles, None)
         }
 
         PcapSource::Interface(interface) => {
@@ -80,6 +225,7 @@ fn main() -> Result<()> {
             )?;
 
             let (abort_tx2, abort_rx2) = bounded::<bool>(0);
+            writer_abort = Some(abort_tx2.clone());
             ctrlc::set_handler(move || {
                 abort_tx2.send(true).ok();
                 abort_tx.send(true).ok();
@@ -87,12 +233,73 @@ fn main() -> Result<()> {
             .expect("Error setting Ctrl-C handler");
 
             debug!("Using network interface: {}", interface.name);
-            packet_source::start_packet_receive(interface, file_out, abort_rx2)
-                .with_context(|| "Failed to start packet capture on network interface.")?
+            source_name = interface.name.clone();
+            let replay_interface = config.replay.is_some().then(|| interface.clone());
+            let (handles, clock_source) = packet_source::start_packet_receive(
+                interface,
+                file_out,
+                abort_rx2,
+                config.backpressure,
+                config.capture,
+                config.pool_size,
+                dropped_frames.clone(),
+            )
+            .with_context(|| "Failed to start packet capture on network interface.")?;
+            debug!("Timestamp clock: {}", clock_source.as_str());
+            // Start sending once the capture runs, so no answer is missed
+            if let (Some(replay), Some(interface)) = (&config.replay, &replay_interface) {
+                replay_done = replay.start(interface, replay_stop.clone())?;
+            }
+            (handles, Some(clock_source))
         }
     };
 
-    let mut device_manager = analyzer::DeviceManager::new();
+    let mut device_manager = analyzer::DeviceManager::new(config.analyzer);
+    device_manager.set_register_watches(config.watch_registers);
+    device_manager.set_memory_budget(config.analyzer_memory.map(|size| size as usize));
+    let memory_guard = config.max_memory.map(MemoryGuard::new);
+    if config.outputs.signals_csv.is_some()
+        || config.outputs.influx.is_some()
+        || config.script.is_some()
+    {
+        device_manager.set_signal_export(config.signals);
+    }
+    if let Some(path) = &config.checkpoint
+        && Path::new(path).exists()
+    {
+        let checkpoint = Checkpoint::load(path)
+            .with_context(|| format!("Failed to load the checkpoint {}", path))?;
+        debug!(
+            "Continuing {} subdevices and {} frames of {}",
+            checkpoint.devices().len(),
+            checkpoint.analyzed_frames(),
+            path
+        );
+        device_manager.restore(checkpoint);
+    }
+    let mut analyzer = Analyzer::with_device_manager(device_manager);
+    let mut sinks = FrameSinks::create(&config.outputs, &source_name, dropped_frames.clone())?;
+    let mut script = config.script.as_deref().map(Script::load).transpose()?;
+
+    let mut pipeline = ParsePipeline::start(rx_data, config.parse_threads);
+    let started = Instant::now();
+    // A source thread that fails ends the frame stream and reports why
+    let mut source_error = None;
+    let mut memory_error = None;
+    let mut status = rx_status.clone();
+    let status_tick = if plain_console && !config.status_interval.is_zero() {
+        tick(config.status_interval)
+    } else {
+        never()
+    };
+    let mut self_stats = config.self_stats.map(|_| {
+        SelfStats::new(
+            &pipeline,
+            buffer_pool.metrics(),
+            dropped_frames.load(Ordering::Relaxed),
+        )
+    });
+    let self_stats_tick = config.self_stats.map_or_else(never, tick);
 
     loop {
         if abort_rx.try_recv().is_ok() {
@@ -103,48 +310,215 @@ fn main() -> Result<()> {
             recv(abort_rx) -> _ => {
                 break;
             }
-            recv(rx_data) -> msg => {
+            recv(status) -> msg => match msg {
+                Ok(event) => handle_source_event(
+                    event,
+                    &mut error_formatter,
+                    &mut sinks,
+                    analyzer.device_manager().get_frame_count(),
+                    &mut source_error,
+                ),
+                Err(_) => status = never(),
+            },
+            recv(status_tick) -> _ => error_formatter.print_status(
+                analyzer.device_manager().get_analyzed_frame_count(),
+                dropped_frames.load(Ordering::Relaxed),
+                analyzer.errors(),
+                analyzer.device_manager().devices(),
+            ),
+            recv(self_stats_tick) -> _ => if let Some(self_stats) = &mut self_stats {
+                let report = self_stats.report(
+                    &pipeline,
+                    buffer_pool.metrics(),
+                    dropped_frames.load(Ordering::Relaxed),
+                );
+                error_formatter.print_self_stats(&report);
+            },
+            recv(replay_done) -> msg => {
                 match msg {
-                    Ok(CapturedData {
-                        data: packet,
-                        timestamp,
-                        from_main,
+                    Ok(Ok(frames)) => error_formatter.print_replay_done(frames),
+                    Ok(Err(error)) => source_error = Some(error),
+                    Err(_) => {}
+                }
+                if let Some(writer_abort) = &writer_abort {
+                    writer_abort.send(true).ok();
+                }
+                break;
+            }
+            recv(pipeline.receiver()) -> msg => {
+                match msg {
+                    Ok(ParsedFrame {
+                        captured:
+                            CapturedData {
+                                sequence,
+                                data: packet,
+                                timestamp,
+                                from_main,
+                            },
+                        valid,
+                        malformations,
                     }) => {
+                        pipeline.advance();
+                        // Number frames as the capture did, so dropped frames leave
+                        // gaps instead of shifting later frame numbers
+                        let frame_number = sequence;
+                        // File readers apply the window already, live capture does not
+                        if config.window.is_past(frame_number, timestamp) {
+                            break;
+                        }
+                        if !config.window.contains(frame_number, timestamp) {
+                            if let Some(trigger) = &capture_trigger {
+                                trigger.analyzed(timestamp);
+                            }
+                            analyzer.skip(frame_number);
+                            buffer_pool.put(BytesMut::from(packet));
+                            continue;
+                        }
+                        if window_in_reader {
+                            analyzer.skip_until(frame_number);
+                        }
+
                         let ethercat_packet = match ec_packet::ECFrame::new(packet.as_ref()) {
                             Some(pkt) => pkt,
                             None => {
                                 warn!("Failed to parse EtherCAT packet");
+                                buffer_pool.put(BytesMut::from(packet));
                                 continue;
                             }
                         };
 
-                        let result = device_manager
-                            .analyze_packet(&ethercat_packet, timestamp, from_main);
+                        let analysis_started = Instant::now();
+                        let direction = if from_main {
+                            Direction::FromMain
+                        } else {
+                            Direction::ToMain
+                        };
+                        let analysis = analyzer.feed_ethercat(
+                            &ethercat_packet,
+                            frame_number,
+                            timestamp,
+                            direction,
+                            valid.then_some(&malformations[..]),
+                        );
+                        let analysis_time = analysis_started.elapsed();
+
+                        error_formatter.report_frame(
+                            &ethercat_packet,
+                            frame_number,
+                            timestamp,
+                            from_main,
+                            analysis.error.as_ref(),
+                        );
+
+                        if let Some(script) = &mut script {
+                            script.datagrams(
+                                &ethercat_packet,
+                                frame_number,
+                                timestamp,
+                                from_main,
+                                analyzer.device_manager(),
+                            );
+                        }
+
+                        let length = packet.len();
+                        buffer_pool.put(BytesMut::from(packet));
+
+                        if !analysis.malformed.is_empty() {
+                            error_formatter.report_malformed_frames(&analysis.malformed);
+                        }
+                        if !analysis.rescans.is_empty() {
+                            error_formatter.report_rescans(&analysis.rescans);
+                        }
+                        if !analysis.bus_size_changes.is_empty() {
+                            error_formatter.report_bus_size_changes(&analysis.bus_size_changes);
+                        }
 
-                        tx_buffer.send(BytesMut::from(packet)).ok();
+                        if let Some(trigger) = &capture_trigger {
+                            let action = trigger
+                                .event()
+                                .occurred(
+                                    analysis.error.as_ref(),
+                                    !analysis.malformed.is_empty(),
+                                    !analysis.rescans.is_empty()
+                                        || !analysis.bus_size_changes.is_empty(),
+                                )
+                                .then(|| trigger.fire(timestamp))
+                                .flatten();
+                            if let Some(action) = action {
+                                error_formatter.report_capture_trigger(
+                                    trigger.event(),
+                                    action,
+                                    frame_number,
+                                    timestamp,
+                                );
+                            }
+                            trigger.analyzed(timestamp);
+                        }
 
                         // Report state transitions immediately
-                        let transitions = device_manager.take_state_transitions();
-                        if !transitions.is_empty() {
-                            error_formatter.report_state_transitions(&transitions);
+                        if !analysis.transitions.is_empty() {
+                            error_formatter.report_state_transitions(&analysis.transitions);
+                        }
+                        if !analysis.error_acks.is_empty() {
+                            error_formatter.report_error_acknowledgements(&analysis.error_acks);
+                        }
+                        if !analysis.register_changes.is_empty() {
+                            error_formatter.report_register_changes(&analysis.register_changes);
+                        }
+                        if !analysis.logical_issues.is_empty() {
+                            error_formatter.report_logical_address_issues(&analysis.logical_issues);
+                        }
+                        if !analysis.firmware_updates.is_empty() {
+                            error_formatter.report_firmware_updates(&analysis.firmware_updates);
+                        }
+
+                        let events = FrameEvents::from_analysis(&analysis, length);
+                        sinks.record_frame(
+                            &events,
+                            &analysis.signal_samples,
+                            analyzer.device_manager(),
+                        );
+                        if let Some(script) = &mut script {
+                            script.events(
+                                &events,
+                                &analysis.signal_samples,
+                                analyzer.device_manager(),
+                            );
+                            error_formatter.report_script_warnings(&script.take_warnings());
                         }
 
-                        // Collect correlations detected during this packet
-                        let correlations = device_manager.take_pending_correlations();
+                        if let Some(error) = analysis.error {
+                            error_formatter.report(error, &analysis.correlations);
+                        }
+
+                        // AL Status Codes of earlier ESM errors that only became
+                        // available with this frame
+                        if !analysis.al_status_code_updates.is_empty() {
+                            error_formatter
+                                .report_al_status_code_updates(&analysis.al_status_code_updates);
+                        }
 
-                        if let Err(error) = result {
-                            error_formatter.report(error, &correlations);
+                        if let Some(self_stats) = &mut self_stats {
+                            self_stats.frame(
+                                &pipeline,
+                                analysis_time,
+                                analysis_started.elapsed() - analysis_time,
+                            );
                         }
 
-                        // Check if any AL Status Codes have been updated for
-                        // devices with pending ESM backward transition errors.
-                        // This handles the case where the AL Status Code becomes
-                        // available in a later packet.
-                        let al_updates = device_manager.check_al_status_code_updates();
-                        if !al_updates.is_empty() {
-                            error_formatter.report_al_status_code_updates(&al_updates);
+                        if capture_trigger
+                            .as_ref()
+                            .is_some_and(|trigger| trigger.is_complete(timestamp))
+                        {
+                            break;
                         }
 
+                        if let Some(guard) = &memory_guard
+                            && let Err(error) = guard.check(analyzer.device_manager_mut())
+                        {
+                            memory_error = Some(error);
+                            break;
+                        }
                     }
                     Err(_) => {
                         break;
@@ -153,16 +527,131 @@ fn main() -> Result<()> {
             }
         }
     }
-    drop(rx_data);
-    drop(tx_buffer);
+    let elapsed = started.elapsed();
+    replay_stop.store(true, Ordering::Relaxed);
+    drop(pipeline);
+    if let Some(trigger) = &capture_trigger {
+        trigger.finish();
+    }
+    if let Some(handle) = handle
+        && let Err(e) = handle.join()
+    {
+        error!("Packet source thread terminated with error: {:?}", e);
+    }
+    let device_manager = analyzer.device_manager();
+    let error_counts = analyzer.errors();
+    for event in rx_status.try_iter() {
+        handle_source_event(
+            event,
+            &mut error_formatter,
+            &mut sinks,
+            device_manager.get_frame_count(),
+            &mut source_error,
+        );
+    }
+
+    sinks.finish_streams()?;
+
+    if let Some(path) = &config.init_sequence {
+        if device_manager.init_complete_packet().is_none() {
+            warn!("Not all subdevices reached Op; the init sequence is incomplete");
+        }
+        init_export::write_init_sequence(
+            path,
+            device_manager.devices(),
+            device_manager.init_sequence(),
+        )?;
+    }
+    if let Some(path) = &config.inventory {
+        inventory::write(path, &source_name, device_manager)?;
+    }
+    if let Some(dir) = &config.dump_registers {
+        register_dump::write(dir, device_manager)?;
+    }
+    if let Some(path) = &config.checkpoint {
+        device_manager
+            .checkpoint()
+            .save(path)
+            .with_context(|| format!("Failed to save the checkpoint {}", path))?;
+    }
 
-    if let Some(handle) = handle {
-        if let Err(e) = handle.join() {
-            error!("Packet source thread terminated with error: {:?}", e);
+    let capture_summary = CaptureSummary {
+        analyzed_frames: device_manager.get_analyzed_frame_count(),
+        elapsed,
+        dropped_frames: dropped_frames.load(Ordering::Relaxed),
+        pool: buffer_pool.metrics(),
+        clock_source,
+        evictions: device_manager.evictions(),
+    };
+    if let Some(dir) = &config.snapshot_on_exit {
+        let path = snapshot::write(
+            dir,
+            &source_name,
+            device_manager,
+            &capture_summary,
+            error_counts,
+        )?;
+        debug!("Snapshot written to {}", path.display());
+    }
+    if !config.quiet {
+        error_formatter.print_summary(
+            &capture_summary,
+            error_counts,
+            device_manager.previous_scans(),
+            device_manager.devices(),
+        );
+        if let Some(script) = &script {
+            error_formatter.print_script_counters(&script.counters());
         }
     }
 
-    error_formatter.print_summary(device_manager.get_frame_count());
+    let mismatches = (!config.expected_topology.is_empty())
+        .then(|| config.expected_topology.check(device_manager.devices()));
+    if let Some(mismatches) = &mismatches {
+        error_formatter.print_topology_check(mismatches);
+    }
+
+    sinks.finish(
+        device_manager,
+        &capture_summary,
+        error_counts,
+        mismatches.as_deref(),
+    )?;
+
+    if mismatches.is_some_and(|mismatches| !mismatches.is_empty()) {
+        anyhow::bail!("Discovered bus topology does not match the expected topology");
+    }
+
+    if let Some(error) = memory_error {
+        return Err(error);
+    }
+    match source_error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
 
-    Ok(())
+/// Report a status event of the packet source. Only the first error is kept as
+/// the exit status, later ones are logged.
+fn handle_source_event(
+    event: SourceEvent,
+    error_formatter: &mut ErrorFormatter,
+    sinks: &mut FrameSinks,
+    packet_number: u64,
+    source_error: &mut Option<anyhow::Error>,
+) {
+    match event {
+        SourceEvent::Failed(error) => match source_error {
+            None => *source_error = Some(error.into()),
+            Some(_) => error!("{:#}", error),
+        },
+        SourceEvent::Skipped { error, timestamp } => {
+            error_formatter.report_skipped_record(&error, packet_number, timestamp)
+        }
+        SourceEvent::Link { up, timestamp } => {
+            error_formatter.report_link_change(up, packet_number, timestamp);
+            sinks.link_change(up, packet_number, timestamp);
+        }
+        SourceEvent::Started(time) => sinks.set_start_time(time),
+    }
 }
@@ -0,0 +1,392 @@
+//! Translations of the operator-facing event messages: WKC failures, ESM errors
+//! and their diagnoses, error acknowledgements and AL Status Code descriptions.
+//!
+//! Every [`Msg`] has a template per [`Lang`] with numbered placeholders (`{0}`,
+//! `{1}`, ...) filled by [`text`]. Identifiers stay as they are in every
+//! language: command names, register addresses, state names and the event
+//! categories of the console. Machine-readable outputs are always English.
+
+use std::fmt;
+
+use crate::registers::AlStatusCode;
+
+/// Language of the operator-facing messages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    En,
+    Ja,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 2] = [Lang::En, Lang::Ja];
+
+    /// ISO 639-1 code of the language.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Ja => "ja",
+        }
+    }
+
+    /// The language of an ISO 639-1 code, also accepting locale names such as
+    /// `ja_JP.UTF-8`.
+    pub fn from_code(code: &str) -> Option<Lang> {
+        let language = code
+            .split(['_', '-', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        Lang::ALL.into_iter().find(|lang| lang.code() == language)
+    }
+}
+
+impl fmt::Display for Lang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// An operator-facing message, translated by [`text`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msg {
+    /// Short WKC causes of the console.
+    WkcNoResponse,
+    WkcPartial,
+    WkcOverCount,
+    /// `{0}` access, `{1}` expected, `{2}` actual, `{3}` cause.
+    WkcDetail,
+    /// `{0}` target state.
+    EsmIllegal,
+    /// `{0}` current, `{1}` requested state.
+    EsmInvalid,
+    /// `{0}` from, `{1}` to, `{2}` [`Msg::EsmErrorFlag`] or nothing.
+    EsmBackward,
+    /// `{0}` requested, `{1}` current state, `{2}` [`Msg::EsmErrorFlag`] or nothing.
+    EsmFailed,
+    /// `{0}` from, `{1}` to.
+    EsmBootstrap,
+    EsmErrorFlag,
+    /// `{0}` address, `{1}` bus position.
+    DiagnosisAutoIncrement,
+    /// `{0}` address.
+    DiagnosisConfigured,
+    /// `{0}` expected, `{1}` command, `{2}` register, `{3}` length.
+    DiagnosisWkcNoResponse,
+    /// `{0}` actual, `{1}` expected, `{2}` missing, `{3}` command, `{4}` register,
+    /// `{5}` length.
+    DiagnosisWkcPartial,
+    /// `{0}` actual, `{1}` expected, `{2}` command, `{3}` register, `{4}` length.
+    DiagnosisWkcOverCount,
+    /// `{0}` target state.
+    DiagnosisIllegal,
+    /// `{0}` requested, `{1}` current state.
+    DiagnosisInvalid,
+    /// `{0}` from, `{1}` to, `{2}` [`Msg::DiagnosisBackwardErrorFlag`] or nothing.
+    DiagnosisBackward,
+    DiagnosisBackwardErrorFlag,
+    /// `{0}` requested, `{1}` current state, `{2}`
+    /// [`Msg::DiagnosisFailedErrorFlag`] or nothing.
+    DiagnosisFailed,
+    DiagnosisFailedErrorFlag,
+    /// `{0}` from, `{1}` to.
+    DiagnosisBootstrap,
+    /// `{0}` formatted code, see [`format_al_status_code`].
+    AlStatusCode,
+    /// `{0}` formatted code, appended to an acknowledgement.
+    AlStatusCodeSuffix,
+    AlStatusCodeVendorSpecific,
+    AlStatusCodeUnknown,
+    /// `{0}` error, `{1}` acknowledge, `{2}` cleared frame.
+    ErrorAcknowledged,
+    /// `{0}` error, `{1}` cleared frame.
+    ErrorClearedWithoutAck,
+}
+
+impl Msg {
+    pub const ALL: [Msg; 28] = [
+        Msg::WkcNoResponse,
+        Msg::WkcPartial,
+        Msg::WkcOverCount,
+        Msg::WkcDetail,
+        Msg::EsmIllegal,
+        Msg::EsmInvalid,
+        Msg::EsmBackward,
+        Msg::EsmFailed,
+        Msg::EsmBootstrap,
+        Msg::EsmErrorFlag,
+        Msg::DiagnosisAutoIncrement,
+        Msg::DiagnosisConfigured,
+        Msg::DiagnosisWkcNoResponse,
+        Msg::DiagnosisWkcPartial,
+        Msg::DiagnosisWkcOverCount,
+        Msg::DiagnosisIllegal,
+        Msg::DiagnosisInvalid,
+        Msg::DiagnosisBackward,
+        Msg::DiagnosisBackwardErrorFlag,
+        Msg::DiagnosisFailed,
+        Msg::DiagnosisFailedErrorFlag,
+        Msg::DiagnosisBootstrap,
+        Msg::AlStatusCode,
+        Msg::AlStatusCodeSuffix,
+        Msg::AlStatusCodeVendorSpecific,
+        Msg::AlStatusCodeUnknown,
+        Msg::ErrorAcknowledged,
+        Msg::ErrorClearedWithoutAck,
+    ];
+
+    /// The templates, in the order of [`Lang`].
+    fn templates(&self) -> [&'static str; 2] {
+        match self {
+            Msg::WkcNoResponse => ["no response", "応答なし"],
+            Msg::WkcPartial => ["partial", "一部応答"],
+            Msg::WkcOverCount => ["over-count", "過剰応答"],
+            Msg::WkcDetail => [
+                "{0}; expected:{1} actual:{2} ({3})",
+                "{0}; 期待値:{1} 実際:{2} ({3})",
+            ],
+            Msg::EsmIllegal => ["illegal -> {0}", "不正遷移 -> {0}"],
+            Msg::EsmInvalid => ["{0} -> {1} invalid", "{0} -> {1} 無効"],
+            Msg::EsmBackward => ["{0} -> {1} backward{2}", "{0} -> {1} 後退{2}"],
+            Msg::EsmFailed => ["-> {0} failed @{1}{2}", "-> {0} 失敗 @{1}{2}"],
+            Msg::EsmBootstrap => [
+                "{0} -> {1} bootstrap only via Init",
+                "{0} -> {1} Bootstrap は Init 経由のみ",
+            ],
+            Msg::EsmErrorFlag => [" +err", " +エラー"],
+            Msg::DiagnosisAutoIncrement => [
+                "Auto-increment address {0} (bus position {1}) does not map to any known device. \
+                 Possible cause: device disconnected or topology change.",
+                "オートインクリメントアドレス {0}（バス位置 {1}）に対応するデバイスがありません。\
+                 考えられる原因: デバイスの切断、またはトポロジーの変更。",
+            ],
+            Msg::DiagnosisConfigured => [
+                "Configured address {0} not found in device map. \
+                 Possible cause: device not yet configured or address conflict.",
+                "設定アドレス {0} がデバイスマップにありません。\
+                 考えられる原因: デバイスが未設定、またはアドレスの重複。",
+            ],
+            Msg::DiagnosisWkcNoResponse => [
+                "WKC=0 (expected {0}): Complete communication failure — \
+                 no device responded to {1} command (register address = {2}, length = {3}). \
+                 Check: cable connections, device power, network topology.",
+                "WKC=0（期待値 {0}）: 通信が完全に途絶しています — \
+                 {1} コマンドにどのデバイスも応答しませんでした（レジスタアドレス = {2}、長さ = {3}）。\
+                 確認項目: ケーブルの接続、デバイスの電源、ネットワーク構成。",
+            ],
+            Msg::DiagnosisWkcPartial => [
+                "WKC={0} (expected {1}): {2} device(s) did not respond to {3} command \
+                 (register address = {4}, length = {5}). \
+                 Partial failure — check individual device status and wiring.",
+                "WKC={0}（期待値 {1}）: {2} 台のデバイスが {3} コマンドに応答しませんでした\
+                 （レジスタアドレス = {4}、長さ = {5}）。\
+                 一部の通信障害です — 各デバイスの状態と配線を確認してください。",
+            ],
+            Msg::DiagnosisWkcOverCount => [
+                "WKC={0} (expected {1}): Unexpected extra responses to {2} command \
+                 (register address = {3}, length = {4}). \
+                 Possible address conflict or duplicate device configuration.",
+                "WKC={0}（期待値 {1}）: {2} コマンドに想定外の応答がありました\
+                 （レジスタアドレス = {3}、長さ = {4}）。\
+                 アドレスの重複、またはデバイス設定の重複の可能性があります。",
+            ],
+            Msg::DiagnosisIllegal => [
+                "Illegal state transition to {0}.",
+                "{0} への不正な状態遷移です。",
+            ],
+            Msg::DiagnosisInvalid => [
+                "Invalid state transition: requested {0} but device is in {1}.",
+                "無効な状態遷移です: {0} が要求されましたが、デバイスは {1} です。",
+            ],
+            Msg::DiagnosisBackward => [
+                "Backward state transition {0} -> {1}.{2} \
+                 The device may have encountered an internal fault.",
+                "状態が {0} -> {1} に戻りました。{2}\
+                 デバイス内部で障害が発生した可能性があります。",
+            ],
+            Msg::DiagnosisBackwardErrorFlag => [
+                " Device reported an error flag.",
+                "デバイスがエラーフラグを報告しています。",
+            ],
+            Msg::DiagnosisFailed => [
+                "State transition to {0} failed; device stuck in {1}.{2} \
+                 Check AL Status Code for details.",
+                "{0} への状態遷移に失敗し、デバイスは {1} のままです。{2}\
+                 詳細は AL ステータスコードを確認してください。",
+            ],
+            Msg::DiagnosisFailedErrorFlag => {
+                [" Error flag is set.", "エラーフラグが立っています。"]
+            }
+            Msg::DiagnosisBootstrap => [
+                "Invalid state transition {0} -> {1}. \
+                 Bootstrap can only be entered from and left to Init.",
+                "無効な状態遷移 {0} -> {1} です。\
+                 Bootstrap へは Init からのみ遷移でき、Init へのみ戻れます。",
+            ],
+            Msg::AlStatusCode => ["AL Status Code: {0}", "AL ステータスコード: {0}"],
+            Msg::AlStatusCodeSuffix => [", AL Status Code {0}", "、AL ステータスコード {0}"],
+            Msg::AlStatusCodeVendorSpecific => ["vendor specific", "ベンダー固有"],
+            Msg::AlStatusCodeUnknown => ["unknown", "不明"],
+            Msg::ErrorAcknowledged => [
+                "error #{0} -> ack #{1} -> cleared #{2}",
+                "エラー #{0} -> 確認 #{1} -> 解除 #{2}",
+            ],
+            Msg::ErrorClearedWithoutAck => [
+                "error #{0} -> cleared #{1} without ack",
+                "エラー #{0} -> 確認なしで解除 #{1}",
+            ],
+        }
+    }
+
+    pub fn template(&self, lang: Lang) -> &'static str {
+        self.templates()[lang as usize]
+    }
+}
+
+/// The message in `lang`, with `{n}` replaced by `args[n]`.
+pub fn text(lang: Lang, msg: Msg, args: &[&dyn fmt::Display]) -> String {
+    let mut text = String::new();
+    let mut rest = msg.template(lang);
+    while let Some(start) = rest.find('{') {
+        let (before, placeholder) = rest.split_at(start);
+        text.push_str(before);
+        let Some(end) = placeholder.find('}') else {
+            rest = placeholder;
+            break;
+        };
+        match placeholder[1..end]
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| args.get(index))
+        {
+            Some(arg) => text.push_str(&arg.to_string()),
+            None => text.push_str(&placeholder[..=end]),
+        }
+        rest = &placeholder[end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Name of an AL Status Code in `lang`.
+pub fn al_status_code_name(lang: Lang, code: AlStatusCode) -> &'static str {
+    if lang == Lang::En {
+        return code.name();
+    }
+    match code {
+        AlStatusCode::NoError => "エラーなし",
+        AlStatusCode::UnspecifiedError => "詳細不明のエラー",
+        AlStatusCode::NoMemory => "メモリ不足",
+        AlStatusCode::InvalidDeviceSetup => "デバイス設定が無効",
+        AlStatusCode::CompatibilityReserved => "予約（互換性）",
+        AlStatusCode::InvalidRequestedStateChange => "要求された状態遷移が無効",
+        AlStatusCode::UnknownRequestedState => "要求された状態が不明",
+        AlStatusCode::BootstrapNotSupported => "Bootstrap 非対応",
+        AlStatusCode::NoValidFirmware => "有効なファームウェアなし",
+        AlStatusCode::InvalidMailboxConfiguration => "メールボックス設定が無効",
+        AlStatusCode::InvalidMailboxConfiguration2 => "メールボックス設定が無効 (2)",
+        AlStatusCode::InvalidSyncManagerConfiguration => "Sync Manager 設定が無効",
+        AlStatusCode::NoValidInputsAvailable => "有効な入力なし",
+        AlStatusCode::NoValidOutputs => "有効な出力なし",
+        AlStatusCode::SynchronizationError => "同期エラー",
+        AlStatusCode::SyncManagerWatchdog => "Sync Manager ウォッチドッグ",
+        AlStatusCode::InvalidSyncManagerTypes => "Sync Manager の種類が無効",
+        AlStatusCode::InvalidOutputConfiguration => "出力設定が無効",
+        AlStatusCode::InvalidInputConfiguration => "入力設定が無効",
+        AlStatusCode::InvalidWatchdogConfiguration => "ウォッチドッグ設定が無効",
+        AlStatusCode::SubDeviceNeedsColdStart => "サブデバイスのコールドスタートが必要",
+        AlStatusCode::SubDeviceNeedsInit => "サブデバイスは INIT が必要",
+        AlStatusCode::SubDeviceNeedsPreop => "サブデバイスは PREOP が必要",
+        AlStatusCode::SubDeviceNeedsSafeop => "サブデバイスは SAFEOP が必要",
+        AlStatusCode::InvalidInputMapping => "入力マッピングが無効",
+        AlStatusCode::InvalidOutputMapping => "出力マッピングが無効",
+        AlStatusCode::InconsistentSettings => "設定の不整合",
+        AlStatusCode::FreeRunNotSupported => "FreeRun 非対応",
+        AlStatusCode::SyncModeNotSupported => "同期モード非対応",
+        AlStatusCode::FreeRunNeeds3BufferMode => "FreeRun には 3 バッファモードが必要",
+        AlStatusCode::BackgroundWatchdog => "バックグラウンドウォッチドッグ",
+        AlStatusCode::NoValidInputsAndOutputs => "有効な入出力なし",
+        AlStatusCode::FatalSyncError => "致命的な同期エラー",
+        AlStatusCode::NoSyncError => "同期信号なし",
+        AlStatusCode::InvalidDcSyncConfiguration => "DC SYNC 設定が無効",
+        AlStatusCode::InvalidDcLatchConfiguration => "DC ラッチ設定が無効",
+        AlStatusCode::PllError => "PLL エラー",
+        AlStatusCode::DcSyncIoError => "DC 同期 IO エラー",
+        AlStatusCode::DcSyncTimeoutError => "DC 同期タイムアウトエラー",
+        AlStatusCode::DcInvalidSyncCycleTime => "DC 同期サイクル時間が無効",
+        AlStatusCode::DcSync0CycleTime => "DC Sync0 サイクル時間",
+        AlStatusCode::DcSync1CycleTime => "DC Sync1 サイクル時間",
+        AlStatusCode::MbxAoe => "メールボックス AoE",
+        AlStatusCode::MbxEoe => "メールボックス EoE",
+        AlStatusCode::MbxCoe => "メールボックス CoE",
+        AlStatusCode::MbxFoe => "メールボックス FoE",
+        AlStatusCode::MbxSoe => "メールボックス SoE",
+        AlStatusCode::MbxVoe => "メールボックス VoE",
+        AlStatusCode::EepromNoAccess => "EEPROM にアクセスできない",
+        AlStatusCode::EepromError => "EEPROM エラー",
+        AlStatusCode::SubDeviceRestartedLocally => "サブデバイスがローカルで再起動",
+        AlStatusCode::DeviceIdentificationValueUpdated => "デバイス識別値が更新された",
+        AlStatusCode::ApplicationControllerAvailable => "アプリケーションコントローラが利用可能",
+    }
+}
+
+/// A raw AL Status Code with its description in `lang`, see
+/// [`format_al_status_code`](crate::registers::format_al_status_code).
+pub fn format_al_status_code(lang: Lang, code: u16) -> String {
+    match AlStatusCode::from_u16(code) {
+        Some(known) => format!("{:#06x} ({})", code, al_status_code_name(lang, known)),
+        None if code >= 0x8000 => format!(
+            "{:#06x} ({})",
+            code,
+            text(lang, Msg::AlStatusCodeVendorSpecific, &[])
+        ),
+        None => format!(
+            "{:#06x} ({})",
+            code,
+            text(lang, Msg::AlStatusCodeUnknown, &[])
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(template: &str) -> Vec<&str> {
+        let mut placeholders: Vec<&str> = template
+            .match_indices('{')
+            .filter_map(|(start, _)| {
+                let end = start + template[start..].find('}')?;
+                Some(&template[start..=end])
+            })
+            .collect();
+        placeholders.sort_unstable();
+        placeholders
+    }
+
+    #[test]
+    fn test_every_translation_has_the_placeholders_of_the_english_message() {
+        for msg in Msg::ALL {
+            let english = placeholders(msg.template(Lang::En));
+            for lang in Lang::ALL {
+                assert_eq!(
+                    placeholders(msg.template(lang)),
+                    english,
+                    "{:?} in {}",
+                    msg,
+                    lang
+                );
+            }
+        }
+        assert_eq!(
+            text(Lang::Ja, Msg::EsmFailed, &[&"OP", &"SAFEOP", &""]),
+            "-> OP 失敗 @SAFEOP"
+        );
+        assert_eq!(
+            format_al_status_code(Lang::Ja, 0x001B),
+            "0x001b (Sync Manager ウォッチドッグ)"
+        );
+        assert_eq!(Lang::from_code("ja_JP.UTF-8"), Some(Lang::Ja));
+        assert_eq!(Lang::from_code("de"), None);
+    }
+}
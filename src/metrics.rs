@@ -0,0 +1,414 @@
+use anyhow::{Context, Result};
+use ecdump::analysis::device_error_category;
+use ecdump::analyzer::{DeviceManager, ECError};
+use ecdump::subdevice::ECState;
+use log::{debug, warn};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::event_stream::FrameEvents;
+
+/// How often the per-subdevice metrics are refreshed from the analyzer.
+const DEVICE_REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+/// Timeout for reading a request, so a stalled client does not block scrapes.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+struct DeviceMetrics {
+    subdevice: String,
+    state: ECState,
+    wkc_errors: u64,
+    esm_errors: u64,
+}
+
+/// Interval between frames sent by the main device, since the last scrape.
+#[derive(Default, Clone, Copy)]
+struct CycleWindow {
+    count: u64,
+    sum: f64,
+    sum_squares: f64,
+    max: f64,
+}
+
+impl CycleWindow {
+    fn add(&mut self, interval: f64) {
+        self.count += 1;
+        self.sum += interval;
+        self.sum_squares += interval * interval;
+        self.max = self.max.max(interval);
+    }
+
+    fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    fn standard_deviation(&self) -> f64 {
+        let mean = self.mean();
+        (self.sum_squares / self.count as f64 - mean * mean)
+            .max(0.0)
+            .sqrt()
+    }
+}
+
+#[derive(Default)]
+struct Metrics {
+    frames_main: u64,
+    frames_returning: u64,
+    bytes: u64,
+    errors: BTreeMap<&'static str, u64>,
+    link_up: bool,
+    devices: Vec<DeviceMetrics>,
+    cycle: CycleWindow,
+    /// Window of the previous scrape, reported again if no frame arrived since.
+    last_cycle: Option<CycleWindow>,
+}
+
+/// Serves the analyzer state in the Prometheus text format on `/metrics`
+/// (`--metrics`). The HTTP server runs on its own thread; the analysis loop
+/// records every frame.
+pub struct MetricsServer {
+    metrics: Arc<Mutex<Metrics>>,
+    last_main_timestamp: Option<Duration>,
+    last_device_refresh: Option<Instant>,
+}
+
+impl MetricsServer {
+    /// Listen on `address`, e.g. `0.0.0.0:9898`. `dropped_frames` is the drop
+    /// counter of the live capture.
+    pub fn start(address: &str, dropped_frames: Arc<AtomicU64>) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("Failed to listen for metrics on {}", address))?;
+        debug!(
+            "Serving metrics on http://{}/metrics",
+            listener.local_addr()?
+        );
+        let metrics = Arc::new(Mutex::new(Metrics {
+            link_up: true,
+            ..Default::default()
+        }));
+        let server_metrics = metrics.clone();
+        std::thread::Builder::new()
+            .name("metrics".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result = stream.map_err(anyhow::Error::from).and_then(|stream| {
+                        serve(
+                            stream,
+                            &server_metrics,
+                            dropped_frames.load(Ordering::Relaxed),
+                        )
+                    });
+                    if let Err(e) = result {
+                        warn!("Failed to serve metrics: {:#}", e);
+                    }
+                }
+            })?;
+        Ok(MetricsServer {
+            metrics,
+            last_main_timestamp: None,
+            last_device_refresh: None,
+        })
+    }
+
+    pub fn record_frame(&mut self, events: &FrameEvents, device_manager: &DeviceManager) {
+        let interval = if events.from_main {
+            let interval = self
+                .last_main_timestamp
+                .map(|last| events.timestamp.saturating_sub(last).as_secs_f64());
+            self.last_main_timestamp = Some(events.timestamp);
+            interval
+        } else {
+            None
+        };
+        let refresh = self
+            .last_device_refresh
+            .is_none_or(|last| last.elapsed() >= DEVICE_REFRESH_INTERVAL);
+
+        let mut metrics = self.metrics.lock().unwrap();
+        if events.from_main {
+            metrics.frames_main += 1;
+        } else {
+            metrics.frames_returning += 1;
+        }
+        metrics.bytes += events.length as u64;
+        if let Some(interval) = interval {
+            metrics.cycle.add(interval);
+        }
+        for _ in events.malformed {
+            *metrics.errors.entry("malformed_frame").or_default() += 1;
+        }
+        match events.error {
+            Some(ECError::InvalidDatagram { .. }) => {
+                *metrics.errors.entry("invalid_datagram").or_default() += 1;
+            }
+            Some(ECError::DeviceError(errors)) => {
+                for error in errors {
+                    *metrics
+                        .errors
+                        .entry(device_error_category(error))
+                        .or_default() += 1;
+                }
+            }
+            None => {}
+        }
+
+        if refresh {
+            self.last_device_refresh = Some(Instant::now());
+            metrics.devices = device_manager
+                .devices()
+                .iter()
+                .map(|device| DeviceMetrics {
+                    subdevice: device.identifier().to_string(),
+                    state: device.state(),
+                    wkc_errors: device.statistics().wkc_errors,
+                    esm_errors: device.statistics().esm_errors,
+                })
+                .collect();
+        }
+    }
+
+    pub fn set_link(&mut self, up: bool) {
+        self.metrics.lock().unwrap().link_up = up;
+    }
+}
+
+fn serve(mut stream: TcpStream, metrics: &Mutex<Metrics>, dropped_frames: u64) -> Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 8192 {
+        let n = stream.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    let (method, path) = (request_line.next(), request_line.next());
+
+    let (status, body) = match (method, path) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = render(&mut metrics.lock().unwrap(), dropped_frames);
+            ("200 OK", body)
+        }
+        (Some("GET"), _) => ("404 Not Found", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Method not allowed\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Render the metrics in the Prometheus text exposition format. Cycle time and
+/// jitter cover the frames since the previous scrape.
+fn render(metrics: &mut Metrics, dropped_frames: u64) -> String {
+    let mut out = String::new();
+    let header = |out: &mut String, name: &str, kind: &str, help: &str| {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+    };
+
+    header(
+        &mut out,
+        "ecdump_frames_total",
+        "counter",
+        "EtherCAT frames analyzed.",
+    );
+    writeln!(
+        out,
+        "ecdump_frames_total{{direction=\"main\"}} {}",
+        metrics.frames_main
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "ecdump_frames_total{{direction=\"returning\"}} {}",
+        metrics.frames_returning
+    )
+    .unwrap();
+    header(
+        &mut out,
+        "ecdump_bytes_total",
+        "counter",
+        "EtherCAT bytes analyzed.",
+    );
+    writeln!(out, "ecdump_bytes_total {}", metrics.bytes).unwrap();
+    header(
+        &mut out,
+        "ecdump_dropped_frames_total",
+        "counter",
+        "Frames lost by the capture before analysis.",
+    );
+    writeln!(out, "ecdump_dropped_frames_total {}", dropped_frames).unwrap();
+    header(
+        &mut out,
+        "ecdump_errors_total",
+        "counter",
+        "Errors detected by category.",
+    );
+    for (category, count) in &metrics.errors {
+        writeln!(
+            out,
+            "ecdump_errors_total{{category=\"{}\"}} {}",
+            category, count
+        )
+        .unwrap();
+    }
+    header(
+        &mut out,
+        "ecdump_link_up",
+        "gauge",
+        "Whether the capture link is up.",
+    );
+    writeln!(out, "ecdump_link_up {}", u8::from(metrics.link_up)).unwrap();
+
+    let cycle = match metrics.cycle.count {
+        0 => metrics.last_cycle,
+        _ => Some(std::mem::take(&mut metrics.cycle)),
+    };
+    metrics.last_cycle = cycle;
+    if let Some(cycle) = cycle {
+        header(
+            &mut out,
+            "ecdump_cycle_time_seconds",
+            "gauge",
+            "Mean interval between frames sent by the main device.",
+        );
+        writeln!(out, "ecdump_cycle_time_seconds {}", cycle.mean()).unwrap();
+        header(
+            &mut out,
+            "ecdump_cycle_time_max_seconds",
+            "gauge",
+            "Longest interval between frames sent by the main device.",
+        );
+        writeln!(out, "ecdump_cycle_time_max_seconds {}", cycle.max).unwrap();
+        header(
+            &mut out,
+            "ecdump_cycle_jitter_seconds",
+            "gauge",
+            "Standard deviation of the interval between frames sent by the main device.",
+        );
+        writeln!(
+            out,
+            "ecdump_cycle_jitter_seconds {}",
+            cycle.standard_deviation()
+        )
+        .unwrap();
+    }
+
+    header(
+        &mut out,
+        "ecdump_subdevices",
+        "gauge",
+        "Subdevices on the bus.",
+    );
+    writeln!(out, "ecdump_subdevices {}", metrics.devices.len()).unwrap();
+    header(
+        &mut out,
+        "ecdump_subdevices_not_op",
+        "gauge",
+        "Subdevices not in Op.",
+    );
+    let not_op = metrics
+        .devices
+        .iter()
+        .filter(|device| device.state != ECState::Op)
+        .count();
+    writeln!(out, "ecdump_subdevices_not_op {}", not_op).unwrap();
+    header(
+        &mut out,
+        "ecdump_subdevice_state",
+        "gauge",
+        "AL state of a subdevice (1 Init, 2 PreOp, 3 Bootstrap, 4 SafeOp, 8 Op).",
+    );
+    for device in &metrics.devices {
+        writeln!(
+            out,
+            "ecdump_subdevice_state{{subdevice=\"{}\"}} {}",
+            escape_label(&device.subdevice),
+            device.state as u8
+        )
+        .unwrap();
+    }
+    header(
+        &mut out,
+        "ecdump_subdevice_wkc_errors_total",
+        "counter",
+        "WKC faults attributed to a subdevice.",
+    );
+    for device in &metrics.devices {
+        writeln!(
+            out,
+            "ecdump_subdevice_wkc_errors_total{{subdevice=\"{}\"}} {}",
+            escape_label(&device.subdevice),
+            device.wkc_errors
+        )
+        .unwrap();
+    }
+    header(
+        &mut out,
+        "ecdump_subdevice_esm_errors_total",
+        "counter",
+        "ESM errors reported for a subdevice.",
+    );
+    for device in &metrics.devices {
+        writeln!(
+            out,
+            "ecdump_subdevice_esm_errors_total{{subdevice=\"{}\"}} {}",
+            escape_label(&device.subdevice),
+            device.esm_errors
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_reports_cycle_of_last_window() {
+        let mut metrics = Metrics {
+            link_up: true,
+            devices: vec![DeviceMetrics {
+                subdevice: "Address \"1001\"".to_string(),
+                state: ECState::SafeOp,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        metrics.cycle.add(0.001);
+        metrics.cycle.add(0.003);
+
+        let first = render(&mut metrics, 3);
+        assert!(first.contains("ecdump_dropped_frames_total 3\n"));
+        assert!(first.contains("ecdump_cycle_time_seconds 0.002\n"));
+        assert!(first.contains("ecdump_cycle_time_max_seconds 0.003\n"));
+        assert!(first.contains("ecdump_subdevices_not_op 1\n"));
+        assert!(first.contains("ecdump_subdevice_state{subdevice=\"Address \\\"1001\\\"\"} 4\n"));
+
+        // No frames since: the previous window is reported again
+        let second = render(&mut metrics, 3);
+        assert!(second.contains("ecdump_cycle_time_seconds 0.002\n"));
+    }
+}
@@ -0,0 +1,261 @@
+use console::{Style, style};
+use ecdump::analyzer::{ECDeviceError, ECError};
+use ecdump::ec_packet::{ECDatagram, ECFrame};
+use ecdump::registers::default_decoder;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+/// Payload bytes shown in hex per datagram at `-vv`.
+const MAX_PAYLOAD_BYTES: usize = 32;
+
+/// How the addressing of a datagram is read, by its command.
+enum Addressing {
+    Position,
+    Node,
+    Broadcast,
+    Logical,
+    None,
+}
+
+fn addressing(datagram: &ECDatagram) -> Addressing {
+    match datagram.command().as_str().as_bytes()[0] {
+        b'A' => Addressing::Position,
+        b'F' => Addressing::Node,
+        b'B' => Addressing::Broadcast,
+        b'L' => Addressing::Logical,
+        _ => Addressing::None,
+    }
+}
+
+/// Address part of a datagram, as in `FPRD 0x1001:0x0130`.
+fn format_address(datagram: &ECDatagram) -> String {
+    let (adp, ado) = datagram.address();
+    match addressing(datagram) {
+        Addressing::Position | Addressing::Node => format!("{:#06x}:{:#06x}", adp, ado),
+        Addressing::Broadcast => format!("{:#06x}", ado),
+        Addressing::Logical => format!("{:#010x}", datagram.logical_address()),
+        Addressing::None => String::new(),
+    }
+}
+
+/// Number of WKC faults the analyzer reported for the frame.
+fn wkc_faults(error: Option<&ECError>) -> usize {
+    match error {
+        Some(ECError::DeviceError(errors)) => errors
+            .iter()
+            .filter(|error| matches!(error, ECDeviceError::InvalidWkc(_)))
+            .count(),
+        _ => 0,
+    }
+}
+
+/// Direction arrow and the WKC verdict, which only returning frames have.
+fn direction_and_status(from_main: bool, error: Option<&ECError>) -> (String, String) {
+    if from_main {
+        return (style("main →").dim().to_string(), String::new());
+    }
+    let status = match wkc_faults(error) {
+        0 => style("WKC ok").green().to_string(),
+        1 => style("WKC mismatch").red().bold().to_string(),
+        n => style(format!("WKC mismatch ({})", n))
+            .red()
+            .bold()
+            .to_string(),
+    };
+    (style("ret  ←").dim().to_string(), status)
+}
+
+/// One line per frame (`-v`): frame number, time, direction, the datagrams with
+/// their working counters and, for returning frames, whether the WKCs matched.
+pub fn format_summary(
+    frame: &ECFrame,
+    frame_number: u64,
+    timestamp: Duration,
+    from_main: bool,
+    error: Option<&ECError>,
+) -> String {
+    let (direction, status) = direction_and_status(from_main, error);
+    let mut out = format!(
+        "{} {} ",
+        Style::new().color256(244).apply_to(format!(
+            "#{:<6} [{:>9.6}s]",
+            frame_number,
+            timestamp.as_secs_f64()
+        )),
+        direction
+    );
+    match frame.parse_datagram() {
+        Ok(datagrams) => {
+            for (i, datagram) in datagrams.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write!(
+                    out,
+                    "{} {} ({}) wkc {}",
+                    style(datagram.command().as_str()).bold(),
+                    format_address(datagram),
+                    datagram.length(),
+                    datagram.wkc()
+                )
+                .unwrap();
+            }
+        }
+        Err(error) => out.push_str(&style(error.to_string()).red().to_string()),
+    }
+    if !status.is_empty() {
+        write!(out, "  {}", status).unwrap();
+    }
+    out
+}
+
+/// Full decode of a frame (`-vv`): a header line, then every datagram with its
+/// header fields, payload and the registers it accesses.
+pub fn format_detail(
+    frame: &ECFrame,
+    frame_number: u64,
+    timestamp: Duration,
+    from_main: bool,
+    error: Option<&ECError>,
+) -> Vec<String> {
+    let (direction, status) = direction_and_status(from_main, error);
+    let mut header = format!(
+        "{} {} {}, {} bytes",
+        style(format!("Frame {}:", frame_number)).bold(),
+        Style::new()
+            .color256(244)
+            .apply_to(format!("[{:.6}s]", timestamp.as_secs_f64())),
+        direction,
+        frame.datagram_length(),
+    );
+    if !status.is_empty() {
+        write!(header, "  {}", status).unwrap();
+    }
+    let mut lines = vec![header];
+    let datagrams = match frame.parse_datagram() {
+        Ok(datagrams) => datagrams,
+        Err(error) => {
+            lines.push(format!("    {}", style(error.to_string()).red()));
+            return lines;
+        }
+    };
+
+    for (i, datagram) in datagrams.iter().enumerate() {
+        let (adp, ado) = datagram.address();
+        let address = match addressing(datagram) {
+            // Subdevices increment the address, so only the sent frame has the position
+            Addressing::Position if from_main => format!(
+                "adp {:#06x} (position {}) ado {:#06x}",
+                adp,
+                (adp as i16).wrapping_neg(),
+                ado
+            ),
+            Addressing::Position => format!("adp {:#06x} ado {:#06x}", adp, ado),
+            Addressing::Node => format!("adp {:#06x} ado {:#06x}", adp, ado),
+            Addressing::Broadcast => format!("ado {:#06x}", ado),
+            Addressing::Logical => format!("logical {:#010x}", datagram.logical_address()),
+            Addressing::None => String::new(),
+        };
+        lines.push(format!(
+            "    [{}] {:<4} idx {:#04x}  {}  len {}  wkc {}{}",
+            i,
+            style(datagram.command().as_str()).bold(),
+            datagram.index(),
+            address,
+            datagram.length(),
+            datagram.wkc(),
+            if datagram.irq() != 0 {
+                format!("  irq {:#06x}", datagram.irq())
+            } else {
+                String::new()
+            }
+        ));
+
+        let payload = datagram.payload();
+        if !payload.is_empty() {
+            let mut hex = payload
+                .iter()
+                .take(MAX_PAYLOAD_BYTES)
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if payload.len() > MAX_PAYLOAD_BYTES {
+                hex.push_str(" …");
+            }
+            lines.push(format!("        data {}", style(hex).dim()));
+        }
+
+        if matches!(
+            addressing(datagram),
+            Addressing::Position | Addressing::Node | Addressing::Broadcast
+        ) {
+            lines.extend(register_lines(ado, payload));
+        }
+    }
+    lines
+}
+
+/// The registers within `ado..ado + payload.len()`, decoded from the payload.
+fn register_lines(ado: u16, payload: &[u8]) -> Vec<String> {
+    let decoder = default_decoder();
+    let end = ado as u32 + payload.len() as u32;
+    let mut lines = Vec::new();
+    // A register the access starts inside of
+    if let Some(definition) = decoder.lookup(ado)
+        && definition.address < ado
+    {
+        lines.push(format!(
+            "        {:#06x} {}",
+            definition.address,
+            style(definition.name).cyan()
+        ));
+    }
+    for definition in decoder
+        .definitions()
+        .filter(|definition| definition.address >= ado && (definition.address as u32) < end)
+    {
+        let offset = (definition.address - ado) as usize;
+        let bytes = &payload[offset..];
+        let value = (bytes.len() >= definition.length() as usize)
+            .then(|| {
+                definition
+                    .kind
+                    .decode(&bytes[..definition.length() as usize])
+            })
+            .flatten();
+        match value {
+            Some(value) => lines.push(format!(
+                "        {:#06x} {} = {}",
+                definition.address,
+                style(definition.name).cyan(),
+                value
+            )),
+            None => lines.push(format!(
+                "        {:#06x} {}",
+                definition.address,
+                style(definition.name).cyan()
+            )),
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_lines_decode_accessed_registers() {
+        console::set_colors_enabled(false);
+        // FPRD of AL Status (0x0130) and AL Status Code (0x0134): 6 bytes
+        let lines = register_lines(0x0130, &[0x08, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("        0x0130 "));
+        assert!(lines[1].starts_with("        0x0134 "));
+
+        // An access starting inside a register names it without decoding
+        let lines = register_lines(0x0135, &[0x00]);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("        0x0134 "));
+    }
+}
@@ -1,275 +1,1048 @@
-use anyhow::{Result, anyhow, bail};
+use anyhow::{Context, Result, anyhow, bail};
+use byteorder_slice::{BigEndian, LittleEndian};
 use bytes::{BufMut, Bytes, BytesMut};
-use crossbeam_channel::{Receiver as CbReceiver, Sender as CbSender, bounded, select, unbounded};
-use log::error;
+use crossbeam_channel::{
+    Receiver as CbReceiver, Sender as CbSender, TrySendError, bounded, select, unbounded,
+};
+use ecdump::capture_file::{
+    CaptureFileError, DEFAULT_TS_RESOLUTION, interface_ts_resolution, is_pcapng_magic,
+    is_truncated, scale_pcapng_timestamp,
+};
+use log::{debug, error, warn};
 use netdev::prelude::OperState;
-use pcap_file::pcap::PcapWriter;
-use pcap_file::{pcap, pcapng, pcapng::Block as PcapNgBlock};
+use pcap_file::{DataLink, Endianness, pcap, pcapng, pcapng::Block as PcapNgBlock};
 use pnet::datalink::Channel::Ethernet;
-use pnet::datalink::{Config, NetworkInterface};
+use pnet::datalink::{Config, DataLinkReceiver, NetworkInterface};
 use pnet::packet::Packet;
 use pnet::packet::ethernet::EthernetPacket;
 use pnet::util::MacAddr;
-use std::borrow::Cow;
-use std::fs::File;
-use std::io::BufWriter;
+use serde::Serialize;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::thread::JoinHandle;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::buffer_pool::{BufferPool, PoolExhaustion};
+use crate::capture_writer::{OutputFile, OutputFormat, RotatingWriter};
+use crate::seek_index::{Indexing, Resume};
+use crate::startup::AnalysisWindow;
+#[cfg(target_os = "linux")]
+use crate::tpacket::{self, TpacketReceiver};
+#[cfg(target_os = "linux")]
+use std::os::fd::{IntoRawFd, RawFd};
 
 pub struct CapturedData {
+    /// Position of the frame in the capture, starting at 1. It matches the frame
+    /// number in the output file, also when frames are dropped before they reach
+    /// the analyzer or other EtherTypes are written with `--all-ethertypes`.
+    pub sequence: u64,
     pub timestamp: Duration,
     pub from_main: bool,
     pub data: Bytes,
 }
 
+/// Handles returned when a packet source is started: the optional writer/reader
+/// thread, the pool to return frame buffers to, the captured data receiver and the
+/// status receiver for [`SourceEvent`]s.
+pub type PacketSourceHandles = (
+    Option<JoinHandle<()>>,
+    BufferPool,
+    CbReceiver<CapturedData>,
+    CbReceiver<SourceEvent>,
+);
+
+/// Error of a packet source thread, reported as a [`SourceEvent`].
+#[derive(Debug)]
+pub enum PacketSourceError {
+    /// The capture file could not be read.
+    Read(CaptureFileError),
+    /// A record of the capture file too short for an Ethernet header.
+    ShortFrame { length: usize },
+    /// Receiving from the capture interface failed.
+    Capture {
+        interface: String,
+        error: std::io::Error,
+    },
+    /// Writing the output file failed.
+    Write(anyhow::Error),
+}
+
+impl fmt::Display for PacketSourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketSourceError::Read(e) => write!(f, "{}", e),
+            PacketSourceError::ShortFrame { length } => {
+                write!(
+                    f,
+                    "Skipped a {} byte record, too short for Ethernet",
+                    length
+                )
+            }
+            PacketSourceError::Capture { interface, error } => {
+                write!(f, "Capture on {} failed: {}", interface, error)
+            }
+            PacketSourceError::Write(e) => write!(f, "Failed to write the output file: {:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for PacketSourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PacketSourceError::Read(e) => e.source(),
+            PacketSourceError::ShortFrame { .. } => None,
+            PacketSourceError::Capture { error, .. } => Some(error),
+            PacketSourceError::Write(_) => None,
+        }
+    }
+}
+
+impl From<CaptureFileError> for PacketSourceError {
+    fn from(error: CaptureFileError) -> Self {
+        PacketSourceError::Read(error)
+    }
+}
+
+/// Status of a packet source, sent alongside the captured frames.
+pub enum SourceEvent {
+    /// The source stopped because of this error.
+    Failed(PacketSourceError),
+    /// A record of the capture file was skipped and reading continues.
+    /// `timestamp` is on the same clock as the frames.
+    Skipped {
+        error: PacketSourceError,
+        timestamp: Duration,
+    },
+    /// The link of the capture interface went down, or came back up and the
+    /// capture was reopened. `timestamp` is on the same clock as the frames.
+    Link { up: bool, timestamp: Duration },
+    /// Wall-clock time that frame timestamps are relative to, sent with the first
+    /// frame. File sources without absolute timestamps do not send it.
+    Started(SystemTime),
+}
+
+/// How often live capture checks the operational state of the interface.
+const LINK_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches the operational state of the capture interface.
+struct LinkMonitor {
+    name: String,
+    up: bool,
+    last_check: Instant,
+}
+
+impl LinkMonitor {
+    fn new(name: String) -> Self {
+        LinkMonitor {
+            name,
+            up: true,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Whether the link is up. Interfaces that do not report a state (such as
+    /// `lo`) count as up as long as they exist.
+    fn is_up(&self) -> bool {
+        // netdev expects the adapter GUID on Windows
+        let name = self.name.trim_start_matches(NPF_PREFIX);
+        match netdev::interface::state::operstate(name) {
+            OperState::Down | OperState::LowerLayerDown | OperState::NotPresent => false,
+            OperState::Unknown => pnet::datalink::interfaces()
+                .iter()
+                .any(|interface| interface.name == self.name),
+            _ => true,
+        }
+    }
+
+    /// The new link state if it changed, checked at most every `LINK_CHECK_INTERVAL`.
+    fn poll(&mut self) -> Option<bool> {
+        if self.last_check.elapsed() < LINK_CHECK_INTERVAL {
+            return None;
+        }
+        self.last_check = Instant::now();
+        let up = self.is_up();
+        (up != self.up).then(|| {
+            self.up = up;
+            up
+        })
+    }
+}
+
+/// Error opening the capture on `interface`, with a hint on how to get the
+/// required privileges.
+fn open_error(error: std::io::Error, interface: &NetworkInterface) -> anyhow::Error {
+    if error.kind() != std::io::ErrorKind::PermissionDenied {
+        return anyhow!("Failed to open {}: {}", interface.name, error);
+    }
+    let hint = if cfg!(target_os = "linux") {
+        "run as root or grant the capture capabilities with \
+         `sudo setcap cap_net_raw,cap_net_admin=eip $(which ecdump)`"
+    } else if cfg!(target_os = "windows") {
+        "install Npcap and run as administrator, or allow non-admin capture in the Npcap setup"
+    } else {
+        "run with sudo or get read access to /dev/bpf*"
+    };
+    anyhow!(
+        "Permission denied capturing on {}: {}",
+        interface.name,
+        hint
+    )
+}
+
+/// What live capture does when the analyzer falls behind and the capture queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BackpressurePolicy {
+    /// Wait for the analyzer (lossless, frames may be dropped by the kernel instead).
+    #[default]
+    Block,
+    /// Discard the oldest queued frame to make room for the new one.
+    DropOldest,
+    /// Discard the newly captured frame.
+    DropNewest,
+}
+
+/// How frames are received from the network interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CaptureBackend {
+    /// Portable socket capture (one system call per frame).
+    #[default]
+    Pnet,
+    /// Memory-mapped `AF_PACKET` TPACKET_V3 ring (Linux only).
+    Tpacket,
+}
+
+/// Settings of live capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureOptions {
+    pub backend: CaptureBackend,
+    /// Bytes of each frame kept in the output file; the analyzer sees whole frames.
+    pub snaplen: Option<u32>,
+    /// Kernel buffer in bytes, `None` for the system default.
+    pub buffer_size: Option<usize>,
+    pub promiscuous: bool,
+    /// Hand frames to ecdump without batching.
+    pub immediate: bool,
+    /// Write frames of every EtherType to the output file, not only EtherCAT.
+    /// Otherwise other frames are already dropped in the kernel on Linux.
+    pub all_ethertypes: bool,
+    pub pool_exhaustion: PoolExhaustion,
+    /// Have the adapter timestamp frames (`tpacket` only).
+    pub hw_timestamps: bool,
+}
+
+/// Clock that timestamped live captured frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Read by ecdump after receiving the frame; includes scheduling jitter.
+    Userspace,
+    /// Taken by the kernel when the frame arrived.
+    Kernel,
+    /// Taken by the network adapter.
+    Hardware,
+}
+
+impl ClockSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClockSource::Userspace => "userspace",
+            ClockSource::Kernel => "kernel",
+            ClockSource::Hardware => "adapter hardware",
+        }
+    }
+}
+
+/// Live capture backend.
+pub trait FrameReceiver: Send {
+    /// Next received frame and its receive time, if the backend timestamps frames.
+    /// Fails with `TimedOut` when no frame arrived within the read timeout.
+    fn next_frame(&mut self) -> std::io::Result<(&[u8], Option<Duration>)>;
+
+    /// Clock that timestamped the last frame, or the expected one before the first.
+    fn clock_source(&self) -> ClockSource;
+}
+
+/// pnet receiver. On Linux frames carry the kernel receive time, read from the
+/// socket after each frame. pnet does not pass on the capture time of Npcap
+/// (Windows) or BPF (macOS), so frames are timestamped in user space there.
+struct PnetReceiver {
+    rx: Box<dyn DataLinkReceiver>,
+    /// Socket handed to pnet, which closes it.
+    #[cfg(target_os = "linux")]
+    fd: RawFd,
+    clock_source: ClockSource,
+}
+
+impl FrameReceiver for PnetReceiver {
+    fn next_frame(&mut self) -> std::io::Result<(&[u8], Option<Duration>)> {
+        let frame = self.rx.next()?;
+        #[cfg(target_os = "linux")]
+        let receive_time = tpacket::last_receive_time(self.fd).ok();
+        #[cfg(not(target_os = "linux"))]
+        let receive_time = None;
+        // Frames without a kernel timestamp get the current time on the same clock
+        self.clock_source = match receive_time {
+            Some(_) => ClockSource::Kernel,
+            None => ClockSource::Userspace,
+        };
+        let receive_time =
+            receive_time.or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).ok());
+        Ok((frame, receive_time))
+    }
+
+    fn clock_source(&self) -> ClockSource {
+        self.clock_source
+    }
+}
+
+/// Prefix of Npcap device names on Windows, followed by the adapter GUID.
+const NPF_PREFIX: &str = "\\Device\\NPF_";
+
+#[derive(Serialize)]
 pub struct NetworkInterfaceInfo {
+    /// Name accepted by `-i`; the adapter GUID on Windows.
     pub name: String,
+    pub index: u32,
+    /// Name shown by the system, e.g. "Ethernet 2" (Windows only).
+    pub friendly_name: Option<String>,
     pub description: String,
+    pub mac: Option<String>,
+    /// Link speed in bit/s, if known.
+    pub speed: Option<u64>,
+    /// Addresses with prefix length, e.g. `192.168.1.2/24`.
+    pub addresses: Vec<String>,
+    #[serde(serialize_with = "serialize_oper_state")]
     pub oper_state: OperState,
     pub is_default: bool,
+    /// Raw Ethernet frames (and so EtherCAT) can be captured; false for loopback,
+    /// point-to-point and tunnel interfaces.
+    pub raw_ethernet: bool,
+    /// Link up, raw Ethernet and no IP configuration besides link-local
+    /// addresses: likely dedicated to an EtherCAT segment.
+    pub ethercat_candidate: bool,
 }
 
-pub fn get_interface_list() -> impl Iterator<Item = NetworkInterfaceInfo> {
+fn serialize_oper_state<S: serde::Serializer>(
+    state: &OperState,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(state.as_str())
+}
+
+/// Link-local addresses are assigned without configuration (IPv6 `fe80::/10`,
+/// IPv4 `169.254/16` when DHCP gets no answer).
+fn is_link_local(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => address.is_link_local(),
+        IpAddr::V6(address) => address.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+/// Interfaces sorted with EtherCAT candidates first, then by index.
+pub fn get_interface_list() -> Vec<NetworkInterfaceInfo> {
     let interfaces = pnet::datalink::interfaces(); // get list from pnet
     let interface_with_oper_state = netdev::get_interfaces();
-    interfaces.into_iter().map(move |iface| {
-        let (oper_state, is_default) = interface_with_oper_state
+    let mut list: Vec<NetworkInterfaceInfo> = interfaces
+        .into_iter()
+        .map(|iface| {
+            let netdev_interface = interface_with_oper_state
+                .iter()
+                .find(|i| i.index == iface.index);
+            let (oper_state, is_default) =
+                netdev_interface.map_or((OperState::Unknown, false), |i| (i.oper_state, i.default));
+            let raw_ethernet = !iface.is_loopback()
+                && !iface.is_point_to_point()
+                && iface.mac.is_some_and(|mac| mac != MacAddr::zero());
+            let configured = iface.ips.iter().any(|ip| !is_link_local(ip.ip()));
+            NetworkInterfaceInfo {
+                name: iface.name.trim_start_matches(NPF_PREFIX).to_string(),
+                index: iface.index,
+                friendly_name: netdev_interface.and_then(|i| i.friendly_name.clone()),
+                description: iface.description,
+                mac: iface.mac.map(|mac| mac.to_string()),
+                speed: netdev_interface.and_then(|i| i.receive_speed.or(i.transmit_speed)),
+                addresses: iface.ips.iter().map(|ip| ip.to_string()).collect(),
+                oper_state,
+                is_default,
+                raw_ethernet,
+                ethercat_candidate: raw_ethernet && oper_state == OperState::Up && !configured,
+            }
+        })
+        .collect();
+    list.sort_by_key(|iface| (!iface.ethercat_candidate, iface.index));
+    list
+}
+
+/// Find the interface `ifname` refers to: its name (on Windows the adapter GUID,
+/// with or without the `\Device\NPF_` prefix), its index or its friendly name
+/// such as "Ethernet 2". As a last resort a name starting with (on Windows
+/// containing) `ifname` is accepted if there is only one. `friendly_names` maps
+/// interface indexes to friendly names.
+fn find_interface(
+    interfaces: Vec<NetworkInterface>,
+    friendly_names: &[(u32, String)],
+    ifname: &str,
+) -> Result<NetworkInterface> {
+    let short_name =
+        |iface: &NetworkInterface| iface.name.trim_start_matches(NPF_PREFIX).to_string();
+    let index = ifname.parse::<u32>().ok();
+    let friendly_index = friendly_names
+        .iter()
+        .find(|(_, name)| name.eq_ignore_ascii_case(ifname))
+        .map(|(index, _)| *index);
+    let exact = interfaces
+        .iter()
+        .position(|iface| iface.name == ifname || short_name(iface).eq_ignore_ascii_case(ifname));
+    let by_index = || {
+        interfaces
             .iter()
-            .find(|i| i.index == iface.index)
-            .map_or((OperState::Unknown, false), |i| (i.oper_state, i.default));
-        NetworkInterfaceInfo {
-            name: iface.name,
-            description: iface.description,
-            oper_state,
-            is_default,
+            .position(|iface| Some(iface.index) == index.or(friendly_index))
+    };
+    let position = match exact.or_else(by_index) {
+        Some(position) => position,
+        None => {
+            let matches: Vec<usize> = (0..interfaces.len())
+                .filter(|&i| {
+                    if cfg!(target_os = "windows") {
+                        interfaces[i].name.contains(ifname)
+                    } else {
+                        interfaces[i].name.starts_with(ifname)
+                    }
+                })
+                .collect();
+            match matches[..] {
+                [position] => position,
+                [] => bail!("Network interface not found: {}", ifname),
+                _ => bail!(
+                    "Network interface name is ambiguous: {} matches {}",
+                    ifname,
+                    matches
+                        .iter()
+                        .map(|&i| short_name(&interfaces[i]))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
         }
-    })
+    };
+    Ok(interfaces
+        .into_iter()
+        .nth(position)
+        .expect("index of a listed interface"))
 }
 
 pub fn get_interface(ifname: Option<String>) -> Result<NetworkInterface> {
+    let netdev_interfaces = netdev::get_interfaces();
     let ifname = match ifname {
         Some(name) => name,
         None => {
-            #[cfg(target_os = "windows")]
-            let mut best_device_name = String::from("\\Device\\NPF_");
-            #[cfg(not(target_os = "windows"))]
-            let mut best_device_name = String::new();
-
-            let default_ifname = netdev::get_default_interface().map_err(|e| anyhow!("{}", e))?;
-            best_device_name.push_str(&default_ifname.name);
-            best_device_name
+            netdev::get_default_interface()
+                .map_err(|e| anyhow!("{}", e))?
+                .name
         }
     };
-    let interface = pnet::datalink::interfaces()
+    let friendly_names: Vec<(u32, String)> = netdev_interfaces
         .into_iter()
-        .find(|iface| {
-            if cfg!(target_os = "windows") {
-                iface.name.contains(&ifname)
-            } else {
-                iface.name.starts_with(&ifname)
+        .filter_map(|i| Some((i.index, i.friendly_name?)))
+        .collect();
+    find_interface(pnet::datalink::interfaces(), &friendly_names, &ifname)
+}
+
+/// Open the capture on `interface` with the configured backend. Reads time out
+/// after `read_timeout` without frames.
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+fn open_receiver(
+    interface: &NetworkInterface,
+    options: &CaptureOptions,
+    read_timeout: Duration,
+    dropped_frames: &Arc<AtomicU64>,
+) -> Result<Box<dyn FrameReceiver>> {
+    Ok(match options.backend {
+        CaptureBackend::Pnet => {
+            if options.hw_timestamps {
+                bail!("Hardware timestamps need the tpacket capture backend (--backend tpacket)");
             }
-        })
-        .ok_or_else(|| anyhow!("Network interface not found: {}", ifname.clone()))?;
-    Ok(interface)
+            let mut config = Config {
+                read_timeout: Some(read_timeout), // Linux/BPF/Netmap only
+                promiscuous: options.promiscuous,
+                ..Default::default()
+            };
+            // On Linux pnet reads one frame at a time, so the buffer that matters
+            // is the socket's; elsewhere the read buffer is the BPF buffer.
+            #[cfg(target_os = "linux")]
+            let fd = {
+                let fd = tpacket::packet_socket(options.buffer_size, !options.all_ethertypes)
+                    .map_err(|e| open_error(e, interface))?
+                    .into_raw_fd();
+                // Switch receive timestamps on
+                tpacket::last_receive_time(fd).ok();
+                config.socket_fd = Some(fd);
+                fd
+            };
+            #[cfg(not(target_os = "linux"))]
+            if let Some(buffer_size) = options.buffer_size {
+                config.read_buffer_size = buffer_size;
+            }
+            match pnet::datalink::channel(interface, config)
+                .map_err(|e| open_error(e, interface))?
+            {
+                Ethernet(_, rx) => Box::new(PnetReceiver {
+                    rx,
+                    #[cfg(target_os = "linux")]
+                    fd,
+                    clock_source: if cfg!(target_os = "linux") {
+                        ClockSource::Kernel
+                    } else {
+                        ClockSource::Userspace
+                    },
+                }),
+                _ => bail!("Unsupported channel type"),
+            }
+        }
+        #[cfg(target_os = "linux")]
+        CaptureBackend::Tpacket => Box::new(
+            TpacketReceiver::open(interface, read_timeout, options, dropped_frames.clone())
+                .map_err(|e| open_error(e, interface))?,
+        ),
+        #[cfg(not(target_os = "linux"))]
+        CaptureBackend::Tpacket => bail!("The tpacket capture backend is only available on Linux"),
+    })
 }
 
 pub fn start_packet_receive(
     interface: NetworkInterface,
-    output_file: Option<BufWriter<File>>,
+    output_file: Option<OutputFile>,
     abort_signal: CbReceiver<bool>,
-) -> Result<(
-    Option<JoinHandle<()>>,
-    CbSender<BytesMut>,
-    CbReceiver<CapturedData>,
-)> {
-    let config = Config {
-        read_timeout: Some(Duration::from_millis(100)), // Linux/BPF/Netmap only
-        ..Default::default()
-    };
-    let (_, mut datalink_rx) = match pnet::datalink::channel(&interface, config)? {
-        Ethernet(tx, rx) => (tx, rx),
-        _ => bail!("Unsupported channel type"),
-    };
+    backpressure: BackpressurePolicy,
+    options: CaptureOptions,
+    pool_size: usize,
+    dropped_frames: Arc<AtomicU64>,
+) -> Result<(PacketSourceHandles, ClockSource)> {
+    let read_timeout = Duration::from_millis(100);
+    let mut datalink_rx = open_receiver(&interface, &options, read_timeout, &dropped_frames)?;
+    let clock_source = datalink_rx.clock_source();
 
     let channel_size = 100;
     let write_to_file = output_file.is_some();
     // let (tx_data, rx_data) = mpsc::sync_channel(channel_size);
     // let (tx_recycle, rx_recycle) = mpsc::channel();
     let (tx_data, rx_data) = bounded::<CapturedData>(channel_size);
-    let (tx_recycle, rx_recycle) = unbounded::<BytesMut>();
     let (tx_data_writer, rx_data_writer) = bounded::<CapturedData>(channel_size * 2);
-    let (tx_cycle_writer, rx_cycle_writer) = unbounded::<BytesMut>();
+    // Shared by the analyzer and writer copies of each frame
+    let pool = BufferPool::new(pool_size, options.pool_exhaustion);
+    let pool_capture = pool.clone();
+    let pool_writer = pool.clone();
+    let (tx_status, rx_status) = unbounded::<SourceEvent>();
+    let tx_status_writer = tx_status.clone();
+    // Only drop-oldest needs to pop from the capture queue itself
+    let rx_data_oldest = (backpressure == BackpressurePolicy::DropOldest).then(|| rx_data.clone());
+    // Absolute time of the first frame, for the timestamps in the output file
+    let capture_epoch = Arc::new(OnceLock::new());
+    let writer_epoch = capture_epoch.clone();
 
     std::thread::Builder::new()
         .name("Packet Capture".to_string())
         .spawn(move || {
             let time_init = Instant::now();
+            let time_init_unix = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let mut first_receive_time = None;
             let mut initial_frame = true;
+            let mut sequence = 0u64;
             let mut src_mac = MacAddr::zero();
+            let mut link = LinkMonitor::new(interface.name.clone());
+            let mut clock_warned = false;
+            // Time on the frame clock, for link events
+            let now = |first_receive_time: Option<Duration>| match first_receive_time {
+                Some(first) => SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .saturating_sub(first),
+                None => time_init.elapsed(),
+            };
             loop {
-                match datalink_rx.next() {
-                    Ok(packet) => {
-                        let timestamp = time_init.elapsed();
-                        let packet = EthernetPacket::new(packet);
-                        let ethercat_packet = match packet {
-                            Some(eth) if eth.get_ethertype().0 == 0x88a4 => eth,
-                            _ => continue,
-                        };
+                match link.poll() {
+                    Some(false) => {
+                        tx_status
+                            .send(SourceEvent::Link {
+                                up: false,
+                                timestamp: now(first_receive_time),
+                            })
+                            .ok();
+                    }
+                    Some(true) => {
+                        // The interface may have been re-created (e.g. a USB adapter
+                        // plugged in again), so look it up and open a new capture
+                        let reopened = pnet::datalink::interfaces()
+                            .into_iter()
+                            .find(|candidate| candidate.name == interface.name)
+                            .ok_or_else(|| anyhow!("interface not found"))
+                            .and_then(|interface| {
+                                open_receiver(&interface, &options, read_timeout, &dropped_frames)
+                            });
+                        match reopened {
+                            Ok(receiver) => {
+                                datalink_rx = receiver;
+                                tx_status
+                                    .send(SourceEvent::Link {
+                                        up: true,
+                                        timestamp: now(first_receive_time),
+                                    })
+                                    .ok();
+                            }
+                            Err(e) => {
+                                // Try again on the next check
+                                debug!("Failed to reopen {}: {:#}", interface.name, e);
+                                link.up = false;
+                            }
+                        }
+                    }
+                    None => {}
+                }
+                if !link.up {
+                    std::thread::sleep(read_timeout);
+                    continue;
+                }
 
-                        if write_to_file {
-                            let send_data = ethercat_packet.packet();
-                            let mut buffer = match rx_cycle_writer.try_recv() {
-                                Ok(buf) => buf,
-                                Err(_) => BytesMut::with_capacity(send_data.len()),
-                            };
-                            buffer.clear();
-                            buffer.put_slice(send_data);
-                            let send_data = buffer.freeze();
-                            tx_data_writer
-                                .send(CapturedData {
-                                    timestamp,
-                                    from_main: false,
-                                    data: send_data,
-                                })
-                                .ok();
+                // Clock of the previous frame
+                if !clock_warned && datalink_rx.clock_source() != clock_source {
+                    warn!(
+                        "Frames are timestamped by the {} clock, not the {} clock",
+                        datalink_rx.clock_source().as_str(),
+                        clock_source.as_str()
+                    );
+                    clock_warned = true;
+                }
+
+                match datalink_rx.next_frame() {
+                    Ok((packet, receive_time)) => {
+                        let timestamp = match receive_time {
+                            Some(receive_time) => {
+                                let first = *first_receive_time.get_or_insert(receive_time);
+                                capture_epoch.get_or_init(|| first);
+                                receive_time.saturating_sub(first)
+                            }
+                            None => {
+                                capture_epoch.get_or_init(|| time_init_unix);
+                                time_init.elapsed()
+                            }
+                        };
+                        let Some(ethernet) = EthernetPacket::new(packet) else {
+                            continue;
+                        };
+                        let is_ethercat = ethernet.get_ethertype().0 == 0x88a4;
+                        if !is_ethercat && !options.all_ethertypes {
+                            continue;
                         }
 
-                        let from_main = if initial_frame {
-                            src_mac = ethercat_packet.get_source();
+                        let from_main = if is_ethercat && initial_frame {
+                            src_mac = ethernet.get_source();
                             initial_frame = false;
+                            tx_status
+                                .send(SourceEvent::Started(SystemTime::now() - timestamp))
+                                .ok();
                             true
                         } else {
-                            ethercat_packet.get_source() == src_mac
+                            ethernet.get_source() == src_mac
                         };
+                        sequence += 1;
 
-                        let ethercat_packet = ethercat_packet.payload();
-                        let mut buffer = match rx_recycle.try_recv() {
-                            Ok(buf) => buf,
-                            Err(_) => BytesMut::with_capacity(ethercat_packet.len()),
-                        };
+                        if write_to_file {
+                            let send_data = ethernet.packet();
+                            // The output file keeps every frame, so that its frame
+                            // numbers match `sequence` even when the pool drops frames
+                            let mut buffer = pool_capture.get_or_allocate(send_data.len());
+                            buffer.put_slice(send_data);
+                            let sent = tx_data_writer.send(CapturedData {
+                                sequence,
+                                timestamp,
+                                from_main,
+                                data: buffer.freeze(),
+                            });
+                            // The writer stops on errors; end the capture with it
+                            if sent.is_err() {
+                                break;
+                            }
+                        }
 
-                        buffer.clear();
+                        // Other EtherTypes only go to the output file
+                        if !is_ethercat {
+                            continue;
+                        }
+
+                        let ethercat_packet = ethernet.payload();
+                        let Some(mut buffer) = pool_capture.get(ethercat_packet.len()) else {
+                            dropped_frames.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        };
                         buffer.put_slice(ethercat_packet);
                         let ethercat_packet = buffer.freeze();
-                        if tx_data
-                            .send(CapturedData {
-                                timestamp,
-                                from_main,
-                                data: ethercat_packet,
-                            })
-                            .is_err()
-                        {
+                        let captured = CapturedData {
+                            sequence,
+                            timestamp,
+                            from_main,
+                            data: ethercat_packet,
+                        };
+                        let sent = match backpressure {
+                            BackpressurePolicy::Block => tx_data.send(captured).is_ok(),
+                            BackpressurePolicy::DropNewest => match tx_data.try_send(captured) {
+                                Err(TrySendError::Full(rejected)) => {
+                                    dropped_frames.fetch_add(1, Ordering::Relaxed);
+                                    pool_capture.put(BytesMut::from(rejected.data));
+                                    true
+                                }
+                                result => result.is_ok(),
+                            },
+                            BackpressurePolicy::DropOldest => {
+                                let mut captured = captured;
+                                loop {
+                                    match tx_data.try_send(captured) {
+                                        Err(TrySendError::Full(rejected)) => {
+                                            if let Some(rx) = &rx_data_oldest
+                                                && let Ok(oldest) = rx.try_recv()
+                                            {
+                                                dropped_frames.fetch_add(1, Ordering::Relaxed);
+                                                pool_capture.put(BytesMut::from(oldest.data));
+                                            }
+                                            captured = rejected;
+                                        }
+                                        result => break result.is_ok(),
+                                    }
+                                }
+                            }
+                        };
+                        if !sent {
                             break;
                         }
                     }
                     Err(e) => match e.kind() {
                         std::io::ErrorKind::TimedOut => continue,
-                        _ => error!("An error occurred while reading: {}", e),
+                        // Reads fail on some systems while the link is down
+                        _ if !link.is_up() => {
+                            link.up = false;
+                            tx_status
+                                .send(SourceEvent::Link {
+                                    up: false,
+                                    timestamp: now(first_receive_time),
+                                })
+                                .ok();
+                        }
+                        _ => {
+                            tx_status
+                                .send(SourceEvent::Failed(PacketSourceError::Capture {
+                                    interface: interface.name.clone(),
+                                    error: e,
+                                }))
+                                .ok();
+                            break;
+                        }
                     },
                 }
             }
         })
-        .expect("Packet Capture Thread");
+        .context("Failed to start the packet capture thread")?;
 
-    let handle = if let Some(output_file) = output_file {
-        let mut pcap_writer = PcapWriter::new(output_file).expect("PcapWriter");
-        let handle = std::thread::Builder::new()
-            .name("Pcap Writer".to_string())
-            .spawn(move || {
-                let mut write_packet = move |captured_data: &CapturedData| {
-                    let pcap_packet = pcap::PcapPacket {
-                        timestamp: captured_data.timestamp,
-                        orig_len: captured_data.data.len() as u32,
-                        data: Cow::Borrowed(&captured_data.data),
-                    };
-                    pcap_writer
-                        .write_packet(&pcap_packet)
-                        .map_err(|e| error!("Failed to write packet to output file: {}", e))
-                        .ok();
-                };
+    let handle = if let Some(mut output_file) = output_file {
+        output_file.clock_source = Some(clock_source);
+        output_file.snaplen = options.snaplen;
+        let capture_writer = output_file.into_capture_writer(DataLink::ETHERNET)?;
+        Some(start_live_writer(
+            capture_writer,
+            rx_data_writer,
+            abort_signal,
+            pool_writer,
+            writer_epoch,
+            tx_status_writer,
+        )?)
+    } else {
+        None
+    };
+
+    Ok(((handle, pool, rx_data, rx_status), clock_source))
+}
 
+/// Write the frames of a live capture until `abort_signal` or the end of
+/// `rx_data`. Their timestamps are relative to the first frame, whose absolute
+/// receive time is set in `epoch` before it is sent.
+fn start_live_writer(
+    mut capture_writer: RotatingWriter,
+    rx_data: CbReceiver<CapturedData>,
+    abort_signal: CbReceiver<bool>,
+    pool: BufferPool,
+    epoch: Arc<OnceLock<Duration>>,
+    tx_status: CbSender<SourceEvent>,
+) -> Result<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name("Pcap Writer".to_string())
+        .spawn(move || {
+            let mut write_packet = |captured_data: &CapturedData| -> Result<()> {
+                // Frame timestamps are relative to the first frame
+                let epoch = epoch.get().copied().unwrap_or_default();
+                capture_writer.write_packet(
+                    captured_data.sequence,
+                    epoch + captured_data.timestamp,
+                    captured_data.timestamp,
+                    &captured_data.data,
+                    captured_data.data.len() as u32,
+                    Some(captured_data.from_main),
+                )?;
+                if rx_data.is_empty() {
+                    capture_writer.flush_idle()?;
+                }
+                Ok(())
+            };
+
+            let mut write_all = || -> Result<()> {
                 loop {
-                    if let Ok(_) = abort_signal.try_recv() {
-                        let packet_num = rx_data_writer.len();
+                    if abort_signal.try_recv().is_ok() {
+                        let packet_num = rx_data.len();
                         for _ in 0..packet_num {
-                            if let Ok(captured_data) = rx_data_writer.try_recv() {
-                                write_packet(&captured_data);
+                            if let Ok(captured_data) = rx_data.try_recv() {
+                                write_packet(&captured_data)?;
                             }
                         }
-                        break;
+                        break Ok(());
                     }
 
                     select! {
                         recv(abort_signal) -> _ => {
-                            let packet_num = rx_data_writer.len();
+                            let packet_num = rx_data.len();
                             for _ in 0..packet_num {
-                                if let Ok(captured_data) = rx_data_writer.try_recv() {
-                                    write_packet(&captured_data);
+                                if let Ok(captured_data) = rx_data.try_recv() {
+                                    write_packet(&captured_data)?;
                                 }
                             }
-                            break;
+                            break Ok(());
                         }
-                        recv(rx_data_writer) -> msg => {
+                        recv(rx_data) -> msg => {
                             match msg {
                                 Ok(captured_data) => {
-                                    write_packet(&captured_data);
+                                    write_packet(&captured_data)?;
 
-                                    if tx_cycle_writer
-                                        .send(BytesMut::from(captured_data.data))
-                                        .is_err()
-                                    {
-                                        break;
-                                    }
+                                    pool.put(BytesMut::from(captured_data.data));
                                 }
-                                Err(_) => break,
+                                Err(_) => break Ok(()),
                             }}
                     }
                 }
-            })
-            .expect("Pcap Writer Thread");
-        Some(handle)
+            };
+            let result = write_all();
+
+            if let Err(e) = result.and(capture_writer.finish()) {
+                tx_status
+                    .send(SourceEvent::Failed(PacketSourceError::Write(e)))
+                    .ok();
+            }
+        })
+        .context("Failed to start the pcap writer thread")
+}
+
+/// Length of the pcap file header, and of the header of each record.
+const PCAP_HEADER_LEN: u64 = 24;
+const PCAP_RECORD_HEADER_LEN: u64 = 16;
+
+/// Length of a pcapng section header block from the fields after the block
+/// type: the block length and the byte-order magic.
+fn section_header_len(fields: [u8; 8]) -> u64 {
+    let len = [fields[0], fields[1], fields[2], fields[3]];
+    if fields[4..] == [0x4d, 0x3c, 0x2b, 0x1a] {
+        u32::from_le_bytes(len) as u64
     } else {
-        None
-    };
+        u32::from_be_bytes(len) as u64
+    }
+}
 
-    Ok((handle, tx_recycle, rx_data))
+/// Initial frame flag, frame number, main device address and first timestamp of
+/// a capture file reader; those of the index entry when continuing at one.
+fn resume_state(
+    resume: Option<&Resume>,
+    tx_status: &CbSender<SourceEvent>,
+) -> (bool, u64, MacAddr, Duration) {
+    match resume {
+        Some(resume) => {
+            tx_status
+                .send(SourceEvent::Started(UNIX_EPOCH + resume.initial_timestamp))
+                .ok();
+            (
+                false,
+                resume.sequence,
+                resume.main_mac,
+                resume.initial_timestamp,
+            )
+        }
+        None => (true, 0, MacAddr::zero(), Duration::ZERO),
+    }
 }
 
+/// Start of a `-T` replay: as if the frames before the index entry had been
+/// replayed, so the first one is not delayed by their duration.
+fn sync_start(resume: Option<&Resume>) -> Instant {
+    let now = Instant::now();
+    resume
+        .and_then(|resume| now.checked_sub(resume.timestamp))
+        .unwrap_or(now)
+}
+
+/// Error that ended reading a capture file; `None` for a file that ends in the
+/// middle of a frame, e.g. because the capture was still being written.
+fn read_error(error: pcap_file::PcapError) -> Option<PacketSourceError> {
+    if is_truncated(&error) {
+        warn!("The capture file is truncated, stopping at the last complete frame");
+        None
+    } else {
+        Some(CaptureFileError::from(error).into())
+    }
+}
+
+/// Read a pcap or pcapng stream, the format is detected from the first bytes.
+/// With `indexing`, the index of the file is recorded, or the stream continues
+/// at an entry of it. Frames outside `window` are not copied out of the reader,
+/// and reading stops after the end of the window.
 pub fn start_read_pcap(
-    pcap_file: File,
-    output_file: Option<BufWriter<File>>,
-    is_pcapng: bool,
+    mut pcap_file: Box<dyn Read + Send>,
+    output_file: Option<OutputFile>,
     abort_signal: CbReceiver<bool>,
     time_sync: bool,
-) -> Result<(
-    Option<JoinHandle<()>>,
-    CbSender<BytesMut>,
-    CbReceiver<CapturedData>,
-)> {
+    window: AnalysisWindow,
+    pool_size: usize,
+    indexing: Option<Indexing>,
+) -> Result<PacketSourceHandles> {
     let channel_size = 0;
     let (tx_data, rx_data) = bounded(channel_size);
-    let (tx_recycle, rx_recycle) = unbounded();
+    let pool = BufferPool::new(pool_size, PoolExhaustion::Allocate);
+    let pool_reader = pool.clone();
+    let (tx_status, rx_status) = unbounded();
+
+    let mut magic = [0u8; 4];
+    pcap_file
+        .read_exact(&mut magic)
+        .map_err(|e| PacketSourceError::Read(e.into()))?;
+    let is_pcapng =
+        is_pcapng_magic(magic).ok_or(PacketSourceError::Read(CaptureFileError::UnknownFormat))?;
+    let mut head = magic.to_vec();
+    // Offset of the first record, for the index
+    let mut offset = PCAP_HEADER_LEN;
+    if is_pcapng {
+        let mut fields = [0u8; 8];
+        pcap_file
+            .read_exact(&mut fields)
+            .map_err(|e| PacketSourceError::Read(e.into()))?;
+        head.extend_from_slice(&fields);
+        offset = section_header_len(fields);
+    }
+    let pcap_file = Box::new(Cursor::new(head).chain(pcap_file));
+    let (mut index, resume) = match indexing {
+        Some(Indexing::Build(builder)) => (Some(builder), None),
+        Some(Indexing::Resume(resume)) => (None, Some(resume)),
+        None => (None, None),
+    };
 
     let handle = if is_pcapng {
-        let mut pcapng_reader = pcapng::PcapNgReader::new(pcap_file).expect("PCAPNG Reader");
+        let mut pcapng_reader =
+            pcapng::PcapNgReader::new(pcap_file).map_err(|e| PacketSourceError::Read(e.into()))?;
+        // pcapng output copies the blocks unchanged; pcap output converts the packets
+        let mut capture_writer = output_file
+            .map(|output_file| match output_file.format {
+                OutputFormat::Pcapng => {
+                    output_file.into_pcapng_copy_writer(pcapng_reader.section())
+                }
+                OutputFormat::Pcap => output_file.into_capture_writer(DataLink::ETHERNET),
+            })
+            .transpose()?;
         std::thread::Builder::new()
             .name("PcapNG Reader".to_string())
             .spawn(move || {
-                let mut initial_frame = true;
-                let mut src_mac = MacAddr::zero();
-                let mut initial_timestamp = Duration::from_secs(0);
-                let time_init = Instant::now();
+                let (mut initial_frame, mut sequence, mut src_mac, mut initial_timestamp) =
+                    resume_state(resume.as_ref(), &tx_status);
+                let time_init = sync_start(resume.as_ref());
+                // if_tsresol of each interface of the current section
+                let mut ts_resolutions: Vec<u8> = Vec::new();
 
-                while abort_signal.try_recv().is_err()
-                    && let Some(Ok(block)) = pcapng_reader.next_block()
-                {
-                    let (data, timestamp) = match block {
-                        PcapNgBlock::EnhancedPacket(epb) => (epb.data, epb.timestamp),
-                        PcapNgBlock::Packet(p) => (p.data, Duration::from_secs(p.timestamp)),
-                        PcapNgBlock::SimplePacket(sp) => (sp.data, Duration::from_secs(0)),
-                        _ => continue,
+                let mut error = None;
+                let mut reached_end = false;
+                while abort_signal.try_recv().is_err() {
+                    // Raw blocks tell their length, for the offsets of the index
+                    let endianness = pcapng_reader.section().endianness;
+                    let block_offset = offset;
+                    let block = match pcapng_reader.next_raw_block() {
+                        Some(Ok(raw_block)) => {
+                            offset += raw_block.initial_len as u64;
+                            match endianness {
+                                Endianness::Big => raw_block.try_into_block::<BigEndian>(),
+                                Endianness::Little => raw_block.try_into_block::<LittleEndian>(),
+                            }
+                        }
+                        Some(Err(e)) => Err(e),
+                        None => {
+                            reached_end = true;
+                            break;
+                        }
+                    };
+                    let block = match block {
+                        Ok(block) => block,
+                        Err(e) => {
+                            error = read_error(e);
+                            break;
+                        }
+                    };
+                    let (data, timestamp, orig_len) = match &block {
+                        PcapNgBlock::EnhancedPacket(epb) => {
+                            let resolution = ts_resolutions
+                                .get(epb.interface_id as usize)
+                                .copied()
+                                .unwrap_or(DEFAULT_TS_RESOLUTION);
+                            (
+                                &epb.data,
+                                scale_pcapng_timestamp(epb.timestamp, resolution),
+                                epb.original_len,
+                            )
+                        }
+                        PcapNgBlock::Packet(p) => {
+                            (&p.data, Duration::from_secs(p.timestamp), p.original_len)
+                        }
+                        PcapNgBlock::SimplePacket(sp) => {
+                            (&sp.data, Duration::from_secs(0), sp.original_len)
+                        }
+                        other => {
+                            match other {
+                                PcapNgBlock::SectionHeader(_) => ts_resolutions.clear(),
+                                PcapNgBlock::InterfaceDescription(idb) => {
+                                    ts_resolutions.push(interface_ts_resolution(idb))
+                                }
+                                _ => {}
+                            }
+                            if let Some(index) = index.as_mut()
+                                && matches!(
+                                    other,
+                                    PcapNgBlock::SectionHeader(_)
+                                        | PcapNgBlock::InterfaceDescription(_)
+                                )
+                            {
+                                index.interfaces_changed();
+                            }
+                            if let Some(capture_writer) = capture_writer.as_mut()
+                                && let Err(e) = capture_writer.copy_block(other)
+                            {
+                                error = Some(PacketSourceError::Write(e));
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    if let Some(index) = index.as_mut() {
+                        index.packet(block_offset);
+                    }
+                    let Some(ethernet) = EthernetPacket::new(data) else {
+                        let error = PacketSourceError::ShortFrame { length: data.len() };
+                        let timestamp = timestamp.saturating_sub(initial_timestamp);
+                        tx_status
+                            .send(SourceEvent::Skipped { error, timestamp })
+                            .ok();
+                        continue;
                     };
-                    let ethernet = EthernetPacket::new(&data).expect("ethernet packet");
                     if ethernet.get_ethertype().0 != 0x88a4 {
                         continue;
                     }
@@ -278,18 +1051,60 @@ pub fn start_read_pcap(
                         src_mac = ethernet.get_source();
                         initial_frame = false;
                         initial_timestamp = timestamp;
+                        if !timestamp.is_zero() {
+                            tx_status
+                                .send(SourceEvent::Started(UNIX_EPOCH + timestamp))
+                                .ok();
+                        }
                         true
                     } else {
                         ethernet.get_source() == src_mac
                     };
+                    sequence += 1;
+                    // Records may be older than the first frame, saturate instead of panicking
+                    let capture_time = timestamp.saturating_sub(initial_timestamp);
+                    if let Some(index) = index.as_mut() {
+                        index.frame(
+                            block_offset,
+                            sequence,
+                            src_mac,
+                            initial_timestamp,
+                            capture_time,
+                        );
+                    }
 
-                    let timestamp = timestamp - initial_timestamp;
+                    if let Some(capture_writer) = capture_writer.as_mut() {
+                        let result = match capture_writer.format() {
+                            OutputFormat::Pcapng => capture_writer.copy_packet_block(
+                                &block,
+                                sequence,
+                                timestamp,
+                                capture_time,
+                            ),
+                            OutputFormat::Pcap => capture_writer.write_packet(
+                                sequence,
+                                timestamp,
+                                capture_time,
+                                data,
+                                orig_len,
+                                Some(from_main),
+                            ),
+                        };
+                        if let Err(e) = result {
+                            error = Some(PacketSourceError::Write(e));
+                            break;
+                        }
+                    }
+
+                    let timestamp = capture_time;
+                    if window.is_past(sequence, timestamp) {
+                        break;
+                    }
+                    if !window.contains(sequence, timestamp) {
+                        continue;
+                    }
                     let ethercat_packet = ethernet.payload();
-                    let mut buffer = match rx_recycle.try_recv() {
-                        Ok(buf) => buf,
-                        Err(_) => BytesMut::with_capacity(ethercat_packet.len()),
-                    };
-                    buffer.clear();
+                    let mut buffer = pool_reader.get_or_allocate(ethercat_packet.len());
                     buffer.put_slice(ethercat_packet);
                     let ethercat_packet = buffer.freeze();
 
@@ -305,6 +1120,7 @@ pub fn start_read_pcap(
 
                     if tx_data
                         .send(CapturedData {
+                            sequence,
                             timestamp,
                             from_main,
                             data: ethercat_packet,
@@ -314,43 +1130,69 @@ pub fn start_read_pcap(
                         break;
                     }
                 }
+
+                // The analyzer only finishes once the frame channel is closed
+                drop(tx_data);
+                if let Some(capture_writer) = capture_writer
+                    && let Err(e) = capture_writer.finish()
+                {
+                    error.get_or_insert(PacketSourceError::Write(e));
+                }
+                if let Some(index) = index
+                    && reached_end
+                    && error.is_none()
+                {
+                    index.save();
+                }
+                if let Some(error) = error {
+                    tx_status.send(SourceEvent::Failed(error)).ok();
+                }
             })
-            .expect("PcapNG Reader Thread")
+            .context("Failed to start the pcapng reader thread")?
     } else {
-        let mut pcap_reader = pcap::PcapReader::new(pcap_file)?;
+        let mut pcap_reader =
+            pcap::PcapReader::new(pcap_file).map_err(|e| PacketSourceError::Read(e.into()))?;
+        let mut capture_writer = output_file
+            .map(|output_file| output_file.into_pcap_copy_writer(pcap_reader.header()))
+            .transpose()?;
         std::thread::Builder::new()
             .name("Pcap Reader".to_string())
             .spawn(move || {
-                let mut initial_frame = true;
-                let mut src_mac = MacAddr::zero();
-                let mut initial_timestamp = Duration::from_secs(0);
-                let mut pcap_writer = match output_file {
-                    Some(writer) => {
-                        let header = pcap::PcapHeader {
-                            datalink: pcap_reader.header().datalink,
-                            ..pcap::PcapHeader::default()
-                        };
-                        Some(PcapWriter::with_header(writer, header).expect("PcapWriter"))
-                    }
-                    None => None,
-                };
-                let time_init = Instant::now();
+                let (mut initial_frame, mut sequence, mut src_mac, mut initial_timestamp) =
+                    resume_state(resume.as_ref(), &tx_status);
+                let time_init = sync_start(resume.as_ref());
 
-                while abort_signal.try_recv().is_err()
-                    && let Some(Ok(packet)) = pcap_reader.next_packet()
-                {
-                    let ethernet = EthernetPacket::new(&packet.data).expect("ethernet packet");
-                    if ethernet.get_ethertype().0 != 0x88a4 {
-                        continue;
+                let mut error = None;
+                let mut reached_end = false;
+                while abort_signal.try_recv().is_err() {
+                    let packet = match pcap_reader.next_packet() {
+                        Some(Ok(packet)) => packet,
+                        Some(Err(e)) => {
+                            error = read_error(e);
+                            break;
+                        }
+                        None => {
+                            reached_end = true;
+                            break;
+                        }
+                    };
+                    let record_offset = offset;
+                    offset += PCAP_RECORD_HEADER_LEN + packet.data.len() as u64;
+                    if let Some(index) = index.as_mut() {
+                        index.packet(record_offset);
                     }
-
-                    if let Some(pcap_writer) = pcap_writer.as_mut() {
-                        pcap_writer
-                            .write_packet(&packet)
-                            .map_err(|e| {
-                                error!("Failed to write packet to output file: {}", e);
-                            })
+                    let Some(ethernet) = EthernetPacket::new(&packet.data) else {
+                        let error = PacketSourceError::ShortFrame {
+                            length: packet.data.len(),
+                        };
+                        let timestamp = packet.timestamp.saturating_sub(initial_timestamp);
+                        tx_status
+                            .send(SourceEvent::Skipped { error, timestamp })
                             .ok();
+                        continue;
+                    };
+                    if ethernet.get_ethertype().0 != 0x88a4 {
+                        continue;
                     }
 
                     // std::thread::sleep(Duration::from_micros(100));
@@ -358,18 +1200,48 @@ pub fn start_read_pcap(
                         src_mac = ethernet.get_source();
                         initial_frame = false;
                         initial_timestamp = packet.timestamp;
+                        tx_status
+                            .send(SourceEvent::Started(UNIX_EPOCH + packet.timestamp))
+                            .ok();
                         true
                     } else {
                         ethernet.get_source() == src_mac
                     };
+                    sequence += 1;
+                    // Records may be older than the first frame, saturate instead of panicking
+                    let timestamp = packet.timestamp.saturating_sub(initial_timestamp);
+                    if let Some(index) = index.as_mut() {
+                        index.frame(
+                            record_offset,
+                            sequence,
+                            src_mac,
+                            initial_timestamp,
+                            timestamp,
+                        );
+                    }
+
+                    if let Some(capture_writer) = capture_writer.as_mut()
+                        && let Err(e) = capture_writer.write_packet(
+                            sequence,
+                            packet.timestamp,
+                            timestamp,
+                            &packet.data,
+                            packet.orig_len,
+                            Some(from_main),
+                        )
+                    {
+                        error = Some(PacketSourceError::Write(e));
+                        break;
+                    }
 
-                    let timestamp = packet.timestamp - initial_timestamp;
+                    if window.is_past(sequence, timestamp) {
+                        break;
+                    }
+                    if !window.contains(sequence, timestamp) {
+                        continue;
+                    }
                     let ethercat_packet = ethernet.payload();
-                    let mut buffer = match rx_recycle.try_recv() {
-                        Ok(buf) => buf,
-                        Err(_) => BytesMut::with_capacity(ethercat_packet.len()),
-                    };
-                    buffer.clear();
+                    let mut buffer = pool_reader.get_or_allocate(ethercat_packet.len());
                     buffer.put_slice(ethercat_packet);
                     let ethercat_packet = buffer.freeze();
 
@@ -385,6 +1257,7 @@ pub fn start_read_pcap(
 
                     if tx_data
                         .send(CapturedData {
+                            sequence,
                             timestamp,
                             from_main,
                             data: ethercat_packet,
@@ -395,8 +1268,256 @@ pub fn start_read_pcap(
                         break;
                     }
                 }
+
+                // The analyzer only finishes once the frame channel is closed
+                drop(tx_data);
+                if let Some(capture_writer) = capture_writer
+                    && let Err(e) = capture_writer.finish()
+                {
+                    error.get_or_insert(PacketSourceError::Write(e));
+                }
+                if let Some(index) = index
+                    && reached_end
+                    && error.is_none()
+                {
+                    index.save();
+                }
+                if let Some(error) = error {
+                    tx_status.send(SourceEvent::Failed(error)).ok();
+                }
             })
-            .expect("Pcap Reader Thread")
+            .context("Failed to start the pcap reader thread")?
     };
-    Ok((Some(handle), tx_recycle, rx_data))
+    Ok((Some(handle), pool, rx_data, rx_status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecdump::ec_packet::{ECCommands, ECFrameBuilder};
+
+    /// A little-endian pcap file header followed by `records`.
+    fn pcap_file(records: &[&[u8]]) -> Vec<u8> {
+        let mut file = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&65535u32.to_le_bytes());
+        file.extend_from_slice(&1u32.to_le_bytes());
+        for (i, record) in records.iter().enumerate() {
+            file.extend_from_slice(&(i as u32).to_le_bytes());
+            file.extend_from_slice(&0u32.to_le_bytes());
+            file.extend_from_slice(&(record.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(record.len() as u32).to_le_bytes());
+            file.extend_from_slice(record);
+        }
+        file
+    }
+
+    /// An Ethernet frame with one BRD of the ESC type register.
+    fn ethercat_frame() -> Vec<u8> {
+        ECFrameBuilder::new()
+            .datagram(ECCommands::BRD, 0, 0, &[0x11, 0x00], 0)
+            .build_ethernet()
+    }
+
+    /// The frames and status events of reading `file` to the end.
+    fn read_all(file: Vec<u8>) -> (Vec<CapturedData>, Vec<SourceEvent>) {
+        read_window(file, AnalysisWindow::default())
+    }
+
+    /// The frames and status events of reading the frames of `file` in `window`.
+    fn read_window(file: Vec<u8>, window: AnalysisWindow) -> (Vec<CapturedData>, Vec<SourceEvent>) {
+        let (_abort_tx, abort_rx) = bounded(0);
+        let (handle, _pool, rx_data, rx_status) = start_read_pcap(
+            Box::new(Cursor::new(file)),
+            None,
+            abort_rx,
+            false,
+            window,
+            4,
+            None,
+        )
+        .unwrap();
+        let frames = rx_data.iter().collect();
+        handle.unwrap().join().unwrap();
+        (frames, rx_status.try_iter().collect())
+    }
+
+    #[test]
+    fn test_read_pcap_skips_short_records() {
+        let frame = ethercat_frame();
+        let (frames, events) = read_all(pcap_file(&[&frame[..6], &frame]));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].sequence, 1);
+        assert!(events.iter().any(|event| matches!(
+            event,
+            SourceEvent::Skipped {
+                error: PacketSourceError::ShortFrame { length: 6 },
+                ..
+            }
+        )));
+        assert!(
+            !events
+                .iter()
+                .any(|event| matches!(event, SourceEvent::Failed(_)))
+        );
+    }
+
+    #[test]
+    fn test_read_pcap_leaves_out_frames_outside_the_window() {
+        let frame = ethercat_frame();
+        let window = AnalysisWindow {
+            from: Some(Duration::from_secs(1)),
+            last_frame: Some(3),
+            ..Default::default()
+        };
+        let (frames, _) = read_window(pcap_file(&[&frame[..]; 5]), window);
+        let read: Vec<_> = frames
+            .iter()
+            .map(|frame| (frame.sequence, frame.timestamp.as_secs()))
+            .collect();
+        assert_eq!(read, [(2, 1), (3, 2)]);
+    }
+
+    #[test]
+    fn test_live_writer_writes_absolute_timestamps() {
+        let path =
+            std::env::temp_dir().join(format!("ecdump-live-writer-{}.pcap", std::process::id()));
+        let output_file = OutputFile {
+            path: path.to_string_lossy().into_owned(),
+            format: OutputFormat::Pcap,
+            rotation: Default::default(),
+            trigger: None,
+            clock_source: None,
+            snaplen: None,
+            flush_when_idle: false,
+            frame_comments: false,
+        };
+        let capture_writer = output_file.into_capture_writer(DataLink::ETHERNET).unwrap();
+        let (tx_data, rx_data) = bounded(1);
+        let (_abort_tx, abort_rx) = bounded(0);
+        let (tx_status, rx_status) = unbounded();
+        let first_frame = Duration::new(1_700_000_000, 250_000_000);
+        let epoch = Arc::new(OnceLock::from(first_frame));
+        let pool = BufferPool::new(4, PoolExhaustion::Allocate);
+        let handle =
+            start_live_writer(capture_writer, rx_data, abort_rx, pool, epoch, tx_status).unwrap();
+        tx_data
+            .send(CapturedData {
+                sequence: 1,
+                timestamp: Duration::from_millis(1500),
+                from_main: true,
+                data: Bytes::from(ethercat_frame()),
+            })
+            .unwrap();
+        drop(tx_data);
+        handle.join().unwrap();
+        assert!(rx_status.try_recv().is_err());
+
+        let file = std::fs::File::open(&path).unwrap();
+        let mut reader = pcap::PcapReader::new(file).unwrap();
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.timestamp, first_frame + Duration::from_millis(1500));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_pcap_accepts_decreasing_timestamps() {
+        let frame = ethercat_frame();
+        let mut file = pcap_file(&[&frame, &frame, &frame]);
+        // The first frame is the newest
+        file[24..28].copy_from_slice(&5u32.to_le_bytes());
+        let (frames, events) = read_all(file);
+        let timestamps: Vec<_> = frames
+            .iter()
+            .map(|frame| (frame.sequence, frame.timestamp))
+            .collect();
+        assert_eq!(
+            timestamps,
+            [
+                (1, Duration::ZERO),
+                (2, Duration::ZERO),
+                (3, Duration::ZERO)
+            ]
+        );
+        assert!(
+            !events
+                .iter()
+                .any(|event| matches!(event, SourceEvent::Failed(_)))
+        );
+    }
+
+    #[test]
+    fn test_read_pcap_reports_corrupt_records() {
+        let frame = ethercat_frame();
+        let mut file = pcap_file(&[&frame]);
+        // A record with more than a second of microseconds
+        file.extend_from_slice(&1u32.to_le_bytes());
+        file.extend_from_slice(&2_000_000u32.to_le_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(&frame);
+        let (frames, events) = read_all(file);
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(
+            events.last(),
+            Some(SourceEvent::Failed(PacketSourceError::Read(
+                CaptureFileError::Pcap(_)
+            )))
+        ));
+
+        // Truncated in the middle of a record: the complete frames are read
+        let mut file = pcap_file(&[&frame, &frame]);
+        file.truncate(file.len() - 5);
+        let (frames, events) = read_all(file);
+        assert_eq!(frames.len(), 1);
+        assert!(
+            !events
+                .iter()
+                .any(|event| matches!(event, SourceEvent::Failed(_)))
+        );
+
+        let error = start_read_pcap(
+            Box::new(Cursor::new(b"not a capture".to_vec())),
+            None,
+            bounded(0).1,
+            false,
+            AnalysisWindow::default(),
+            4,
+            None,
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error.downcast_ref::<PacketSourceError>(),
+            Some(PacketSourceError::Read(CaptureFileError::UnknownFormat))
+        ));
+    }
+
+    fn interface(name: &str, index: u32) -> NetworkInterface {
+        NetworkInterface {
+            name: name.to_string(),
+            description: String::new(),
+            index,
+            mac: None,
+            ips: Vec::new(),
+            flags: 0,
+        }
+    }
+
+    #[test]
+    fn test_find_interface_by_name_index_and_friendly_name() {
+        let interfaces = vec![
+            interface("\\Device\\NPF_{0A1B}", 3),
+            interface("\\Device\\NPF_{7C2D}", 12),
+        ];
+        let friendly_names = [(3, "Ethernet".to_string()), (12, "Ethernet 2".to_string())];
+        let find = |ifname| find_interface(interfaces.clone(), &friendly_names, ifname);
+
+        assert_eq!(find("{7c2d}").unwrap().index, 12);
+        assert_eq!(find("\\Device\\NPF_{0A1B}").unwrap().index, 3);
+        assert_eq!(find("12").unwrap().index, 12);
+        assert_eq!(find("ethernet 2").unwrap().index, 12);
+        assert_eq!(find("Ethernet").unwrap().index, 3);
+        assert!(find("Wi-Fi").is_err());
+    }
 }
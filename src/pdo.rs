@@ -0,0 +1,335 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::mailbox::SdoDownload;
+use crate::registers::{FmmuConfig, SyncManagerConfig};
+
+/// RxPDO mapping objects (outputs, main device -> subdevice).
+const RX_PDO_MAPPING: std::ops::RangeInclusive<u16> = 0x1600..=0x17FF;
+/// TxPDO mapping objects (inputs, subdevice -> main device).
+const TX_PDO_MAPPING: std::ops::RangeInclusive<u16> = 0x1A00..=0x1BFF;
+/// RxPDO assignment of sync manager 2.
+const RX_PDO_ASSIGN: u16 = 0x1C12;
+/// TxPDO assignment of sync manager 3.
+const TX_PDO_ASSIGN: u16 = 0x1C13;
+
+/// Direction of process data as seen from the main device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PdoDirection {
+    /// RxPDO, written by the main device.
+    Output,
+    /// TxPDO, read by the main device.
+    Input,
+}
+
+impl fmt::Display for PdoDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdoDirection::Output => write!(f, "out"),
+            PdoDirection::Input => write!(f, "in"),
+        }
+    }
+}
+
+/// A single entry of a PDO mapping object (`index:subindex`, bit length).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PdoEntry {
+    pub index: u16,
+    pub subindex: u8,
+    pub bit_length: u8,
+}
+
+impl PdoEntry {
+    pub fn from_u32(value: u32) -> Self {
+        PdoEntry {
+            index: (value >> 16) as u16,
+            subindex: (value >> 8) as u8,
+            bit_length: value as u8,
+        }
+    }
+
+    /// Padding entries (index 0) reserve bits without carrying a signal.
+    pub fn is_padding(&self) -> bool {
+        self.index == 0
+    }
+}
+
+/// PDO mapping and assignment objects downloaded by the main device through CoE.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PdoConfig {
+    mappings: BTreeMap<u16, Vec<PdoEntry>>,
+    assignments: BTreeMap<u16, Vec<u16>>,
+}
+
+impl PdoConfig {
+    /// Apply an SDO download. Returns `true` if a mapping or assignment object changed.
+    pub fn apply_sdo_download(&mut self, sdo: &SdoDownload) -> bool {
+        let is_mapping = RX_PDO_MAPPING.contains(&sdo.index) || TX_PDO_MAPPING.contains(&sdo.index);
+        let is_assign = sdo.index == RX_PDO_ASSIGN || sdo.index == TX_PDO_ASSIGN;
+        if !is_mapping && !is_assign {
+            return false;
+        }
+
+        if sdo.complete_access {
+            // Subindex 0 (u8) and a padding byte, followed by all entries
+            let Some(&count) = sdo.data.first() else {
+                return false;
+            };
+            let entries = sdo.data.get(2..).unwrap_or_default();
+            if is_mapping {
+                let list: Vec<_> = entries
+                    .chunks_exact(4)
+                    .take(count as usize)
+                    .map(|c| PdoEntry::from_u32(u32::from_le_bytes([c[0], c[1], c[2], c[3]])))
+                    .collect();
+                self.mappings.insert(sdo.index, list);
+            } else {
+                let list: Vec<_> = entries
+                    .chunks_exact(2)
+                    .take(count as usize)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                self.assignments.insert(sdo.index, list);
+            }
+            return true;
+        }
+
+        let value = sdo
+            .data
+            .iter()
+            .rev()
+            .fold(0_u32, |acc, b| (acc << 8) | *b as u32);
+        if is_mapping {
+            Self::set_subindex(
+                self.mappings.entry(sdo.index).or_default(),
+                sdo.subindex,
+                value,
+                PdoEntry::from_u32(value),
+            );
+        } else {
+            Self::set_subindex(
+                self.assignments.entry(sdo.index).or_default(),
+                sdo.subindex,
+                value,
+                value as u16,
+            );
+        }
+        true
+    }
+
+    /// Subindex 0 sets the number of entries, subindex n the n-th entry.
+    fn set_subindex<T: Copy + Default>(list: &mut Vec<T>, subindex: u8, raw: u32, value: T) {
+        if subindex == 0 {
+            list.truncate(raw as usize);
+            return;
+        }
+        let subindex = subindex as usize;
+        if list.len() < subindex {
+            list.resize(subindex, T::default());
+        }
+        list[subindex - 1] = value;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    pub fn mapping(&self, index: u16) -> Option<&[PdoEntry]> {
+        self.mappings.get(&index).map(Vec::as_slice)
+    }
+
+    /// PDOs assigned to the sync manager of `direction`. Without an explicit
+    /// assignment, all known mapping objects of that direction in index order.
+    pub fn assigned_pdos(&self, direction: PdoDirection) -> Vec<u16> {
+        let (assign, range) = match direction {
+            PdoDirection::Output => (RX_PDO_ASSIGN, RX_PDO_MAPPING),
+            PdoDirection::Input => (TX_PDO_ASSIGN, TX_PDO_MAPPING),
+        };
+        match self.assignments.get(&assign) {
+            Some(pdos) => pdos.clone(),
+            None => self
+                .mappings
+                .range(range)
+                .map(|(index, _)| *index)
+                .collect(),
+        }
+    }
+}
+
+/// A named PDO entry located in the logical process image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdoSignal {
+    pub name: String,
+    pub index: u16,
+    pub subindex: u8,
+    pub bit_length: u8,
+    pub direction: PdoDirection,
+    /// Bit offset of the signal in the logical address space.
+    pub logical_bit: u64,
+    pub signed: bool,
+}
+
+impl PdoSignal {
+    /// Object key in `index:subindex` form, e.g. `0x6064:00`.
+    pub fn key(&self) -> String {
+        format!("{:#06x}:{:02x}", self.index, self.subindex)
+    }
+
+    /// Whether `selector` names this signal (object key or name, case-insensitive).
+    pub fn matches(&self, selector: &str) -> bool {
+        selector.eq_ignore_ascii_case(&self.key())
+            || selector.eq_ignore_ascii_case(&self.name)
+            || selector.eq_ignore_ascii_case(&format!("{:04x}:{:02x}", self.index, self.subindex))
+    }
+
+    /// Extract the signal from a logical datagram starting at `logical_address`.
+    /// Returns `None` if the datagram does not cover the whole signal.
+    pub fn extract(&self, logical_address: u32, data: &[u8]) -> Option<i64> {
+        if self.bit_length == 0 || self.bit_length > 64 {
+            return None;
+        }
+        let start_bit = self.logical_bit.checked_sub(logical_address as u64 * 8)?;
+        let end_bit = start_bit + self.bit_length as u64;
+        if end_bit > data.len() as u64 * 8 {
+            return None;
+        }
+
+        let mut value: u64 = 0;
+        for i in 0..self.bit_length as u64 {
+            let bit = start_bit + i;
+            if data[(bit / 8) as usize] & (1 << (bit % 8)) != 0 {
+                value |= 1 << i;
+            }
+        }
+        if self.signed && self.bit_length < 64 && value & (1 << (self.bit_length - 1)) != 0 {
+            value |= u64::MAX << self.bit_length;
+        }
+        Some(value as i64)
+    }
+}
+
+/// Locate the entries of the assigned PDOs in the logical process image through
+/// the sync manager (SM2 outputs, SM3 inputs) and the FMMU that maps it.
+pub fn locate_signals(
+    config: &PdoConfig,
+    sync_managers: &[(u16, SyncManagerConfig)],
+    fmmus: &[(u16, FmmuConfig)],
+) -> Vec<PdoSignal> {
+    let mut signals = Vec::new();
+    for (direction, sm_index) in [(PdoDirection::Output, 2), (PdoDirection::Input, 3)] {
+        let Some((_, sm)) = sync_managers.iter().find(|(n, _)| *n == sm_index) else {
+            continue;
+        };
+        let Some((_, fmmu)) = fmmus.iter().find(|(_, fmmu)| {
+            fmmu.active
+                && (direction == PdoDirection::Output && fmmu.write
+                    || direction == PdoDirection::Input && fmmu.read)
+                && fmmu.physical_start <= sm.physical_start
+                && (sm.physical_start as u32) < fmmu.physical_start as u32 + fmmu.length as u32
+        }) else {
+            continue;
+        };
+
+        let logical_start =
+            fmmu.logical_start as u64 + (sm.physical_start - fmmu.physical_start) as u64;
+        let mut bit = logical_start * 8;
+        for pdo in config.assigned_pdos(direction) {
+            for entry in config.mapping(pdo).unwrap_or_default() {
+                if !entry.is_padding() {
+                    let (name, signed) = object_name(entry.index)
+                        .map(|(name, signed)| (name.to_string(), signed))
+                        .unwrap_or_else(|| {
+                            (
+                                format!("{:#06x}:{:02x}", entry.index, entry.subindex),
+                                false,
+                            )
+                        });
+                    signals.push(PdoSignal {
+                        name,
+                        index: entry.index,
+                        subindex: entry.subindex,
+                        bit_length: entry.bit_length,
+                        direction,
+                        logical_bit: bit,
+                        signed,
+                    });
+                }
+                bit += entry.bit_length as u64;
+            }
+        }
+    }
+    signals
+}
+
+/// Names of common CiA 402 drive profile objects and whether they are signed.
+fn object_name(index: u16) -> Option<(&'static str, bool)> {
+    let entry = match index {
+        0x603F => ("Error code", false),
+        0x6040 => ("Controlword", false),
+        0x6041 => ("Statusword", false),
+        0x6060 => ("Modes of operation", true),
+        0x6061 => ("Modes of operation display", true),
+        0x6062 => ("Position demand value", true),
+        0x6064 => ("Position actual value", true),
+        0x606C => ("Velocity actual value", true),
+        0x6071 => ("Target torque", true),
+        0x6072 => ("Max torque", false),
+        0x6077 => ("Torque actual value", true),
+        0x607A => ("Target position", true),
+        0x60B1 => ("Velocity offset", true),
+        0x60B2 => ("Torque offset", true),
+        0x60B8 => ("Touch probe function", false),
+        0x60B9 => ("Touch probe status", false),
+        0x60BA => ("Touch probe 1 positive edge", true),
+        0x60F4 => ("Following error actual value", true),
+        0x60FD => ("Digital inputs", false),
+        0x60FE => ("Digital outputs", false),
+        0x60FF => ("Target velocity", true),
+        _ => return None,
+    };
+    Some(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sdo(index: u16, subindex: u8, data: &[u8]) -> SdoDownload<'_> {
+        SdoDownload {
+            index,
+            subindex,
+            complete_access: false,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_locate_and_extract_signals() {
+        let mut config = PdoConfig::default();
+        // 0x1A00: Statusword (16 bit), Position actual value (32 bit)
+        config.apply_sdo_download(&sdo(0x1A00, 1, &0x6041_0010_u32.to_le_bytes()));
+        config.apply_sdo_download(&sdo(0x1A00, 2, &0x6064_0020_u32.to_le_bytes()));
+        config.apply_sdo_download(&sdo(0x1C13, 1, &0x1A00_u16.to_le_bytes()));
+
+        let sm3 = SyncManagerConfig::from_bytes(&[0x00, 0x11, 0x06, 0x00, 0x20, 0x00, 0x01, 0x00])
+            .unwrap();
+        let fmmu = FmmuConfig::from_bytes(&[
+            0x10, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x07, 0x00, 0x11, 0x00, 0x01, 0x01, 0x00,
+            0x00, 0x00,
+        ])
+        .unwrap();
+
+        let signals = locate_signals(&config, &[(3, sm3)], &[(0, fmmu)]);
+        assert_eq!(signals.len(), 2);
+        assert_eq!(signals[1].name, "Position actual value");
+        assert_eq!(signals[1].logical_bit, 0x12 * 8);
+        assert!(signals[1].matches("0x6064:00"));
+
+        // LRW covering logical 0x10..0x18
+        let data = [0x37, 0x02, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00];
+        assert_eq!(signals[0].extract(0x10, &data), Some(0x0237));
+        assert_eq!(signals[1].extract(0x10, &data), Some(-1));
+        assert_eq!(signals[1].extract(0x14, &data), None);
+    }
+}
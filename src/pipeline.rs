@@ -0,0 +1,148 @@
+use crossbeam_channel::{Receiver as CbReceiver, Sender as CbSender, bounded};
+use ecdump::ec_packet::{ECFrame, FrameMalformation};
+use smallvec::SmallVec;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::packet_source::CapturedData;
+
+/// Frames queued per parse worker, in each direction.
+const WORKER_QUEUE_SIZE: usize = 64;
+
+/// A captured frame after the parse stage.
+pub struct ParsedFrame {
+    pub captured: CapturedData,
+    /// `false` if the frame could not be parsed; the analyzer reports the error.
+    pub valid: bool,
+    pub malformations: SmallVec<[FrameMalformation; 2]>,
+}
+
+impl ParsedFrame {
+    fn parse(captured: CapturedData) -> Self {
+        let (valid, malformations) = match ECFrame::new(&captured.data) {
+            Some(frame) if frame.protocol_type() == 0x01 => match frame.parse_datagram() {
+                Ok(datagrams) => (true, frame.malformations(&datagrams)),
+                Err(_) => (false, SmallVec::new()),
+            },
+            _ => (false, SmallVec::new()),
+        };
+        ParsedFrame {
+            captured,
+            valid,
+            malformations,
+        }
+    }
+}
+
+/// Frames parsed by all workers and the time they took, for `--self-stats`.
+#[derive(Default)]
+struct ParseTimes {
+    frames: AtomicU64,
+    nanos: AtomicU64,
+}
+
+/// Queue depths and parse work of the pipeline at one moment.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PipelineLoad {
+    /// Frames waiting between the packet source and the dispatcher.
+    pub source_queue: usize,
+    pub source_capacity: usize,
+    /// Parsed frames waiting for the analyzer, over all workers.
+    pub parsed_queue: usize,
+    pub parsed_capacity: usize,
+    /// Frames parsed so far and the time spent parsing them.
+    pub parsed_frames: u64,
+    pub parse_nanos: u64,
+}
+
+/// Parse stage between the packet source and the analyzer.
+///
+/// A dispatcher hands captured frames round-robin to a pool of parse workers, each
+/// with its own bounded queue. Reading the worker outputs in the same round-robin
+/// order restores capture order without a reorder buffer. Dropping the pipeline
+/// stops the workers and the dispatcher, which in turn closes the packet source.
+pub struct ParsePipeline {
+    outputs: Vec<CbReceiver<ParsedFrame>>,
+    next: usize,
+    /// Kept only to measure the queue of the packet source, so it is closed
+    /// together with the pipeline.
+    source: CbReceiver<CapturedData>,
+    times: Arc<ParseTimes>,
+}
+
+impl ParsePipeline {
+    pub fn start(rx_data: CbReceiver<CapturedData>, workers: usize) -> Self {
+        let workers = workers.max(1);
+        let times = Arc::new(ParseTimes::default());
+        let mut inputs = Vec::with_capacity(workers);
+        let mut outputs = Vec::with_capacity(workers);
+
+        for i in 0..workers {
+            let (tx_in, rx_in) = bounded::<CapturedData>(WORKER_QUEUE_SIZE);
+            let (tx_out, rx_out) = bounded::<ParsedFrame>(WORKER_QUEUE_SIZE);
+            inputs.push(tx_in);
+            outputs.push(rx_out);
+            let times = times.clone();
+            std::thread::Builder::new()
+                .name(format!("Parse Worker {}", i))
+                .spawn(move || {
+                    for captured in rx_in {
+                        let started = Instant::now();
+                        let parsed = ParsedFrame::parse(captured);
+                        times.frames.fetch_add(1, Ordering::Relaxed);
+                        times
+                            .nanos
+                            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                        if tx_out.send(parsed).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("Parse Worker Thread");
+        }
+
+        let source = rx_data.clone();
+        std::thread::Builder::new()
+            .name("Parse Dispatcher".to_string())
+            .spawn(move || Self::dispatch(rx_data, inputs))
+            .expect("Parse Dispatcher Thread");
+
+        ParsePipeline {
+            outputs,
+            next: 0,
+            source,
+            times,
+        }
+    }
+
+    fn dispatch(rx_data: CbReceiver<CapturedData>, inputs: Vec<CbSender<CapturedData>>) {
+        for (captured, input) in rx_data.into_iter().zip(inputs.iter().cycle()) {
+            if input.send(captured).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Receiver that delivers the next frame in capture order.
+    pub fn receiver(&self) -> &CbReceiver<ParsedFrame> {
+        &self.outputs[self.next]
+    }
+
+    /// Advance to the next worker after a frame was received from [`Self::receiver`].
+    pub fn advance(&mut self) {
+        self.next = (self.next + 1) % self.outputs.len();
+    }
+
+    /// Current queue depths and parse work.
+    pub fn load(&self) -> PipelineLoad {
+        PipelineLoad {
+            source_queue: self.source.len(),
+            source_capacity: self.source.capacity().unwrap_or(0),
+            parsed_queue: self.outputs.iter().map(CbReceiver::len).sum(),
+            parsed_capacity: self.outputs.len() * WORKER_QUEUE_SIZE,
+            parsed_frames: self.times.frames.load(Ordering::Relaxed),
+            parse_nanos: self.times.nanos.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Extension point for decoding protocols the analyzer does not know, such as
+//! vendor-specific mailbox protocols (VoE) or proprietary register maps.
+//!
+//! A [`ProtocolHandler`] registered with
+//! [`DeviceManager::add_protocol_handler`](crate::analyzer::DeviceManager::add_protocol_handler)
+//! sees every datagram after the device model was updated with it, and every
+//! mailbox message exchanged with a subdevice. Messages it pushes become
+//! [`ProtocolEvent`]s of the frame.
+
+use std::time::Duration;
+
+use crate::ec_packet::ECDatagram;
+use crate::mailbox::MailboxHeader;
+use crate::subdevice::{SubDevice, SubdeviceIdentifier};
+
+pub trait ProtocolHandler: Send {
+    /// Name shown with the events of the handler.
+    fn name(&self) -> &str;
+
+    /// Called for every datagram of every frame.
+    fn datagram(&mut self, _datagram: &DatagramContext, _events: &mut Vec<String>) {}
+
+    /// Called for every mailbox message, once it returned to the main device.
+    fn mailbox(&mut self, _message: &MailboxMessage, _events: &mut Vec<String>) {}
+}
+
+/// A datagram as seen by a [`ProtocolHandler`].
+pub struct DatagramContext<'a> {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub from_main: bool,
+    pub datagram: &'a ECDatagram<'a>,
+    /// The subdevice addressed by a physical (auto-increment or configured
+    /// address) datagram.
+    pub device: Option<&'a SubDevice>,
+}
+
+/// A message written to or read from the mailbox of a subdevice.
+pub struct MailboxMessage<'a> {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub device: &'a SubDevice,
+    /// Written by the main device (`true`) or read from the subdevice.
+    pub to_subdevice: bool,
+    pub header: MailboxHeader,
+    /// The service data following the header, e.g. the VoE header and payload.
+    pub data: &'a [u8],
+}
+
+/// A message of a [`ProtocolHandler`].
+#[derive(Debug, Clone)]
+pub struct ProtocolEvent {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub subdevice_id: Option<SubdeviceIdentifier>,
+    /// [`ProtocolHandler::name`] of the handler.
+    pub handler: String,
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::DeviceManager;
+    use crate::ec_packet::{ECCommands, ECFrame, ECFrameBuilder};
+
+    struct BroadcastCounter(u32);
+
+    impl ProtocolHandler for BroadcastCounter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn datagram(&mut self, datagram: &DatagramContext, events: &mut Vec<String>) {
+            if !datagram.from_main && datagram.device.is_none() {
+                self.0 += 1;
+                events.push(format!("broadcast {}", self.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_handler_events_are_collected_per_frame() {
+        let mut device_manager = DeviceManager::default();
+        device_manager.add_protocol_handler(Box::new(BroadcastCounter(0)));
+        // BRD of the ESC type register answered by two subdevices
+        let frame = ECFrameBuilder::new()
+            .datagram(ECCommands::BRD, 0, 0, &[0x11, 0x00], 2)
+            .build();
+        let frame = ECFrame::new(&frame).unwrap();
+
+        device_manager
+            .analyze_packet(&frame, Duration::ZERO, true)
+            .unwrap();
+        assert!(device_manager.take_protocol_events().is_empty());
+        device_manager
+            .analyze_packet(&frame, Duration::from_micros(50), false)
+            .unwrap();
+        let events = device_manager.take_protocol_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].packet_number, 2);
+        assert_eq!(events[0].handler, "counter");
+        assert_eq!(events[0].message, "broadcast 1");
+    }
+}
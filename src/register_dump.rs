@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use ecdump::analyzer::DeviceManager;
+use ecdump::registers::default_decoder;
+use ecdump::subdevice::{ECState, RegisterDumpEntry, SubDevice, SubDeviceIdentity};
+use schemars::{JsonSchema, Schema, schema_for};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::schema::{self, REGISTER_DUMP_VERSION};
+
+/// Unnamed register bytes per line.
+const RAW_BYTES_PER_LINE: u16 = 16;
+
+/// A named register, or a run of bytes outside the known registers.
+#[derive(Serialize, JsonSchema)]
+pub struct DumpLine {
+    pub address: u16,
+    pub name: Option<&'static str>,
+    /// Bytes in address order, `null` where the value was not seen.
+    pub bytes: Vec<Option<u8>>,
+    /// Decoded value of a named register whose bytes are all known.
+    pub value: Option<String>,
+    /// Shadows the bytes were taken from: `wr` written by the main device, `rd`
+    /// read from the subdevice, `brd` broadcast read.
+    pub source: Vec<String>,
+}
+
+impl DumpLine {
+    /// The decoded value, or the bytes in hex.
+    pub fn display_value(&self) -> String {
+        match &self.value {
+            Some(value) => value.clone(),
+            None => hex_bytes(&self.bytes),
+        }
+    }
+}
+
+/// Final register space of a subdevice.
+#[derive(Serialize, JsonSchema)]
+struct DeviceDump {
+    schema_version: u32,
+    position: usize,
+    subdevice: String,
+    configured_address: Option<u16>,
+    identity: Option<SubDeviceIdentity>,
+    state: ECState,
+    registers: Vec<DumpLine>,
+}
+
+/// Write the final register space of every subdevice to `dir`, one annotated text
+/// file and one JSON file per subdevice, named by position and identifier (e.g.
+/// `000-address-1001.txt`).
+pub fn write(dir: &str, device_manager: &DeviceManager) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create register dump directory: {}", dir))?;
+    for (position, device) in device_manager.devices().iter().enumerate() {
+        let dump = DeviceDump {
+            schema_version: REGISTER_DUMP_VERSION,
+            position,
+            subdevice: device.identifier().to_string(),
+            configured_address: device.configured_address(),
+            identity: device.identity(),
+            state: device.state(),
+            registers: dump_lines(device),
+        };
+        let stem = format!(
+            "{:03}-{}",
+            position,
+            dump.subdevice.to_ascii_lowercase().replace(' ', "-")
+        );
+        let path = Path::new(dir).join(format!("{}.txt", stem));
+        write_file(&path, |writer| write_text(writer, &dump))?;
+        let path = Path::new(dir).join(format!("{}.json", stem));
+        write_file(&path, |writer| {
+            serde_json::to_writer_pretty(&mut *writer, &dump)?;
+            writeln!(writer)?;
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// JSON Schema of the `--dump-registers` JSON files.
+pub fn schema() -> Schema {
+    schema::versioned(schema_for!(DeviceDump), REGISTER_DUMP_VERSION)
+}
+
+fn write_file(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create register dump: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_text(writer: &mut impl Write, dump: &DeviceDump) -> Result<()> {
+    writeln!(writer, "# Subdevice #{} {}", dump.position, dump.subdevice)?;
+    if let Some(identity) = dump.identity {
+        writeln!(writer, "# {}", identity)?;
+    }
+    writeln!(writer, "# State {}", dump.state)?;
+    writeln!(
+        writer,
+        "# Source: wr = written by the main device, rd = read, brd = broadcast read"
+    )?;
+    for line in &dump.registers {
+        writeln!(writer, "{}", format_line(line))?;
+    }
+    Ok(())
+}
+
+/// Text line of a register: address, name, source and value.
+pub fn format_line(line: &DumpLine) -> String {
+    format!(
+        "{:#06x}  {:<28} {:<7} {}",
+        line.address,
+        line.name.unwrap_or("-"),
+        line.source.join("+"),
+        line.display_value()
+    )
+}
+
+fn hex_bytes(bytes: &[Option<u8>]) -> String {
+    let mut out = String::new();
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        match byte {
+            Some(byte) => write!(out, "{:02x}", byte).unwrap(),
+            None => out.push_str("??"),
+        }
+    }
+    out
+}
+
+/// The known register bytes grouped into the registers of the default decoder,
+/// and runs of at most [`RAW_BYTES_PER_LINE`] bytes elsewhere.
+pub fn dump_lines(device: &SubDevice) -> Vec<DumpLine> {
+    let decoder = default_decoder();
+    let entries: BTreeMap<u16, RegisterDumpEntry> = device
+        .register_dump()
+        .map(|entry| (entry.address, entry))
+        .collect();
+    let mut lines = Vec::new();
+    // First address not covered by an earlier line
+    let mut next = 0u32;
+    for &address in entries.keys() {
+        if (address as u32) < next {
+            continue;
+        }
+        let (start, end, name) = match decoder.lookup(address) {
+            Some(definition) => (
+                definition.address,
+                definition.address as u32 + definition.length() as u32,
+                Some(definition.name),
+            ),
+            None => {
+                let mut end = address as u32 + 1;
+                while end < address as u32 + RAW_BYTES_PER_LINE as u32
+                    && end <= u16::MAX as u32
+                    && entries.contains_key(&(end as u16))
+                    && decoder.lookup(end as u16).is_none()
+                {
+                    end += 1;
+                }
+                (address, end, None)
+            }
+        };
+        next = end;
+
+        let range = (start as u32..end).map(|address| entries.get(&(address as u16)));
+        let bytes: Vec<Option<u8>> = range.clone().map(|entry| entry.map(|e| e.value)).collect();
+        let mut source = Vec::new();
+        for entry in range.flatten() {
+            let shadow = entry.shadow.to_string();
+            if !source.contains(&shadow) {
+                source.push(shadow);
+            }
+        }
+        let value = name
+            .and_then(|_| decoder.decode_iter(start, bytes.iter().copied()))
+            .map(|value| value.to_string());
+        lines.push(DumpLine {
+            address: start,
+            name,
+            bytes,
+            value,
+            source,
+        });
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecdump::registers::RegisterAddress;
+
+    #[test]
+    fn test_dump_lines_group_named_registers() {
+        let mut device = SubDevice::new();
+        device.write_reg_wr(RegisterAddress::ConfiguredStationAddress, &[0x01, 0x10]);
+        // One byte of AL Status Code, and unnamed bytes
+        device.write_reg_rd(RegisterAddress::AlStatusCode, &[0x1b]);
+        device.write_reg_rd(0x0f80, &[0xaa, 0xbb]);
+
+        let lines = dump_lines(&device);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].name, Some("ConfiguredStationAddress"));
+        assert_eq!(lines[0].value.as_deref(), Some("0x1001"));
+        assert_eq!(lines[0].source, ["wr"]);
+        assert_eq!(lines[1].name, Some("AlStatusCode"));
+        assert_eq!(lines[1].bytes, [Some(0x1b), None]);
+        assert_eq!(lines[1].value, None);
+        assert_eq!(lines[2].name, None);
+        assert_eq!(lines[2].bytes, [Some(0xaa), Some(0xbb)]);
+    }
+}
@@ -0,0 +1,307 @@
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Number of bytes covered by a single page of the register image.
+pub const PAGE_SIZE: usize = 256;
+/// Number of pages needed to cover the 64 KiB ESC address space.
+const PAGE_COUNT: usize = 0x1_0000 / PAGE_SIZE;
+/// Number of `u64` words in a per-page bitmap.
+const BITMAP_WORDS: usize = PAGE_SIZE / 64;
+
+#[derive(Debug, Clone)]
+struct Page {
+    data: [u8; PAGE_SIZE],
+    /// Bytes that have been written at least once.
+    present: [u64; BITMAP_WORDS],
+    /// Bytes written since the last call to `clear_dirty`.
+    dirty: [u64; BITMAP_WORDS],
+    /// Calls to `age` since the page was last written.
+    idle: u32,
+}
+
+impl Page {
+    fn new() -> Self {
+        Page {
+            data: [0; PAGE_SIZE],
+            present: [0; BITMAP_WORDS],
+            dirty: [0; BITMAP_WORDS],
+            idle: 0,
+        }
+    }
+
+    fn bit(bitmap: &[u64; BITMAP_WORDS], offset: usize) -> bool {
+        bitmap[offset / 64] & (1 << (offset % 64)) != 0
+    }
+
+    fn set_bit(bitmap: &mut [u64; BITMAP_WORDS], offset: usize) {
+        bitmap[offset / 64] |= 1 << (offset % 64);
+    }
+}
+
+/// Shadow image of a subdevice's 64 KiB register address space.
+///
+/// Memory is allocated lazily in 256-byte pages, so a device that only ever sees
+/// a handful of registers costs a few hundred bytes. Each page keeps a presence
+/// bitmap (which bytes hold a known value) and a dirty bitmap (which bytes were
+/// written since the last `clear_dirty`). Pages that were not written for a
+/// while can be evicted to bound memory, see `age` and `evict`.
+#[derive(Debug, Clone)]
+pub struct RegisterImage {
+    pages: Vec<Option<Box<Page>>>,
+}
+
+impl Default for RegisterImage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegisterImage {
+    pub fn new() -> Self {
+        RegisterImage {
+            pages: vec![None; PAGE_COUNT],
+        }
+    }
+
+    fn split(address: u16) -> (usize, usize) {
+        let address = address as usize;
+        (address / PAGE_SIZE, address % PAGE_SIZE)
+    }
+
+    /// Write `data` starting at `address`. Addresses wrap around at 0xFFFF.
+    pub fn write(&mut self, address: u16, data: &[u8]) {
+        for (i, value) in data.iter().enumerate() {
+            let (page_idx, offset) = Self::split(address.wrapping_add(i as u16));
+            let page = self.pages[page_idx].get_or_insert_with(|| Box::new(Page::new()));
+            page.data[offset] = *value;
+            page.idle = 0;
+            Page::set_bit(&mut page.present, offset);
+            Page::set_bit(&mut page.dirty, offset);
+        }
+    }
+
+    /// Value of a single byte, or `None` if it has never been written.
+    pub fn get(&self, address: u16) -> Option<u8> {
+        let (page_idx, offset) = Self::split(address);
+        let page = self.pages[page_idx].as_ref()?;
+        Page::bit(&page.present, offset).then(|| page.data[offset])
+    }
+
+    /// Read `length` bytes starting at `address`. Unknown bytes are yielded as `None`.
+    pub fn read(&self, address: u16, length: u16) -> impl Iterator<Item = Option<u8>> + '_ {
+        (0..length).map(move |i| self.get(address.wrapping_add(i)))
+    }
+
+    /// Whether the byte at `address` was written since the last `clear_dirty`.
+    pub fn is_dirty(&self, address: u16) -> bool {
+        let (page_idx, offset) = Self::split(address);
+        self.pages[page_idx]
+            .as_ref()
+            .is_some_and(|page| Page::bit(&page.dirty, offset))
+    }
+
+    /// Addresses of all bytes written since the last `clear_dirty`, in ascending order.
+    pub fn dirty_addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        self.addresses_matching(|page| &page.dirty)
+    }
+
+    /// Reset the dirty bitmap of every page.
+    pub fn clear_dirty(&mut self) {
+        for page in self.pages.iter_mut().flatten() {
+            page.dirty = [0; BITMAP_WORDS];
+        }
+    }
+
+    /// Iterate over all known bytes as `(address, value)` pairs in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.addresses_matching(|page| &page.present)
+            .map(move |address| {
+                let (page_idx, offset) = Self::split(address);
+                let page = self.pages[page_idx].as_ref().expect("present page");
+                (address, page.data[offset])
+            })
+    }
+
+    /// Number of bytes with a known value.
+    pub fn len(&self) -> usize {
+        self.pages
+            .iter()
+            .flatten()
+            .map(|page| {
+                page.present
+                    .iter()
+                    .map(|w| w.count_ones() as usize)
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.iter().all(Option::is_none)
+    }
+
+    /// Approximate heap usage of the image in bytes.
+    pub fn memory_usage(&self) -> usize {
+        let allocated = self.pages.iter().flatten().count();
+        self.pages.capacity() * std::mem::size_of::<Option<Box<Page>>>()
+            + allocated * std::mem::size_of::<Page>()
+    }
+
+    /// Count one more period without writes for every page.
+    pub fn age(&mut self) {
+        for page in self.pages.iter_mut().flatten() {
+            page.idle = page.idle.saturating_add(1);
+        }
+    }
+
+    /// Start address of every allocated page with the number of `age` calls since
+    /// it was last written.
+    pub fn page_ages(&self) -> impl Iterator<Item = (u16, u32)> + '_ {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, page)| Some(((idx * PAGE_SIZE) as u16, page.as_ref()?.idle)))
+    }
+
+    /// Forget the page containing `address`. Returns the number of known bytes
+    /// it held, `None` if it was not allocated.
+    pub fn evict(&mut self, address: u16) -> Option<usize> {
+        let (page_idx, _) = Self::split(address);
+        let page = self.pages[page_idx].take()?;
+        Some(page.present.iter().map(|w| w.count_ones() as usize).sum())
+    }
+
+    fn addresses_matching<'a>(
+        &'a self,
+        bitmap: impl Fn(&Page) -> &[u64; BITMAP_WORDS] + 'a,
+    ) -> impl Iterator<Item = u16> + 'a {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, page)| page.as_ref().map(|page| (idx, page)))
+            .flat_map(move |(idx, page)| {
+                let words = *bitmap(page);
+                (0..PAGE_SIZE)
+                    .filter(move |offset| words[offset / 64] & (1 << (offset % 64)) != 0)
+                    .map(move |offset| (idx * PAGE_SIZE + offset) as u16)
+            })
+    }
+}
+
+/// A page of a serialized [`RegisterImage`]. Dirty bits are not kept.
+#[derive(Serialize, Deserialize)]
+struct StoredPage {
+    index: usize,
+    /// Bytes as hex, `00` for unknown ones.
+    data: String,
+    present: [u64; BITMAP_WORDS],
+}
+
+/// Serialized as the pages holding known values.
+impl Serialize for RegisterImage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.pages.iter().enumerate().filter_map(|(index, page)| {
+            let page = page.as_ref()?;
+            Some(StoredPage {
+                index,
+                data: page
+                    .data
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect(),
+                present: page.present,
+            })
+        }))
+    }
+}
+
+impl<'de> Deserialize<'de> for RegisterImage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut image = RegisterImage::new();
+        for stored in Vec::<StoredPage>::deserialize(deserializer)? {
+            if stored.index >= PAGE_COUNT || stored.data.len() != PAGE_SIZE * 2 {
+                return Err(D::Error::custom("invalid register image page"));
+            }
+            let mut page = Page::new();
+            for (offset, byte) in page.data.iter_mut().enumerate() {
+                let hex = stored.data.get(offset * 2..offset * 2 + 2);
+                *byte = hex
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| D::Error::custom("invalid register image data"))?;
+            }
+            page.present = stored.present;
+            image.pages[stored.index] = Some(Box::new(page));
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_unknown_and_written_bytes() {
+        let mut image = RegisterImage::new();
+        assert!(image.is_empty());
+        image.write(0x0130, &[0x08, 0x00]);
+
+        let bytes: Vec<_> = image.read(0x012F, 4).collect();
+        assert_eq!(bytes, vec![None, Some(0x08), Some(0x00), None]);
+        assert_eq!(image.len(), 2);
+    }
+
+    #[test]
+    fn test_write_wraps_at_end_of_address_space() {
+        let mut image = RegisterImage::new();
+        image.write(0xFFFF, &[0xAA, 0xBB]);
+        assert_eq!(image.get(0xFFFF), Some(0xAA));
+        assert_eq!(image.get(0x0000), Some(0xBB));
+    }
+
+    #[test]
+    fn test_dirty_tracking() {
+        let mut image = RegisterImage::new();
+        image.write(0x0120, &[0x02]);
+        image.write(0x0010, &[0x01, 0x10]);
+        assert!(image.is_dirty(0x0120));
+        assert_eq!(
+            image.dirty_addresses().collect::<Vec<_>>(),
+            vec![0x0010, 0x0011, 0x0120]
+        );
+
+        image.clear_dirty();
+        assert!(!image.is_dirty(0x0120));
+        assert_eq!(image.dirty_addresses().count(), 0);
+        // Values survive clearing the dirty bitmap
+        assert_eq!(image.get(0x0120), Some(0x02));
+    }
+
+    #[test]
+    fn test_evict_idle_page() {
+        let mut image = RegisterImage::new();
+        image.write(0x0010, &[0x01, 0x02]);
+        image.age();
+        image.write(0x0130, &[0x08]);
+        assert_eq!(
+            image.page_ages().collect::<Vec<_>>(),
+            vec![(0x0000, 1), (0x0100, 0)]
+        );
+
+        assert_eq!(image.evict(0x0000), Some(2));
+        assert_eq!(image.evict(0x0000), None);
+        assert_eq!(image.get(0x0010), None);
+        assert_eq!(image.get(0x0130), Some(0x08));
+    }
+
+    #[test]
+    fn test_iter_in_address_order() {
+        let mut image = RegisterImage::new();
+        image.write(0x0900, &[0x11]);
+        image.write(0x0012, &[0x22]);
+        assert_eq!(
+            image.iter().collect::<Vec<_>>(),
+            vec![(0x0012, 0x22), (0x0900, 0x11)]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use crate::registers::{RegisterType, RegisterValue, default_decoder, parse_u16};
+use crate::subdevice::{RegisterShadow, SubdeviceIdentifier};
+
+/// A register selected with `--watch-reg`, optionally restricted to a single
+/// subdevice by its configured station address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterWatch {
+    pub address: u16,
+    /// Taken from the register definition, 1 byte for unknown registers.
+    pub length: u16,
+    pub station_address: Option<u16>,
+}
+
+impl RegisterWatch {
+    /// Parse `ADDR[:addr=STATION]`, e.g. `0x0130` or `0x092C:addr=0x1001`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts = s.trim().split(':');
+        let address = parse_u16(parts.next().unwrap_or_default())?;
+
+        let mut station_address = None;
+        for option in parts {
+            match option.split_once('=') {
+                Some(("addr", value)) => station_address = Some(parse_u16(value)?),
+                _ => return Err(format!("unknown register watch option '{}'", option)),
+            }
+        }
+
+        let length = default_decoder()
+            .get(address)
+            .map(|def| def.length())
+            .filter(|len| *len <= 8)
+            .unwrap_or(1);
+
+        Ok(RegisterWatch {
+            address,
+            length,
+            station_address,
+        })
+    }
+
+    /// Whether a datagram accessing `length` bytes at `address` touches this register.
+    pub fn overlaps(&self, address: u16, length: u16) -> bool {
+        let start = address as u32;
+        let end = start + length as u32;
+        let watch_start = self.address as u32;
+        start < watch_start + self.length as u32 && watch_start < end
+    }
+
+    /// Whether the watch applies to a subdevice with the given configured station address.
+    pub fn matches_device(&self, configured_address: Option<u16>) -> bool {
+        self.station_address
+            .is_none_or(|station| configured_address == Some(station))
+    }
+
+    /// Decode a value of the watched register, falling back to a plain integer
+    /// for registers without a definition.
+    pub fn decode(&self, bytes: &[u8]) -> Option<RegisterValue> {
+        default_decoder().decode(self.address, bytes).or_else(|| {
+            let kind = match self.length {
+                1 => RegisterType::U8,
+                2 => RegisterType::U16,
+                4 => RegisterType::U32,
+                _ => RegisterType::U64,
+            };
+            kind.decode(bytes)
+        })
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        default_decoder().get(self.address).map(|def| def.name)
+    }
+}
+
+/// A change of a watched register value.
+#[derive(Debug, Clone)]
+pub struct RegisterChange {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub subdevice_id: SubdeviceIdentifier,
+    pub watch: RegisterWatch,
+    pub shadow: RegisterShadow,
+    /// `None` the first time the register value is observed.
+    pub old: Option<RegisterValue>,
+    pub new: RegisterValue,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_register_watch() {
+        let watch = RegisterWatch::parse("0x0130").unwrap();
+        assert_eq!(watch.address, 0x0130);
+        assert_eq!(watch.station_address, None);
+
+        let watch = RegisterWatch::parse("0x092C:addr=0x1001").unwrap();
+        assert_eq!(watch.address, 0x092C);
+        assert_eq!(watch.station_address, Some(0x1001));
+        assert!(watch.matches_device(Some(0x1001)));
+        assert!(!watch.matches_device(Some(0x1002)));
+
+        assert!(RegisterWatch::parse("0x0130:pos=1").is_err());
+        assert!(RegisterWatch::parse("zz").is_err());
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let watch = RegisterWatch {
+            address: 0x0130,
+            length: 2,
+            station_address: None,
+        };
+        assert!(watch.overlaps(0x0130, 1));
+        assert!(watch.overlaps(0x0120, 0x20));
+        assert!(!watch.overlaps(0x0132, 4));
+        assert!(!watch.overlaps(0x0120, 0x10));
+    }
+}
@@ -1,6 +1,11 @@
 use crate::subdevice::ECState;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::fmt;
+use std::sync::OnceLock;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AlControl {
     pub state: Result<ECState, u8>,
     pub acknowledge: bool,
@@ -34,9 +39,14 @@ impl AlControl {
 
         Some(Self { state, acknowledge })
     }
+
+    /// Decode a 16-bit AL Control access. The upper byte is reserved.
+    pub fn from_u16(al_control: u16) -> Self {
+        Self::new(al_control as u8)
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AlStatus {
     pub state: Result<ECState, u8>,
     pub error: bool,
@@ -70,6 +80,16 @@ impl AlStatus {
 
         Some(Self { state, error })
     }
+
+    /// Decode a 16-bit AL Status access. The upper byte is reserved.
+    pub fn from_u16(al_status: u16) -> Self {
+        Self::new(al_status as u8)
+    }
+
+    /// Decode a 16-bit AL Status access, rejecting undefined states.
+    pub fn try_from_u16(al_status: u16) -> Option<Self> {
+        Self::try_from(al_status as u8)
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -311,13 +331,665 @@ impl AlStatusCode {
 /// Format a raw AL Status Code `u16` value as a human-readable string.
 /// Known codes are resolved to their name; unknown codes show as hex.
 pub fn format_al_status_code(code: u16) -> String {
-    match AlStatusCode::from_u16(code) {
-        Some(known) => format!("{:#06x} ({})", code, known.name()),
-        None if code >= 0x8000 => format!("{:#06x} (vendor specific)", code),
-        None => format!("{:#06x} (unknown)", code),
+    crate::messages::format_al_status_code(crate::messages::Lang::En, code)
+}
+
+/// Decoded Fieldbus Memory Management Unit (FMMU) configuration (ETG1000.4 Table 57).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct FmmuConfig {
+    pub logical_start: u32,
+    pub length: u16,
+    pub logical_start_bit: u8,
+    pub logical_end_bit: u8,
+    pub physical_start: u16,
+    pub physical_start_bit: u8,
+    /// The FMMU maps inputs (the main device reads from the logical image).
+    pub read: bool,
+    /// The FMMU maps outputs (the main device writes to the logical image).
+    pub write: bool,
+    pub active: bool,
+}
+
+impl FmmuConfig {
+    /// Size of one FMMU entry in bytes.
+    pub const SIZE: u16 = 16;
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE as usize {
+            return None;
+        }
+        Some(FmmuConfig {
+            logical_start: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            length: u16::from_le_bytes([bytes[4], bytes[5]]),
+            logical_start_bit: bytes[6] & 0x07,
+            logical_end_bit: bytes[7] & 0x07,
+            physical_start: u16::from_le_bytes([bytes[8], bytes[9]]),
+            physical_start_bit: bytes[10] & 0x07,
+            read: bytes[11] & 0x01 != 0,
+            write: bytes[11] & 0x02 != 0,
+            active: bytes[12] & 0x01 != 0,
+        })
+    }
+
+    /// Logical address range `[start, end)` covered by this FMMU.
+    pub fn logical_range(&self) -> std::ops::Range<u32> {
+        self.logical_start..self.logical_start.saturating_add(self.length as u32)
+    }
+}
+
+/// Decoded Sync Manager configuration (ETG1000.4 Table 59).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct SyncManagerConfig {
+    pub physical_start: u16,
+    pub length: u16,
+    pub control: u8,
+    pub status: u8,
+    pub active: bool,
+    pub pdi_control: u8,
+}
+
+impl SyncManagerConfig {
+    /// Size of one sync manager entry in bytes.
+    pub const SIZE: u16 = 8;
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE as usize {
+            return None;
+        }
+        Some(SyncManagerConfig {
+            physical_start: u16::from_le_bytes([bytes[0], bytes[1]]),
+            length: u16::from_le_bytes([bytes[2], bytes[3]]),
+            control: bytes[4],
+            status: bytes[5],
+            active: bytes[6] & 0x01 != 0,
+            pdi_control: bytes[7],
+        })
+    }
+
+    /// Operation mode bits 0-1 of the control byte: `0b10` is mailbox mode.
+    pub fn is_mailbox(&self) -> bool {
+        self.control & 0x03 == 0x02
+    }
+
+    /// Direction bits 2-3 of the control byte: `0b01` means the main device writes.
+    pub fn is_write(&self) -> bool {
+        (self.control >> 2) & 0x03 == 0x01
+    }
+
+    /// Whether `reg_addr` falls within the physical area of this sync manager.
+    pub fn contains(&self, reg_addr: u16) -> bool {
+        reg_addr >= self.physical_start
+            && (reg_addr as u32) < self.physical_start as u32 + self.length as u32
+    }
+}
+
+/// Byte offsets of the identity fields in the SII EEPROM (ETG2010 Table 2).
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod SiiAddress {
+    /// Vendor ID, `u32`.
+    pub const VendorId: u16 = 0x0010;
+    /// Product code, `u32`.
+    pub const ProductCode: u16 = 0x0014;
+    /// Revision number, `u32`.
+    pub const RevisionNumber: u16 = 0x0018;
+    /// Serial number, `u32`.
+    pub const SerialNumber: u16 = 0x001C;
+}
+
+/// A named bit range inside a register value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitField {
+    pub name: &'static str,
+    /// Bit offset from the least significant bit.
+    pub offset: u8,
+    /// Width of the field in bits.
+    pub width: u8,
+}
+
+impl BitField {
+    pub const fn new(name: &'static str, offset: u8, width: u8) -> Self {
+        BitField {
+            name,
+            offset,
+            width,
+        }
+    }
+
+    /// Extract this field from a raw register value.
+    pub fn extract(&self, raw: u64) -> u64 {
+        let mask = if self.width >= 64 {
+            u64::MAX
+        } else {
+            (1u64 << self.width) - 1
+        };
+        (raw >> self.offset) & mask
+    }
+}
+
+/// The typed layout of a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I64,
+    /// A little-endian value of `size` bytes split into named bit fields.
+    Bitfield {
+        size: u16,
+        fields: &'static [BitField],
+    },
+}
+
+impl RegisterType {
+    /// Size of the register in bytes.
+    pub fn size(&self) -> u16 {
+        match self {
+            RegisterType::U8 => 1,
+            RegisterType::U16 => 2,
+            RegisterType::U32 => 4,
+            RegisterType::U64 | RegisterType::I64 => 8,
+            RegisterType::Bitfield { size, .. } => *size,
+        }
+    }
+
+    /// Decode a little-endian byte slice into a typed value.
+    /// Returns `None` if `bytes` is shorter than the register size.
+    pub fn decode(&self, bytes: &[u8]) -> Option<RegisterValue> {
+        let size = self.size() as usize;
+        if bytes.len() < size || size > 8 {
+            return None;
+        }
+        let raw = le_bytes_to_u64(&bytes[..size]);
+        let value = match self {
+            RegisterType::U8 => RegisterValue::U8(raw as u8),
+            RegisterType::U16 => RegisterValue::U16(raw as u16),
+            RegisterType::U32 => RegisterValue::U32(raw as u32),
+            RegisterType::U64 => RegisterValue::U64(raw),
+            RegisterType::I64 => RegisterValue::I64(raw as i64),
+            RegisterType::Bitfield { fields, .. } => RegisterValue::Bitfield {
+                raw,
+                fields: fields.iter().map(|f| (f.name, f.extract(raw))).collect(),
+            },
+        };
+        Some(value)
+    }
+}
+
+/// A decoded register value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegisterValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I64(i64),
+    Bitfield {
+        raw: u64,
+        fields: Vec<(&'static str, u64)>,
+    },
+}
+
+impl RegisterValue {
+    /// The raw value as an unsigned integer.
+    pub fn raw(&self) -> u64 {
+        match self {
+            RegisterValue::U8(v) => *v as u64,
+            RegisterValue::U16(v) => *v as u64,
+            RegisterValue::U32(v) => *v as u64,
+            RegisterValue::U64(v) => *v,
+            RegisterValue::I64(v) => *v as u64,
+            RegisterValue::Bitfield { raw, .. } => *raw,
+        }
+    }
+
+    /// Look up a named bit field of a bitfield register.
+    pub fn field(&self, name: &str) -> Option<u64> {
+        match self {
+            RegisterValue::Bitfield { fields, .. } => {
+                fields.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RegisterValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterValue::U8(v) => write!(f, "{:#04x}", v),
+            RegisterValue::U16(v) => write!(f, "{:#06x}", v),
+            RegisterValue::U32(v) => write!(f, "{:#010x}", v),
+            RegisterValue::U64(v) => write!(f, "{:#018x}", v),
+            RegisterValue::I64(v) => write!(f, "{}", v),
+            RegisterValue::Bitfield { raw, fields } => {
+                write!(f, "{:#x} {{", raw)?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {}: {}", name, value)?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+/// Describes a register at a fixed address with a name and typed layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterDefinition {
+    pub address: u16,
+    pub name: &'static str,
+    pub kind: RegisterType,
+}
+
+impl RegisterDefinition {
+    pub const fn new(address: u16, name: &'static str, kind: RegisterType) -> Self {
+        RegisterDefinition {
+            address,
+            name,
+            kind,
+        }
+    }
+
+    pub fn length(&self) -> u16 {
+        self.kind.size()
+    }
+
+    /// Whether `address` falls within this register.
+    pub fn contains(&self, address: u16) -> bool {
+        address >= self.address && (address - self.address) < self.length()
     }
 }
 
+/// Registry mapping register address ranges to typed decoders.
+#[derive(Debug, Clone, Default)]
+pub struct RegisterDecoder {
+    /// Sorted by address.
+    definitions: Vec<RegisterDefinition>,
+}
+
+impl RegisterDecoder {
+    /// Create an empty decoder without any register definitions.
+    pub fn new() -> Self {
+        RegisterDecoder {
+            definitions: Vec::new(),
+        }
+    }
+
+    /// Create a decoder pre-populated with the standard ESC registers.
+    pub fn with_defaults() -> Self {
+        let mut decoder = Self::new();
+        for def in DEFAULT_DEFINITIONS {
+            decoder.register(*def);
+        }
+        decoder
+    }
+
+    /// Add a register definition. A definition at the same address is replaced.
+    pub fn register(&mut self, definition: RegisterDefinition) {
+        match self
+            .definitions
+            .binary_search_by_key(&definition.address, |d| d.address)
+        {
+            Ok(pos) => self.definitions[pos] = definition,
+            Err(pos) => self.definitions.insert(pos, definition),
+        }
+    }
+
+    /// Find the definition whose range contains `address`.
+    pub fn lookup(&self, address: u16) -> Option<&RegisterDefinition> {
+        let pos = self.definitions.partition_point(|d| d.address <= address);
+        self.definitions[..pos]
+            .iter()
+            .rev()
+            .find(|d| d.contains(address))
+    }
+
+    /// Find the definition starting exactly at `address`.
+    pub fn get(&self, address: u16) -> Option<&RegisterDefinition> {
+        self.definitions
+            .binary_search_by_key(&address, |d| d.address)
+            .ok()
+            .map(|pos| &self.definitions[pos])
+    }
+
+    /// Human-readable name of the register containing `address`.
+    pub fn name(&self, address: u16) -> Option<&'static str> {
+        self.lookup(address).map(|d| d.name)
+    }
+
+    /// All registered definitions in address order.
+    pub fn definitions(&self) -> impl Iterator<Item = &RegisterDefinition> {
+        self.definitions.iter()
+    }
+
+    /// Decode the register starting at `address` from a little-endian byte slice.
+    pub fn decode(&self, address: u16, bytes: &[u8]) -> Option<RegisterValue> {
+        self.get(address)?.kind.decode(bytes)
+    }
+
+    /// Decode the register starting at `address` from a shadow register byte iterator.
+    /// Returns `None` if any byte of the register is unknown.
+    pub fn decode_iter(
+        &self,
+        address: u16,
+        bytes: impl Iterator<Item = Option<u8>>,
+    ) -> Option<RegisterValue> {
+        let def = self.get(address)?;
+        let bytes = collect_bytes(bytes.take(def.length() as usize))?;
+        def.kind.decode(&bytes)
+    }
+}
+
+/// The shared decoder with the standard ESC register definitions.
+pub fn default_decoder() -> &'static RegisterDecoder {
+    static DECODER: OnceLock<RegisterDecoder> = OnceLock::new();
+    DECODER.get_or_init(RegisterDecoder::with_defaults)
+}
+
+/// Parse a register or station address given in hex (`0x1001`) or decimal.
+pub fn parse_u16(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("invalid address '{}'", s))
+}
+
+/// Collect a shadow register byte iterator, failing if any byte is unknown.
+pub fn collect_bytes(bytes: impl Iterator<Item = Option<u8>>) -> Option<SmallVec<[u8; 8]>> {
+    bytes.collect()
+}
+
+/// Assemble a little-endian `u16` from a shadow register byte iterator.
+pub fn read_le_u16(bytes: impl Iterator<Item = Option<u8>>) -> Option<u16> {
+    let bytes = collect_bytes(bytes.take(2))?;
+    (bytes.len() == 2).then(|| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// Assemble a little-endian `u32` from a shadow register byte iterator.
+pub fn read_le_u32(bytes: impl Iterator<Item = Option<u8>>) -> Option<u32> {
+    let bytes = collect_bytes(bytes.take(4))?;
+    (bytes.len() == 4).then(|| le_bytes_to_u64(&bytes) as u32)
+}
+
+/// Assemble a little-endian `u64` from a shadow register byte iterator.
+pub fn read_le_u64(bytes: impl Iterator<Item = Option<u8>>) -> Option<u64> {
+    let bytes = collect_bytes(bytes.take(8))?;
+    (bytes.len() == 8).then(|| le_bytes_to_u64(&bytes))
+}
+
+fn le_bytes_to_u64(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+const PORT_DESCRIPTOR_FIELDS: &[BitField] = &[
+    BitField::new("port0", 0, 2),
+    BitField::new("port1", 2, 2),
+    BitField::new("port2", 4, 2),
+    BitField::new("port3", 6, 2),
+];
+
+const SUPPORT_FLAGS_FIELDS: &[BitField] = &[
+    BitField::new("fmmu_bit_operation", 0, 1),
+    BitField::new("no_fmmu_area", 1, 1),
+    BitField::new("dc_supported", 2, 1),
+    BitField::new("dc_64bit", 3, 1),
+    BitField::new("low_jitter_ebus", 4, 1),
+    BitField::new("enhanced_link_detection_ebus", 5, 1),
+    BitField::new("enhanced_link_detection_mii", 6, 1),
+    BitField::new("separate_fcs_error_handling", 7, 1),
+    BitField::new("enhanced_dc_sync_activation", 8, 1),
+    BitField::new("lrw_not_supported", 9, 1),
+    BitField::new("brw_aprw_fprw_not_supported", 10, 1),
+    BitField::new("special_fmmu_sm_configuration", 11, 1),
+];
+
+const DL_STATUS_FIELDS: &[BitField] = &[
+    BitField::new("pdi_operational", 0, 1),
+    BitField::new("watchdog_status", 1, 1),
+    BitField::new("enhanced_link_detection", 2, 1),
+    BitField::new("link_port0", 4, 1),
+    BitField::new("link_port1", 5, 1),
+    BitField::new("link_port2", 6, 1),
+    BitField::new("link_port3", 7, 1),
+    BitField::new("loop_port0", 8, 1),
+    BitField::new("communication_port0", 9, 1),
+    BitField::new("loop_port1", 10, 1),
+    BitField::new("communication_port1", 11, 1),
+    BitField::new("loop_port2", 12, 1),
+    BitField::new("communication_port2", 13, 1),
+    BitField::new("loop_port3", 14, 1),
+    BitField::new("communication_port3", 15, 1),
+];
+
+const AL_CONTROL_FIELDS: &[BitField] = &[
+    BitField::new("state", 0, 4),
+    BitField::new("acknowledge", 4, 1),
+    BitField::new("device_identification", 5, 1),
+];
+
+const AL_STATUS_FIELDS: &[BitField] = &[
+    BitField::new("state", 0, 4),
+    BitField::new("error", 4, 1),
+    BitField::new("device_identification", 5, 1),
+];
+
+const SII_CONTROL_FIELDS: &[BitField] = &[
+    BitField::new("write_enable", 0, 1),
+    BitField::new("read_size", 6, 1),
+    BitField::new("address_algorithm", 7, 1),
+    BitField::new("read", 8, 1),
+    BitField::new("write", 9, 1),
+    BitField::new("reload", 10, 1),
+    BitField::new("checksum_error", 11, 1),
+    BitField::new("device_info_error", 12, 1),
+    BitField::new("command_error", 13, 1),
+    BitField::new("write_error", 14, 1),
+    BitField::new("busy", 15, 1),
+];
+
+const DEFAULT_DEFINITIONS: &[RegisterDefinition] = &[
+    RegisterDefinition::new(RegisterAddress::Type, "Type", RegisterType::U8),
+    RegisterDefinition::new(RegisterAddress::Revision, "Revision", RegisterType::U8),
+    RegisterDefinition::new(RegisterAddress::Build, "Build", RegisterType::U16),
+    RegisterDefinition::new(RegisterAddress::FmmuCount, "FmmuCount", RegisterType::U8),
+    RegisterDefinition::new(
+        RegisterAddress::SyncManagerChannels,
+        "SyncManagerChannels",
+        RegisterType::U8,
+    ),
+    RegisterDefinition::new(RegisterAddress::RamSize, "RamSize", RegisterType::U8),
+    RegisterDefinition::new(
+        RegisterAddress::PortDescriptors,
+        "PortDescriptors",
+        RegisterType::Bitfield {
+            size: 1,
+            fields: PORT_DESCRIPTOR_FIELDS,
+        },
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::SupportFlags,
+        "SupportFlags",
+        RegisterType::Bitfield {
+            size: 2,
+            fields: SUPPORT_FLAGS_FIELDS,
+        },
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::ConfiguredStationAddress,
+        "ConfiguredStationAddress",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::ConfiguredStationAlias,
+        "ConfiguredStationAlias",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DlStatus,
+        "DlStatus",
+        RegisterType::Bitfield {
+            size: 2,
+            fields: DL_STATUS_FIELDS,
+        },
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::AlControl,
+        "AlControl",
+        RegisterType::Bitfield {
+            size: 1,
+            fields: AL_CONTROL_FIELDS,
+        },
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::AlStatus,
+        "AlStatus",
+        RegisterType::Bitfield {
+            size: 1,
+            fields: AL_STATUS_FIELDS,
+        },
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::AlStatusCode,
+        "AlStatusCode",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::WatchdogDivider,
+        "WatchdogDivider",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::PdiWatchdog,
+        "PdiWatchdog",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::SyncManagerWatchdog,
+        "SyncManagerWatchdog",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::SyncManagerWatchdogStatus,
+        "SyncManagerWatchdogStatus",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::SyncManagerWatchdogCounter,
+        "SyncManagerWatchdogCounter",
+        RegisterType::U8,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::PdiWatchdogCounter,
+        "PdiWatchdogCounter",
+        RegisterType::U8,
+    ),
+    RegisterDefinition::new(RegisterAddress::SiiConfig, "SiiConfig", RegisterType::U16),
+    RegisterDefinition::new(
+        RegisterAddress::SiiControl,
+        "SiiControl",
+        RegisterType::Bitfield {
+            size: 2,
+            fields: SII_CONTROL_FIELDS,
+        },
+    ),
+    RegisterDefinition::new(RegisterAddress::SiiAddress, "SiiAddress", RegisterType::U32),
+    RegisterDefinition::new(RegisterAddress::SiiData, "SiiData", RegisterType::U64),
+    RegisterDefinition::new(
+        RegisterAddress::DcTimePort0,
+        "DcTimePort0",
+        RegisterType::U32,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcTimePort1,
+        "DcTimePort1",
+        RegisterType::U32,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcTimePort2,
+        "DcTimePort2",
+        RegisterType::U32,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcTimePort3,
+        "DcTimePort3",
+        RegisterType::U32,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcSystemTime,
+        "DcSystemTime",
+        RegisterType::U64,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcReceiveTime,
+        "DcReceiveTime",
+        RegisterType::U64,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcSystemTimeOffset,
+        "DcSystemTimeOffset",
+        RegisterType::I64,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcSystemTimeTransmissionDelay,
+        "DcSystemTimeTransmissionDelay",
+        RegisterType::U32,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcSystemTimeDifference,
+        "DcSystemTimeDifference",
+        RegisterType::U32,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcControlLoopParam1,
+        "DcControlLoopParam1",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcControlLoopParam2,
+        "DcControlLoopParam2",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcControlLoopParam3,
+        "DcControlLoopParam3",
+        RegisterType::U16,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcCyclicUnitControl,
+        "DcCyclicUnitControl",
+        RegisterType::U8,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcSyncActive,
+        "DcSyncActive",
+        RegisterType::U8,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcSyncStartTime,
+        "DcSyncStartTime",
+        RegisterType::U32,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcSync0CycleTime,
+        "DcSync0CycleTime",
+        RegisterType::U32,
+    ),
+    RegisterDefinition::new(
+        RegisterAddress::DcSync1CycleTime,
+        "DcSync1CycleTime",
+        RegisterType::U32,
+    ),
+];
+
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
 #[allow(dead_code)]
@@ -522,3 +1194,63 @@ pub mod RegisterAddress {
     /// See [`RegisterAddress::DcSync0CycleTime`].
     pub const DcSync1CycleTime: u16 = 0x09A4;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_integer_registers() {
+        let decoder = default_decoder();
+        assert_eq!(
+            decoder.decode(RegisterAddress::ConfiguredStationAddress, &[0x01, 0x10]),
+            Some(RegisterValue::U16(0x1001))
+        );
+        assert_eq!(
+            decoder.decode(RegisterAddress::DcSystemTimeOffset, &[0xff; 8]),
+            Some(RegisterValue::I64(-1))
+        );
+        // Too short for the register width
+        assert_eq!(
+            decoder.decode(RegisterAddress::DcSync0CycleTime, &[0x00]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_bitfield_register() {
+        let value = default_decoder()
+            .decode(RegisterAddress::AlStatus, &[0x14])
+            .unwrap();
+        assert_eq!(value.field("state"), Some(0x04));
+        assert_eq!(value.field("error"), Some(1));
+        assert_eq!(value.field("nonexistent"), None);
+    }
+
+    #[test]
+    fn test_lookup_within_range() {
+        let decoder = default_decoder();
+        assert_eq!(
+            decoder.name(RegisterAddress::AlStatusCode + 1),
+            Some("AlStatusCode")
+        );
+        assert_eq!(decoder.name(0x0003), Some("Build"));
+        assert_eq!(decoder.name(0x0131), None);
+    }
+
+    #[test]
+    fn test_decode_iter_requires_all_bytes() {
+        let decoder = default_decoder();
+        let bytes = [Some(0x34), Some(0x12)];
+        assert_eq!(
+            decoder.decode_iter(RegisterAddress::AlStatusCode, bytes.into_iter()),
+            Some(RegisterValue::U16(0x1234))
+        );
+        let partial = [Some(0x34), None];
+        assert_eq!(
+            decoder.decode_iter(RegisterAddress::AlStatusCode, partial.into_iter()),
+            None
+        );
+        assert_eq!(read_le_u16([Some(0x01)].into_iter()), None);
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::process::{Command, Stdio};
+
+use crate::child_stream::ChildStream;
+
+/// Capture command run on the remote host, writing pcap to stdout. `{iface}` is
+/// replaced by the interface name.
+const DEFAULT_REMOTE_COMMAND: &str = "tcpdump -i {iface} -U -s 0 -w - ether proto 0x88a4";
+
+/// Live capture on a remote machine over SSH (`--ssh [USER@]HOST:IFACE`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteCapture {
+    /// SSH destination, `[USER@]HOST`.
+    pub destination: String,
+    pub interface: String,
+    /// Overrides [`DEFAULT_REMOTE_COMMAND`].
+    pub command: Option<String>,
+}
+
+impl RemoteCapture {
+    /// Parse `[USER@]HOST:IFACE`, e.g. `admin@ipc01:eth1`. IPv6 hosts may be
+    /// written in brackets, `admin@[fe80::1]:eth1`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().rsplit_once(':') {
+            Some((destination, interface)) if !destination.is_empty() && !interface.is_empty() => {
+                // ssh takes IPv6 addresses without brackets
+                let destination = match destination.rsplit_once('@') {
+                    Some((user, host)) => format!("{}@{}", user, unbracket(host)),
+                    None => unbracket(destination).to_string(),
+                };
+                Ok(RemoteCapture {
+                    destination,
+                    interface: interface.to_string(),
+                    command: None,
+                })
+            }
+            _ => Err(format!("expected [USER@]HOST:IFACE, got '{}'", s)),
+        }
+    }
+
+    fn remote_command(&self) -> String {
+        self.command
+            .as_deref()
+            .unwrap_or(DEFAULT_REMOTE_COMMAND)
+            .replace("{iface}", &shell_quote(&self.interface))
+    }
+
+    /// Start the remote capture. Returns the pcap stream.
+    pub fn start(&self) -> Result<ChildStream> {
+        ChildStream::spawn(
+            Command::new("ssh")
+                // The destination is never an ssh option
+                .arg("--")
+                .arg(&self.destination)
+                .arg(self.remote_command())
+                .stdin(Stdio::null()),
+        )
+    }
+}
+
+fn unbracket(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// Quote `s` as one word for the remote shell.
+fn shell_quote(s: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Option<(String, String)> {
+        RemoteCapture::parse(s)
+            .ok()
+            .map(|remote| (remote.destination, remote.interface))
+    }
+
+    #[test]
+    fn test_parse() {
+        let pair = |destination: &str, interface: &str| {
+            Some((destination.to_string(), interface.to_string()))
+        };
+        assert_eq!(parse("ipc01:eth0"), pair("ipc01", "eth0"));
+        assert_eq!(parse("user@host:eth1"), pair("user@host", "eth1"));
+        assert_eq!(parse("fe80::1:eth1"), pair("fe80::1", "eth1"));
+        assert_eq!(parse("[fe80::1]:eth1"), pair("fe80::1", "eth1"));
+        assert_eq!(
+            parse("admin@[2001:db8::2]:enp3s0"),
+            pair("admin@2001:db8::2", "enp3s0")
+        );
+        assert_eq!(parse("host"), None);
+        assert_eq!(parse("host:"), None);
+        assert_eq!(parse(":eth0"), None);
+    }
+
+    #[test]
+    fn test_interface_is_quoted_for_the_remote_shell() {
+        let mut remote = RemoteCapture::parse("host:eth0").unwrap();
+        assert_eq!(
+            remote.remote_command(),
+            "tcpdump -i eth0 -U -s 0 -w - ether proto 0x88a4"
+        );
+        remote.interface = "eth0; rm -rf ~'".to_string();
+        assert_eq!(
+            remote.remote_command(),
+            "tcpdump -i 'eth0; rm -rf ~'\\''' -U -s 0 -w - ether proto 0x88a4"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{Context, Result, anyhow, bail};
+use crossbeam_channel::{Receiver as CbReceiver, unbounded};
+use ecdump::capture_file;
+use pnet::datalink::{Channel::Ethernet, Config, NetworkInterface};
+use pnet::packet::ethernet::EthernetPacket;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::compression;
+
+/// Time given to the subdevices to answer the last replayed frame before the
+/// capture stops.
+const REPLAY_GRACE: Duration = Duration::from_millis(200);
+
+/// Replay of a capture file onto the capture interface (`--replay`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub path: String,
+    /// Playback speed; 2.0 halves the gaps between frames.
+    pub speed: f64,
+}
+
+/// A frame to send, `offset` after the first one.
+struct ReplayFrame {
+    offset: Duration,
+    data: Vec<u8>,
+}
+
+impl Replay {
+    /// Read the EtherCAT frames sent by the main device (the source address of
+    /// the first EtherCAT frame), keeping their Ethernet headers.
+    fn read_frames(&self) -> Result<Vec<ReplayFrame>> {
+        let file = compression::open_input(&self.path)?;
+        let mut frames = Vec::new();
+        let mut main = None;
+        let mut first_timestamp = None;
+        let push = |timestamp: Duration, data: &[u8]| {
+            let Some(ethernet) = EthernetPacket::new(data) else {
+                return;
+            };
+            if ethernet.get_ethertype().0 != 0x88a4
+                || *main.get_or_insert(ethernet.get_source()) != ethernet.get_source()
+            {
+                return;
+            }
+            let first: Duration = *first_timestamp.get_or_insert(timestamp);
+            frames.push(ReplayFrame {
+                offset: timestamp.saturating_sub(first),
+                data: data.to_vec(),
+            });
+        };
+
+        capture_file::read_frames(file, push)?;
+        Ok(frames)
+    }
+
+    /// Send the main device frames of the file on `interface` with their original
+    /// spacing, scaled by the speed. Runs in the background; the receiver gets the
+    /// number of frames sent once the last one had time to be answered. Setting
+    /// `stop` ends the replay early.
+    pub fn start(
+        &self,
+        interface: &NetworkInterface,
+        stop: Arc<AtomicBool>,
+    ) -> Result<CbReceiver<Result<u64>>> {
+        let frames = self
+            .read_frames()
+            .with_context(|| format!("Failed to read the replay file: {}", self.path))?;
+        if frames.is_empty() {
+            bail!("No EtherCAT frames to replay in {}", self.path);
+        }
+        let mut tx = match pnet::datalink::channel(interface, Config::default())? {
+            Ethernet(tx, _) => tx,
+            _ => bail!("Unsupported channel type"),
+        };
+        let speed = self.speed;
+        let (tx_done, rx_done) = unbounded();
+        std::thread::Builder::new()
+            .name("Replay".to_string())
+            .spawn(move || {
+                let started = Instant::now();
+                let mut sent = 0;
+                let result = (|| {
+                    for frame in &frames {
+                        let due = started + frame.offset.div_f64(speed);
+                        loop {
+                            if stop.load(Ordering::Relaxed) {
+                                return Ok(sent);
+                            }
+                            let now = Instant::now();
+                            if now >= due {
+                                break;
+                            }
+                            // Short sleeps so `stop` is seen promptly
+                            std::thread::sleep((due - now).min(Duration::from_millis(100)));
+                        }
+                        match tx.send_to(&frame.data, None) {
+                            Some(Ok(())) => sent += 1,
+                            Some(Err(e)) => return Err(anyhow!("Failed to send frame: {}", e)),
+                            None => bail!("Failed to send frame: no buffer space"),
+                        }
+                    }
+                    std::thread::sleep(REPLAY_GRACE);
+                    Ok(sent)
+                })();
+                tx_done.send(result).ok();
+            })
+            .context("Failed to start the replay thread")?;
+        Ok(rx_done)
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use ecdump::analysis::ErrorCounts;
+use ecdump::analyzer::DeviceManager;
+use ecdump::registers::{FmmuConfig, SyncManagerConfig};
+use ecdump::subdevice::{ECState, SubDeviceIdentity, SubDeviceStatistics};
+use ecdump::topology::TopologyMismatch;
+use schemars::{JsonSchema, Schema, schema_for};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::error_formatter::CaptureSummary;
+use crate::event_stream::FrameEvents;
+use crate::schema::{self, REPORT_VERSION};
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+struct StateChange {
+    frame: u64,
+    timestamp: f64,
+    subdevice: String,
+    from: ECState,
+    to: ECState,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+struct BusChange {
+    frame: u64,
+    timestamp: f64,
+    /// `rescan` when the main device restarted its scan, `size` when the number of
+    /// subdevices changed without one.
+    kind: &'static str,
+    previous_device_count: usize,
+    device_count: usize,
+}
+
+/// End-of-run report of a capture.
+#[derive(Serialize, JsonSchema)]
+struct Report<'a> {
+    schema_version: u32,
+    capture: CaptureReport,
+    devices: Vec<DeviceReport<'a>>,
+    state_timeline: &'a [StateChange],
+    errors: &'a ErrorCounts,
+    topology: TopologyReport<'a>,
+    /// Frame after which all subdevices reached Op.
+    init_complete_frame: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct CaptureReport {
+    analyzed_frames: u64,
+    skipped_frames: u64,
+    dropped_frames: u64,
+    /// Wall-clock duration of the run in seconds.
+    duration: f64,
+    clock_source: Option<&'static str>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct DeviceReport<'a> {
+    position: usize,
+    subdevice: String,
+    configured_address: Option<u16>,
+    alias: Option<u16>,
+    identity: Option<SubDeviceIdentity>,
+    state: ECState,
+    requested_state: Option<ECState>,
+    al_status_code: Option<u16>,
+    statistics: &'a SubDeviceStatistics,
+    fmmus: Vec<Indexed<FmmuConfig>>,
+    sync_managers: Vec<Indexed<SyncManagerConfig>>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Indexed<T> {
+    index: u16,
+    #[serde(flatten)]
+    config: T,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct TopologyReport<'a> {
+    device_count: usize,
+    /// Earlier scans when the main device rescanned the bus.
+    previous_scans: Vec<ScanReport>,
+    changes: &'a [BusChange],
+    /// Differences to `--expect-devices`/`--expect-address`, `None` without an
+    /// expectation.
+    mismatches: Option<&'a [TopologyMismatch]>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ScanReport {
+    number: u32,
+    first_frame: u64,
+    last_frame: u64,
+    device_count: usize,
+}
+
+/// Collects the events of the run that the analyzer does not keep, and writes the
+/// end-of-run JSON report (`--report`).
+#[derive(Default)]
+pub struct ReportBuilder {
+    state_timeline: Vec<StateChange>,
+    bus_changes: Vec<BusChange>,
+}
+
+impl ReportBuilder {
+    pub fn record_frame(&mut self, events: &FrameEvents) {
+        self.state_timeline
+            .extend(events.transitions.iter().map(|transition| StateChange {
+                frame: transition.packet_number,
+                timestamp: transition.timestamp.as_secs_f64(),
+                subdevice: transition.subdevice_id.to_string(),
+                from: transition.from,
+                to: transition.to,
+            }));
+        self.bus_changes
+            .extend(events.rescans.iter().map(|rescan| BusChange {
+                frame: rescan.packet_number,
+                timestamp: rescan.timestamp.as_secs_f64(),
+                kind: "rescan",
+                previous_device_count: rescan.previous_device_count,
+                device_count: rescan.device_count,
+            }));
+        self.bus_changes
+            .extend(events.bus_size_changes.iter().map(|change| BusChange {
+                frame: change.packet_number,
+                timestamp: change.timestamp.as_secs_f64(),
+                kind: "size",
+                previous_device_count: change.previous_device_count,
+                device_count: change.device_count,
+            }));
+    }
+
+    /// Write the report of the finished run to `path`.
+    pub fn write(
+        &self,
+        path: &str,
+        device_manager: &DeviceManager,
+        capture: &CaptureSummary,
+        errors: &ErrorCounts,
+        mismatches: Option<&[TopologyMismatch]>,
+    ) -> Result<()> {
+        let devices = device_manager
+            .devices()
+            .iter()
+            .enumerate()
+            .map(|(position, device)| DeviceReport {
+                position,
+                subdevice: device.identifier().to_string(),
+                configured_address: device.configured_address(),
+                alias: device.configured_alias(),
+                identity: device.identity(),
+                state: device.state(),
+                requested_state: device.requested_state(),
+                al_status_code: device.al_status_code(),
+                statistics: device.statistics(),
+                fmmus: device
+                    .fmmus()
+                    .map(|(index, config)| Indexed { index, config })
+                    .collect(),
+                sync_managers: device
+                    .sync_managers()
+                    .map(|(index, config)| Indexed { index, config })
+                    .collect(),
+            })
+            .collect();
+        let report = Report {
+            schema_version: REPORT_VERSION,
+            capture: CaptureReport {
+                analyzed_frames: capture.analyzed_frames,
+                skipped_frames: device_manager.get_skipped_frame_count(),
+                dropped_frames: capture.dropped_frames,
+                duration: capture.elapsed.as_secs_f64(),
+                clock_source: capture.clock_source.map(|clock| clock.as_str()),
+            },
+            devices,
+            state_timeline: &self.state_timeline,
+            errors,
+            topology: TopologyReport {
+                device_count: device_manager.device_count(),
+                previous_scans: device_manager
+                    .previous_scans()
+                    .iter()
+                    .map(|scan| ScanReport {
+                        number: scan.number,
+                        first_frame: scan.start_packet,
+                        last_frame: scan.end_packet,
+                        device_count: scan.devices.len(),
+                    })
+                    .collect(),
+                changes: &self.bus_changes,
+                mismatches,
+            },
+            init_complete_frame: device_manager.init_complete_packet(),
+        };
+
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create report file: {}", path))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, &report)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// JSON Schema of the `--report` file.
+pub fn schema() -> Schema {
+    schema::versioned(schema_for!(Report), REPORT_VERSION)
+}
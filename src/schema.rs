@@ -0,0 +1,88 @@
+//! Schema versions of the machine-readable outputs, and `--print-schema`.
+//!
+//! The report, the event stream, the inventory, the register dumps and the
+//! snapshots carry a
+//! `schema_version` field (on every line of the event stream); SQLite databases
+//! carry it as `PRAGMA user_version`. A version is raised when a field is
+//! removed, renamed or changes its meaning, not when fields are added.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use schemars::Schema;
+use serde_json::json;
+
+use crate::{event_stream, inventory, register_dump, report, snapshot, sqlite_store};
+
+pub const REPORT_VERSION: u32 = 1;
+pub const EVENTS_VERSION: u32 = 1;
+pub const INVENTORY_VERSION: u32 = 1;
+pub const REGISTER_DUMP_VERSION: u32 = 1;
+pub const SQLITE_VERSION: u32 = 1;
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// An output whose schema `--print-schema` prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaOutput {
+    /// `--report`, JSON Schema
+    Report,
+    /// One line of `--events`, JSON Schema
+    Events,
+    /// `--inventory` as JSON, JSON Schema
+    Inventory,
+    /// A `--dump-registers` JSON file, JSON Schema
+    RegisterDump,
+    /// `--sqlite`, SQL
+    Sqlite,
+    /// The `--snapshot-on-exit` JSON file, JSON Schema
+    Snapshot,
+}
+
+/// Print the schema of `output` on standard output.
+pub fn print(output: SchemaOutput) -> Result<()> {
+    let schema = match output {
+        SchemaOutput::Report => report::schema(),
+        SchemaOutput::Events => event_stream::schema(),
+        SchemaOutput::Inventory => inventory::schema(),
+        SchemaOutput::RegisterDump => register_dump::schema(),
+        SchemaOutput::Snapshot => snapshot::schema(),
+        SchemaOutput::Sqlite => {
+            println!("PRAGMA user_version = {};", SQLITE_VERSION);
+            println!("{}", sqlite_store::SCHEMA.trim_end());
+            return Ok(());
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+/// `schema` with its `schema_version` field fixed to `version`.
+pub fn versioned(mut schema: Schema, version: u32) -> Schema {
+    if let Some(field) = schema
+        .get_mut("properties")
+        .and_then(|properties| properties.get_mut("schema_version"))
+    {
+        field["const"] = json!(version);
+    }
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_schemas_fix_the_version() {
+        for schema in [
+            report::schema(),
+            event_stream::schema(),
+            inventory::schema(),
+            register_dump::schema(),
+            snapshot::schema(),
+        ] {
+            assert_eq!(
+                schema.get("properties").unwrap()["schema_version"]["const"],
+                1
+            );
+        }
+    }
+}
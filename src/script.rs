@@ -0,0 +1,380 @@
+//! `--script`: site-specific checks written in [Rhai](https://rhai.rs), run on
+//! every analyzer event and datagram without recompiling ecdump.
+//!
+//! ```rhai
+//! // Flag output bit 3 of 0x1005 toggling outside of Op
+//! fn on_event(e) {
+//!     if e.category == "signal" && e.payload.name == "Outputs.Bit 3" {
+//!         let dev = device(0x1005);
+//!         if dev != () && dev.state != "Op" {
+//!             warn(`bit 3 toggled in ${dev.state}`);
+//!             count("bit3_outside_op");
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Hooks get `this` bound to a map kept between calls, for state of the script.
+//! Scripts cannot access files or the network; the analyzer state is read only.
+
+use crate::event_stream::{Event, FrameEvents};
+use anyhow::{Context, Result, anyhow};
+use ecdump::analyzer::{DeviceManager, SignalSample};
+use ecdump::ec_packet::ECFrame;
+use ecdump::registers::collect_bytes;
+use ecdump::subdevice::{RegisterShadow, SubDevice};
+use log::error;
+use rhai::{AST, Blob, CallFnOptions, Dynamic, Engine, INT, Map, Scope};
+use serde_json::json;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+scoped_tls::scoped_thread_local!(static DEVICE_MANAGER: DeviceManager);
+
+/// Operations a single hook call may take before it is aborted, so a script
+/// stuck in a loop cannot stall the analysis.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A message of the script's `warn`.
+#[derive(Debug, Clone)]
+pub struct ScriptWarning {
+    pub packet_number: u64,
+    pub timestamp: Duration,
+    pub message: String,
+}
+
+/// State shared with the functions registered with the engine.
+#[derive(Default)]
+struct ScriptState {
+    packet_number: u64,
+    timestamp: Duration,
+    warnings: Vec<ScriptWarning>,
+    counters: BTreeMap<String, INT>,
+}
+
+pub struct Script {
+    path: String,
+    engine: Engine,
+    ast: AST,
+    /// `this` of the hooks.
+    this: Dynamic,
+    state: Rc<RefCell<ScriptState>>,
+    on_event: bool,
+    on_datagram: bool,
+    /// Set after a runtime error, which stops the script.
+    failed: bool,
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read script: {}", path))?;
+        Self::compile(path, &text)
+    }
+
+    /// A script from its source; `path` names it in messages.
+    fn compile(path: &str, text: &str) -> Result<Self> {
+        let state = Rc::new(RefCell::new(ScriptState::default()));
+        let engine = engine(&state);
+        let ast = engine
+            .compile(text)
+            .map_err(|e| anyhow!("Failed to compile script {}: {}", path, e))?;
+        let has_hook = |name: &str| {
+            ast.iter_functions()
+                .any(|f| f.name == name && f.params.len() == 1)
+        };
+        let (on_event, on_datagram) = (has_hook("on_event"), has_hook("on_datagram"));
+        if !on_event && !on_datagram {
+            anyhow::bail!(
+                "Script {} defines neither on_event(e) nor on_datagram(d)",
+                path
+            );
+        }
+        Ok(Script {
+            path: path.to_string(),
+            engine,
+            ast,
+            this: Dynamic::from_map(Map::new()),
+            state,
+            on_event,
+            on_datagram,
+            failed: false,
+        })
+    }
+
+    /// Call `on_datagram` for every datagram of an analyzed frame.
+    pub fn datagrams(
+        &mut self,
+        frame: &ECFrame,
+        packet_number: u64,
+        timestamp: Duration,
+        from_main: bool,
+        device_manager: &DeviceManager,
+    ) {
+        if !self.on_datagram || self.failed {
+            return;
+        }
+        let Ok(datagrams) = frame.parse_datagram() else {
+            return;
+        };
+        for datagram in &datagrams {
+            let (adp, ado) = datagram.address();
+            let mut d = Map::new();
+            d.insert("frame".into(), (packet_number as INT).into());
+            d.insert("timestamp".into(), timestamp.as_secs_f64().into());
+            d.insert("from_main".into(), from_main.into());
+            d.insert("command".into(), datagram.command().as_str().into());
+            d.insert("adp".into(), (adp as INT).into());
+            d.insert("ado".into(), (ado as INT).into());
+            d.insert(
+                "logical_address".into(),
+                (datagram.logical_address() as INT).into(),
+            );
+            d.insert("length".into(), (datagram.length() as INT).into());
+            d.insert("wkc".into(), (datagram.wkc() as INT).into());
+            d.insert(
+                "data".into(),
+                Dynamic::from_blob(datagram.payload().to_vec()),
+            );
+            self.call(
+                "on_datagram",
+                packet_number,
+                timestamp,
+                d.into(),
+                device_manager,
+            );
+        }
+    }
+
+    /// Call `on_event` for every event of an analyzed frame, and for every
+    /// process data signal change as a `signal` event.
+    pub fn events(
+        &mut self,
+        events: &FrameEvents,
+        samples: &[SignalSample],
+        device_manager: &DeviceManager,
+    ) {
+        if !self.on_event || self.failed {
+            return;
+        }
+        let signals = samples.iter().map(|sample| {
+            Event::new(
+                sample.packet_number,
+                sample.timestamp,
+                Some(sample.subdevice_id),
+                "signal",
+                json!({
+                    "object": sample.key,
+                    "name": sample.name,
+                    "direction": sample.direction.to_string(),
+                    "value": sample.value,
+                }),
+            )
+        });
+        for event in events.events().into_iter().chain(signals) {
+            let Ok(e) = rhai::serde::to_dynamic(&event) else {
+                continue;
+            };
+            let timestamp = Duration::from_secs_f64(event.timestamp);
+            self.call("on_event", event.frame, timestamp, e, device_manager);
+        }
+    }
+
+    /// Warnings of the script since the last call.
+    pub fn take_warnings(&mut self) -> Vec<ScriptWarning> {
+        std::mem::take(&mut self.state.borrow_mut().warnings)
+    }
+
+    /// The script's counters by name.
+    pub fn counters(&self) -> Vec<(String, i64)> {
+        let state = self.state.borrow();
+        state
+            .counters
+            .iter()
+            .map(|(name, &value)| (name.clone(), value))
+            .collect()
+    }
+
+    fn call(
+        &mut self,
+        hook: &str,
+        packet_number: u64,
+        timestamp: Duration,
+        arg: Dynamic,
+        device_manager: &DeviceManager,
+    ) {
+        if self.failed {
+            return;
+        }
+        {
+            let mut state = self.state.borrow_mut();
+            state.packet_number = packet_number;
+            state.timestamp = timestamp;
+        }
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.this);
+        let result = DEVICE_MANAGER.set(device_manager, || {
+            self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut Scope::new(),
+                &self.ast,
+                hook,
+                (arg,),
+            )
+        });
+        if let Err(e) = result {
+            // Logging is off by default, so the failure is also shown as a warning
+            error!(
+                "Script {} failed at frame #{}: {}",
+                self.path, packet_number, e
+            );
+            self.state.borrow_mut().warnings.push(ScriptWarning {
+                packet_number,
+                timestamp,
+                message: format!("{} stopped: {}", hook, e),
+            });
+            self.failed = true;
+        }
+    }
+}
+
+/// An engine with the script API: `warn`, `count`, `counter`, `device`,
+/// `device_at`, `register` and `written`.
+fn engine(state: &Rc<RefCell<ScriptState>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let warn_state = state.clone();
+    engine.register_fn("warn", move |message: &str| {
+        let mut state = warn_state.borrow_mut();
+        let warning = ScriptWarning {
+            packet_number: state.packet_number,
+            timestamp: state.timestamp,
+            message: message.to_string(),
+        };
+        state.warnings.push(warning);
+    });
+    let count_state = state.clone();
+    engine.register_fn("count", move |name: &str, n: INT| {
+        *count_state
+            .borrow_mut()
+            .counters
+            .entry(name.to_string())
+            .or_default() += n;
+    });
+    let count_state = state.clone();
+    engine.register_fn("count", move |name: &str| {
+        *count_state
+            .borrow_mut()
+            .counters
+            .entry(name.to_string())
+            .or_default() += 1;
+    });
+    let counter_state = state.clone();
+    engine.register_fn("counter", move |name: &str| -> INT {
+        counter_state
+            .borrow()
+            .counters
+            .get(name)
+            .copied()
+            .unwrap_or(0)
+    });
+
+    engine.register_fn("device", |address: INT| -> Dynamic {
+        DEVICE_MANAGER
+            .with(|device_manager| {
+                let address = u16::try_from(address).ok()?;
+                let devices = device_manager.devices();
+                let position = devices
+                    .iter()
+                    .position(|device| device.configured_address() == Some(address))?;
+                Some(device_map(position, &devices[position]))
+            })
+            .unwrap_or(Dynamic::UNIT)
+    });
+    engine.register_fn("device_at", |position: INT| -> Dynamic {
+        DEVICE_MANAGER
+            .with(|device_manager| {
+                let position = usize::try_from(position).ok()?;
+                let device = device_manager.devices().get(position)?;
+                Some(device_map(position, device))
+            })
+            .unwrap_or(Dynamic::UNIT)
+    });
+    engine.register_fn("register", |address: INT, register: INT, length: INT| {
+        read_register(address, register, length, RegisterShadow::Read)
+    });
+    engine.register_fn("written", |address: INT, register: INT, length: INT| {
+        read_register(address, register, length, RegisterShadow::Written)
+    });
+    engine
+}
+
+/// A subdevice as seen by a script.
+fn device_map(position: usize, device: &SubDevice) -> Dynamic {
+    let optional = |value: Option<u16>| value.map_or(Dynamic::UNIT, |value| (value as INT).into());
+    let mut map = Map::new();
+    map.insert("position".into(), (position as INT).into());
+    map.insert("address".into(), optional(device.configured_address()));
+    map.insert("alias".into(), optional(device.configured_alias()));
+    map.insert("state".into(), device.state().to_string().into());
+    map.insert("al_status_code".into(), optional(device.al_status_code()));
+    map.into()
+}
+
+/// `length` bytes of a register of the subdevice with the configured station
+/// address `address`, `()` unless all of them are known.
+fn read_register(address: INT, register: INT, length: INT, shadow: RegisterShadow) -> Dynamic {
+    DEVICE_MANAGER
+        .with(|device_manager| {
+            let device =
+                device_manager.device_by_configured_address(u16::try_from(address).ok()?)?;
+            let bytes = device
+                .register_image(shadow)
+                .read(u16::try_from(register).ok()?, u16::try_from(length).ok()?);
+            let bytes: Blob = collect_bytes(bytes)?.to_vec();
+            Some(Dynamic::from_blob(bytes))
+        })
+        .unwrap_or(Dynamic::UNIT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecdump::ec_packet::{ECCommands, ECFrameBuilder};
+
+    #[test]
+    fn test_datagram_hook_counts_and_warns() {
+        let mut script = Script::compile(
+            "test.rhai",
+            r#"
+                fn on_datagram(d) {
+                    count(d.command, d.wkc);
+                    this.seen = (this.seen ?? 0) + 1;
+                    if this.seen == 2 && device_at(0) == () {
+                        warn(`no subdevice, ${d.data.len()} bytes`);
+                    }
+                }
+            "#,
+        )
+        .unwrap();
+        // BRD of the ESC type register answered by two subdevices
+        let frame = ECFrameBuilder::new()
+            .datagram(ECCommands::BRD, 0, 0, &[0x11, 0x00], 2)
+            .build();
+        let frame = ECFrame::new(&frame).unwrap();
+        let device_manager = DeviceManager::default();
+
+        script.datagrams(&frame, 1, Duration::ZERO, true, &device_manager);
+        assert!(script.take_warnings().is_empty());
+        script.datagrams(&frame, 2, Duration::from_micros(50), false, &device_manager);
+        let warnings = script.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].packet_number, 2);
+        assert_eq!(warnings[0].message, "no subdevice, 2 bytes");
+        assert_eq!(script.counters(), [("BRD".to_string(), 4)]);
+    }
+}
@@ -0,0 +1,305 @@
+//! `--index`: a seek index of a capture file, kept next to it as `FILE.ecidx`,
+//! so `--from` and `--first-frame` start reading close to the window instead of
+//! at the start of a multi-gigabyte file.
+//!
+//! The index is recorded while a file is read from the start and saved once the
+//! whole file was read. It holds the file offset, frame number and timestamp of
+//! every [`INTERVAL`]th EtherCAT frame, and is rebuilt when the file changes.
+//! Reading continues at the last entry before the window with the file header in
+//! front, so frame numbers, timestamps and the main device are the same as when
+//! reading the whole file.
+
+use anyhow::{Context, Result, bail};
+use log::{debug, warn};
+use pnet::util::MacAddr;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Read, Seek, SeekFrom};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::compression::Compression;
+use crate::startup::AnalysisWindow;
+
+/// EtherCAT frames between two entries of the index.
+pub const INTERVAL: u64 = 1024;
+
+/// Raised when the format of the index file changes.
+const VERSION: u32 = 1;
+
+/// Index file of the capture file at `path`.
+pub fn index_path(path: &str) -> String {
+    format!("{}.ecidx", path)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SeekIndex {
+    version: u32,
+    /// Length and modification time of the indexed file, to detect changes.
+    file_len: u64,
+    modified_ns: u64,
+    /// Bytes before the first packet record: the pcap file header, or the
+    /// pcapng section header and interface descriptions.
+    header_len: u64,
+    /// Source address of the first EtherCAT frame, the main device.
+    main_mac: [u8; 6],
+    /// Timestamp of the first EtherCAT frame since the epoch.
+    first_timestamp_ns: u64,
+    entries: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct IndexEntry {
+    /// File offset of the record of the frame.
+    offset: u64,
+    frame: u64,
+    /// Timestamp relative to the first frame.
+    timestamp_ns: u64,
+}
+
+impl SeekIndex {
+    /// The index of the capture file at `path`, unless it is missing or was
+    /// made for another version of the file.
+    fn load(path: &str) -> Option<Self> {
+        let text = std::fs::read(index_path(path)).ok()?;
+        let index: SeekIndex = match serde_json::from_slice(&text) {
+            Ok(index) => index,
+            Err(e) => {
+                warn!("Ignoring the unreadable index {}: {}", index_path(path), e);
+                return None;
+            }
+        };
+        let current = file_version(path).ok()?;
+        (index.version == VERSION && (index.file_len, index.modified_ns) == current)
+            .then_some(index)
+    }
+
+    /// The last entry at or before the start of `window`, assuming timestamps
+    /// increase with the frame number.
+    fn entry_before(&self, window: &AnalysisWindow) -> Option<IndexEntry> {
+        if window.from.is_none() && window.first_frame.is_none() {
+            return None;
+        }
+        self.entries
+            .iter()
+            .take_while(|entry| {
+                window.first_frame.is_none_or(|first| entry.frame <= first)
+                    && window
+                        .from
+                        .is_none_or(|from| Duration::from_nanos(entry.timestamp_ns) <= from)
+            })
+            .last()
+            .copied()
+    }
+}
+
+/// Length and modification time of a file.
+fn file_version(path: &str) -> std::io::Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok((metadata.len(), modified.as_nanos() as u64))
+}
+
+/// How the reader of a capture file uses the index.
+pub enum Indexing {
+    /// Record an index while reading the file from the start.
+    Build(IndexBuilder),
+    /// The stream continues at an entry of the index.
+    Resume(Resume),
+}
+
+/// Reader state at an entry of the index.
+pub struct Resume {
+    /// Number of the frame before the entry.
+    pub sequence: u64,
+    pub main_mac: MacAddr,
+    /// Timestamp of the first frame of the file.
+    pub initial_timestamp: Duration,
+    /// Timestamp of the entry relative to the first frame.
+    pub timestamp: Duration,
+}
+
+/// Open the capture file at `path` for `--index`: at the last indexed frame
+/// before `window` if its index is valid, else from the start, recording a new
+/// index.
+pub fn open(
+    path: &str,
+    window: &AnalysisWindow,
+) -> Result<(Box<dyn Read + Send>, Option<Indexing>)> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let len = file.read(&mut magic)?;
+    if Compression::from_magic(&magic[..len]).is_some() {
+        bail!("--index needs an uncompressed capture file");
+    }
+    file.seek(SeekFrom::Start(0))?;
+
+    let Some(index) = SeekIndex::load(path) else {
+        debug!("Recording the index {}", index_path(path));
+        let builder = IndexBuilder::new(path)?;
+        return Ok((Box::new(file), Some(Indexing::Build(builder))));
+    };
+    let Some(entry) = index.entry_before(window) else {
+        return Ok((Box::new(file), None));
+    };
+    debug!(
+        "Starting at frame #{} (offset {}) of the index",
+        entry.frame, entry.offset
+    );
+    let mut header = vec![0u8; index.header_len as usize];
+    file.read_exact(&mut header)
+        .context("The index does not match the capture file")?;
+    file.seek(SeekFrom::Start(entry.offset))?;
+    let resume = Resume {
+        sequence: entry.frame - 1,
+        main_mac: MacAddr::from(index.main_mac),
+        initial_timestamp: Duration::from_nanos(index.first_timestamp_ns),
+        timestamp: Duration::from_nanos(entry.timestamp_ns),
+    };
+    Ok((
+        Box::new(Cursor::new(header).chain(file)),
+        Some(Indexing::Resume(resume)),
+    ))
+}
+
+/// Records the index while a capture file is read from the start.
+pub struct IndexBuilder {
+    path: String,
+    version: (u64, u64),
+    header_len: Option<u64>,
+    main_mac: MacAddr,
+    first_timestamp: Duration,
+    entries: Vec<IndexEntry>,
+    /// Set when interfaces change after the first packet, which a stream starting
+    /// with the header would not know of.
+    stopped: bool,
+}
+
+impl IndexBuilder {
+    fn new(path: &str) -> Result<Self> {
+        Ok(IndexBuilder {
+            path: path.to_string(),
+            version: file_version(path)?,
+            header_len: None,
+            main_mac: MacAddr::zero(),
+            first_timestamp: Duration::ZERO,
+            entries: Vec::new(),
+            stopped: false,
+        })
+    }
+
+    /// A packet record at `offset`, of any EtherType; the first one ends the
+    /// header.
+    pub fn packet(&mut self, offset: u64) {
+        self.header_len.get_or_insert(offset);
+    }
+
+    /// A section header or interface description block.
+    pub fn interfaces_changed(&mut self) {
+        if self.header_len.is_some() {
+            self.stopped = true;
+        }
+    }
+
+    /// EtherCAT frame number `sequence` at `offset`, `timestamp` relative to the
+    /// first frame at `initial_timestamp`.
+    pub fn frame(
+        &mut self,
+        offset: u64,
+        sequence: u64,
+        main_mac: MacAddr,
+        initial_timestamp: Duration,
+        timestamp: Duration,
+    ) {
+        if sequence == 1 {
+            self.main_mac = main_mac;
+            self.first_timestamp = initial_timestamp;
+        }
+        if (sequence - 1).is_multiple_of(INTERVAL) && !self.stopped {
+            self.entries.push(IndexEntry {
+                offset,
+                frame: sequence,
+                timestamp_ns: timestamp.as_nanos() as u64,
+            });
+        }
+    }
+
+    /// Write the index of a file that was read to the end, unless the file
+    /// changed meanwhile.
+    pub fn save(self) {
+        let Some(header_len) = self.header_len else {
+            return;
+        };
+        if file_version(&self.path).ok() != Some(self.version) {
+            debug!("{} changed while reading, not saving its index", self.path);
+            return;
+        }
+        let index = SeekIndex {
+            version: VERSION,
+            file_len: self.version.0,
+            modified_ns: self.version.1,
+            header_len,
+            main_mac: self.main_mac.octets(),
+            first_timestamp_ns: self.first_timestamp.as_nanos() as u64,
+            entries: self.entries,
+        };
+        let path = index_path(&self.path);
+        let result = File::create(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(serde_json::to_writer(BufWriter::new(file), &index)?));
+        if let Err(e) = result {
+            warn!("Failed to write the index {}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_before_window() {
+        let entry = |frame: u64, seconds: u64| IndexEntry {
+            offset: frame * 100,
+            frame,
+            timestamp_ns: seconds * 1_000_000_000,
+        };
+        let index = SeekIndex {
+            version: VERSION,
+            file_len: 0,
+            modified_ns: 0,
+            header_len: 24,
+            main_mac: [0; 6],
+            first_timestamp_ns: 0,
+            entries: vec![entry(1, 0), entry(1025, 10), entry(2049, 20)],
+        };
+        let window = |from: Option<u64>, first_frame: Option<u64>| AnalysisWindow {
+            from: from.map(Duration::from_secs),
+            first_frame,
+            ..AnalysisWindow::default()
+        };
+
+        assert!(index.entry_before(&window(None, None)).is_none());
+        assert_eq!(
+            index.entry_before(&window(Some(15), None)).unwrap().frame,
+            1025
+        );
+        assert_eq!(
+            index.entry_before(&window(None, Some(2049))).unwrap().frame,
+            2049
+        );
+        assert_eq!(
+            index
+                .entry_before(&window(Some(25), Some(1500)))
+                .unwrap()
+                .frame,
+            1025
+        );
+        assert_eq!(
+            index.entry_before(&window(None, Some(5000))).unwrap().frame,
+            2049
+        );
+    }
+}
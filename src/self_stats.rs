@@ -0,0 +1,214 @@
+//! `--self-stats`: periodic measurements of ecdump itself, so a deployment can
+//! tell whether dropped frames are lost in the capture, the buffer pool, the
+//! parse workers or the analyzer.
+
+use std::time::{Duration, Instant};
+
+use crate::buffer_pool::PoolMetrics;
+use crate::pipeline::{ParsePipeline, PipelineLoad};
+
+/// Where frames were dropped during an interval, judged from the queues: a slow
+/// stage fills the queue in front of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bottleneck {
+    /// The queues had room, so the kernel or capture backend lost the frames.
+    Capture,
+    /// Every buffer of the pool was in use.
+    Pool,
+    /// The packet source queue was full while the analyzer kept up.
+    Parsing,
+    /// Parsed frames were waiting for the analyzer.
+    Analysis,
+}
+
+impl Bottleneck {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Bottleneck::Capture => "capture (raise --buffer-size)",
+            Bottleneck::Pool => "buffer pool (raise --pool-size)",
+            Bottleneck::Parsing => "parsing (raise --parse-threads)",
+            Bottleneck::Analysis => "analysis and outputs",
+        }
+    }
+}
+
+/// Measurements of one `--self-stats` interval.
+#[derive(Debug, Clone, Copy)]
+pub struct SelfStatsReport {
+    pub elapsed: Duration,
+    pub analyzed_frames: u64,
+    pub dropped_frames: u64,
+    /// Highest queue depths seen by the analyzer, with the capacities.
+    pub source_queue_peak: usize,
+    pub source_capacity: usize,
+    pub parsed_queue_peak: usize,
+    pub parsed_capacity: usize,
+    /// Share of buffer requests served from the pool.
+    pub pool_hit_rate: Option<f64>,
+    pub parse_per_frame: Option<Duration>,
+    pub analyze_per_frame: Option<Duration>,
+    /// Reporting and writing the outputs of a frame after its analysis.
+    pub outputs_per_frame: Option<Duration>,
+    /// Set when frames were dropped.
+    pub bottleneck: Option<Bottleneck>,
+}
+
+/// Counters at the start of an interval.
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    parsed_frames: u64,
+    parse_nanos: u64,
+    dropped_frames: u64,
+    pool: PoolMetrics,
+}
+
+/// Collects the measurements of the analysis loop between two reports.
+pub struct SelfStats {
+    started: Instant,
+    previous: Totals,
+    analyzed_frames: u64,
+    analyze: Duration,
+    outputs: Duration,
+    source_queue_peak: usize,
+    parsed_queue_peak: usize,
+}
+
+impl SelfStats {
+    pub fn new(pipeline: &ParsePipeline, pool: PoolMetrics, dropped_frames: u64) -> Self {
+        SelfStats {
+            started: Instant::now(),
+            previous: Self::totals(pipeline.load(), pool, dropped_frames),
+            analyzed_frames: 0,
+            analyze: Duration::ZERO,
+            outputs: Duration::ZERO,
+            source_queue_peak: 0,
+            parsed_queue_peak: 0,
+        }
+    }
+
+    fn totals(load: PipelineLoad, pool: PoolMetrics, dropped_frames: u64) -> Totals {
+        Totals {
+            parsed_frames: load.parsed_frames,
+            parse_nanos: load.parse_nanos,
+            dropped_frames,
+            pool,
+        }
+    }
+
+    /// An analyzed frame, with the time of its analysis and of its outputs.
+    pub fn frame(&mut self, pipeline: &ParsePipeline, analyze: Duration, outputs: Duration) {
+        let load = pipeline.load();
+        self.analyzed_frames += 1;
+        self.analyze += analyze;
+        self.outputs += outputs;
+        self.source_queue_peak = self.source_queue_peak.max(load.source_queue);
+        self.parsed_queue_peak = self.parsed_queue_peak.max(load.parsed_queue);
+    }
+
+    /// The measurements since the last report, starting the next interval.
+    pub fn report(
+        &mut self,
+        pipeline: &ParsePipeline,
+        pool: PoolMetrics,
+        dropped_frames: u64,
+    ) -> SelfStatsReport {
+        let load = pipeline.load();
+        let totals = Self::totals(load, pool, dropped_frames);
+        let previous = std::mem::replace(&mut self.previous, totals);
+        let per_frame = |total: Duration, frames: u64| {
+            (frames > 0).then(|| total / frames.min(u32::MAX as u64) as u32)
+        };
+
+        let parsed_frames = totals.parsed_frames - previous.parsed_frames;
+        let requests =
+            (totals.pool.hits + totals.pool.misses) - (previous.pool.hits + previous.pool.misses);
+        let dropped = totals.dropped_frames - previous.dropped_frames;
+        let mut report = SelfStatsReport {
+            elapsed: self.started.elapsed(),
+            analyzed_frames: self.analyzed_frames,
+            dropped_frames: dropped,
+            source_queue_peak: self.source_queue_peak.max(load.source_queue),
+            source_capacity: load.source_capacity,
+            parsed_queue_peak: self.parsed_queue_peak.max(load.parsed_queue),
+            parsed_capacity: load.parsed_capacity,
+            pool_hit_rate: (requests > 0)
+                .then(|| (totals.pool.hits - previous.pool.hits) as f64 / requests as f64),
+            parse_per_frame: per_frame(
+                Duration::from_nanos(totals.parse_nanos - previous.parse_nanos),
+                parsed_frames,
+            ),
+            analyze_per_frame: per_frame(self.analyze, self.analyzed_frames),
+            outputs_per_frame: per_frame(self.outputs, self.analyzed_frames),
+            bottleneck: None,
+        };
+        if dropped > 0 {
+            report.bottleneck = Some(Self::bottleneck(
+                &report,
+                totals.pool.exhausted > previous.pool.exhausted,
+            ));
+        }
+
+        self.started = Instant::now();
+        self.analyzed_frames = 0;
+        self.analyze = Duration::ZERO;
+        self.outputs = Duration::ZERO;
+        self.source_queue_peak = 0;
+        self.parsed_queue_peak = 0;
+        report
+    }
+
+    fn bottleneck(report: &SelfStatsReport, pool_exhausted: bool) -> Bottleneck {
+        // Depths are sampled after the analyzer took a frame, so a full queue
+        // shows as almost full
+        let full = |peak: usize, capacity: usize| capacity > 0 && peak * 10 >= capacity * 9;
+        if pool_exhausted {
+            Bottleneck::Pool
+        } else if full(report.parsed_queue_peak, report.parsed_capacity) {
+            Bottleneck::Analysis
+        } else if full(report.source_queue_peak, report.source_capacity) {
+            Bottleneck::Parsing
+        } else {
+            Bottleneck::Capture
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bottleneck_is_the_stage_before_the_first_full_queue() {
+        let report = |source_queue_peak: usize, parsed_queue_peak: usize| SelfStatsReport {
+            elapsed: Duration::from_secs(1),
+            analyzed_frames: 1000,
+            dropped_frames: 10,
+            source_queue_peak,
+            source_capacity: 100,
+            parsed_queue_peak,
+            parsed_capacity: 128,
+            pool_hit_rate: None,
+            parse_per_frame: None,
+            analyze_per_frame: None,
+            outputs_per_frame: None,
+            bottleneck: None,
+        };
+
+        assert_eq!(
+            SelfStats::bottleneck(&report(100, 127), false),
+            Bottleneck::Analysis
+        );
+        assert_eq!(
+            SelfStats::bottleneck(&report(99, 20), false),
+            Bottleneck::Parsing
+        );
+        assert_eq!(
+            SelfStats::bottleneck(&report(3, 0), false),
+            Bottleneck::Capture
+        );
+        assert_eq!(
+            SelfStats::bottleneck(&report(100, 128), true),
+            Bottleneck::Pool
+        );
+    }
+}
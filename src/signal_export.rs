@@ -0,0 +1,45 @@
+use anyhow::{Context, Result};
+use ecdump::analyzer::SignalSample;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Writes process data signal changes as a long-format CSV time series
+/// (one row per signal change).
+pub struct SignalCsvWriter {
+    writer: BufWriter<File>,
+}
+
+impl SignalCsvWriter {
+    pub fn create(path: &str) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create signal export file: {}", path))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(
+            writer,
+            "frame,timestamp,subdevice,object,signal,direction,value"
+        )?;
+        Ok(SignalCsvWriter { writer })
+    }
+
+    pub fn write_samples(&mut self, samples: &[SignalSample]) -> Result<()> {
+        for sample in samples {
+            writeln!(
+                self.writer,
+                "{},{:.6},{},{},\"{}\",{},{}",
+                sample.packet_number,
+                sample.timestamp.as_secs_f64(),
+                sample.subdevice_id,
+                sample.key,
+                sample.name.replace('"', "\"\""),
+                sample.direction,
+                sample.value
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use ecdump::analysis::ErrorCounts;
+use ecdump::analyzer::{DeviceManager, SignalSample};
+use ecdump::topology::TopologyMismatch;
+use log::error;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::time::{Duration, SystemTime};
+
+use crate::error_formatter::CaptureSummary;
+use crate::event_stream::{Event, EventStream, FrameEvents};
+use crate::influx::InfluxWriter;
+use crate::metrics::MetricsServer;
+use crate::report::ReportBuilder;
+use crate::signal_export::SignalCsvWriter;
+use crate::sqlite_store::SqliteStore;
+use crate::startup::Outputs;
+use crate::system_log::SystemLog;
+
+/// The outputs besides the terminal report that record analyzed frames. An
+/// output that fails while the capture runs is logged and closed, so it does not
+/// stop the capture.
+pub struct FrameSinks {
+    signals: Option<SignalCsvWriter>,
+    report: Option<(String, ReportBuilder)>,
+    event_stream: Option<EventStream>,
+    sqlite: Option<SqliteStore>,
+    metrics: Option<MetricsServer>,
+    influx: Option<InfluxWriter>,
+    system_log: Option<SystemLog>,
+}
+
+impl FrameSinks {
+    /// Open the outputs selected in `outputs`. `source` names the capture source.
+    pub fn create(outputs: &Outputs, source: &str, dropped_frames: Arc<AtomicU64>) -> Result<Self> {
+        Ok(FrameSinks {
+            signals: match &outputs.signals_csv {
+                Some(path) => Some(SignalCsvWriter::create(path)?),
+                None => None,
+            },
+            report: outputs
+                .report
+                .as_ref()
+                .map(|path| (path.clone(), ReportBuilder::default())),
+            event_stream: match &outputs.events {
+                Some(path) => Some(EventStream::create(path)?),
+                None => None,
+            },
+            sqlite: match &outputs.sqlite {
+                Some(path) => Some(SqliteStore::create(path, source)?),
+                None => None,
+            },
+            metrics: match &outputs.metrics {
+                Some(address) => Some(MetricsServer::start(address, dropped_frames)?),
+                None => None,
+            },
+            influx: match &outputs.influx {
+                Some(destination) => Some(InfluxWriter::create(destination, source)?),
+                None => None,
+            },
+            system_log: match outputs.syslog {
+                true => Some(SystemLog::open(source)?),
+                false => None,
+            },
+        })
+    }
+
+    pub fn record_frame(
+        &mut self,
+        events: &FrameEvents,
+        samples: &[SignalSample],
+        device_manager: &DeviceManager,
+    ) {
+        if let Some(writer) = self.signals.as_mut()
+            && let Err(e) = writer.write_samples(samples)
+        {
+            error!("Failed to write signal samples: {:?}", e);
+            self.signals = None;
+        }
+        if let Some((_, report)) = self.report.as_mut() {
+            report.record_frame(events);
+        }
+        if let Some(stream) = self.event_stream.as_mut()
+            && let Err(e) = stream.write_frame(events)
+        {
+            error!("Failed to write events: {:?}", e);
+            self.event_stream = None;
+        }
+        if let Some(store) = self.sqlite.as_mut()
+            && let Err(e) = store.write_frame(events)
+        {
+            error!("Failed to write to the database: {:?}", e);
+            self.sqlite = None;
+        }
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.record_frame(events, device_manager);
+        }
+        if let Some(influx) = self.influx.as_mut()
+            && let Err(e) = influx.write_frame(events, samples, device_manager)
+        {
+            error!("Failed to write InfluxDB metrics: {:?}", e);
+            self.influx = None;
+        }
+        if let Some(system_log) = self.system_log.as_mut() {
+            system_log.record_frame(events);
+        }
+    }
+
+    pub fn link_change(&mut self, up: bool, frame: u64, timestamp: Duration) {
+        if let Some(stream) = self.event_stream.as_mut()
+            && let Err(e) = stream.write_link_change(up, frame, timestamp)
+        {
+            error!("Failed to write events: {:?}", e);
+            self.event_stream = None;
+        }
+        if let Some(store) = self.sqlite.as_mut()
+            && let Err(e) = store.write_event(&Event::link_change(up, frame, timestamp))
+        {
+            error!("Failed to write to the database: {:?}", e);
+            self.sqlite = None;
+        }
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.set_link(up);
+        }
+        if let Some(system_log) = self.system_log.as_mut() {
+            system_log.link_change(up, frame);
+        }
+    }
+
+    /// Set the wall-clock time of the first frame.
+    pub fn set_start_time(&mut self, time: SystemTime) {
+        if let Some(store) = self.sqlite.as_mut()
+            && let Err(e) = store.set_start_time(time)
+        {
+            error!("Failed to write to the database: {:?}", e);
+            self.sqlite = None;
+        }
+        if let Some(influx) = self.influx.as_mut() {
+            influx.set_start_time(time);
+        }
+    }
+
+    /// Flush and close the streamed outputs once the capture ended.
+    pub fn finish_streams(&mut self) -> Result<()> {
+        if let Some(writer) = self.signals.take() {
+            writer
+                .finish()
+                .with_context(|| "Failed to write signal export file")?;
+        }
+        if let Some(stream) = self.event_stream.take() {
+            stream
+                .finish()
+                .with_context(|| "Failed to write event file")?;
+        }
+        if let Some(influx) = self.influx.take() {
+            influx
+                .finish()
+                .with_context(|| "Failed to write InfluxDB metrics")?;
+        }
+        Ok(())
+    }
+
+    /// Write the outputs that cover the whole run.
+    pub fn finish(
+        self,
+        device_manager: &DeviceManager,
+        capture: &CaptureSummary,
+        errors: &ErrorCounts,
+        mismatches: Option<&[TopologyMismatch]>,
+    ) -> Result<()> {
+        if let Some(store) = self.sqlite {
+            store
+                .finish(device_manager, capture)
+                .with_context(|| "Failed to write the database")?;
+        }
+        if let Some((path, report)) = &self.report {
+            report.write(path, device_manager, capture, errors, mismatches)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! `--snapshot-on-exit`: the state of the analysis when ecdump stops, in one
+//! timestamped text file and one JSON file to attach to bug reports.
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use ecdump::analysis::ErrorCounts;
+use ecdump::analyzer::DeviceManager;
+use ecdump::subdevice::{ECState, SubDeviceIdentity, SubDeviceStatistics};
+use schemars::{JsonSchema, Schema, schema_for};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error_formatter::CaptureSummary;
+use crate::register_dump::{self, DumpLine};
+use crate::schema::{self, SNAPSHOT_VERSION};
+
+/// State of the analysis when ecdump stopped.
+#[derive(Serialize, JsonSchema)]
+struct Snapshot<'a> {
+    schema_version: u32,
+    /// Local time the snapshot was taken.
+    time: String,
+    /// Capture source: pcap file, interface or SSH destination.
+    source: &'a str,
+    analyzed_frames: u64,
+    dropped_frames: u64,
+    /// Wall-clock duration of the run in seconds.
+    duration: f64,
+    errors: &'a ErrorCounts,
+    devices: Vec<DeviceSnapshot<'a>>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct DeviceSnapshot<'a> {
+    position: usize,
+    subdevice: String,
+    configured_address: Option<u16>,
+    alias: Option<u16>,
+    identity: Option<SubDeviceIdentity>,
+    state: ECState,
+    al_status_code: Option<u16>,
+    statistics: &'a SubDeviceStatistics,
+    registers: Vec<DumpLine>,
+}
+
+/// Write `ecdump-snapshot-<time>.txt` and `.json` to `dir`, and return the path
+/// of the JSON file.
+pub fn write(
+    dir: &str,
+    source: &str,
+    device_manager: &DeviceManager,
+    capture: &CaptureSummary,
+    errors: &ErrorCounts,
+) -> Result<PathBuf> {
+    let now = Local::now();
+    let snapshot = Snapshot {
+        schema_version: SNAPSHOT_VERSION,
+        time: now.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+        source,
+        analyzed_frames: capture.analyzed_frames,
+        dropped_frames: capture.dropped_frames,
+        duration: capture.elapsed.as_secs_f64(),
+        errors,
+        devices: device_manager
+            .devices()
+            .iter()
+            .enumerate()
+            .map(|(position, device)| DeviceSnapshot {
+                position,
+                subdevice: device.identifier().to_string(),
+                configured_address: device.configured_address(),
+                alias: device.configured_alias(),
+                identity: device.identity(),
+                state: device.state(),
+                al_status_code: device.al_status_code(),
+                statistics: device.statistics(),
+                registers: register_dump::dump_lines(device),
+            })
+            .collect(),
+    };
+
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create snapshot directory: {}", dir))?;
+    let stem = format!("ecdump-snapshot-{}", now.format("%Y%m%d-%H%M%S"));
+    let text_path = Path::new(dir).join(format!("{}.txt", stem));
+    write_file(&text_path, |writer| write_text(writer, &snapshot))?;
+    let json_path = Path::new(dir).join(format!("{}.json", stem));
+    write_file(&json_path, |writer| {
+        serde_json::to_writer_pretty(&mut *writer, &snapshot)?;
+        writeln!(writer)?;
+        Ok(())
+    })?;
+    Ok(json_path)
+}
+
+/// JSON Schema of the `--snapshot-on-exit` JSON file.
+pub fn schema() -> Schema {
+    schema::versioned(schema_for!(Snapshot), SNAPSHOT_VERSION)
+}
+
+fn write_file(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create snapshot: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_text(writer: &mut impl Write, snapshot: &Snapshot) -> Result<()> {
+    writeln!(writer, "# ecdump snapshot {}", snapshot.time)?;
+    writeln!(writer, "# Source {}", snapshot.source)?;
+    writeln!(
+        writer,
+        "# {} frames analyzed, {} dropped, {:.3}s",
+        snapshot.analyzed_frames, snapshot.dropped_frames, snapshot.duration
+    )?;
+
+    writeln!(writer, "\n## Errors")?;
+    if snapshot.errors.is_empty() {
+        writeln!(writer, "none")?;
+    }
+    for (category, statistics) in snapshot.errors.iter() {
+        writeln!(
+            writer,
+            "{:<30} {:>7}  first #{} [{:.6}s]  last #{} [{:.6}s]",
+            category,
+            statistics.count,
+            statistics.first_frame,
+            statistics.first_timestamp,
+            statistics.last_frame,
+            statistics.last_timestamp
+        )?;
+    }
+
+    writeln!(writer, "\n## Subdevices")?;
+    for device in &snapshot.devices {
+        let stats = device.statistics;
+        writeln!(
+            writer,
+            "#{:<3} {:<14} {:<9} dgrams:{} rd:{}B wr:{}B mbx:{} transitions:{} wkc:{} esm:{}",
+            device.position,
+            device.subdevice,
+            device.state.to_string(),
+            stats.datagrams,
+            stats.bytes_read,
+            stats.bytes_written,
+            stats.mailbox_messages,
+            stats.state_transitions,
+            stats.wkc_errors,
+            stats.esm_errors
+        )?;
+    }
+
+    for device in &snapshot.devices {
+        writeln!(
+            writer,
+            "\n## Registers of #{} {}",
+            device.position, device.subdevice
+        )?;
+        for line in &device.registers {
+            writeln!(writer, "{}", register_dump::format_line(line))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,393 @@
+//! SQLite storage of a capture (`--sqlite`), for SQL queries over long captures.
+//!
+//! Every run appends a row to `runs`; frames, events and subdevice snapshots refer
+//! to it by `run_id`. Timestamps are seconds since the first frame of the run,
+//! `runs.start_time` is the wall-clock time of that frame and the `event_times`
+//! view adds the wall-clock time of every event. WKC faults per hour and subdevice
+//! over the last week:
+//!
+//! ```sql
+//! SELECT strftime('%Y-%m-%d %H:00', time) AS hour, subdevice, count(*)
+//! FROM event_times
+//! WHERE category = 'wkc_mismatch' AND time >= datetime('now', '-7 days')
+//! GROUP BY hour, subdevice;
+//! ```
+//!
+//! `PRAGMA user_version` holds the schema version, see [`SQLITE_VERSION`].
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use ecdump::analyzer::DeviceManager;
+use ecdump::subdevice::SubDevice;
+use rusqlite::{Connection, params};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error_formatter::CaptureSummary;
+use crate::event_stream::{Event, FrameEvents};
+use crate::schema::SQLITE_VERSION;
+
+/// Database schema. The comments are kept by SQLite, so `.schema` documents the
+/// tables of an existing database.
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    -- Capture source: pcap file, interface or SSH destination
+    source TEXT NOT NULL,
+    -- Wall-clock times (UTC) when ecdump started and finished
+    started_at TEXT NOT NULL,
+    finished_at TEXT,
+    -- Wall-clock time (UTC) of the first frame, which timestamps are relative to.
+    -- NULL for files without absolute timestamps
+    start_time TEXT,
+    analyzed_frames INTEGER,
+    -- Frames lost by live capture
+    dropped_frames INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS frames (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    frame INTEGER NOT NULL,
+    -- Seconds since the first frame
+    timestamp REAL NOT NULL,
+    -- 1 for frames sent by the main device, 0 for returning frames
+    from_main INTEGER NOT NULL,
+    -- EtherCAT frame length in bytes, without the Ethernet header
+    length INTEGER NOT NULL,
+    PRIMARY KEY (run_id, frame)
+) WITHOUT ROWID;
+
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    frame INTEGER NOT NULL,
+    -- Seconds since the first frame
+    timestamp REAL NOT NULL,
+    -- Subdevice the event belongs to, NULL for bus events
+    subdevice TEXT,
+    -- Event category as in the --events stream, e.g. 'wkc_mismatch'
+    category TEXT NOT NULL,
+    -- Category-specific JSON object, query with json_extract()
+    payload TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_category ON events (category, run_id, frame);
+CREATE INDEX IF NOT EXISTS events_subdevice ON events (subdevice, run_id, frame);
+
+CREATE TABLE IF NOT EXISTS subdevices (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    -- Bus scan the snapshot was taken at the end of; the last scan is the final state
+    scan INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    subdevice TEXT NOT NULL,
+    configured_address INTEGER,
+    alias INTEGER,
+    vendor_id INTEGER,
+    product_code INTEGER,
+    revision INTEGER,
+    serial_number INTEGER,
+    state TEXT NOT NULL,
+    al_status_code INTEGER,
+    datagrams INTEGER NOT NULL,
+    bytes_read INTEGER NOT NULL,
+    bytes_written INTEGER NOT NULL,
+    mailbox_messages INTEGER NOT NULL,
+    wkc_errors INTEGER NOT NULL,
+    esm_errors INTEGER NOT NULL,
+    state_transitions INTEGER NOT NULL,
+    PRIMARY KEY (run_id, scan, position)
+);
+
+-- Events with their wall-clock time (UTC)
+CREATE VIEW IF NOT EXISTS event_times AS
+SELECT events.*,
+       strftime('%Y-%m-%d %H:%M:%f', julianday(runs.start_time) + events.timestamp / 86400.0)
+           AS time
+FROM events JOIN runs ON runs.id = events.run_id;
+";
+
+/// Frames written per transaction at most.
+const COMMIT_FRAMES: usize = 1000;
+/// Longest time between commits, so other processes can follow a live capture.
+const COMMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes a run to a SQLite database. Rows are written in transactions of up to
+/// [`COMMIT_FRAMES`] frames or [`COMMIT_INTERVAL`].
+pub struct SqliteStore {
+    connection: Connection,
+    run_id: i64,
+    pending_frames: usize,
+    last_commit: Instant,
+}
+
+impl SqliteStore {
+    /// Open or create the database at `path` and start a run capturing from `source`.
+    pub fn create(path: &str, source: &str) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite database: {}", path))?;
+        // 0 for new databases and those written before the schema was versioned,
+        // which have the tables of version 1
+        let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version != 0 && version != SQLITE_VERSION {
+            bail!(
+                "{} has schema version {}, this ecdump writes version {}",
+                path,
+                version,
+                SQLITE_VERSION
+            );
+        }
+        connection
+            .execute_batch(SCHEMA)
+            .with_context(|| format!("Failed to create the schema of {}", path))?;
+        connection.pragma_update(None, "user_version", SQLITE_VERSION)?;
+        connection.execute(
+            "INSERT INTO runs (source, started_at) VALUES (?1, ?2)",
+            params![source, format_time(SystemTime::now())],
+        )?;
+        let run_id = connection.last_insert_rowid();
+        connection.execute_batch("BEGIN")?;
+        Ok(SqliteStore {
+            connection,
+            run_id,
+            pending_frames: 0,
+            last_commit: Instant::now(),
+        })
+    }
+
+    /// Record the wall-clock time of the first frame.
+    pub fn set_start_time(&mut self, time: SystemTime) -> Result<()> {
+        self.connection.execute(
+            "UPDATE runs SET start_time = ?1 WHERE id = ?2",
+            params![format_time(time), self.run_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn write_frame(&mut self, events: &FrameEvents) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT OR REPLACE INTO frames (run_id, frame, timestamp, from_main, length)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute(params![
+                self.run_id,
+                events.frame,
+                events.timestamp.as_secs_f64(),
+                events.from_main,
+                events.length,
+            ])?;
+        for event in events.events() {
+            self.write_event(&event)?;
+        }
+
+        self.pending_frames += 1;
+        if self.pending_frames >= COMMIT_FRAMES || self.last_commit.elapsed() >= COMMIT_INTERVAL {
+            self.connection.execute_batch("COMMIT; BEGIN")?;
+            self.pending_frames = 0;
+            self.last_commit = Instant::now();
+        }
+        Ok(())
+    }
+
+    pub fn write_event(&mut self, event: &Event) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO events (run_id, frame, timestamp, subdevice, category, payload)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?
+            .execute(params![
+                self.run_id,
+                event.frame,
+                event.timestamp,
+                event.subdevice,
+                event.category,
+                event.payload.to_string(),
+            ])?;
+        Ok(())
+    }
+
+    /// Store the subdevices of every bus scan and the capture statistics, and
+    /// commit the run.
+    pub fn finish(self, device_manager: &DeviceManager, capture: &CaptureSummary) -> Result<()> {
+        for scan in device_manager.previous_scans() {
+            self.write_subdevices(scan.number, &scan.devices)?;
+        }
+        self.write_subdevices(device_manager.scan_number(), device_manager.devices())?;
+        self.connection.execute(
+            "UPDATE runs SET finished_at = ?1, analyzed_frames = ?2, dropped_frames = ?3
+             WHERE id = ?4",
+            params![
+                format_time(SystemTime::now()),
+                capture.analyzed_frames,
+                capture.dropped_frames,
+                self.run_id,
+            ],
+        )?;
+        self.connection.execute_batch("COMMIT")?;
+        Ok(())
+    }
+
+    fn write_subdevices(&self, scan: u32, devices: &[SubDevice]) -> Result<()> {
+        let mut statement = self.connection.prepare_cached(
+            "INSERT INTO subdevices (run_id, scan, position, subdevice, configured_address,
+                 alias, vendor_id, product_code, revision, serial_number, state,
+                 al_status_code, datagrams, bytes_read, bytes_written, mailbox_messages,
+                 wkc_errors, esm_errors, state_transitions)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                 ?17, ?18, ?19)",
+        )?;
+        for (position, device) in devices.iter().enumerate() {
+            let identity = device.identity();
+            let statistics = device.statistics();
+            statement.execute(params![
+                self.run_id,
+                scan,
+                position,
+                device.identifier().to_string(),
+                device.configured_address(),
+                device.configured_alias(),
+                identity.map(|identity| identity.vendor_id),
+                identity.map(|identity| identity.product_code),
+                identity.and_then(|identity| identity.revision),
+                identity.and_then(|identity| identity.serial_number),
+                device.state().to_string(),
+                device.al_status_code(),
+                statistics.datagrams,
+                statistics.bytes_read,
+                statistics.bytes_written,
+                statistics.mailbox_messages,
+                statistics.wkc_errors,
+                statistics.esm_errors,
+                statistics.state_transitions,
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+/// UTC time in the format of SQLite's date and time functions.
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%Y-%m-%d %H:%M:%S%.6f")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecdump::analysis::{AnalysisOptions, analyze_file};
+    use ecdump::analyzer::BusSizeChange;
+    use std::path::Path;
+
+    /// A named in-memory database, kept while `reader` is open. The store's
+    /// connection shares it, so `reader` sees what the store committed.
+    fn memory_database(name: &str) -> (String, Connection) {
+        let path = format!("file:ecdump-{}?mode=memory&cache=shared", name);
+        let reader = Connection::open(&path).unwrap();
+        (path, reader)
+    }
+
+    fn count(reader: &Connection, table: &str) -> u64 {
+        reader
+            .query_row(&format!("SELECT count(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    fn frame_events(frame: u64, bus_size_changes: &[BusSizeChange]) -> FrameEvents<'_> {
+        FrameEvents {
+            frame,
+            timestamp: Duration::from_micros(frame * 100),
+            from_main: frame % 2 == 1,
+            length: 16,
+            malformed: &[],
+            rescans: &[],
+            bus_size_changes,
+            transitions: &[],
+            error_acks: &[],
+            register_changes: &[],
+            logical_issues: &[],
+            firmware_updates: &[],
+            error: None,
+            al_status_code_updates: &[],
+        }
+    }
+
+    #[test]
+    fn test_run_is_written_and_committed() {
+        let (path, reader) = memory_database("run");
+        let mut store = SqliteStore::create(&path, "eth0").unwrap();
+        let version: u32 = reader
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SQLITE_VERSION);
+        assert_eq!(count(&reader, "runs"), 1);
+
+        let change = [BusSizeChange {
+            packet_number: 1,
+            timestamp: Duration::from_micros(100),
+            previous_device_count: 2,
+            device_count: 1,
+        }];
+        store.write_frame(&frame_events(1, &change)).unwrap();
+        for frame in 2..=COMMIT_FRAMES as u64 {
+            store.write_frame(&frame_events(frame, &[])).unwrap();
+        }
+        // A full batch is committed and visible to other connections
+        assert_eq!(store.pending_frames, 0);
+        assert_eq!(count(&reader, "frames"), COMMIT_FRAMES as u64);
+        let (frame, category, payload): (u64, String, String) = reader
+            .query_row("SELECT frame, category, payload FROM events", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((frame, category.as_str()), (1, "bus_size_change"));
+        assert!(payload.contains("\"device_count\":1"));
+
+        store
+            .write_frame(&frame_events(COMMIT_FRAMES as u64 + 1, &[]))
+            .unwrap();
+        assert_eq!(store.pending_frames, 1);
+
+        let capture = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/pdo_mapping.pcap");
+        let report = analyze_file(capture, AnalysisOptions::default()).unwrap();
+        let summary = CaptureSummary {
+            analyzed_frames: COMMIT_FRAMES as u64 + 1,
+            elapsed: Duration::ZERO,
+            dropped_frames: 3,
+            pool: Default::default(),
+            clock_source: None,
+            evictions: Default::default(),
+        };
+        store.finish(report.device_manager(), &summary).unwrap();
+        assert_eq!(count(&reader, "frames"), COMMIT_FRAMES as u64 + 1);
+        assert_eq!(
+            count(&reader, "subdevices"),
+            report.device_manager().device_count() as u64
+        );
+        let (source, finished, analyzed, dropped): (String, Option<String>, u64, u64) = reader
+            .query_row(
+                "SELECT source, finished_at, analyzed_frames, dropped_frames FROM runs",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(source, "eth0");
+        assert!(finished.is_some());
+        assert_eq!((analyzed, dropped), (COMMIT_FRAMES as u64 + 1, 3));
+
+        // A second run is appended
+        SqliteStore::create(&path, "eth1").unwrap();
+        assert_eq!(count(&reader, "runs"), 2);
+    }
+
+    #[test]
+    fn test_other_schema_version_is_refused() {
+        let (path, reader) = memory_database("version");
+        reader
+            .pragma_update(None, "user_version", SQLITE_VERSION + 1)
+            .unwrap();
+        let error = SqliteStore::create(&path, "eth0").err().unwrap();
+        assert!(error.to_string().contains("schema version"));
+        assert_eq!(count(&reader, "sqlite_master"), 0);
+    }
+}
@@ -1,91 +1,961 @@
+use crate::batch::Batch;
+use crate::bench::Bench;
+use crate::buffer_pool::{DEFAULT_POOL_SIZE, PoolExhaustion};
+use crate::capture_trigger::{CaptureTrigger, TriggerEvent, TriggerMode};
+use crate::capture_writer::{OutputFormat, Rotation};
+use crate::config_file;
+use crate::error_formatter::ConsoleMode;
+use crate::extcap::{self, ExtcapRequest};
+use crate::packet_source::{BackpressurePolicy, CaptureBackend, CaptureOptions};
+use crate::remote::RemoteCapture;
+use crate::replay::Replay;
+use crate::schema::SchemaOutput;
+use anyhow::{Context, Result};
 use clap::error::ErrorKind;
 use clap::{CommandFactory, Parser};
+use ecdump::analyzer::AnalyzerConfig;
+use ecdump::messages::Lang;
+use ecdump::register_watch::RegisterWatch;
+use ecdump::registers::parse_u16;
+use ecdump::topology::TopologyExpectation;
 use fern::colors::{Color, ColoredLevelConfig};
+use std::ffi::OsString;
+use std::time::Duration;
 
 pub struct Config {
     pub list_interfaces: bool,
+    /// `ecdump diff A B`
+    pub diff: Option<(String, String)>,
+    /// `ecdump bench FILE`
+    pub bench: Option<Bench>,
+    /// `ecdump batch DIR`: analyze the capture files of a directory.
+    pub batch: Option<Batch>,
+    /// Request of Wireshark running ecdump as an extcap.
+    pub extcap: Option<ExtcapRequest>,
+    /// `--replay`, sent on the capture interface.
+    pub replay: Option<Replay>,
+    /// `-D` output as JSON.
+    pub json: bool,
+    /// `--print-schema`: print the schema of an output and exit.
+    pub print_schema: Option<SchemaOutput>,
     pub verbose: u8,
+    /// `--quiet`: no exit summary.
+    pub quiet: bool,
+    /// `--no-color`: plain console output, as with `NO_COLOR`.
+    pub no_color: bool,
+    pub console: ConsoleMode,
+    /// `--status-interval`, zero to disable.
+    pub status_interval: Duration,
+    /// `--self-stats` interval.
+    pub self_stats: Option<Duration>,
+    /// `--lang` of the event messages.
+    pub lang: Lang,
     pub debug: u8,
+    pub log_file: Option<LogFile>,
     pub pcap_source: PcapSource,
     pub output_file: Option<String>,
+    /// `None` to pick the format from the output file extension.
+    pub output_format: Option<OutputFormat>,
+    /// `--frame-comments`: analyzer frame numbers as pcapng packet comments.
+    pub frame_comments: bool,
+    pub rotation: Rotation,
+    /// `--freeze-on` or `--start-on`.
+    pub capture_trigger: Option<CaptureTrigger>,
     pub time_sync: bool,
+    pub watch_registers: Vec<RegisterWatch>,
+    pub signals: Vec<String>,
+    /// `--script` file.
+    pub script: Option<String>,
+    pub init_sequence: Option<String>,
+    /// `--inventory`, JSON or CSV by extension.
+    pub inventory: Option<String>,
+    /// `--dump-registers` directory.
+    pub dump_registers: Option<String>,
+    /// `--snapshot-on-exit` directory.
+    pub snapshot_on_exit: Option<String>,
+    /// `--checkpoint` file.
+    pub checkpoint: Option<String>,
+    pub outputs: Outputs,
+    pub expected_topology: TopologyExpectation,
+    pub window: AnalysisWindow,
+    pub parse_threads: usize,
+    pub backpressure: BackpressurePolicy,
+    pub capture: CaptureOptions,
+    pub pool_size: usize,
+    /// `--analyzer-memory`, the memory budget of the device model.
+    pub analyzer_memory: Option<u64>,
+    /// `--max-memory`, the limit of the heap.
+    pub max_memory: Option<u64>,
+    /// `--lenient-wkc`, `--no-rescan`, `--no-logical-check`, `--no-mailbox` and
+    /// `--journal-depth`.
+    pub analyzer: AnalyzerConfig,
+}
+
+/// Outputs recording the analyzed frames besides the terminal report.
+pub struct Outputs {
+    pub signals_csv: Option<String>,
+    pub report: Option<String>,
+    /// `-` for standard output.
+    pub events: Option<String>,
+    pub sqlite: Option<String>,
+    /// Address of the Prometheus endpoint.
+    pub metrics: Option<String>,
+    /// File or `http://` URL of the InfluxDB write API.
+    pub influx: Option<String>,
+    /// `--syslog`: critical events to the system log.
+    pub syslog: bool,
+}
+
+/// Frames to analyze, selected by timestamp (relative to the first frame) and/or
+/// frame number. Bounds are inclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnalysisWindow {
+    pub from: Option<Duration>,
+    pub to: Option<Duration>,
+    pub first_frame: Option<u64>,
+    pub last_frame: Option<u64>,
+}
+
+impl AnalysisWindow {
+    /// Whether the frame is inside the window.
+    pub fn contains(&self, frame_number: u64, timestamp: Duration) -> bool {
+        self.from.is_none_or(|from| timestamp >= from)
+            && self.first_frame.is_none_or(|first| frame_number >= first)
+            && !self.is_past(frame_number, timestamp)
+    }
+
+    /// Whether the frame is after the end of the window, so reading can stop.
+    pub fn is_past(&self, frame_number: u64, timestamp: Duration) -> bool {
+        self.to.is_some_and(|to| timestamp > to)
+            || self.last_frame.is_some_and(|last| frame_number > last)
+    }
+}
+
+/// Parse a capture-relative time such as `12.5s`, `12.5`, `250ms` or `100us`.
+fn parse_time(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (value, scale) = if let Some(v) = s.strip_suffix("ms") {
+        (v, 1e-3)
+    } else if let Some(v) = s.strip_suffix("us") {
+        (v, 1e-6)
+    } else if let Some(v) = s.strip_suffix('s') {
+        (v, 1.0)
+    } else {
+        (s, 1.0)
+    };
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .map(|v| Duration::from_secs_f64(v * scale))
+        .ok_or_else(|| format!("invalid time '{}'", s))
+}
+
+fn parse_lang(s: &str) -> Result<Lang, String> {
+    Lang::from_code(s.trim()).ok_or_else(|| {
+        let codes: Vec<&str> = Lang::ALL.iter().map(|lang| lang.code()).collect();
+        format!("unsupported language '{}' (use {})", s, codes.join(", "))
+    })
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    s.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .ok_or_else(|| format!("invalid speed '{}'", s))
+}
+
+/// Parse a file size in megabytes (`100`) or with a `k`, `M` or `G` suffix (`500k`).
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (value, scale) = if let Some(v) = s.strip_suffix(['k', 'K']) {
+        (v, 1e3)
+    } else if let Some(v) = s.strip_suffix(['m', 'M']) {
+        (v, 1e6)
+    } else if let Some(v) = s.strip_suffix(['g', 'G']) {
+        (v, 1e9)
+    } else {
+        (s, 1e6)
+    };
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .map(|v| (v * scale) as u64)
+        .ok_or_else(|| format!("invalid size '{}'", s))
 }
 
 pub enum PcapSource {
     Interface(Option<String>),
     File(PcapFileConfig),
+    Remote(RemoteCapture),
 }
 
 pub struct PcapFileConfig {
     pub file_path: String,
-    pub is_pcapng: bool,
+    /// `--index`: read and keep a seek index next to the file.
+    pub index: bool,
+}
+
+/// `--log-file`: log records kept in a file, filtered independently of the console.
+pub struct LogFile {
+    pub path: String,
+    pub format: LogFormat,
+    pub level: log::LevelFilter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// One line per record with time, level and module.
+    #[default]
+    Text,
+    /// One JSON object per line with `time`, `level`, `target` and `message`.
+    Json,
 }
 
 pub fn parse_args() -> Config {
+    #[derive(clap::Subcommand, Debug)]
+    enum Command {
+        /// Analyze two capture files and report how they differ
+        ///
+        /// Compares the subdevices, the init sequence of the main device, the
+        /// registers it wrote and the error counts. Exits with status 1 if the
+        /// captures differ.
+        Diff {
+            /// The reference capture
+            a: String,
+            /// The capture compared with it
+            b: String,
+        },
+        /// Measure the throughput of the parser, the analyzer and the whole pipeline
+        ///
+        /// Prints frames/s, MB/s and allocations per frame of each stage on the
+        /// capture file, read into memory first. The pipeline stage uses
+        /// --parse-threads and --pool-size.
+        Bench {
+            /// The capture file
+            file: String,
+            /// Runs of each stage; the fastest is reported
+            #[arg(long, value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+            runs: u32,
+        },
+        /// Analyze all capture files under a directory in parallel
+        ///
+        /// Writes the --report of each file (FILE.report.json) and an aggregate
+        /// summary with the errors of all files and the subdevices with the most
+        /// errors (ecdump-batch.json). Exits with status 1 if a file could not
+        /// be analyzed.
+        Batch {
+            /// Directory searched for .pcap and .pcapng files, also compressed
+            dir: String,
+            /// Write the reports and the summary to this directory instead
+            #[arg(long, value_name = "DIR")]
+            out: Option<String>,
+            /// Files analyzed at the same time (default: number of CPUs)
+            #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+            jobs: Option<u16>,
+        },
+    }
+
     #[derive(Parser, Debug)]
     #[command(name = "ecdump", about = "An EtherCAT network analyzer", version)]
+    #[command(group(clap::ArgGroup::new("signal_output").multiple(true)))]
     struct Cli {
+        #[command(subcommand)]
+        command: Option<Command>,
+
+        /// Read option values from a TOML file (default: `ecdump.toml` if it exists)
+        ///
+        /// Keys are long option names, e.g. `interface = "eth1"`, `quiet = true`
+        /// or `watch-reg = ["0x0130", "0x0134"]`. Options on the command line
+        /// take precedence. `[profile.NAME]` tables hold values applied with
+        /// --profile NAME on top of the others.
+        #[arg(long, value_name = "FILE")]
+        config: Option<String>,
+
+        /// Apply the `[profile.NAME]` values of the config file
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+
         /// Set the input file path
-        #[arg(short, long)]
+        ///
+        /// gzip and zstd compressed files are decompressed on the fly.
+        #[arg(short, long, conflicts_with = "interface")]
         file: Option<String>,
 
         /// Set the output file path
+        ///
+        /// Names ending in `.gz` or `.zst` are compressed with gzip or zstd.
         #[arg(short, long, value_name = "FILE")]
         write: Option<String>,
 
-        /// Set the network interface name
+        /// Set the output file format (default: pcapng for `.pcapng` files, pcap otherwise)
+        #[arg(long, value_enum, requires = "write")]
+        format: Option<OutputFormat>,
+
+        /// Comment every frame of the pcapng output with its frame number and time in the analysis
         ///
-        /// If not provided, the default interface will be used.
+        /// The comment reads `ecdump frame #N [T s]`, so an event reported for
+        /// frame N is found in Wireshark with `frame.comment contains "#N "`, also
+        /// when the output file numbers its frames differently.
+        #[arg(long, requires = "write")]
+        frame_comments: bool,
+
+        /// Start a new output file once it reaches this size in megabytes (e.g. `100`, `500k`, `2G`)
+        ///
+        /// Files are numbered `NAME_00000.EXT`, `NAME_00001.EXT`, ... unless the
+        /// output name contains strftime-style time fields, e.g. `ecat-%Y%m%d-%H%M%S.pcapng`.
+        #[arg(short = 'C', long, value_name = "SIZE", value_parser = parse_size, requires = "write")]
+        file_size: Option<u64>,
+
+        /// Start a new output file after this much capture time (e.g. `3600`, `600s`)
+        #[arg(short = 'G', long, value_name = "TIME", value_parser = parse_time, requires = "write")]
+        rotate_every: Option<Duration>,
+
+        /// Keep only the newest N output files when rotating, deleting older ones
+        #[arg(short = 'W', long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), requires = "write")]
+        file_count: Option<u32>,
+
+        /// Keep only the most recent SIZE of output in a ring of files (e.g. `100M`)
+        #[arg(
+            long,
+            value_name = "SIZE",
+            value_parser = parse_size,
+            requires = "write",
+            conflicts_with_all = ["file_size", "rotate_every", "file_count"]
+        )]
+        ring_size: Option<u64>,
+
+        /// Keep only the most recent TIME of output in a ring of files (e.g. `300s`)
+        #[arg(
+            long,
+            value_name = "TIME",
+            value_parser = parse_time,
+            requires = "write",
+            conflicts_with_all = ["file_size", "rotate_every", "file_count"]
+        )]
+        ring_time: Option<Duration>,
+
+        /// Stop writing the output file at the first such analyzer event
+        ///
+        /// With `--ring-size`/`--ring-time` the ring buffer then keeps the frames
+        /// leading up to the fault.
+        #[arg(long, value_enum, value_name = "EVENT", requires = "write")]
+        freeze_on: Option<TriggerEvent>,
+
+        /// Write only the frames from the first such analyzer event on
+        #[arg(
+            long,
+            value_enum,
+            value_name = "EVENT",
+            requires = "write",
+            conflicts_with = "freeze_on"
+        )]
+        start_on: Option<TriggerEvent>,
+
+        /// With `--start-on`, write the frames for TIME after each event (e.g. `5s`)
+        #[arg(long, value_name = "TIME", value_parser = parse_time, requires = "start_on")]
+        post_trigger: Option<Duration>,
+
+        /// With `--start-on`, stop the capture after N events
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), requires = "start_on")]
+        stop_after_events: Option<u64>,
+
+        /// Set the network interface by name, index or friendly name
+        ///
+        /// Accepts any of the values shown by `-D`: the name (the adapter GUID on
+        /// Windows), the index (`#3` is `-i 3`) or the friendly name such as
+        /// "Ethernet 2". If not provided, the default interface will be used.
         #[arg(short, long)]
         interface: Option<String>,
 
+        /// Capture on a remote machine over SSH, e.g. `admin@ipc01:eth1`
+        ///
+        /// Runs tcpdump on the remote host and analyzes the streamed frames locally;
+        /// the SSH user needs capture permission there.
+        #[arg(long, value_name = "[USER@]HOST:IFACE", value_parser = RemoteCapture::parse, conflicts_with_all = ["file", "interface"])]
+        ssh: Option<RemoteCapture>,
+
+        /// Command run on the remote host instead of tcpdump, writing pcap to stdout
+        ///
+        /// `{iface}` is replaced by the interface name, e.g.
+        /// `sudo dumpcap -i {iface} -P -w -`.
+        #[arg(long, value_name = "COMMAND", requires = "ssh")]
+        remote_command: Option<String>,
+
+        /// Send the main device frames of a capture file on the interface
+        ///
+        /// Frames keep their original spacing (see --replay-speed) while the
+        /// interface is captured and analyzed as usual, e.g. to replay a recorded
+        /// init sequence against real subdevices. Stops shortly after the last frame.
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["file", "ssh"])]
+        replay: Option<String>,
+
+        /// Playback speed of --replay; 2 halves the gaps between frames
+        #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_speed, requires = "replay")]
+        replay_speed: f64,
+
         /// Show available network interfaces
         #[arg(short = 'D', long, default_value_t = false)]
         list_interfaces: bool,
 
-        /// Enable verbose reporting (can be used multiple times for increased verbosity)
+        /// Print the interface list as JSON
+        #[arg(long, requires = "list_interfaces")]
+        json: bool,
+
+        /// Print the schema of a machine-readable output and exit
+        ///
+        /// JSON Schema for the JSON outputs, SQL for the database. Every output
+        /// carries its schema version, see `schema_version`.
+        #[arg(long, value_enum, value_name = "OUTPUT")]
+        print_schema: Option<SchemaOutput>,
+
+        /// Print every frame besides the reported events (can be used multiple times)
+        ///
+        /// `-v` prints one line per frame with its datagrams and working counters,
+        /// `-vv` adds error details and decodes every datagram with the registers
+        /// it accesses.
         #[arg(short, long, action = clap::ArgAction::Count)]
         verbose: u8,
 
+        /// Do not print the summary of frames, subdevices and errors when the capture ends
+        #[arg(short, long)]
+        quiet: bool,
+
+        /// Print without colors; also set by the NO_COLOR environment variable
+        #[arg(long)]
+        no_color: bool,
+
+        /// How events are printed
+        ///
+        /// `plain` never moves the cursor or colors the output: repeated events
+        /// are summarized on a line of their own, and a status block is printed
+        /// every --status-interval. `auto` uses it when standard output is not a
+        /// terminal, e.g. redirected to a file or under systemd.
+        #[arg(long, value_enum, value_name = "MODE", default_value_t = ConsoleMode::Auto)]
+        console: ConsoleMode,
+
+        /// Time between the status blocks of `--console plain` (e.g. `10`, `500ms`); 0 disables them
+        #[arg(long, value_name = "TIME", default_value = "10", value_parser = parse_time)]
+        status_interval: Duration,
+
+        /// Print measurements of ecdump itself every TIME (default: 5s)
+        ///
+        /// Frames analyzed and dropped, the peak depths of the capture and parse
+        /// queues, the buffer pool hit rate and the time per frame spent parsing,
+        /// analyzing and writing outputs. When frames were dropped, names the
+        /// stage that could not keep up.
+        #[arg(long, value_name = "TIME", num_args = 0..=1, default_missing_value = "5", value_parser = parse_time)]
+        self_stats: Option<Duration>,
+
+        /// Language of the event messages: `en` or `ja`
+        ///
+        /// Translates the WKC and ESM errors, their diagnoses, error
+        /// acknowledgements and AL Status Codes on the console. Event categories,
+        /// state names and the JSON and database outputs stay in English.
+        #[arg(long, value_name = "LANG", default_value = "en", value_parser = parse_lang)]
+        lang: Lang,
+
         /// Synchronize packet timestamps with the current system time (only applicable when reading from a file)
         #[arg(short = 'T', default_value_t = false)]
         time_sync: bool,
 
+        /// Print a line every time a register changes value
+        ///
+        /// Comma-separated register addresses, each optionally restricted to one
+        /// subdevice by its configured station address, e.g. `0x0130,0x092C:addr=0x1001`.
+        #[arg(long, value_name = "REG[:addr=ADDR]", value_delimiter = ',', value_parser = RegisterWatch::parse)]
+        watch_reg: Vec<RegisterWatch>,
+
+        /// Export process data signal changes to a CSV file
+        ///
+        /// Signals are located from the CoE PDO mapping (0x1600/0x1A00) and
+        /// assignment (0x1C12/0x1C13) downloads and the FMMU configuration.
+        #[arg(long, value_name = "FILE", group = "signal_output")]
+        signals_csv: Option<String>,
+
+        /// Select signals to export by object (e.g. `0x6064:00`) or name (default: all)
+        #[arg(
+            long,
+            value_name = "SIGNAL",
+            value_delimiter = ',',
+            requires = "signal_output"
+        )]
+        signal: Vec<String>,
+
+        /// Run a Rhai script on every analyzer event and datagram
+        ///
+        /// The script defines `on_event(e)` and/or `on_datagram(d)` and can read
+        /// subdevice states and registers, print warnings with `warn` and keep
+        /// counters with `count`, see the README.
+        #[arg(long, value_name = "FILE", group = "signal_output")]
+        script: Option<String>,
+
+        /// Export the main device's startup sequence to a CSV file
+        ///
+        /// Register writes and SDO downloads per subdevice, from the first frame
+        /// until all subdevices reached Op.
+        #[arg(long, value_name = "FILE")]
+        init_sequence: Option<String>,
+
+        /// Write the discovered subdevices to a JSON or CSV (`.csv`) file at the end of the run
+        ///
+        /// Position, configured address, alias, identity from the SII EEPROM, ESC
+        /// type and the kind and link state of each port.
+        #[arg(long, value_name = "FILE")]
+        inventory: Option<String>,
+
+        /// Write the final register space of every subdevice to DIR at the end of the run
+        ///
+        /// One text and one JSON file per subdevice with the known registers by
+        /// name, their values and whether they were written by the main device or
+        /// read from the subdevice, for archiving and diffing configurations.
+        #[arg(long, value_name = "DIR")]
+        dump_registers: Option<String>,
+
+        /// Write the state of the analysis to a timestamped file in DIR (default: current directory) when ecdump stops
+        ///
+        /// `ecdump-snapshot-<time>.txt` and `.json` with the capture counters,
+        /// errors by category, subdevice table and the register space of every
+        /// subdevice, to attach to bug reports. Also written when stopped with
+        /// Ctrl-C.
+        #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = ".")]
+        snapshot_on_exit: Option<String>,
+
+        /// Continue the device history of FILE, if it exists, and save it there when ecdump stops
+        ///
+        /// Keeps the subdevices with their registers, statistics, bus scans and
+        /// init sequence across captures, e.g. rotated files of a day-long
+        /// recording analyzed one after another. Frame numbers continue from
+        /// the previous capture. Also saved when stopped with Ctrl-C.
+        #[arg(long, value_name = "FILE")]
+        checkpoint: Option<String>,
+
+        /// Write the analysis results to a JSON file at the end of the run
+        ///
+        /// Subdevices with identity, final state, statistics and FMMU/sync manager
+        /// configuration, the state timeline, error statistics and the bus topology.
+        #[arg(long, value_name = "FILE")]
+        report: Option<String>,
+
+        /// Stream analyzer events to a JSON Lines file as they happen (`-` for stdout)
+        ///
+        /// One object per event with frame number, timestamp, subdevice, category
+        /// and decoded payload. With `-` the terminal report is not printed.
+        #[arg(long, value_name = "FILE")]
+        events: Option<String>,
+
+        /// Store frames, analyzer events and subdevice snapshots in a SQLite database
+        ///
+        /// Every run is appended to the database. The schema is documented in the
+        /// database itself, see `sqlite3 FILE .schema`.
+        #[arg(long, value_name = "FILE")]
+        sqlite: Option<String>,
+
+        /// Serve Prometheus metrics on http://ADDRESS/metrics, e.g. `0.0.0.0:9898`
+        ///
+        /// Frame and error counters, capture drops, link state, subdevices not in
+        /// Op and the cycle time and jitter of the main device's frames.
+        #[arg(long, value_name = "ADDRESS")]
+        metrics: Option<String>,
+
+        /// Export per-cycle metrics, DC time differences and signal changes in InfluxDB line protocol
+        ///
+        /// To a file, or to an `http://` write API URL such as
+        /// `http://localhost:8086/api/v2/write?org=ORG&bucket=BUCKET&precision=ns`
+        /// with the token from the `INFLUX_TOKEN` environment variable. Signals
+        /// are selected with `--signal`.
+        #[arg(long, value_name = "FILE|URL", group = "signal_output")]
+        influx: Option<String>,
+
+        /// Forward critical events to syslog (the Event Log on Windows)
+        ///
+        /// Link down, subdevices leaving Op and WKC storms (10 faults within a
+        /// second), with `key=value` fields for alerting rules.
+        #[arg(long)]
+        syslog: bool,
+
+        /// Exit with an error if the number of discovered subdevices differs
+        #[arg(long, value_name = "COUNT")]
+        expect_devices: Option<usize>,
+
+        /// Exit with an error if the configured station addresses differ (in bus order)
+        #[arg(long, value_name = "ADDR", value_delimiter = ',', value_parser = parse_u16)]
+        expect_address: Vec<u16>,
+
+        /// Skip frames captured before this time (relative to the first frame, e.g. `12.5s`)
+        ///
+        /// Skipped frames are not analyzed, so subdevices initialized before the
+        /// window are only known once the main device scans the bus again.
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        from: Option<Duration>,
+
+        /// Stop after frames captured at this time (relative to the first frame, e.g. `20s`)
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        to: Option<Duration>,
+
+        /// Skip frames before this frame number
+        #[arg(long, value_name = "N")]
+        first_frame: Option<u64>,
+
+        /// Stop after this frame number
+        #[arg(long, value_name = "N")]
+        last_frame: Option<u64>,
+
+        /// Keep a seek index next to the input file (`FILE.ecidx`)
+        ///
+        /// The index is saved after the file was read to the end once; later
+        /// runs with `--from` or `--first-frame` then start reading close to
+        /// the window instead of at the start of the file.
+        #[arg(long, requires = "file")]
+        index: bool,
+
+        /// Number of threads parsing frames ahead of the analyzer
+        #[arg(long, value_name = "N", default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..=64))]
+        parse_threads: u16,
+
+        /// What to do when live capture outpaces the analyzer
+        ///
+        /// Dropped frames are counted in the summary.
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t = BackpressurePolicy::Block)]
+        backpressure: BackpressurePolicy,
+
+        /// How live capture receives frames from the interface
+        ///
+        /// `tpacket` uses a memory-mapped ring shared with the kernel (Linux only)
+        /// and keeps up with 100 µs cycles at line rate; frames the kernel still
+        /// drops are counted in the summary.
+        ///
+        /// Both take the frame timestamps from the kernel on Linux. On Windows and
+        /// macOS `pnet` does not pass on the Npcap/BPF capture time, so frames are
+        /// timestamped by ecdump when it reads them.
+        #[arg(long, value_enum, value_name = "BACKEND", default_value_t = CaptureBackend::Pnet)]
+        backend: CaptureBackend,
+
+        /// Bytes of each frame written to the output file during live capture
+        ///
+        /// Frames are still analyzed in full; only the saved copy is truncated.
+        #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u32).range(14..))]
+        snaplen: Option<u32>,
+
+        /// Kernel capture buffer size, e.g. `64M` (plain numbers are megabytes)
+        ///
+        /// The socket receive buffer with `pnet` (the BPF buffer on macOS), the
+        /// ring size with `tpacket`. Larger buffers absorb longer analyzer stalls.
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        buffer_size: Option<u64>,
+
+        /// Only capture frames addressed to this host
+        ///
+        /// Leave promiscuous mode on for SPAN ports and taps, which forward frames
+        /// addressed to other hosts.
+        #[arg(long)]
+        no_promiscuous: bool,
+
+        /// Deliver frames as soon as they arrive instead of in batches
+        ///
+        /// Lowers the latency of live reports with `tpacket`, which otherwise hands
+        /// over frames every 10 ms. `pnet` always delivers immediately.
+        #[arg(long)]
+        immediate: bool,
+
+        /// Timestamp frames in the network adapter (`tpacket` backend only)
+        ///
+        /// Switches the adapter to timestamp all received frames while capturing
+        /// and restores its previous setting afterwards. Fails on adapters without
+        /// hardware timestamping.
+        #[arg(long)]
+        hw_timestamps: bool,
+
+        /// Write frames of every EtherType to the output file during live capture
+        ///
+        /// Only EtherCAT frames are analyzed. Without this flag other frames are
+        /// filtered out as early as possible (in the kernel on Linux), so busy
+        /// networks do not load the capture.
+        #[arg(long, requires = "write")]
+        all_ethertypes: bool,
+
+        /// Number of frame buffers shared by the capture, writer and analyzer
+        ///
+        /// Buffers are allocated on first use and reused afterwards. The summary
+        /// shows how often the pool ran out.
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_POOL_SIZE, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        pool_size: usize,
+
+        /// What live capture does when every buffer of the pool is in use
+        ///
+        /// Frames dropped with `drop` are counted in the summary. Files are always
+        /// read, and `-w` files written, without losing frames.
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t = PoolExhaustion::Allocate)]
+        pool_exhaustion: PoolExhaustion,
+
+        /// Keep the register shadows and bus scan history of the analyzer within SIZE (e.g. `64M`)
+        ///
+        /// The register pages written longest ago are evicted first, then the
+        /// subdevices of the oldest bus scans; the summary counts what was
+        /// evicted. ESC information, AL, FMMU and sync manager registers and the
+        /// SII identity are kept.
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        analyzer_memory: Option<u64>,
+
+        /// Stop growing once the heap of ecdump reaches SIZE (e.g. `1G`)
+        ///
+        /// ecdump first shrinks the memory of the analyzer as with
+        /// --analyzer-memory; when nothing more can be evicted, the analysis stops
+        /// as with Ctrl-C, writes its outputs and exits with an error.
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_memory: Option<u64>,
+
+        /// Report only WKCs below the expected one; datagrams more subdevices answered are analyzed as successful
+        #[arg(long)]
+        lenient_wkc: bool,
+
+        /// Keep one device model when the main device scans the bus again, without reporting rescans
+        #[arg(long)]
+        no_rescan: bool,
+
+        /// Do not check the logical addressing of LRD/LWR/LRW datagrams against the FMMUs
+        #[arg(long)]
+        no_logical_check: bool,
+
+        /// Do not decode mailbox messages
+        ///
+        /// Skips CoE PDO assignments, so signals of subdevices mapped through CoE
+        /// are not found, FoE firmware updates and the SDO downloads of the init
+        /// sequence.
+        #[arg(long)]
+        no_mailbox: bool,
+
+        /// WKC errors kept to correlate with later ESM errors of the same subdevice; 0 disables the correlation
+        #[arg(long, value_name = "N", default_value_t = AnalyzerConfig::default().journal_depth)]
+        journal_depth: usize,
+
         #[arg(short, long, hide = true, action = clap::ArgAction::Count)]
         debug: u8,
+
+        /// Append log messages to FILE
+        ///
+        /// The file has its own level, so detailed logs can be kept without
+        /// printing them on the console.
+        #[arg(long, value_name = "FILE")]
+        log_file: Option<String>,
+
+        /// Format of the `--log-file` records
+        #[arg(long, value_enum, value_name = "FORMAT", default_value_t = LogFormat::Text, requires = "log_file")]
+        log_format: LogFormat,
+
+        /// Most detailed level written to the `--log-file`
+        ///
+        /// One of off, error, warn, info, debug and trace.
+        #[arg(long, value_name = "LEVEL", default_value_t = log::LevelFilter::Debug, requires = "log_file")]
+        log_level: log::LevelFilter,
+
+        // Wireshark extcap protocol, see `extcap.rs`
+        #[arg(long, hide = true)]
+        extcap_interfaces: bool,
+        #[arg(long, hide = true)]
+        extcap_dlts: bool,
+        #[arg(long, hide = true)]
+        extcap_config: bool,
+        #[arg(long, hide = true, requires = "fifo")]
+        capture: bool,
+        #[arg(long, hide = true)]
+        extcap_interface: Option<String>,
+        #[arg(long, hide = true)]
+        extcap_version: Option<String>,
+        #[arg(long, hide = true)]
+        extcap_capture_filter: Option<String>,
+        #[arg(long, hide = true, conflicts_with = "write")]
+        fifo: Option<String>,
     }
-    let args = Cli::parse();
-
-    if args.file.is_some() && args.interface.is_some() {
-        let mut cmd = Cli::command();
-        cmd.error(
-            ErrorKind::ArgumentConflict,
-            "Cannot specify both --file and --interface options at the same time",
-        )
-        .exit();
+    // Values of the config file go before the command-line arguments, leaving
+    // out the options given on the command line
+    let command = Cli::command();
+    let command_line: Vec<OsString> = std::env::args_os().collect();
+    let config_arguments = match command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(&command_line)
+    {
+        Ok(matches) => config_file::arguments(&command, &matches)
+            .unwrap_or_else(|e| command.clone().error(ErrorKind::InvalidValue, e).exit()),
+        // --help and --version
+        Err(_) => Vec::new(),
+    };
+    let mut args = Cli::parse_from(
+        command_line
+            .iter()
+            .take(1)
+            .chain(&config_arguments)
+            .chain(command_line.iter().skip(1)),
+    );
+
+    let extcap = if args.extcap_interfaces {
+        Some(ExtcapRequest::Interfaces)
+    } else if args.extcap_dlts {
+        Some(ExtcapRequest::Dlts)
+    } else if args.extcap_config {
+        Some(ExtcapRequest::Config)
+    } else if args.capture {
+        // Wireshark reads pcapng from the fifo
+        args.write = args.fifo.take();
+        args.format = Some(OutputFormat::Pcapng);
+        // A capture filter from Wireshark is ignored, the capture keeps EtherCAT frames
+        Some(ExtcapRequest::Capture)
+    } else {
+        None
+    };
+    if let Some(name) = &args.extcap_interface
+        && name != extcap::INTERFACE
+    {
+        Cli::command()
+            .error(
+                ErrorKind::InvalidValue,
+                format!("Unknown extcap interface: {}", name),
+            )
+            .exit();
     }
 
     let pcap_source = if let Some(file) = args.file {
-        let is_pcapng = file.to_lowercase().ends_with(".pcapng");
         PcapSource::File(PcapFileConfig {
             file_path: file,
-            is_pcapng,
+            index: args.index,
         })
+    } else if let Some(mut remote) = args.ssh {
+        remote.command = args.remote_command;
+        PcapSource::Remote(remote)
     } else {
         PcapSource::Interface(args.interface)
     };
 
     Config {
         list_interfaces: args.list_interfaces,
+        diff: match &args.command {
+            Some(Command::Diff { a, b }) => Some((a.clone(), b.clone())),
+            _ => None,
+        },
+        bench: match &args.command {
+            Some(Command::Bench { file, runs }) => Some(Bench {
+                path: file.clone(),
+                runs: *runs,
+            }),
+            _ => None,
+        },
+        batch: match args.command {
+            Some(Command::Batch { dir, out, jobs }) => Some(Batch {
+                dir,
+                out,
+                jobs: jobs.map_or_else(
+                    || std::thread::available_parallelism().map_or(1, |n| n.get()),
+                    |jobs| jobs as usize,
+                ),
+            }),
+            _ => None,
+        },
+        extcap,
+        replay: args.replay.map(|path| Replay {
+            path,
+            speed: args.replay_speed,
+        }),
+        json: args.json,
+        print_schema: args.print_schema,
         verbose: args.verbose,
+        quiet: args.quiet,
+        no_color: args.no_color,
+        console: args.console,
+        status_interval: args.status_interval,
+        self_stats: args.self_stats,
+        lang: args.lang,
         debug: args.debug,
+        log_file: args.log_file.map(|path| LogFile {
+            path,
+            format: args.log_format,
+            level: args.log_level,
+        }),
         pcap_source,
         output_file: args.write,
+        output_format: args.format,
+        frame_comments: args.frame_comments,
+        rotation: if args.ring_size.is_some() || args.ring_time.is_some() {
+            Rotation::ring(args.ring_size, args.ring_time)
+        } else {
+            Rotation {
+                max_size: args.file_size,
+                interval: args.rotate_every.filter(|interval| !interval.is_zero()),
+                max_files: args.file_count.map(|count| count as usize),
+            }
+        },
+        capture_trigger: match (args.freeze_on, args.start_on) {
+            (Some(event), _) => Some(CaptureTrigger::new(event, TriggerMode::Freeze)),
+            (None, Some(event)) => Some(CaptureTrigger::new(
+                event,
+                TriggerMode::Start {
+                    post_trigger: args.post_trigger,
+                    stop_after_events: args.stop_after_events,
+                },
+            )),
+            (None, None) => None,
+        },
         time_sync: args.time_sync,
+        watch_registers: args.watch_reg,
+        signals: args.signal,
+        script: args.script,
+        init_sequence: args.init_sequence,
+        inventory: args.inventory,
+        dump_registers: args.dump_registers,
+        snapshot_on_exit: args.snapshot_on_exit,
+        checkpoint: args.checkpoint,
+        outputs: Outputs {
+            signals_csv: args.signals_csv,
+            report: args.report,
+            events: args.events,
+            sqlite: args.sqlite,
+            metrics: args.metrics,
+            influx: args.influx,
+            syslog: args.syslog,
+        },
+        expected_topology: TopologyExpectation {
+            device_count: args.expect_devices,
+            addresses: args.expect_address,
+        },
+        window: AnalysisWindow {
+            from: args.from,
+            to: args.to,
+            first_frame: args.first_frame,
+            last_frame: args.last_frame,
+        },
+        parse_threads: args.parse_threads as usize,
+        backpressure: args.backpressure,
+        capture: CaptureOptions {
+            backend: args.backend,
+            snaplen: args.snaplen,
+            buffer_size: args.buffer_size.map(|size| size as usize),
+            promiscuous: !args.no_promiscuous,
+            immediate: args.immediate,
+            all_ethertypes: args.all_ethertypes,
+            pool_exhaustion: args.pool_exhaustion,
+            hw_timestamps: args.hw_timestamps,
+        },
+        pool_size: args.pool_size,
+        analyzer_memory: args.analyzer_memory,
+        max_memory: args.max_memory,
+        analyzer: AnalyzerConfig {
+            strict_wkc: !args.lenient_wkc,
+            tolerate_rescan: !args.no_rescan,
+            logical_addressing: !args.no_logical_check,
+            mailbox_decoding: !args.no_mailbox,
+            journal_depth: args.journal_depth,
+        },
     }
 }
 
-pub fn set_up_logging(verbose: u8) {
+/// Log to the console at the `-d` level, and to the `--log-file` if given.
+/// Console messages go to standard error instead of standard output with
+/// `to_stderr`.
+pub fn set_up_logging(verbose: u8, log_file: Option<&LogFile>, to_stderr: bool) -> Result<()> {
     // use crate::logger::SimpleAsyncLogger;
     // let logger = Box::new(SimpleAsyncLogger::new(
     //     if verbose {
@@ -139,33 +1009,75 @@ pub fn set_up_logging(verbose: u8) {
         .debug(Color::Blue)
         .trace(Color::BrightBlack);
 
-    fern::Dispatch::new()
+    let console_level = if verbose == 0 {
+        log::LevelFilter::Off
+    } else if verbose == 1 {
+        log::LevelFilter::Warn
+    } else if verbose == 2 {
+        log::LevelFilter::Info
+    } else if verbose == 3 {
+        log::LevelFilter::Debug
+    } else {
+        log::LevelFilter::Trace
+    };
+    let console = fern::Dispatch::new()
         // Perform allocation-free log formatting
         .format(move |out, message, record| {
-            out.finish(format_args!(
-                "[{} {}] {}",
-                chrono::Local::now().format("%H:%M:%S%.6f"),
-                colors_line.color(record.level()),
-                message
-            ))
-        })
-        .level(if verbose == 0 {
-            log::LevelFilter::Off
-        } else if verbose == 1 {
-            log::LevelFilter::Warn
-        } else if verbose == 2 {
-            log::LevelFilter::Info
-        } else if verbose == 3 {
-            log::LevelFilter::Debug
-        } else {
-            log::LevelFilter::Trace
+            // Follows `--no-color` and NO_COLOR like the event lines
+            if console::colors_enabled() {
+                out.finish(format_args!(
+                    "[{} {}] {}",
+                    chrono::Local::now().format("%H:%M:%S%.6f"),
+                    colors_line.color(record.level()),
+                    message
+                ))
+            } else {
+                out.finish(format_args!(
+                    "[{} {}] {}",
+                    chrono::Local::now().format("%H:%M:%S%.6f"),
+                    record.level(),
+                    message
+                ))
+            }
         })
-        // Output to stdout, files, and other Dispatch configurations
-        .chain(std::io::stdout())
-        // .chain(fern::log_file("output.log").unwrap())
-        // Apply globally
-        .apply()
-        .unwrap();
+        .level(console_level);
+    // Output to stdout, files, and other Dispatch configurations
+    let console = if to_stderr {
+        console.chain(std::io::stderr())
+    } else {
+        console.chain(std::io::stdout())
+    };
+
+    let mut dispatch = fern::Dispatch::new().chain(console);
+    if let Some(log_file) = log_file {
+        let output = fern::log_file(&log_file.path)
+            .with_context(|| format!("Failed to open log file: {}", log_file.path))?;
+        let file = match log_file.format {
+            LogFormat::Text => fern::Dispatch::new().format(|out, message, record| {
+                out.finish(format_args!(
+                    "[{} {:<5} {}] {}",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.6f"),
+                    record.level(),
+                    record.target(),
+                    message
+                ))
+            }),
+            LogFormat::Json => fern::Dispatch::new().format(|out, message, record| {
+                let line = serde_json::json!({
+                    "time": chrono::Local::now()
+                        .to_rfc3339_opts(chrono::SecondsFormat::Micros, false),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": message.to_string(),
+                });
+                out.finish(format_args!("{}", line))
+            }),
+        };
+        dispatch = dispatch.chain(file.level(log_file.level).chain(output));
+    }
+    // Apply globally
+    dispatch.apply()?;
+    Ok(())
 
     // use std::io::Write;
     //     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug"))
@@ -1,12 +1,21 @@
-use crate::registers::{AlControl, AlStatus, RegisterAddress};
+use crate::mailbox::{FoeMessage, FoeOpCode, SdoDownload};
+use crate::pdo::{PdoConfig, PdoSignal, locate_signals};
+use crate::register_image::RegisterImage;
+use crate::registers::{
+    AlControl, AlStatus, FmmuConfig, RegisterAddress, RegisterValue, SiiAddress, SyncManagerConfig,
+    collect_bytes, default_decoder, read_le_u32,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::fmt;
+use std::ops::Range;
 
 use log::debug;
-use log::info;
-use log::warn;
 
-#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 #[repr(u8)]
 pub enum ECState {
     #[default]
@@ -29,7 +38,8 @@ impl fmt::Display for ECState {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum ESMError {
     IllegalTransition {
         to: ECState,
@@ -48,6 +58,11 @@ pub enum ESMError {
         current: ECState,
         has_error: bool,
     },
+    /// Bootstrap may only be entered from, and left to, Init.
+    InvalidBootstrapTransition {
+        from: ECState,
+        to: ECState,
+    },
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -67,16 +82,276 @@ impl fmt::Display for SubdeviceIdentifier {
     }
 }
 
-#[derive(Debug)]
+/// Traffic and error counters accumulated for a single subdevice.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SubDeviceStatistics {
+    /// Number of datagrams addressed to this subdevice (responses only).
+    pub datagrams: u64,
+    /// Bytes read from this subdevice by the main device.
+    pub bytes_read: u64,
+    /// Bytes written to this subdevice by the main device.
+    pub bytes_written: u64,
+    /// Datagrams accessing one of the mailbox sync manager areas.
+    pub mailbox_messages: u64,
+    /// WKC faults attributed to this subdevice.
+    pub wkc_errors: u64,
+    /// ESM errors reported for this subdevice.
+    pub esm_errors: u64,
+    /// Number of observed state transitions.
+    pub state_transitions: u64,
+}
+
+impl SubDeviceStatistics {
+    /// Total number of errors of any kind attributed to the subdevice.
+    pub fn total_errors(&self) -> u64 {
+        self.wkc_errors + self.esm_errors
+    }
+}
+
+/// Identity of a subdevice as read from its SII EEPROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct SubDeviceIdentity {
+    pub vendor_id: u32,
+    pub product_code: u32,
+    pub revision: Option<u32>,
+    pub serial_number: Option<u32>,
+}
+
+impl fmt::Display for SubDeviceIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "vendor {:#010x} product {:#010x}",
+            self.vendor_id, self.product_code
+        )?;
+        if let Some(revision) = self.revision {
+            write!(f, " rev {:#010x}", revision)?;
+        }
+        if let Some(serial) = self.serial_number {
+            write!(f, " serial {}", serial)?;
+        }
+        Ok(())
+    }
+}
+
+/// ESC information registers (0x0000-0x0003) as read from the subdevice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct EscInfo {
+    /// Type register; the values are assigned per ESC vendor.
+    pub esc_type: u8,
+    pub revision: Option<u8>,
+    pub build: Option<u16>,
+}
+
+/// Physical layer of an ESC port, from the port descriptors (0x0007).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PortKind {
+    NotImplemented,
+    /// Implemented but not configured in the SII EEPROM.
+    NotConfigured,
+    Ebus,
+    /// MII, RMII or RGMII.
+    Mii,
+}
+
+impl fmt::Display for PortKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortKind::NotImplemented => write!(f, "not implemented"),
+            PortKind::NotConfigured => write!(f, "not configured"),
+            PortKind::Ebus => write!(f, "EBUS"),
+            PortKind::Mii => write!(f, "MII"),
+        }
+    }
+}
+
+/// Usage of one ESC port, from the port descriptors and the DL status (0x0110).
+/// Fields are `None` until the main device read the register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct PortInfo {
+    pub port: u8,
+    pub kind: Option<PortKind>,
+    /// Physical link detected.
+    pub link: Option<bool>,
+    /// Port closed (looped back), so frames do not leave the subdevice here.
+    pub loop_closed: Option<bool>,
+    /// Stable communication established.
+    pub communication: Option<bool>,
+}
+
+/// Progress of an AL Status error indication through the acknowledge handshake.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorAckPhase {
+    #[default]
+    Idle,
+    /// The subdevice set the error flag in AL Status.
+    ErrorIndicated {
+        error_packet: u64,
+        /// Number of AL Control writes seen when the error was indicated.
+        al_control_writes: u64,
+    },
+    /// The main device wrote AL Control with the acknowledge bit set.
+    Acknowledged { error_packet: u64, ack_packet: u64 },
+}
+
+/// A completed error indication: error set -> (main device acks) -> subdevice clears.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorAckSequence {
+    pub error_packet: u64,
+    /// `None` if the error flag was cleared without an acknowledge from the main device.
+    pub ack_packet: Option<u64>,
+    pub cleared_packet: u64,
+    pub al_status_code: Option<u16>,
+}
+
+/// A firmware update observed on a subdevice: enter Bootstrap, FoE transfer,
+/// reboot into Init and re-initialization by the main device.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareUpdateSession {
+    /// Packet in which the subdevice entered Bootstrap.
+    pub enter_packet: u64,
+    /// Packet in which the subdevice left Bootstrap for Init.
+    pub reboot_packet: Option<u64>,
+    /// Packet in which the subdevice reached PreOp again after the reboot.
+    pub reinit_packet: Option<u64>,
+    /// File name of the last FoE read/write request.
+    pub file_name: Option<String>,
+    /// FoE file data sent by the main device.
+    pub bytes_written: u64,
+    /// FoE file data returned by the subdevice.
+    pub bytes_read: u64,
+    pub data_packets: u64,
+    pub foe_errors: u64,
+}
+
+/// Selects one of the register shadows kept for a subdevice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegisterShadow {
+    /// Values written by the main device (APWR/FPWR/BWR).
+    Written,
+    /// Values read back from the subdevice (APRD/FPRD).
+    Read,
+    /// Values read through broadcast reads (BRD).
+    BroadcastRead,
+}
+
+impl RegisterShadow {
+    /// The side of the link that provided values in this shadow.
+    pub fn owner(&self) -> RegisterOwner {
+        match self {
+            RegisterShadow::Written => RegisterOwner::MainDevice,
+            RegisterShadow::Read | RegisterShadow::BroadcastRead => RegisterOwner::SubDevice,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            RegisterShadow::Written => 0,
+            RegisterShadow::Read => 1,
+            RegisterShadow::BroadcastRead => 2,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => RegisterShadow::Written,
+            1 => RegisterShadow::Read,
+            _ => RegisterShadow::BroadcastRead,
+        }
+    }
+}
+
+/// Which side last provided the value of a register: commanded by the main
+/// device or reported by the subdevice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegisterOwner {
+    MainDevice,
+    SubDevice,
+}
+
+impl fmt::Display for RegisterOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterOwner::MainDevice => write!(f, "main"),
+            RegisterOwner::SubDevice => write!(f, "subdevice"),
+        }
+    }
+}
+
+/// A register byte in the merged register view, taken from the shadow that was updated last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterDumpEntry {
+    pub address: u16,
+    pub value: u8,
+    pub shadow: RegisterShadow,
+}
+
+impl RegisterDumpEntry {
+    pub fn owner(&self) -> RegisterOwner {
+        self.shadow.owner()
+    }
+}
+
+impl fmt::Display for RegisterShadow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterShadow::Written => write!(f, "wr"),
+            RegisterShadow::Read => write!(f, "rd"),
+            RegisterShadow::BroadcastRead => write!(f, "brd"),
+        }
+    }
+}
+
+/// Registers kept when shadows are evicted: ESC information, AL Control and
+/// Status, FMMUs and sync managers, which the analysis depends on.
+const PINNED_REGISTERS: [Range<u16>; 2] = [0x0000..0x0200, 0x0600..0x0900];
+/// SII bytes kept when shadows are evicted, with the identity of the subdevice.
+const PINNED_SII: Range<u16> = 0x0000..0x0100;
+
+/// A page of the register shadows that can be evicted to bound memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ShadowRange {
+    /// The page at this address in the brd, wr and rd shadows.
+    Registers(u16),
+    /// The page at this address of the SII EEPROM image.
+    Sii(u16),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubDevice {
     state: ECState,
     configured_address: Option<u16>,
     al_status: Option<AlStatus>,
     al_status_code: Option<u16>,
     al_control: Option<AlControl>,
-    register_brd: BTreeMap<u16, u8>,
-    register_wr: BTreeMap<u16, u8>,
-    register_rd: BTreeMap<u16, u8>,
+    register_brd: RegisterImage,
+    /// Shadow that last updated each register byte (see `RegisterShadow::to_u8`).
+    register_source: RegisterImage,
+    register_wr: RegisterImage,
+    register_rd: RegisterImage,
+    /// SII EEPROM contents observed through the SII data register, byte addressed.
+    sii: RegisterImage,
+    statistics: SubDeviceStatistics,
+    /// Number of main device writes covering the AL Control register.
+    al_control_writes: u64,
+    error_ack_phase: ErrorAckPhase,
+    completed_error_acks: Vec<ErrorAckSequence>,
+    firmware_update: Option<FirmwareUpdateSession>,
+    completed_firmware_updates: Vec<FirmwareUpdateSession>,
+    pdo: PdoConfig,
+    /// Incremented whenever the PDO mapping, FMMU or sync manager configuration changes.
+    process_data_generation: u64,
+    /// Mailbox contents are not decoded, see
+    /// [`AnalyzerConfig::mailbox_decoding`](crate::analyzer::AnalyzerConfig::mailbox_decoding).
+    #[serde(skip)]
+    ignore_mailbox: bool,
+}
+
+impl Default for SubDevice {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SubDevice {
@@ -87,12 +362,322 @@ impl SubDevice {
             al_status: None,
             al_status_code: None,
             al_control: None,
-            register_brd: BTreeMap::new(),
-            register_wr: BTreeMap::new(),
-            register_rd: BTreeMap::new(),
+            register_brd: RegisterImage::new(),
+            register_source: RegisterImage::new(),
+            register_wr: RegisterImage::new(),
+            register_rd: RegisterImage::new(),
+            sii: RegisterImage::new(),
+            statistics: SubDeviceStatistics::default(),
+            al_control_writes: 0,
+            error_ack_phase: ErrorAckPhase::Idle,
+            completed_error_acks: Vec::new(),
+            firmware_update: None,
+            completed_firmware_updates: Vec::new(),
+            pdo: PdoConfig::default(),
+            process_data_generation: 0,
+            ignore_mailbox: false,
+        }
+    }
+
+    /// PDO mapping and assignment objects downloaded by the main device.
+    pub fn pdo_config(&self) -> &PdoConfig {
+        &self.pdo
+    }
+
+    /// Changes whenever the layout returned by `pdo_signals` may have changed.
+    pub fn process_data_generation(&self) -> u64 {
+        self.process_data_generation
+    }
+
+    /// Named PDO entries located in the logical process image.
+    pub fn pdo_signals(&self) -> Vec<PdoSignal> {
+        if self.pdo.is_empty() {
+            return Vec::new();
+        }
+        let sync_managers: Vec<_> = self.sync_managers().collect();
+        let fmmus: Vec<_> = self.fmmus().collect();
+        locate_signals(&self.pdo, &sync_managers, &fmmus)
+    }
+
+    /// Decode the CoE and FoE messages exchanged through the mailbox (the default).
+    pub(crate) fn set_mailbox_decoding(&mut self, decode: bool) {
+        self.ignore_mailbox = !decode;
+    }
+
+    /// Record PDO mapping/assignment downloads sent through the mailbox.
+    fn observe_coe(&mut self, reg_addr: u16, data: &[u8]) {
+        if self.ignore_mailbox || !self.is_mailbox_access(reg_addr) {
+            return;
+        }
+        if let Some(sdo) = SdoDownload::from_mailbox(data)
+            && self.pdo.apply_sdo_download(&sdo)
+        {
+            self.process_data_generation += 1;
+        }
+    }
+
+    /// The firmware update session in progress, if the subdevice entered Bootstrap.
+    pub fn firmware_update(&self) -> Option<&FirmwareUpdateSession> {
+        self.firmware_update.as_ref()
+    }
+
+    /// Take the firmware update sessions completed since the last call.
+    pub fn take_firmware_updates(&mut self) -> Vec<FirmwareUpdateSession> {
+        std::mem::take(&mut self.completed_firmware_updates)
+    }
+
+    /// Advance the firmware update session after a state change from `old_state`.
+    fn track_firmware_update(&mut self, old_state: ECState, packet_num: u64) {
+        let new_state = self.state;
+        if new_state == old_state {
+            return;
+        }
+
+        if new_state == ECState::Bootstrap {
+            // Re-entering Bootstrap before re-initialization ends the previous session
+            if let Some(session) = self.firmware_update.take() {
+                self.completed_firmware_updates.push(session);
+            }
+            self.firmware_update = Some(FirmwareUpdateSession {
+                enter_packet: packet_num,
+                ..Default::default()
+            });
+            return;
+        }
+
+        let Some(session) = self.firmware_update.as_mut() else {
+            return;
+        };
+        if old_state == ECState::Bootstrap {
+            session.reboot_packet = Some(packet_num);
+        } else if session.reboot_packet.is_some() && new_state >= ECState::PreOp {
+            session.reinit_packet = Some(packet_num);
+            if let Some(session) = self.firmware_update.take() {
+                self.completed_firmware_updates.push(session);
+            }
+        }
+    }
+
+    /// Record FoE traffic exchanged through the mailbox while in Bootstrap.
+    fn observe_foe(&mut self, reg_addr: u16, data: &[u8], from_main_device: bool) {
+        if self.ignore_mailbox
+            || self.state != ECState::Bootstrap
+            || !self.is_mailbox_access(reg_addr)
+        {
+            return;
+        }
+        let Some(session) = self.firmware_update.as_mut() else {
+            return;
+        };
+        let Some(foe) = FoeMessage::from_mailbox(data) else {
+            return;
+        };
+
+        match foe.opcode {
+            FoeOpCode::ReadRequest | FoeOpCode::WriteRequest => {
+                session.file_name = foe.file_name();
+            }
+            FoeOpCode::Data => {
+                session.data_packets += 1;
+                if from_main_device {
+                    session.bytes_written += foe.data.len() as u64;
+                } else {
+                    session.bytes_read += foe.data.len() as u64;
+                }
+            }
+            FoeOpCode::Error => session.foe_errors += 1,
+            _ => {}
+        }
+    }
+
+    /// Current phase of the AL Status error acknowledge handshake.
+    pub fn error_ack_phase(&self) -> ErrorAckPhase {
+        self.error_ack_phase
+    }
+
+    /// Take the error acknowledge sequences completed since the last call.
+    pub fn take_error_ack_sequences(&mut self) -> Vec<ErrorAckSequence> {
+        std::mem::take(&mut self.completed_error_acks)
+    }
+
+    /// Advance the error acknowledge handshake using the latest AL Control/AL Status values.
+    fn track_error_acknowledge(&mut self, packet_num: u64) {
+        let Some(al_status) = self.al_status else {
+            return;
+        };
+        // Only an acknowledge written after the error was indicated counts
+        let acknowledged = |writes_at_error: u64| {
+            self.al_control_writes > writes_at_error
+                && self.al_control.is_some_and(|c| c.acknowledge)
+        };
+
+        self.error_ack_phase = match (self.error_ack_phase, al_status.error) {
+            (ErrorAckPhase::Idle, true) => ErrorAckPhase::ErrorIndicated {
+                error_packet: packet_num,
+                al_control_writes: self.al_control_writes,
+            },
+            (
+                ErrorAckPhase::ErrorIndicated {
+                    error_packet,
+                    al_control_writes,
+                },
+                true,
+            ) if acknowledged(al_control_writes) => ErrorAckPhase::Acknowledged {
+                error_packet,
+                ack_packet: packet_num,
+            },
+            (ErrorAckPhase::ErrorIndicated { error_packet, .. }, false) => {
+                self.complete_error_ack(error_packet, None, packet_num);
+                ErrorAckPhase::Idle
+            }
+            (
+                ErrorAckPhase::Acknowledged {
+                    error_packet,
+                    ack_packet,
+                },
+                false,
+            ) => {
+                self.complete_error_ack(error_packet, Some(ack_packet), packet_num);
+                ErrorAckPhase::Idle
+            }
+            (phase, _) => phase,
+        };
+    }
+
+    fn complete_error_ack(&mut self, error_packet: u64, ack_packet: Option<u64>, packet_num: u64) {
+        let al_status_code = self
+            .decode_reg_rd(RegisterAddress::AlStatusCode)
+            .map(|value| value.raw() as u16);
+        self.completed_error_acks.push(ErrorAckSequence {
+            error_packet,
+            ack_packet,
+            cleared_packet: packet_num,
+            al_status_code,
+        });
+    }
+
+    /// Read an AL Control/AL Status register that may have been accessed as `u8` or `u16`.
+    /// The reserved upper byte is treated as zero when only the lower byte was accessed.
+    fn read_al_register(image: &RegisterImage, address: u16) -> Option<u16> {
+        let mut iter = image.read(address, 2);
+        let low = iter.next().flatten()?;
+        let high = iter.next().flatten().unwrap_or(0);
+        Some(u16::from_le_bytes([low, high]))
+    }
+
+    pub fn statistics(&self) -> &SubDeviceStatistics {
+        &self.statistics
+    }
+
+    pub fn statistics_mut(&mut self) -> &mut SubDeviceStatistics {
+        &mut self.statistics
+    }
+
+    /// Returns true if `reg_addr` falls within a sync manager area configured
+    /// in mailbox mode (typically SM0/SM1).
+    pub fn is_mailbox_access(&self, reg_addr: u16) -> bool {
+        (0..2)
+            .filter_map(|n| self.sync_manager(n))
+            .any(|sm| sm.is_mailbox() && sm.contains(reg_addr))
+    }
+
+    /// FMMU `n` as configured by the main device, if all of its bytes were observed.
+    pub fn fmmu(&self, n: u16) -> Option<FmmuConfig> {
+        let address = RegisterAddress::Fmmu0 + n * FmmuConfig::SIZE;
+        let bytes = collect_bytes(self.read_reg_wr(address, FmmuConfig::SIZE))?;
+        FmmuConfig::from_bytes(&bytes)
+    }
+
+    /// All FMMUs whose configuration is known, with their index.
+    pub fn fmmus(&self) -> impl Iterator<Item = (u16, FmmuConfig)> + '_ {
+        (0..16).filter_map(|n| self.fmmu(n).map(|fmmu| (n, fmmu)))
+    }
+
+    /// Sync manager `n` as configured by the main device, if all of its bytes were observed.
+    pub fn sync_manager(&self, n: u16) -> Option<SyncManagerConfig> {
+        let address = RegisterAddress::Sm0 + n * SyncManagerConfig::SIZE;
+        let bytes = collect_bytes(self.read_reg_wr(address, SyncManagerConfig::SIZE))?;
+        SyncManagerConfig::from_bytes(&bytes)
+    }
+
+    /// All sync managers whose configuration is known, with their index.
+    pub fn sync_managers(&self) -> impl Iterator<Item = (u16, SyncManagerConfig)> + '_ {
+        (0..16).filter_map(|n| self.sync_manager(n).map(|sm| (n, sm)))
+    }
+
+    /// Vendor ID and product code (plus revision/serial if seen) read from the SII EEPROM.
+    pub fn identity(&self) -> Option<SubDeviceIdentity> {
+        Some(SubDeviceIdentity {
+            vendor_id: read_le_u32(self.sii.read(SiiAddress::VendorId, 4))?,
+            product_code: read_le_u32(self.sii.read(SiiAddress::ProductCode, 4))?,
+            revision: read_le_u32(self.sii.read(SiiAddress::RevisionNumber, 4)),
+            serial_number: read_le_u32(self.sii.read(SiiAddress::SerialNumber, 4)),
+        })
+    }
+
+    /// ESC type, revision and build, if the main device read them.
+    pub fn esc_info(&self) -> Option<EscInfo> {
+        let decode = |address| self.decode_reg_rd(address).map(|value| value.raw());
+        Some(EscInfo {
+            esc_type: decode(RegisterAddress::Type)? as u8,
+            revision: decode(RegisterAddress::Revision).map(|raw| raw as u8),
+            build: decode(RegisterAddress::Build).map(|raw| raw as u16),
+        })
+    }
+
+    /// Kind and link state of the four ports.
+    pub fn ports(&self) -> [PortInfo; 4] {
+        let descriptors = self
+            .read_reg_rd(RegisterAddress::PortDescriptors, 1)
+            .next()
+            .flatten();
+        let dl_status = self
+            .decode_reg_rd(RegisterAddress::DlStatus)
+            .map(|value| value.raw() as u16);
+        std::array::from_fn(|port| {
+            let bit = |n: usize| dl_status.map(|status| status & (1 << n) != 0);
+            PortInfo {
+                port: port as u8,
+                kind: descriptors.map(|descriptors| match (descriptors >> (port * 2)) & 0b11 {
+                    0 => PortKind::NotImplemented,
+                    1 => PortKind::NotConfigured,
+                    2 => PortKind::Ebus,
+                    _ => PortKind::Mii,
+                }),
+                link: bit(4 + port),
+                loop_closed: bit(8 + port * 2),
+                communication: bit(9 + port * 2),
+            }
+        })
+    }
+
+    /// The SII EEPROM contents observed so far, byte addressed.
+    pub fn sii(&self) -> &RegisterImage {
+        &self.sii
+    }
+
+    /// One of the raw register shadows kept for this subdevice.
+    pub fn register_image(&self, shadow: RegisterShadow) -> &RegisterImage {
+        match shadow {
+            RegisterShadow::Written => &self.register_wr,
+            RegisterShadow::Read => &self.register_rd,
+            RegisterShadow::BroadcastRead => &self.register_brd,
         }
     }
 
+    pub fn al_status(&self) -> Option<AlStatus> {
+        self.al_status
+    }
+
+    pub fn al_control(&self) -> Option<AlControl> {
+        self.al_control
+    }
+
+    /// State most recently requested by the main device through AL Control.
+    pub fn requested_state(&self) -> Option<ECState> {
+        self.al_control.and_then(|al_control| al_control.state.ok())
+    }
+
     pub fn configured_address(&self) -> Option<u16> {
         self.configured_address
     }
@@ -115,49 +700,227 @@ impl SubDevice {
         }
     }
 
-    fn configured_alias(&self) -> Option<u16> {
-        let mut iter = self.read_reg_rd(RegisterAddress::ConfiguredStationAlias, 2);
-        let low = iter.next().flatten()?;
-        let high = iter.next().flatten()?;
-        Some(u16::from_le_bytes([low, high]))
+    pub fn configured_alias(&self) -> Option<u16> {
+        self.decode_reg_rd(RegisterAddress::ConfiguredStationAlias)
+            .map(|value| value.raw() as u16)
     }
 
-    fn write_reg_impl(register: &mut BTreeMap<u16, u8>, reg_addr: u16, data: &[u8]) {
-        for (i, value) in data.iter().enumerate() {
-            register.insert(reg_addr.wrapping_add(i as u16), *value);
-        }
+    /// Last DC System Time Difference read from the subdevice in ns: its local
+    /// copy of the system time minus the received one. The register holds the
+    /// magnitude in bits 0-30 and the sign in bit 31.
+    pub fn dc_system_time_difference(&self) -> Option<i32> {
+        let raw = self
+            .decode_reg_rd(RegisterAddress::DcSystemTimeDifference)?
+            .raw() as u32;
+        let magnitude = (raw & 0x7FFF_FFFF) as i32;
+        Some(if raw & 0x8000_0000 != 0 {
+            -magnitude
+        } else {
+            magnitude
+        })
     }
 
-    fn read_reg_impl(
-        register: &BTreeMap<u16, u8>,
-        reg_addr: u16,
-        length: u16,
-    ) -> impl Iterator<Item = Option<u8>> {
-        (0..length).map(move |i| register.get(&reg_addr.wrapping_add(i)).copied())
+    pub fn write_reg_wr(&mut self, reg_addr: u16, data: &[u8]) {
+        self.register_wr.write(reg_addr, data);
+        self.record_source(reg_addr, data.len(), RegisterShadow::Written);
+        let end = reg_addr as u32 + data.len() as u32;
+        if reg_addr <= RegisterAddress::AlControl && end > RegisterAddress::AlControl as u32 {
+            self.al_control_writes += 1;
+        }
+        if reg_addr < RegisterAddress::Sm0 + 16 * SyncManagerConfig::SIZE
+            && end > RegisterAddress::Fmmu0 as u32
+        {
+            self.process_data_generation += 1;
+        }
+        self.observe_foe(reg_addr, data, true);
+        self.observe_coe(reg_addr, data);
     }
 
-    pub fn write_reg_wr(&mut self, reg_addr: u16, data: &[u8]) {
-        Self::write_reg_impl(&mut self.register_wr, reg_addr, data);
+    /// Decode the register starting at `reg_addr` from the written shadow using the
+    /// standard register definitions. `None` if it is not defined or not fully known.
+    fn decode_reg_wr(&self, reg_addr: u16) -> Option<RegisterValue> {
+        let length = default_decoder().get(reg_addr)?.length();
+        default_decoder().decode_iter(reg_addr, self.read_reg_wr(reg_addr, length))
     }
 
     pub fn read_reg_wr(&self, reg_addr: u16, length: u16) -> impl Iterator<Item = Option<u8>> {
-        Self::read_reg_impl(&self.register_wr, reg_addr, length)
+        self.register_wr.read(reg_addr, length)
     }
 
     pub fn write_reg_rd(&mut self, reg_addr: u16, data: &[u8]) {
-        Self::write_reg_impl(&mut self.register_rd, reg_addr, data);
+        self.register_rd.write(reg_addr, data);
+        self.record_source(reg_addr, data.len(), RegisterShadow::Read);
+        self.capture_sii_data(reg_addr, data);
+        self.observe_foe(reg_addr, data, false);
+    }
+
+    /// Record SII EEPROM words returned through the SII data register.
+    /// The EEPROM word address is taken from the last SII address written by the main device.
+    fn capture_sii_data(&mut self, reg_addr: u16, data: &[u8]) {
+        let end = reg_addr as u32 + data.len() as u32;
+        let sii_data = RegisterAddress::SiiData as u32;
+        if (reg_addr as u32) > sii_data || end <= sii_data {
+            return;
+        }
+
+        // Skip reads that return the busy flag in the same datagram
+        let control_offset = RegisterAddress::SiiControl as i32 + 1 - reg_addr as i32;
+        if control_offset >= 0
+            && let Some(control_high) = data.get(control_offset as usize)
+            && control_high & 0x80 != 0
+        {
+            return;
+        }
+
+        let Some(word_address) = self
+            .decode_reg_wr(RegisterAddress::SiiAddress)
+            .map(|value| value.raw() as u32)
+        else {
+            return;
+        };
+        let start = (sii_data - reg_addr as u32) as usize;
+        let words = &data[start..data.len().min(start + 8)];
+        self.sii.write((word_address as u16).wrapping_mul(2), words);
+    }
+
+    /// Decode the register starting at `reg_addr` from the read shadow, like
+    /// [`Self::decode_reg_wr`].
+    fn decode_reg_rd(&self, reg_addr: u16) -> Option<RegisterValue> {
+        let length = default_decoder().get(reg_addr)?.length();
+        default_decoder().decode_iter(reg_addr, self.read_reg_rd(reg_addr, length))
     }
 
     pub fn read_reg_rd(&self, reg_addr: u16, length: u16) -> impl Iterator<Item = Option<u8>> {
-        Self::read_reg_impl(&self.register_rd, reg_addr, length)
+        self.register_rd.read(reg_addr, length)
     }
 
     pub fn write_reg_brd(&mut self, reg_addr: u16, data: &[u8]) {
-        Self::write_reg_impl(&mut self.register_brd, reg_addr, data);
+        self.register_brd.write(reg_addr, data);
+        self.record_source(reg_addr, data.len(), RegisterShadow::BroadcastRead);
+    }
+
+    fn record_source(&mut self, reg_addr: u16, length: usize, shadow: RegisterShadow) {
+        let sources = [shadow.to_u8(); 64];
+        let mut offset = 0;
+        while offset < length {
+            let chunk = (length - offset).min(sources.len());
+            self.register_source
+                .write(reg_addr.wrapping_add(offset as u16), &sources[..chunk]);
+            offset += chunk;
+        }
+    }
+
+    /// Shadow that last updated the register byte at `reg_addr`.
+    pub fn register_source(&self, reg_addr: u16) -> Option<RegisterShadow> {
+        self.register_source
+            .get(reg_addr)
+            .map(RegisterShadow::from_u8)
+    }
+
+    /// Whether the register byte at `reg_addr` was last commanded by the main
+    /// device or reported by the subdevice.
+    pub fn register_owner(&self, reg_addr: u16) -> Option<RegisterOwner> {
+        self.register_source(reg_addr).map(|shadow| shadow.owner())
+    }
+
+    /// All known register bytes in address order, merged from the wr/rd/brd
+    /// shadows by taking the value from the shadow that was updated last.
+    pub fn register_dump(&self) -> impl Iterator<Item = RegisterDumpEntry> + '_ {
+        self.register_source.iter().filter_map(|(address, source)| {
+            let shadow = RegisterShadow::from_u8(source);
+            let value = self.register_image(shadow).get(address)?;
+            Some(RegisterDumpEntry {
+                address,
+                value,
+                shadow,
+            })
+        })
     }
 
     pub fn read_reg_brd(&self, reg_addr: u16, length: u16) -> impl Iterator<Item = Option<u8>> {
-        Self::read_reg_impl(&self.register_brd, reg_addr, length)
+        self.register_brd.read(reg_addr, length)
+    }
+
+    fn register_shadows(&self) -> [&RegisterImage; 4] {
+        [
+            &self.register_brd,
+            &self.register_source,
+            &self.register_wr,
+            &self.register_rd,
+        ]
+    }
+
+    /// Approximate heap usage of the register shadows and SII image in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.register_shadows()
+            .iter()
+            .map(|image| image.memory_usage())
+            .sum::<usize>()
+            + self.sii.memory_usage()
+    }
+
+    /// Count one more period without writes for every page of the shadows.
+    pub(crate) fn age_shadows(&mut self) {
+        for image in [
+            &mut self.register_brd,
+            &mut self.register_source,
+            &mut self.register_wr,
+            &mut self.register_rd,
+            &mut self.sii,
+        ] {
+            image.age();
+        }
+    }
+
+    /// Pages that may be evicted, with the periods since they were last written.
+    pub(crate) fn evictable_ranges(&self) -> Vec<(ShadowRange, u32)> {
+        let mut registers = BTreeMap::new();
+        for image in self.register_shadows() {
+            for (address, idle) in image.page_ages() {
+                let entry = registers.entry(address).or_insert(u32::MAX);
+                *entry = idle.min(*entry);
+            }
+        }
+        registers
+            .into_iter()
+            .filter(|(address, _)| !PINNED_REGISTERS.iter().any(|r| r.contains(address)))
+            .map(|(address, idle)| (ShadowRange::Registers(address), idle))
+            .chain(
+                self.sii
+                    .page_ages()
+                    .filter(|(address, _)| !PINNED_SII.contains(address))
+                    .map(|(address, idle)| (ShadowRange::Sii(address), idle)),
+            )
+            .collect()
+    }
+
+    /// Forget a page of the shadows. Returns the number of pages freed and of
+    /// known register values they held.
+    pub(crate) fn evict(&mut self, range: ShadowRange) -> (u64, u64) {
+        let (images, address) = match range {
+            ShadowRange::Registers(address) => (
+                vec![
+                    &mut self.register_brd,
+                    &mut self.register_wr,
+                    &mut self.register_rd,
+                ],
+                address,
+            ),
+            ShadowRange::Sii(address) => (vec![&mut self.sii], address),
+        };
+        let (mut pages, mut values) = (0, 0);
+        for image in images {
+            if let Some(known) = image.evict(address) {
+                pages += 1;
+                values += known as u64;
+            }
+        }
+        if let ShadowRange::Registers(address) = range
+            && self.register_source.evict(address).is_some()
+        {
+            pages += 1;
+        }
+        (pages, values)
     }
 
     pub fn state_machine_step<T: CommandStepper>(
@@ -168,17 +931,9 @@ impl SubDevice {
     }
 
     fn load_al_status_code(&mut self) {
-        let al_status_code = {
-            let mut iter = self.read_reg_rd(RegisterAddress::AlStatusCode, 2);
-            let low = iter.next().flatten();
-            let high = iter.next().flatten();
-            if let (Some(low), Some(high)) = (low, high) {
-                Some(u16::from_le_bytes([low, high]))
-            } else {
-                None
-            }
-        };
-        self.al_status_code = al_status_code;
+        self.al_status_code = self
+            .decode_reg_rd(RegisterAddress::AlStatusCode)
+            .map(|value| value.raw() as u16);
     }
 }
 
@@ -198,12 +953,16 @@ pub trait CommandStepper {
                 let _ = Self::op(subdevice);
             }
             ECState::Bootstrap => {
-                // No transitions defined yet
+                let _ = Self::bootstrap(subdevice);
             }
         }
         Self::common(subdevice);
+        subdevice.track_error_acknowledge(packet_num);
 
-        Self::change_state(subdevice, packet_num)
+        let old_state = subdevice.state;
+        let result = Self::change_state(subdevice, packet_num);
+        subdevice.track_firmware_update(old_state, packet_num);
+        result
     }
 
     fn init(_subdevice: &mut SubDevice) -> Option<()> {
@@ -218,6 +977,9 @@ pub trait CommandStepper {
     fn op(_subdevice: &mut SubDevice) -> Option<()> {
         Some(())
     }
+    fn bootstrap(_subdevice: &mut SubDevice) -> Option<()> {
+        Some(())
+    }
     fn common(_subdevice: &mut SubDevice) -> Option<()> {
         Some(())
     }
@@ -235,6 +997,12 @@ pub trait CommandStepper {
                     });
 
             if let Ok(new_state) = al_status.state {
+                if let Some(result) =
+                    Self::change_bootstrap_state(subdevice, new_state, change_requested)
+                {
+                    return result;
+                }
+
                 match change_requested {
                     Some(requested_state) => {
                         let old_state = subdevice.state;
@@ -248,7 +1016,7 @@ pub trait CommandStepper {
                         }
 
                         if new_state < old_state {
-                            warn!(
+                            debug!(
                                 "#{} SubDevice {} state changed backward from {:?} to {:?}",
                                 packet_num,
                                 subdevice.identifier(),
@@ -263,7 +1031,7 @@ pub trait CommandStepper {
                             });
                         }
                         if new_state < requested_state {
-                            info!(
+                            debug!(
                                 "#{} SubDevice {} state change to {:?} failed",
                                 packet_num,
                                 subdevice.identifier(),
@@ -317,25 +1085,85 @@ pub trait CommandStepper {
 
         Ok(())
     }
+
+    /// Handle transitions into and out of Bootstrap, which sits outside the
+    /// Init < PreOp < SafeOp < Op ordering. Returns `None` for all other transitions.
+    fn change_bootstrap_state(
+        subdevice: &mut SubDevice,
+        new_state: ECState,
+        requested: Option<ECState>,
+    ) -> Option<Result<(), ESMError>> {
+        let old_state = subdevice.state;
+        let entering = new_state == ECState::Bootstrap && old_state != ECState::Bootstrap;
+        let leaving = old_state == ECState::Bootstrap && new_state != ECState::Bootstrap;
+        if !entering && !leaving {
+            return None;
+        }
+
+        subdevice.state = new_state;
+        let other = if entering { old_state } else { new_state };
+        if other != ECState::Init {
+            subdevice.load_al_status_code();
+            return Some(Err(ESMError::InvalidBootstrapTransition {
+                from: old_state,
+                to: new_state,
+            }));
+        }
+        // Init <-> Bootstrap is only expected on request from the main device
+        if requested != Some(new_state) {
+            subdevice.load_al_status_code();
+            if subdevice.al_control.is_none() {
+                return Some(Err(ESMError::IllegalTransition { to: new_state }));
+            }
+            return Some(Err(ESMError::InvalidStateTransition {
+                requested: requested.unwrap_or(old_state),
+                current: new_state,
+            }));
+        }
+        Some(Ok(()))
+    }
+}
+
+/// Stepper for FPWR/BWR datagrams, run on the copy of the main device's write
+/// that returns to it, as the read steppers are. Writes carry no AL Status, so
+/// only the requested state is updated; transitions are still decided on the
+/// next read.
+pub struct WriteCommandStepper;
+impl CommandStepper for WriteCommandStepper {
+    fn execute(subdevice: &mut SubDevice, packet_num: u64) -> Result<(), ESMError> {
+        let previous = subdevice.requested_state();
+        Self::common(subdevice);
+
+        let requested = subdevice.requested_state();
+        if requested.is_some() && requested != previous {
+            debug!(
+                "#{} SubDevice {} requested state {:?}",
+                packet_num,
+                subdevice.identifier(),
+                requested
+            );
+        }
+        Ok(())
+    }
+
+    fn common(subdevice: &mut SubDevice) -> Option<()> {
+        subdevice.al_control =
+            SubDevice::read_al_register(&subdevice.register_wr, RegisterAddress::AlControl)
+                .map(AlControl::from_u16);
+
+        Some(())
+    }
 }
 
 pub struct BrdCommandStepper;
 impl CommandStepper for BrdCommandStepper {
     fn common(subdevice: &mut SubDevice) -> Option<()> {
-        let al_control = {
-            let mut iter = subdevice.read_reg_wr(RegisterAddress::AlControl, 1);
-            iter.next().flatten().map(|b| AlControl::new(b))
-        };
-        subdevice.al_control = al_control;
-
-        let al_status = {
-            let mut iter = subdevice.read_reg_brd(RegisterAddress::AlStatus, 1);
-            iter.next()
-                .flatten()
-                .map(|b| AlStatus::try_from(b))
-                .flatten()
-        };
-        subdevice.al_status = al_status;
+        subdevice.al_control =
+            SubDevice::read_al_register(&subdevice.register_wr, RegisterAddress::AlControl)
+                .map(AlControl::from_u16);
+        subdevice.al_status =
+            SubDevice::read_al_register(&subdevice.register_brd, RegisterAddress::AlStatus)
+                .and_then(AlStatus::try_from_u16);
 
         Some(())
     }
@@ -345,18 +1173,12 @@ pub struct AprdCommandStepper;
 impl CommandStepper for AprdCommandStepper {
     fn init(subdevice: &mut SubDevice) -> Option<()> {
         if subdevice.configured_address.is_none() {
-            let configued_address_wr = {
-                let mut iter = subdevice.read_reg_wr(RegisterAddress::ConfiguredStationAddress, 2);
-                let low = iter.next().flatten()?;
-                let high = iter.next().flatten()?;
-                u16::from_le_bytes([low, high])
-            };
-            let configued_address_rd = {
-                let mut iter = subdevice.read_reg_rd(RegisterAddress::ConfiguredStationAddress, 2);
-                let low = iter.next().flatten()?;
-                let high = iter.next().flatten()?;
-                u16::from_le_bytes([low, high])
-            };
+            let configued_address_wr = subdevice
+                .decode_reg_wr(RegisterAddress::ConfiguredStationAddress)?
+                .raw() as u16;
+            let configued_address_rd = subdevice
+                .decode_reg_rd(RegisterAddress::ConfiguredStationAddress)?
+                .raw() as u16;
             if configued_address_wr != configued_address_rd {
                 return None;
             }
@@ -370,18 +1192,261 @@ impl CommandStepper for AprdCommandStepper {
 pub struct FprdCommandStepper;
 impl CommandStepper for FprdCommandStepper {
     fn common(subdevice: &mut SubDevice) -> Option<()> {
-        let al_control = {
-            let mut iter = subdevice.read_reg_wr(RegisterAddress::AlControl, 1);
-            iter.next().flatten().map(|b| AlControl::new(b))
+        subdevice.al_control =
+            SubDevice::read_al_register(&subdevice.register_wr, RegisterAddress::AlControl)
+                .map(AlControl::from_u16);
+        subdevice.al_status =
+            SubDevice::read_al_register(&subdevice.register_rd, RegisterAddress::AlStatus)
+                .map(AlStatus::from_u16);
+
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_from_sii_reads() {
+        let mut device = SubDevice::new();
+        assert!(device.identity().is_none());
+
+        // Vendor ID + product code at word address 0x0008
+        device.write_reg_wr(RegisterAddress::SiiAddress, &[0x08, 0x00, 0x00, 0x00]);
+        device.write_reg_rd(
+            RegisterAddress::SiiData,
+            &[0x02, 0x00, 0x00, 0x00, 0x52, 0x0c, 0x44, 0x07],
+        );
+
+        let identity = device.identity().unwrap();
+        assert_eq!(identity.vendor_id, 0x0000_0002);
+        assert_eq!(identity.product_code, 0x0744_0c52);
+        assert_eq!(identity.revision, None);
+    }
+
+    #[test]
+    fn test_evict_idle_register_pages_but_keep_pinned_ones() {
+        let mut device = SubDevice::new();
+        device.write_reg_rd(RegisterAddress::AlStatus, &[0x08, 0x00]);
+        device.write_reg_wr(0x0910, &[0x01, 0x02, 0x03, 0x04]);
+        device.age_shadows();
+        device.write_reg_rd(0x1100, &[0xAA]);
+
+        assert_eq!(
+            device.evictable_ranges(),
+            vec![
+                (ShadowRange::Registers(0x0900), 1),
+                (ShadowRange::Registers(0x1100), 0)
+            ]
+        );
+        let usage = device.memory_usage();
+        // The wr page with its values and the page of the source shadow
+        assert_eq!(device.evict(ShadowRange::Registers(0x0900)), (2, 4));
+        assert!(device.memory_usage() < usage);
+        assert_eq!(device.read_reg_wr(0x0910, 1).next(), Some(None));
+        assert_eq!(device.register_dump().count(), 3);
+    }
+
+    #[test]
+    fn test_dc_system_time_difference_sign() {
+        let mut device = SubDevice::new();
+        assert_eq!(device.dc_system_time_difference(), None);
+        device.write_reg_rd(RegisterAddress::DcSystemTimeDifference, &[0x64, 0, 0, 0]);
+        assert_eq!(device.dc_system_time_difference(), Some(100));
+        device.write_reg_rd(RegisterAddress::DcSystemTimeDifference, &[0x64, 0, 0, 0x80]);
+        assert_eq!(device.dc_system_time_difference(), Some(-100));
+    }
+
+    #[test]
+    fn test_ports_from_descriptors_and_dl_status() {
+        let mut device = SubDevice::new();
+        // Ports 0 and 1 EBUS, 2 not implemented, 3 MII
+        device.write_reg_rd(RegisterAddress::PortDescriptors, &[0b1100_1010]);
+        // Link on port 0 and 1, port 1 open with communication, others closed
+        device.write_reg_rd(RegisterAddress::DlStatus, &[0x30, 0b0101_1011]);
+
+        let ports = device.ports();
+        assert_eq!(ports[0].kind, Some(PortKind::Ebus));
+        assert_eq!(ports[2].kind, Some(PortKind::NotImplemented));
+        assert_eq!(ports[3].kind, Some(PortKind::Mii));
+        assert_eq!(ports[1].link, Some(true));
+        assert_eq!(ports[1].loop_closed, Some(false));
+        assert_eq!(ports[1].communication, Some(true));
+        assert_eq!(ports[2].link, Some(false));
+        assert_eq!(ports[2].loop_closed, Some(true));
+    }
+
+    #[test]
+    fn test_sii_read_while_busy_is_ignored() {
+        let mut device = SubDevice::new();
+        device.write_reg_wr(RegisterAddress::SiiAddress, &[0x08, 0x00, 0x00, 0x00]);
+        // SiiControl (busy) + SiiAddress + SiiData in a single read
+        let mut data = [0u8; 14];
+        data[1] = 0x80;
+        device.write_reg_rd(RegisterAddress::SiiControl, &data);
+        assert!(device.sii().is_empty());
+    }
+
+    #[test]
+    fn test_mailbox_access_from_sync_manager_config() {
+        let mut device = SubDevice::new();
+        // SM0: start 0x1000, length 128, mailbox mode, write direction
+        device.write_reg_wr(
+            RegisterAddress::Sm0,
+            &[0x00, 0x10, 0x80, 0x00, 0x26, 0x00, 0x01, 0x00],
+        );
+        let sm = device.sync_manager(0).unwrap();
+        assert!(sm.is_mailbox());
+        assert!(sm.is_write());
+        assert!(device.is_mailbox_access(0x1000));
+        assert!(device.is_mailbox_access(0x107F));
+        assert!(!device.is_mailbox_access(0x1080));
+    }
+
+    #[test]
+    fn test_error_acknowledge_handshake_with_u16_access() {
+        let mut device = SubDevice::new();
+        let step = |device: &mut SubDevice, packet_num: u64| {
+            FprdCommandStepper::common(device);
+            device.track_error_acknowledge(packet_num);
         };
-        subdevice.al_control = al_control;
 
-        let al_status = {
-            let mut iter = subdevice.read_reg_rd(RegisterAddress::AlStatus, 1);
-            iter.next().flatten().map(|b| AlStatus::new(b))
+        // Request SafeOp, device answers PreOp + error (u16 reads)
+        device.write_reg_wr(RegisterAddress::AlControl, &[0x04, 0x00]);
+        device.write_reg_rd(
+            RegisterAddress::AlStatus,
+            &[0x12, 0x00, 0x00, 0x00, 0x1d, 0x00],
+        );
+        step(&mut device, 10);
+        assert!(matches!(
+            device.error_ack_phase(),
+            ErrorAckPhase::ErrorIndicated {
+                error_packet: 10,
+                ..
+            }
+        ));
+
+        // Stale acknowledge bit is not enough; a fresh write with the bit set is
+        step(&mut device, 11);
+        assert!(matches!(
+            device.error_ack_phase(),
+            ErrorAckPhase::ErrorIndicated { .. }
+        ));
+        device.write_reg_wr(RegisterAddress::AlControl, &[0x12, 0x00]);
+        step(&mut device, 12);
+        assert_eq!(
+            device.error_ack_phase(),
+            ErrorAckPhase::Acknowledged {
+                error_packet: 10,
+                ack_packet: 12
+            }
+        );
+
+        device.write_reg_rd(RegisterAddress::AlStatus, &[0x02, 0x00]);
+        step(&mut device, 13);
+        assert_eq!(device.error_ack_phase(), ErrorAckPhase::Idle);
+        assert_eq!(
+            device.take_error_ack_sequences(),
+            vec![ErrorAckSequence {
+                error_packet: 10,
+                ack_packet: Some(12),
+                cleared_packet: 13,
+                al_status_code: Some(0x001d),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_firmware_update_session() {
+        let mut device = SubDevice::new();
+        let step = |device: &mut SubDevice, packet_num: u64, al_control: u8, al_status: u8| {
+            device.write_reg_wr(RegisterAddress::AlControl, &[al_control]);
+            device.write_reg_rd(RegisterAddress::AlStatus, &[al_status]);
+            device.state_machine_step::<FprdCommandStepper>(packet_num)
         };
-        subdevice.al_status = al_status;
 
-        Some(())
+        // Init -> Bootstrap, then write 4 bytes of firmware through the boot mailbox
+        assert!(step(&mut device, 1, 0x03, 0x03).is_ok());
+        assert_eq!(device.state(), ECState::Bootstrap);
+        device.write_reg_wr(
+            RegisterAddress::Sm0,
+            &[0x00, 0x10, 0x80, 0x00, 0x26, 0x00, 0x01, 0x00],
+        );
+        device.write_reg_wr(
+            0x1000,
+            &[
+                0x0a, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0xde, 0xad,
+                0xbe, 0xef,
+            ],
+        );
+
+        // Reboot into Init, then re-initialization up to PreOp
+        assert!(step(&mut device, 5, 0x01, 0x01).is_ok());
+        assert!(step(&mut device, 9, 0x02, 0x02).is_ok());
+
+        let sessions = device.take_firmware_updates();
+        assert_eq!(
+            sessions,
+            vec![FirmwareUpdateSession {
+                enter_packet: 1,
+                reboot_packet: Some(5),
+                reinit_packet: Some(9),
+                bytes_written: 4,
+                data_packets: 1,
+                ..Default::default()
+            }]
+        );
+        assert!(device.firmware_update().is_none());
+    }
+
+    #[test]
+    fn test_bootstrap_only_entered_from_init() {
+        let mut device = SubDevice::new();
+        device.write_reg_wr(RegisterAddress::AlControl, &[0x02]);
+        device.write_reg_rd(RegisterAddress::AlStatus, &[0x02]);
+        assert!(device.state_machine_step::<FprdCommandStepper>(1).is_ok());
+
+        device.write_reg_wr(RegisterAddress::AlControl, &[0x03]);
+        device.write_reg_rd(RegisterAddress::AlStatus, &[0x03]);
+        assert!(matches!(
+            device.state_machine_step::<FprdCommandStepper>(2),
+            Err(ESMError::InvalidBootstrapTransition {
+                from: ECState::PreOp,
+                to: ECState::Bootstrap
+            })
+        ));
+    }
+
+    #[test]
+    fn test_write_updates_requested_state() {
+        let mut device = SubDevice::new();
+        assert_eq!(device.requested_state(), None);
+
+        device.write_reg_wr(RegisterAddress::AlControl, &[0x04, 0x00]);
+        assert!(device.state_machine_step::<WriteCommandStepper>(1).is_ok());
+        assert_eq!(device.requested_state(), Some(ECState::SafeOp));
+        // The current state only changes once AL Status is read back
+        assert_eq!(device.state(), ECState::Init);
+    }
+
+    #[test]
+    fn test_register_ownership() {
+        let mut device = SubDevice::new();
+        device.write_reg_wr(RegisterAddress::AlControl, &[0x02, 0x00]);
+        device.write_reg_rd(RegisterAddress::AlControl, &[0x02]);
+        assert_eq!(
+            device.register_owner(RegisterAddress::AlControl),
+            Some(RegisterOwner::SubDevice)
+        );
+        assert_eq!(
+            device.register_owner(RegisterAddress::AlControl + 1),
+            Some(RegisterOwner::MainDevice)
+        );
+
+        let dump: Vec<_> = device.register_dump().collect();
+        assert_eq!(dump.len(), 2);
+        assert_eq!(dump[0].shadow, RegisterShadow::Read);
+        assert_eq!(dump[1].shadow, RegisterShadow::Written);
     }
 }
@@ -0,0 +1,306 @@
+//! Forwarding of critical events to the system log (`--syslog`): syslog on Unix,
+//! the Event Log on Windows.
+//!
+//! Every message starts with a readable text followed by `key=value` fields
+//! (`event`, `source`, `frame` and event-specific ones), so alerting rules can
+//! match on them without parsing the text.
+
+use anyhow::Result;
+use ecdump::analyzer::{ECDeviceError, ECError};
+use ecdump::subdevice::ECState;
+use log::warn;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::event_stream::FrameEvents;
+
+/// WKC faults within [`WKC_STORM_WINDOW`] that make a WKC storm.
+const WKC_STORM_THRESHOLD: usize = 10;
+const WKC_STORM_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    Error,
+    Warning,
+    Notice,
+}
+
+/// Sends link changes, subdevices leaving Op and WKC storms to the system log.
+pub struct SystemLog {
+    sink: sink::Sink,
+    source: String,
+    /// Timestamps of the WKC faults within the last [`WKC_STORM_WINDOW`].
+    wkc_faults: VecDeque<Duration>,
+    in_wkc_storm: bool,
+}
+
+impl SystemLog {
+    /// `source` names the capture source in every message.
+    pub fn open(source: &str) -> Result<Self> {
+        Ok(SystemLog {
+            sink: sink::Sink::open()?,
+            source: source.to_string(),
+            wkc_faults: VecDeque::new(),
+            in_wkc_storm: false,
+        })
+    }
+
+    pub fn record_frame(&mut self, events: &FrameEvents) {
+        for transition in events.transitions {
+            if transition.from == ECState::Op && transition.to != ECState::Op {
+                self.send(
+                    Severity::Error,
+                    format!(
+                        "Subdevice {} left Op: {} -> {}",
+                        transition.subdevice_id, transition.from, transition.to
+                    ),
+                    "subdevice_left_op",
+                    transition.packet_number,
+                    &[
+                        ("subdevice", transition.subdevice_id.to_string()),
+                        ("from", transition.from.to_string()),
+                        ("to", transition.to.to_string()),
+                    ],
+                );
+            }
+        }
+
+        if let Some(ECError::DeviceError(errors)) = events.error {
+            for error in errors {
+                if matches!(error, ECDeviceError::InvalidWkc(_)) {
+                    self.wkc_faults.push_back(error.timestamp());
+                }
+            }
+        }
+        while let Some(first) = self.wkc_faults.front()
+            && events.timestamp.saturating_sub(*first) > WKC_STORM_WINDOW
+        {
+            self.wkc_faults.pop_front();
+        }
+        if !self.in_wkc_storm && self.wkc_faults.len() >= WKC_STORM_THRESHOLD {
+            self.in_wkc_storm = true;
+            self.send(
+                Severity::Warning,
+                format!(
+                    "WKC storm: {} working counter faults within {}s",
+                    self.wkc_faults.len(),
+                    WKC_STORM_WINDOW.as_secs()
+                ),
+                "wkc_storm",
+                events.frame,
+                &[("faults", self.wkc_faults.len().to_string())],
+            );
+        } else if self.in_wkc_storm && self.wkc_faults.is_empty() {
+            self.in_wkc_storm = false;
+            self.send(
+                Severity::Notice,
+                "WKC storm ended".to_string(),
+                "wkc_storm_ended",
+                events.frame,
+                &[],
+            );
+        }
+    }
+
+    pub fn link_change(&mut self, up: bool, frame: u64) {
+        if up {
+            self.send(
+                Severity::Notice,
+                "Link up".to_string(),
+                "link_up",
+                frame,
+                &[],
+            );
+        } else {
+            self.send(
+                Severity::Error,
+                "Link down".to_string(),
+                "link_down",
+                frame,
+                &[],
+            );
+        }
+    }
+
+    fn send(
+        &mut self,
+        severity: Severity,
+        text: String,
+        event: &str,
+        frame: u64,
+        fields: &[(&str, String)],
+    ) {
+        let mut message = text;
+        write!(
+            message,
+            " event={} source={} frame={}",
+            event,
+            quote(&self.source),
+            frame
+        )
+        .unwrap();
+        for (key, value) in fields {
+            write!(message, " {}={}", key, quote(value)).unwrap();
+        }
+        // A system log that is unavailable does not stop the capture
+        if let Err(e) = self.sink.send(severity, event, &message) {
+            warn!("Failed to write to the system log: {:#}", e);
+        }
+    }
+}
+
+/// Field value, quoted if it contains spaces or quotes.
+fn quote(value: &str) -> String {
+    if value.is_empty() || value.contains([' ', '"', '=']) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(unix)]
+mod sink {
+    use super::Severity;
+    use anyhow::{Context, Result};
+    use std::os::unix::net::UnixDatagram;
+
+    /// Sockets of the local syslog daemon: Linux, then macOS.
+    const SOCKETS: [&str; 2] = ["/dev/log", "/var/run/syslog"];
+    /// LOG_DAEMON
+    const FACILITY: u8 = 3;
+
+    pub struct Sink {
+        socket: UnixDatagram,
+    }
+
+    impl Sink {
+        pub fn open() -> Result<Self> {
+            Ok(Sink { socket: connect()? })
+        }
+
+        /// Send an RFC 3164 message, reconnecting once if the daemon restarted.
+        pub fn send(&mut self, severity: Severity, _event: &str, message: &str) -> Result<()> {
+            let code = match severity {
+                Severity::Error => 3,
+                Severity::Warning => 4,
+                Severity::Notice => 5,
+            };
+            let line = format!(
+                "<{}>{} ecdump[{}]: {}",
+                FACILITY * 8 + code,
+                chrono::Local::now().format("%b %e %H:%M:%S"),
+                std::process::id(),
+                message
+            );
+            if self.socket.send(line.as_bytes()).is_err() {
+                self.socket = connect()?;
+                self.socket.send(line.as_bytes())?;
+            }
+            Ok(())
+        }
+    }
+
+    fn connect() -> Result<UnixDatagram> {
+        let socket = UnixDatagram::unbound()?;
+        let mut result = Err(std::io::Error::from(std::io::ErrorKind::NotFound));
+        for path in SOCKETS {
+            result = socket.connect(path);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result.context("Failed to connect to the syslog daemon")?;
+        Ok(socket)
+    }
+}
+
+#[cfg(windows)]
+mod sink {
+    use super::Severity;
+    use anyhow::{Result, bail};
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::System::EventLog::{
+        DeregisterEventSource, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+        EVENTLOG_WARNING_TYPE, RegisterEventSourceW, ReportEventW,
+    };
+
+    /// Writes to the Application log with the `ecdump` source. Without a message
+    /// file registered for the source, Event Viewer shows the message as the
+    /// event's only insertion string.
+    pub struct Sink {
+        handle: HANDLE,
+    }
+
+    impl Sink {
+        pub fn open() -> Result<Self> {
+            let source = wide("ecdump");
+            // SAFETY: `source` is a NUL-terminated UTF-16 string.
+            let handle = unsafe { RegisterEventSourceW(std::ptr::null(), source.as_ptr()) };
+            if handle.is_null() {
+                bail!(
+                    "Failed to register the event source: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+            Ok(Sink { handle })
+        }
+
+        pub fn send(&mut self, severity: Severity, event: &str, message: &str) -> Result<()> {
+            let kind = match severity {
+                Severity::Error => EVENTLOG_ERROR_TYPE,
+                Severity::Warning => EVENTLOG_WARNING_TYPE,
+                Severity::Notice => EVENTLOG_INFORMATION_TYPE,
+            };
+            let event_id = match event {
+                "link_down" | "link_up" => 1,
+                "subdevice_left_op" => 2,
+                _ => 3,
+            };
+            let message = wide(message);
+            let strings = [message.as_ptr()];
+            // SAFETY: `handle` is a registered event source and `strings` holds
+            // one NUL-terminated UTF-16 string.
+            let ok = unsafe {
+                ReportEventW(
+                    self.handle,
+                    kind,
+                    0,
+                    event_id,
+                    std::ptr::null_mut(),
+                    1,
+                    0,
+                    strings.as_ptr(),
+                    std::ptr::null(),
+                )
+            };
+            if ok == 0 {
+                bail!("{}", std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Sink {
+        fn drop(&mut self) {
+            // SAFETY: `handle` was returned by RegisterEventSourceW.
+            unsafe { DeregisterEventSource(self.handle) };
+        }
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_field_values() {
+        assert_eq!(quote("vth0"), "vth0");
+        assert_eq!(quote("Address 1002"), "\"Address 1002\"");
+        assert_eq!(quote("a\"b"), "\"a\\\"b\"");
+    }
+}
@@ -0,0 +1,119 @@
+use crate::subdevice::SubDevice;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Bus topology expected by the user (`--expect-devices`, `--expect-address`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopologyExpectation {
+    pub device_count: Option<usize>,
+    /// Configured station addresses in bus order.
+    pub addresses: Vec<u16>,
+}
+
+/// A difference between the expected and the discovered bus topology.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TopologyMismatch {
+    DeviceCount {
+        expected: usize,
+        actual: usize,
+    },
+    /// The configured station address at `position` differs. `None` means no
+    /// device (or no configured address) at that position.
+    Address {
+        position: usize,
+        expected: Option<u16>,
+        actual: Option<u16>,
+    },
+}
+
+impl TopologyExpectation {
+    pub fn is_empty(&self) -> bool {
+        self.device_count.is_none() && self.addresses.is_empty()
+    }
+
+    /// Compare the discovered subdevices against the expectation.
+    pub fn check(&self, devices: &[SubDevice]) -> Vec<TopologyMismatch> {
+        let mut mismatches = Vec::new();
+
+        if let Some(expected) = self.device_count
+            && expected != devices.len()
+        {
+            mismatches.push(TopologyMismatch::DeviceCount {
+                expected,
+                actual: devices.len(),
+            });
+        }
+
+        if !self.addresses.is_empty() {
+            let positions = self.addresses.len().max(devices.len());
+            for position in 0..positions {
+                let expected = self.addresses.get(position).copied();
+                let actual = devices.get(position).and_then(|d| d.configured_address());
+                if expected != actual {
+                    mismatches.push(TopologyMismatch::Address {
+                        position,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registers::RegisterAddress;
+    use crate::subdevice::AprdCommandStepper;
+
+    fn device(address: u16) -> SubDevice {
+        let mut device = SubDevice::new();
+        device.write_reg_wr(
+            RegisterAddress::ConfiguredStationAddress,
+            &address.to_le_bytes(),
+        );
+        device.write_reg_rd(
+            RegisterAddress::ConfiguredStationAddress,
+            &address.to_le_bytes(),
+        );
+        device.state_machine_step::<AprdCommandStepper>(1).unwrap();
+        device
+    }
+
+    #[test]
+    fn test_topology_diff() {
+        let expectation = TopologyExpectation {
+            device_count: Some(3),
+            addresses: vec![0x1001, 0x1002, 0x1003],
+        };
+        let devices = vec![device(0x1001), device(0x1003)];
+
+        let mismatches = expectation.check(&devices);
+        assert_eq!(
+            mismatches[0],
+            TopologyMismatch::DeviceCount {
+                expected: 3,
+                actual: 2
+            }
+        );
+        assert_eq!(
+            &mismatches[1..],
+            &[
+                TopologyMismatch::Address {
+                    position: 1,
+                    expected: Some(0x1002),
+                    actual: Some(0x1003)
+                },
+                TopologyMismatch::Address {
+                    position: 2,
+                    expected: Some(0x1003),
+                    actual: None
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,602 @@
+use pnet::datalink::NetworkInterface;
+use std::io;
+use std::ops::Range;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+use std::time::Duration;
+
+use crate::packet_source::{CaptureOptions, ClockSource, FrameReceiver};
+
+/// Ring geometry: 64 blocks of 256 KiB unless a buffer size is given. A block is
+/// handed to user space when it is full or `BLOCK_TIMEOUT_MS` after its first
+/// frame (1 ms in immediate mode).
+const BLOCK_SIZE: u32 = 1 << 18;
+const DEFAULT_BLOCK_COUNT: u32 = 64;
+const FRAME_SIZE: u32 = 2048;
+const BLOCK_TIMEOUT_MS: u32 = 10;
+
+/// Receiver on an `AF_PACKET` socket with a memory-mapped `TPACKET_V3` RX ring.
+///
+/// The kernel writes frames straight into blocks shared with user space, so
+/// there is no copy or system call per frame. Every frame carries the kernel
+/// receive time, or the adapter's with `--hw-timestamps`. Frames
+/// the kernel had to drop because the ring was full are added to `dropped_frames`.
+pub struct TpacketReceiver {
+    fd: OwnedFd,
+    ring: *mut u8,
+    ring_size: usize,
+    block_count: usize,
+    /// Block currently read or waited for.
+    block: usize,
+    /// Offset of the next frame in the current block and frames left in it,
+    /// `None` while the block belongs to the kernel.
+    cursor: Option<(usize, u32)>,
+    timeout: Duration,
+    /// Clock of the last frame, from its `tp_status`.
+    clock_source: ClockSource,
+    dropped_frames: Arc<AtomicU64>,
+    /// Restores the adapter's timestamping after `--hw-timestamps`.
+    _hardware_timestamps: Option<HardwareTimestamps>,
+}
+
+// The ring is only accessed through `&mut self`.
+unsafe impl Send for TpacketReceiver {}
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// Adapter timestamping switched on by [`enable_hardware_timestamps`]. The
+/// previous configuration is restored on drop, also when opening fails later.
+struct HardwareTimestamps {
+    fd: OwnedFd,
+    interface: String,
+    previous: libc::hwtstamp_config,
+}
+
+impl Drop for HardwareTimestamps {
+    fn drop(&mut self) {
+        let mut config = self.previous;
+        hwtstamp_ioctl(&self.fd, &self.interface, libc::SIOCSHWTSTAMP, &mut config).ok();
+    }
+}
+
+fn hwtstamp_ioctl(
+    fd: &OwnedFd,
+    interface: &str,
+    request: libc::c_ulong,
+    config: &mut libc::hwtstamp_config,
+) -> io::Result<()> {
+    // SAFETY: all-zero is a valid `ifreq`.
+    let mut ifreq: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in ifreq
+        .ifr_name
+        .iter_mut()
+        .zip(interface.bytes().take(libc::IFNAMSIZ - 1))
+    {
+        *dst = src as libc::c_char;
+    }
+    ifreq.ifr_ifru.ifru_data = config as *mut libc::hwtstamp_config as *mut libc::c_char;
+    // SAFETY: `ifreq` points to a valid `hwtstamp_config` for the duration of the call.
+    check(unsafe { libc::ioctl(fd.as_raw_fd(), request as _, &mut ifreq) })?;
+    Ok(())
+}
+
+/// Switch the adapter to timestamp all received frames and have the ring report
+/// those timestamps. Fails on adapters (and virtual interfaces) without support.
+fn enable_hardware_timestamps(
+    fd: &OwnedFd,
+    interface: &NetworkInterface,
+) -> io::Result<HardwareTimestamps> {
+    let mut previous = libc::hwtstamp_config {
+        flags: 0,
+        tx_type: libc::HWTSTAMP_TX_OFF as libc::c_int,
+        rx_filter: libc::HWTSTAMP_FILTER_NONE as libc::c_int,
+    };
+    // Drivers that cannot report their configuration are assumed to have
+    // timestamping off
+    hwtstamp_ioctl(fd, &interface.name, libc::SIOCGHWTSTAMP, &mut previous).ok();
+    let mut config = libc::hwtstamp_config {
+        rx_filter: libc::HWTSTAMP_FILTER_ALL as libc::c_int,
+        ..previous
+    };
+    hwtstamp_ioctl(fd, &interface.name, libc::SIOCSHWTSTAMP, &mut config)?;
+    // Dropping `enabled` on errors below puts the adapter back
+    let enabled = HardwareTimestamps {
+        fd: fd.try_clone()?,
+        interface: interface.name.clone(),
+        previous,
+    };
+    set_option(
+        fd,
+        libc::PACKET_TIMESTAMP,
+        &(libc::SOF_TIMESTAMPING_RAW_HARDWARE as libc::c_int),
+    )?;
+    Ok(enabled)
+}
+
+fn set_option<T>(fd: &OwnedFd, name: libc::c_int, value: &T) -> io::Result<()> {
+    // SAFETY: `value` is a valid `T` for the duration of the call.
+    check(unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_PACKET,
+            name,
+            value as *const T as *const libc::c_void,
+            size_of::<T>() as libc::socklen_t,
+        )
+    })?;
+    Ok(())
+}
+
+/// `SIOCGSTAMPNS`, not exported by `libc`.
+const SIOCGSTAMPNS: libc::c_ulong = 0x8907;
+
+/// Kernel receive time of the last frame read from the packet socket `fd`.
+///
+/// The first call switches timestamping on for the socket and fails with
+/// `ENOENT`. `SO_TIMESTAMPNS` cannot be used instead: it delivers the time in a
+/// control message, which pnet's `recvfrom` discards, and the kernel then no
+/// longer records the time for this call.
+pub fn last_receive_time(fd: RawFd) -> io::Result<Duration> {
+    // SAFETY: all-zero is a valid `timespec`.
+    let mut time: libc::timespec = unsafe { std::mem::zeroed() };
+    // SAFETY: `time` is a writable `timespec` for the duration of the call.
+    check(unsafe { libc::ioctl(fd, SIOCGSTAMPNS as _, &mut time) })?;
+    Ok(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+/// Classic BPF program accepting only EtherCAT frames (EtherType 0x88a4).
+static ETHERCAT_FILTER: [libc::sock_filter; 4] = [
+    // ldh [12]
+    bpf_statement(0x28, 12),
+    // jeq #0x88a4, accept, drop
+    libc::sock_filter {
+        code: 0x15,
+        jt: 0,
+        jf: 1,
+        k: 0x88a4,
+    },
+    // ret #0x40000 (whole frame)
+    bpf_statement(0x06, 0x40000),
+    // ret #0
+    bpf_statement(0x06, 0),
+];
+
+const fn bpf_statement(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+/// `AF_PACKET` socket with the given receive buffer size, receiving all protocols
+/// or, with `ethercat_only`, only EtherCAT frames filtered in the kernel.
+pub fn packet_socket(receive_buffer: Option<usize>, ethercat_only: bool) -> io::Result<OwnedFd> {
+    let protocol = (libc::ETH_P_ALL as u16).to_be();
+    // SAFETY: plain system call; the returned descriptor is owned below.
+    let fd = check(unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol as i32) })?;
+    // SAFETY: `fd` is a new descriptor nobody else owns.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    if let Some(size) = receive_buffer {
+        let size = size.min(i32::MAX as usize) as libc::c_int;
+        // SAFETY: `size` is a valid `c_int` for the duration of the call.
+        check(unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVBUF,
+                &size as *const libc::c_int as *const libc::c_void,
+                size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        })?;
+    }
+    if ethercat_only {
+        let program = libc::sock_fprog {
+            len: ETHERCAT_FILTER.len() as u16,
+            filter: ETHERCAT_FILTER.as_ptr() as *mut libc::sock_filter,
+        };
+        // SAFETY: `program` points to `ETHERCAT_FILTER`, which the kernel copies.
+        check(unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ATTACH_FILTER,
+                &program as *const libc::sock_fprog as *const libc::c_void,
+                size_of::<libc::sock_fprog>() as libc::socklen_t,
+            )
+        })?;
+    }
+    Ok(fd)
+}
+
+impl TpacketReceiver {
+    /// Open the ring on `interface`. `next` fails with `TimedOut` after `timeout`
+    /// without frames.
+    pub fn open(
+        interface: &NetworkInterface,
+        timeout: Duration,
+        options: &CaptureOptions,
+        dropped_frames: Arc<AtomicU64>,
+    ) -> io::Result<Self> {
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        let fd = packet_socket(None, !options.all_ethertypes)?;
+        let block_count = options.buffer_size.map_or(DEFAULT_BLOCK_COUNT, |size| {
+            (size / BLOCK_SIZE as usize).clamp(1, u32::MAX as usize) as u32
+        });
+
+        set_option(
+            &fd,
+            libc::PACKET_VERSION,
+            &(libc::tpacket_versions::TPACKET_V3 as libc::c_int),
+        )?;
+        let request = libc::tpacket_req3 {
+            tp_block_size: BLOCK_SIZE,
+            tp_block_nr: block_count,
+            tp_frame_size: FRAME_SIZE,
+            tp_frame_nr: BLOCK_SIZE / FRAME_SIZE * block_count,
+            tp_retire_blk_tov: if options.immediate {
+                1
+            } else {
+                BLOCK_TIMEOUT_MS
+            },
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        let hardware_timestamps = if options.hw_timestamps {
+            let enabled = enable_hardware_timestamps(&fd, interface).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("hardware timestamping not available: {}", e),
+                )
+            })?;
+            Some(enabled)
+        } else {
+            None
+        };
+        let clock_source = if hardware_timestamps.is_some() {
+            ClockSource::Hardware
+        } else {
+            ClockSource::Kernel
+        };
+        set_option(&fd, libc::PACKET_RX_RING, &request)?;
+
+        let ring_size = BLOCK_SIZE as usize * block_count as usize;
+        // SAFETY: maps the ring just configured on `fd`; unmapped in `drop`.
+        let ring = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                ring_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if ring == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let receiver = TpacketReceiver {
+            fd,
+            ring: ring as *mut u8,
+            ring_size,
+            block_count: block_count as usize,
+            block: 0,
+            cursor: None,
+            timeout,
+            clock_source,
+            dropped_frames,
+            _hardware_timestamps: hardware_timestamps,
+        };
+
+        // SAFETY: all-zero is a valid `sockaddr_ll`/`packet_mreq`.
+        let mut address: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        address.sll_family = libc::AF_PACKET as u16;
+        address.sll_protocol = protocol;
+        address.sll_ifindex = interface.index as i32;
+        // SAFETY: `address` is a valid `sockaddr_ll` of the given size.
+        check(unsafe {
+            libc::bind(
+                receiver.fd.as_raw_fd(),
+                &address as *const libc::sockaddr_ll as *const libc::sockaddr,
+                size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        })?;
+
+        if options.promiscuous {
+            let mut membership: libc::packet_mreq = unsafe { std::mem::zeroed() };
+            membership.mr_ifindex = interface.index as i32;
+            membership.mr_type = libc::PACKET_MR_PROMISC as u16;
+            set_option(&receiver.fd, libc::PACKET_ADD_MEMBERSHIP, &membership)?;
+        }
+
+        Ok(receiver)
+    }
+
+    fn block_desc(&self, block: usize) -> *mut libc::tpacket_block_desc {
+        // SAFETY: `block` < `block_count`, so the offset is within the ring.
+        unsafe { self.ring.add(block * BLOCK_SIZE as usize) as *mut libc::tpacket_block_desc }
+    }
+
+    /// The bytes of `block`.
+    ///
+    /// # Safety
+    ///
+    /// The block must belong to user space, and the slice must not be used
+    /// after the block is released.
+    unsafe fn block_data<'a>(&self, block: usize) -> &'a [u8] {
+        // SAFETY: `block` < `block_count`, so the block is within the ring.
+        unsafe {
+            std::slice::from_raw_parts(
+                self.ring.add(block * BLOCK_SIZE as usize),
+                BLOCK_SIZE as usize,
+            )
+        }
+    }
+
+    fn block_status(&self, block: usize) -> *mut u32 {
+        // SAFETY: the block descriptor is at the start of the block.
+        unsafe { ptr::addr_of_mut!((*self.block_desc(block)).hdr.bh1.block_status) }
+    }
+
+    /// Hand the current block back to the kernel and move on to the next one.
+    fn release_block(&mut self) {
+        fence(Ordering::Release);
+        // SAFETY: the block belongs to user space until this write.
+        unsafe { ptr::write_volatile(self.block_status(self.block), libc::TP_STATUS_KERNEL) };
+        self.block = (self.block + 1) % self.block_count;
+        self.cursor = None;
+        self.count_drops();
+    }
+
+    /// Add the kernel's drop counter (reset on every read) to `dropped_frames`.
+    fn count_drops(&self) {
+        // SAFETY: all-zero is a valid `tpacket_stats_v3`.
+        let mut stats: libc::tpacket_stats_v3 = unsafe { std::mem::zeroed() };
+        let mut len = size_of::<libc::tpacket_stats_v3>() as libc::socklen_t;
+        // SAFETY: `stats` and `len` describe a writable buffer of the right size.
+        let result = unsafe {
+            libc::getsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_PACKET,
+                libc::PACKET_STATISTICS,
+                &mut stats as *mut libc::tpacket_stats_v3 as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result == 0 && stats.tp_drops > 0 {
+            self.dropped_frames
+                .fetch_add(stats.tp_drops as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn wait(&self) -> io::Result<()> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN | libc::POLLERR,
+            revents: 0,
+        };
+        let timeout = self.timeout.as_millis().min(i32::MAX as u128) as libc::c_int;
+        // SAFETY: `poll_fd` is a valid array of one element.
+        match unsafe { libc::poll(&mut poll_fd, 1, timeout) } {
+            0 => {
+                self.count_drops();
+                Err(io::ErrorKind::TimedOut.into())
+            }
+            result if result < 0 => {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    Ok(())
+                } else {
+                    Err(error)
+                }
+            }
+            // Pending socket errors, e.g. ENETDOWN while the link is down
+            _ if poll_fd.revents & libc::POLLERR != 0 => {
+                let mut error: libc::c_int = 0;
+                let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+                // SAFETY: `error` and `len` describe a writable `c_int`.
+                check(unsafe {
+                    libc::getsockopt(
+                        self.fd.as_raw_fd(),
+                        libc::SOL_SOCKET,
+                        libc::SO_ERROR,
+                        &mut error as *mut libc::c_int as *mut libc::c_void,
+                        &mut len,
+                    )
+                })?;
+                if error == 0 {
+                    Ok(())
+                } else {
+                    Err(io::Error::from_raw_os_error(error))
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A frame header in a ring block, see [`parse_frame`].
+#[derive(Debug, PartialEq, Eq)]
+struct RingFrame {
+    /// Frame data within the block, `None` if `tp_snaplen` reaches past it.
+    data: Option<Range<usize>>,
+    /// Offset of the next frame header in the block.
+    next_offset: usize,
+    receive_time: Duration,
+    clock_source: ClockSource,
+}
+
+/// Offset of the first frame and the number of frames of a block handed to
+/// user space.
+fn parse_block(block: &[u8]) -> (usize, u32) {
+    assert!(block.len() >= size_of::<libc::tpacket_block_desc>());
+    // SAFETY: the descriptor lies within `block` (asserted above).
+    let desc = unsafe { ptr::read_unaligned(block.as_ptr() as *const libc::tpacket_block_desc) };
+    // SAFETY: TPACKET_V3 blocks carry the `bh1` header.
+    let header = unsafe { desc.hdr.bh1 };
+    (header.offset_to_first_pkt as usize, header.num_pkts)
+}
+
+/// The frame header at `offset` of `block`. `None` if the header reaches past
+/// the block, which means the block is corrupt.
+fn parse_frame(block: &[u8], offset: usize) -> Option<RingFrame> {
+    if offset + size_of::<libc::tpacket3_hdr>() > block.len() {
+        return None;
+    }
+    // SAFETY: the header lies within `block` (checked above).
+    let header =
+        unsafe { ptr::read_unaligned(block.as_ptr().add(offset) as *const libc::tpacket3_hdr) };
+    let start = offset + header.tp_mac as usize;
+    let end = start + header.tp_snaplen as usize;
+    Some(RingFrame {
+        data: (end <= block.len()).then_some(start..end),
+        next_offset: offset + header.tp_next_offset as usize,
+        receive_time: Duration::new(header.tp_sec as u64, header.tp_nsec),
+        // The adapter may not timestamp every frame, even with
+        // `HWTSTAMP_FILTER_ALL`; those carry `TP_STATUS_TS_SOFTWARE`
+        // (or no flag) and the kernel's receive time
+        clock_source: match header.tp_status {
+            status if status & libc::TP_STATUS_TS_RAW_HARDWARE != 0 => ClockSource::Hardware,
+            _ => ClockSource::Kernel,
+        },
+    })
+}
+
+impl FrameReceiver for TpacketReceiver {
+    fn next_frame(&mut self) -> io::Result<(&[u8], Option<Duration>)> {
+        loop {
+            match self.cursor {
+                Some((offset, remaining)) if remaining > 0 => {
+                    // SAFETY: the current block belongs to user space, the frame
+                    // stays valid until the block is released on a later call.
+                    let block = unsafe { self.block_data(self.block) };
+                    // A header reaching past the block means the block is corrupt;
+                    // give it back without reading further frames
+                    let Some(frame) = parse_frame(block, offset) else {
+                        self.cursor = Some((offset, 0));
+                        continue;
+                    };
+                    self.cursor = Some((frame.next_offset, remaining - 1));
+                    let Some(data) = frame.data else {
+                        continue;
+                    };
+                    self.clock_source = frame.clock_source;
+                    return Ok((&block[data], Some(frame.receive_time)));
+                }
+                Some(_) => self.release_block(),
+                None => {
+                    // SAFETY: the status word is shared with the kernel, read it volatile.
+                    let status = unsafe { ptr::read_volatile(self.block_status(self.block)) };
+                    if status & libc::TP_STATUS_USER == 0 {
+                        self.wait()?;
+                        continue;
+                    }
+                    fence(Ordering::Acquire);
+                    // SAFETY: the block belongs to user space now.
+                    let block = unsafe { self.block_data(self.block) };
+                    self.cursor = Some(parse_block(block));
+                }
+            }
+        }
+    }
+
+    fn clock_source(&self) -> ClockSource {
+        self.clock_source
+    }
+}
+
+impl Drop for TpacketReceiver {
+    fn drop(&mut self) {
+        // SAFETY: `ring` was mapped with `ring_size` in `open`.
+        unsafe { libc::munmap(self.ring as *mut libc::c_void, self.ring_size) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A zeroed ring block with the given frame headers, each `(offset, header)`.
+    fn block(first_offset: u32, frames: &[(usize, libc::tpacket3_hdr)]) -> Vec<u8> {
+        let mut block = vec![0u8; BLOCK_SIZE as usize];
+        // SAFETY: all-zero is a valid `tpacket_block_desc`.
+        let mut desc: libc::tpacket_block_desc = unsafe { std::mem::zeroed() };
+        desc.hdr.bh1.num_pkts = frames.len() as u32;
+        desc.hdr.bh1.offset_to_first_pkt = first_offset;
+        // SAFETY: both lie within the block.
+        unsafe {
+            ptr::write_unaligned(block.as_mut_ptr() as *mut libc::tpacket_block_desc, desc);
+            for (offset, header) in frames {
+                ptr::write_unaligned(
+                    block.as_mut_ptr().add(*offset) as *mut libc::tpacket3_hdr,
+                    *header,
+                );
+            }
+        }
+        block
+    }
+
+    fn header(next_offset: u32, snaplen: u32) -> libc::tpacket3_hdr {
+        // SAFETY: all-zero is a valid `tpacket3_hdr`.
+        let mut header: libc::tpacket3_hdr = unsafe { std::mem::zeroed() };
+        header.tp_next_offset = next_offset;
+        header.tp_snaplen = snaplen;
+        header.tp_len = snaplen;
+        header.tp_mac = 80;
+        header.tp_sec = 1_700_000_000;
+        header.tp_nsec = 250;
+        header
+    }
+
+    #[test]
+    fn test_parse_block() {
+        let mut hardware = header(0, 60);
+        hardware.tp_status = libc::TP_STATUS_USER | libc::TP_STATUS_TS_RAW_HARDWARE;
+        let block = block(48, &[(48, header(128, 60)), (176, hardware)]);
+        assert_eq!(parse_block(&block), (48, 2));
+
+        let first = parse_frame(&block, 48).unwrap();
+        assert_eq!(
+            first,
+            RingFrame {
+                data: Some(128..188),
+                next_offset: 176,
+                receive_time: Duration::new(1_700_000_000, 250),
+                clock_source: ClockSource::Kernel,
+            }
+        );
+        let second = parse_frame(&block, first.next_offset).unwrap();
+        assert_eq!(second.data, Some(256..316));
+        assert_eq!(second.clock_source, ClockSource::Hardware);
+    }
+
+    #[test]
+    fn test_next_offset_past_the_block_end() {
+        let block = block(48, &[(48, header(BLOCK_SIZE, 60))]);
+        let frame = parse_frame(&block, 48).unwrap();
+        assert_eq!(frame.data, Some(128..188));
+        assert_eq!(parse_frame(&block, frame.next_offset), None);
+        // A header that starts inside the block but ends outside of it
+        let last = BLOCK_SIZE as usize - size_of::<libc::tpacket3_hdr>();
+        assert!(parse_frame(&block, last).is_some());
+        assert_eq!(parse_frame(&block, last + 1), None);
+    }
+
+    #[test]
+    fn test_snaplen_past_the_block_end() {
+        let block = block(48, &[(48, header(128, BLOCK_SIZE))]);
+        let frame = parse_frame(&block, 48).unwrap();
+        assert_eq!(frame.data, None);
+        // The frame is left out, the block goes on with the next one
+        assert_eq!(frame.next_offset, 176);
+    }
+}
@@ -0,0 +1,35 @@
+//! `--events -` must leave standard output to the JSON Lines stream, whatever
+//! else is enabled.
+
+use std::process::Command;
+
+#[test]
+fn test_events_on_stdout_are_json_lines() {
+    let output = Command::new(env!("CARGO_BIN_EXE_ecdump"))
+        .args([
+            "-f",
+            "testdata/wkc_esm_error.pcap",
+            "--events",
+            "-",
+            // Two subdevices are on the bus, so the topology check fails
+            "--expect-devices",
+            "1",
+            "-ddd",
+            "--self-stats",
+            "1ms",
+        ])
+        .output()
+        .expect("ecdump runs");
+    // Exits with 1 because of the errors found
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8(output.stdout).expect("UTF-8 output");
+    assert!(stdout.lines().count() > 0);
+    for line in stdout.lines() {
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(line) {
+            panic!("not a JSON line: {:?} ({})", line, e);
+        }
+    }
+    // Log messages went to standard error instead
+    assert!(String::from_utf8_lossy(&output.stderr).contains("DEBUG"));
+}